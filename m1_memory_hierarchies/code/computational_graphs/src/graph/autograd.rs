@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use super::graph_error::GraphError;
+use crate::shared::graph_operators::LossKind;
+
+// Reverse-mode differentiation, or backpropagation, for the graph runners.
+//
+// Once the forward pass has run, every intermediate result is still in its buffer,
+// as long as the memory planner wasn't allowed to reuse it, which is why a graph
+// has to be built for training to be trained. The backward pass then walks the
+// nodes in reverse order. Every node reads the gradient of the loss with respect
+// to its output, and adds the gradients with respect to its inputs to their
+// gradient buffers. By the time a node is reached, every node reading its output
+// has already run, so its output gradient is complete.
+//
+// The gradient of the loss with respect to itself is 1, so the output gradient
+// of every Loss node is a 1x1 buffer holding 1, which is what starts it all.
+//
+// Planning the backward pass only shuffles buffer indices around,
+// so the CPU and GPU runners share it, just like the memory planner.
+// Each runner describes its forward nodes as ForwardRecords and creates
+// the gradient buffers when the planner asks for them.
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum BackwardOperator {
+    LinearLayer,
+    ReLU,
+    Softmax,
+    SoftmaxAxis { axis: usize },
+    LogSoftmax,
+    Loss { kind: LossKind },
+    // A Softmax followed by a cross entropy Loss, which is much better
+    // behaved numerically when done in one step
+    SoftmaxCrossEntropy,
+    Add,
+}
+
+// A forward node, with the same buffer layout as the nodes of the runners
+// LinearLayer - [input, weights, bias, output]
+// ReLU, Softmax, SoftmaxAxis, LogSoftmax - [input, output]
+// Loss - [input, target, output]
+// Add - [left, right, output]
+#[derive(Clone, Debug)]
+pub struct ForwardRecord {
+    pub name: String,
+    pub operator: BackwardOperator,
+    pub buffer_indices: Vec<usize>,
+}
+
+// LinearLayer - [input, weights, output_gradient, input_gradient, weights_gradient, bias_gradient]
+// ReLU - [input, output_gradient, input_gradient]
+// Softmax, SoftmaxAxis, LogSoftmax - [output, output_gradient, input_gradient]
+// Loss - [input, target, output_gradient, input_gradient]
+// SoftmaxCrossEntropy - [softmax_output, target, output_gradient, softmax_input_gradient]
+// Add - [output_gradient, left_gradient, right_gradient]
+#[derive(Clone, Debug)]
+pub struct BackwardNode {
+    pub name: String,
+    pub operator: BackwardOperator,
+    pub buffer_indices: Vec<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ParameterKind {
+    Weights,
+    Bias,
+}
+
+// A tensor which training is allowed to change, and where its gradient is
+#[derive(Clone, Debug)]
+pub struct Parameter {
+    pub node_name: String,
+    pub kind: ParameterKind,
+    pub value_index: usize,
+    pub gradient_index: usize,
+}
+
+impl Parameter {
+    // The name the gradient is returned under, like "LinearLayer_0.weights"
+    pub fn name(&self) -> String {
+        let kind: &str = match self.kind {
+            ParameterKind::Weights => "weights",
+            ParameterKind::Bias => "bias",
+        };
+        format!("{}.{}", self.node_name, kind)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BackwardPlan {
+    // In the order they should run
+    pub nodes: Vec<BackwardNode>,
+    pub parameters: Vec<Parameter>,
+    // The gradient buffer of every buffer which has one
+    pub gradient_indices: HashMap<usize, usize>,
+    // The output gradients of the losses, which hold 1 and are never zeroed
+    pub seed_indices: Vec<usize>,
+}
+
+impl BackwardPlan {
+    // Every gradient buffer which has to be zeroed before a backward pass
+    pub fn accumulated_indices(&self) -> Vec<usize> {
+        let mut indices: Vec<usize> = self
+            .gradient_indices
+            .values()
+            .copied()
+            .filter(|index| !self.seed_indices.contains(index))
+            .collect();
+        indices.sort_unstable();
+        indices
+    }
+}
+
+// The buffers a record reads which a gradient can flow back to
+fn differentiable_inputs(record: &ForwardRecord) -> &[usize] {
+    match record.operator {
+        BackwardOperator::Add => &record.buffer_indices[0..2],
+        _ => &record.buffer_indices[0..1],
+    }
+}
+
+fn expected_buffer_count(operator: BackwardOperator) -> usize {
+    match operator {
+        BackwardOperator::LinearLayer => 4,
+        BackwardOperator::ReLU
+        | BackwardOperator::Softmax
+        | BackwardOperator::SoftmaxAxis { axis: _ }
+        | BackwardOperator::LogSoftmax => 2,
+        BackwardOperator::Loss { kind: _ } | BackwardOperator::Add => 3,
+        BackwardOperator::SoftmaxCrossEntropy => 4,
+    }
+}
+
+// records are the differentiable nodes in the order they run, shapes holds the
+// (row_count, column_count) of every buffer the records use. create_buffer is called
+// with a label and a shape for every gradient buffer, and has to return its index.
+// The buffers it creates must start out as zeroes.
+pub fn plan_backward(
+    records: &[ForwardRecord],
+    shapes: &[(usize, usize)],
+    mut create_buffer: impl FnMut(&str, usize, usize) -> usize,
+) -> Result<BackwardPlan, GraphError> {
+    for record in records {
+        if record.operator == BackwardOperator::SoftmaxCrossEntropy
+            || record.buffer_indices.len() != expected_buffer_count(record.operator)
+            || record
+                .buffer_indices
+                .iter()
+                .any(|buffer_index| shapes.len() <= *buffer_index)
+        {
+            return Err(GraphError::MalformedNode(format!(
+                "plan_backward received the invalid record {:?}.",
+                record
+            )));
+        }
+    }
+
+    let output_index = |record: &ForwardRecord| -> usize { *record.buffer_indices.last().unwrap() };
+
+    // Which record produced each buffer, and how many records read it
+    let mut producers: HashMap<usize, usize> = HashMap::<usize, usize>::new();
+    let mut reader_counts: HashMap<usize, usize> = HashMap::<usize, usize>::new();
+    for (record_index, record) in records.iter().enumerate() {
+        producers.insert(output_index(record), record_index);
+        for input_index in differentiable_inputs(record) {
+            *reader_counts.entry(*input_index).or_insert(0) += 1;
+        }
+    }
+
+    let mut plan: BackwardPlan = BackwardPlan::default();
+
+    for record in records {
+        if let BackwardOperator::Loss { kind: _ } = record.operator {
+            let seed_index: usize = create_buffer(&format!("{}_seed", record.name), 1, 1);
+            plan.gradient_indices
+                .insert(output_index(record), seed_index);
+            plan.seed_indices.push(seed_index);
+        }
+    }
+    if plan.seed_indices.is_empty() {
+        return Err(GraphError::UnsupportedOperator(
+            "A graph needs at least one Loss node to be trained.".to_string(),
+        ));
+    }
+
+    let mut gradient_of = |plan: &mut BackwardPlan, buffer_index: usize, label: &str| -> usize {
+        *plan
+            .gradient_indices
+            .entry(buffer_index)
+            .or_insert_with(|| {
+                let (row_count, column_count) = shapes[buffer_index];
+                create_buffer(&format!("{}_gradient", label), row_count, column_count)
+            })
+    };
+
+    let mut merged: Vec<bool> = vec![false; records.len()];
+    for record_index in (0..records.len()).rev() {
+        let record: &ForwardRecord = &records[record_index];
+        if merged[record_index] {
+            continue;
+        }
+
+        // Nodes which don't lead to a loss get no gradient, and don't pass one on
+        let output_gradient: usize = match plan.gradient_indices.get(&output_index(record)) {
+            Some(output_gradient) => *output_gradient,
+            None => continue,
+        };
+
+        let name: &str = &record.name;
+        let buffer_indices: Vec<usize> = match record.operator {
+            BackwardOperator::LinearLayer => {
+                let (input, weights, bias) = (
+                    record.buffer_indices[0],
+                    record.buffer_indices[1],
+                    record.buffer_indices[2],
+                );
+                let input_gradient: usize = gradient_of(&mut plan, input, name);
+                let weights_gradient: usize =
+                    gradient_of(&mut plan, weights, &format!("{}_weights", name));
+                let bias_gradient: usize = gradient_of(&mut plan, bias, &format!("{}_bias", name));
+                plan.parameters.push(Parameter {
+                    node_name: name.to_string(),
+                    kind: ParameterKind::Weights,
+                    value_index: weights,
+                    gradient_index: weights_gradient,
+                });
+                plan.parameters.push(Parameter {
+                    node_name: name.to_string(),
+                    kind: ParameterKind::Bias,
+                    value_index: bias,
+                    gradient_index: bias_gradient,
+                });
+
+                vec![
+                    input,
+                    weights,
+                    output_gradient,
+                    input_gradient,
+                    weights_gradient,
+                    bias_gradient,
+                ]
+            }
+            BackwardOperator::ReLU => {
+                let input: usize = record.buffer_indices[0];
+                let input_gradient: usize = gradient_of(&mut plan, input, name);
+                vec![input, output_gradient, input_gradient]
+            }
+            BackwardOperator::Softmax
+            | BackwardOperator::SoftmaxAxis { axis: _ }
+            | BackwardOperator::LogSoftmax => {
+                let (input, output) = (record.buffer_indices[0], record.buffer_indices[1]);
+                let input_gradient: usize = gradient_of(&mut plan, input, name);
+                vec![output, output_gradient, input_gradient]
+            }
+            BackwardOperator::Loss { kind } => {
+                let (input, target) = (record.buffer_indices[0], record.buffer_indices[1]);
+
+                // If the probabilities of a cross entropy come straight from a Softmax
+                // nobody else reads, the two are differentiated together.
+                // The merged version only knows the softmax over the whole tensor.
+                let softmax_index: Option<usize> = producers
+                    .get(&input)
+                    .copied()
+                    .filter(|_| kind == LossKind::CrossEntropy)
+                    .filter(|producer| records[*producer].operator == BackwardOperator::Softmax)
+                    .filter(|_| reader_counts.get(&input) == Some(&1));
+
+                match softmax_index {
+                    Some(softmax_index) => {
+                        merged[softmax_index] = true;
+                        let softmax_input: usize = records[softmax_index].buffer_indices[0];
+                        let softmax_input_gradient: usize =
+                            gradient_of(&mut plan, softmax_input, &records[softmax_index].name);
+                        plan.nodes.push(BackwardNode {
+                            name: format!("{}_{}", records[softmax_index].name, name),
+                            operator: BackwardOperator::SoftmaxCrossEntropy,
+                            buffer_indices: vec![
+                                input,
+                                target,
+                                output_gradient,
+                                softmax_input_gradient,
+                            ],
+                        });
+                        continue;
+                    }
+                    None => {
+                        let input_gradient: usize = gradient_of(&mut plan, input, name);
+                        vec![input, target, output_gradient, input_gradient]
+                    }
+                }
+            }
+            BackwardOperator::Add => {
+                let (left, right) = (record.buffer_indices[0], record.buffer_indices[1]);
+                let left_gradient: usize = gradient_of(&mut plan, left, name);
+                let right_gradient: usize = gradient_of(&mut plan, right, name);
+                vec![output_gradient, left_gradient, right_gradient]
+            }
+            BackwardOperator::SoftmaxCrossEntropy => unreachable!("Rejected above"),
+        };
+
+        plan.nodes.push(BackwardNode {
+            name: name.to_string(),
+            operator: record.operator,
+            buffer_indices,
+        });
+    }
+
+    Ok(plan)
+}
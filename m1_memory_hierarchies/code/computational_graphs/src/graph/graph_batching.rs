@@ -0,0 +1,136 @@
+use crate::shared::graph_operators::GraphOperator;
+use crate::shared::graph_operators::GraphOperator::*;
+use crate::shared::tensor2d::Tensor2D;
+
+use super::graph_dag::{GraphDAG, GraphNode};
+use super::graph_error::GraphError;
+
+// Batching stacks batch_size samples on top of each other, so a graph built for
+// a single input of r rows runs on batch_size * r rows with one dispatch per node.
+// Linear layers, ReLU, Add and Concat along the columns work on every row by itself,
+// so the only thing which has to change is the shape of the inputs and the biases,
+// which are repeated for every sample.
+// Softmax without an axis, LogSoftmax and Loss reduce over every element of the tensor,
+// softmax along the columns reduces over every sample, and Concat along the rows
+// would interleave the samples, so a batched graph can't contain those.
+// Softmax along the rows normalizes every row by itself, which batches just fine.
+pub fn batch_graph(graph: &GraphDAG, batch_size: usize) -> Result<GraphDAG, GraphError> {
+    if batch_size == 0 {
+        return Err(GraphError::DimensionMismatch(
+            "A batch has to contain at least one sample".to_string(),
+        ));
+    }
+
+    let mut nodes: Vec<GraphNode> = Vec::<GraphNode>::with_capacity(graph.nodes.len());
+    for node in &graph.nodes {
+        let operator: GraphOperator = match &node.operator {
+            HostToDevice { input } => HostToDevice {
+                input: tile_rows(input, batch_size),
+            },
+            LinearLayer { weights, bias } => LinearLayer {
+                weights: weights.clone(),
+                bias: tile_rows(bias, batch_size),
+            },
+            LinearReLUFused { weights, bias } => LinearReLUFused {
+                weights: weights.clone(),
+                bias: tile_rows(bias, batch_size),
+            },
+            Concat { axis: 0 } => {
+                return Err(GraphError::UnsupportedOperator(format!(
+                    "Node {} concatenates along the rows, which would mix the samples of a batch",
+                    node.name
+                )));
+            }
+            Softmax { axis: Some(0) } => {
+                return Err(GraphError::UnsupportedOperator(format!(
+                    "Node {} is a softmax along the columns, which would mix the samples of a batch",
+                    node.name
+                )));
+            }
+            Softmax { axis: None }
+            | LogSoftmax
+            | LinearReLUSoftmaxFused {
+                weights: _,
+                bias: _,
+            }
+            | Loss { kind: _, target: _ } => {
+                return Err(GraphError::UnsupportedOperator(format!(
+                    "Node {} is a {}, which reduces over the whole tensor and can't be batched",
+                    node.name,
+                    GraphDAG::operator_name(&node.operator)
+                )));
+            }
+            Empty
+            | DeviceToHost
+            | ReLU
+            | Add
+            | AddReLUFused
+            | Concat { axis: _ }
+            | Softmax { axis: Some(_) } => node.operator.clone(),
+        };
+
+        nodes.push(GraphNode {
+            name: node.name.clone(),
+            operator,
+            inputs: node.inputs.clone(),
+        });
+    }
+
+    Ok(GraphDAG { nodes })
+}
+
+// The tensor repeated count times along the rows
+pub fn tile_rows(tensor: &Tensor2D, count: usize) -> Tensor2D {
+    let mut tiled: Tensor2D = Tensor2D::new(0.0, tensor.row_count * count, tensor.column_count);
+    for chunk in tiled.data.chunks_exact_mut(tensor.len()) {
+        chunk.copy_from_slice(&tensor.data);
+    }
+    tiled
+}
+
+// Stacks the samples into a tensor of row_count * batch_size rows.
+// A batch can be run with fewer samples than it was built for,
+// in which case the rows of the missing samples are left as 0.
+pub fn stack_rows(
+    samples: &[Tensor2D],
+    row_count: usize,
+    column_count: usize,
+    batch_size: usize,
+) -> Result<Tensor2D, GraphError> {
+    if samples.is_empty() || batch_size < samples.len() {
+        return Err(GraphError::DimensionMismatch(format!(
+            "Got {} samples for a batch of {}",
+            samples.len(),
+            batch_size
+        )));
+    }
+
+    let mut stacked: Tensor2D = Tensor2D::new(0.0, row_count * batch_size, column_count);
+    for (index, sample) in samples.iter().enumerate() {
+        if sample.row_count != row_count || sample.column_count != column_count {
+            return Err(GraphError::DimensionMismatch(format!(
+                "Sample {} has {} rows and {} columns, but the graph takes {} rows and {} columns",
+                index, sample.row_count, sample.column_count, row_count, column_count
+            )));
+        }
+        let offset: usize = index * sample.len();
+        stacked.data[offset..offset + sample.len()].copy_from_slice(&sample.data);
+    }
+
+    Ok(stacked)
+}
+
+// The inverse of stack_rows, returns the first sample_count of the batch_size samples
+pub fn split_rows(tensor: &Tensor2D, batch_size: usize, sample_count: usize) -> Vec<Tensor2D> {
+    let row_count: usize = tensor.row_count / batch_size;
+    tensor
+        .data
+        .chunks_exact(row_count * tensor.column_count)
+        .take(sample_count)
+        .map(|chunk| {
+            let mut sample: Tensor2D = Tensor2D::new(0.0, row_count, tensor.column_count);
+            sample.data.copy_from_slice(chunk);
+            sample
+        })
+        .collect()
+}
@@ -0,0 +1,273 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        graph::{
+            graph_batching::{batch_graph, split_rows, stack_rows, tile_rows},
+            graph_dag::GraphDAG,
+            graph_error::GraphError,
+            graph_runner::GraphRunner,
+        },
+        shared::{graph_operators::GraphOperator, tensor2d::Tensor2D},
+    };
+
+    const ERROR_TOLERANCE: f32 = 0.00001;
+
+    fn sample(offset: f32, row_count: usize, column_count: usize) -> Tensor2D {
+        let mut tensor: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+        for (index, value) in tensor.data.iter_mut().enumerate() {
+            *value = ((index as f32 + offset) * 1.7).sin();
+        }
+        tensor
+    }
+
+    fn mlp_graph(input: Tensor2D) -> Vec<GraphOperator> {
+        vec![
+            GraphOperator::HostToDevice { input },
+            GraphOperator::LinearLayer {
+                weights: sample(1.0, 4, 5),
+                bias: sample(2.0, 2, 5),
+            },
+            GraphOperator::ReLU,
+            GraphOperator::LinearLayer {
+                weights: sample(3.0, 5, 3),
+                bias: sample(4.0, 2, 3),
+            },
+            GraphOperator::DeviceToHost,
+        ]
+    }
+
+    fn assert_close(expected: &Tensor2D, actual: &Tensor2D) {
+        assert_eq!(
+            (expected.row_count, expected.column_count),
+            (actual.row_count, actual.column_count)
+        );
+        for (expected, actual) in expected.data.iter().zip(actual.data.iter()) {
+            assert!(
+                (expected - actual).abs() < ERROR_TOLERANCE,
+                "expected {} but got {}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn stacking() {
+        let tensor: Tensor2D = sample(0.0, 2, 3);
+        let tiled: Tensor2D = tile_rows(&tensor, 3);
+        assert_eq!((tiled.row_count, tiled.column_count), (6, 3));
+        assert_eq!(tiled.data[12..18], tensor.data[..]);
+
+        let samples: Vec<Tensor2D> = vec![sample(0.0, 2, 3), sample(6.0, 2, 3)];
+        let stacked: Tensor2D = stack_rows(&samples, 2, 3, 3).unwrap();
+        assert_eq!((stacked.row_count, stacked.column_count), (6, 3));
+        // The missing third sample is left as 0
+        assert!(stacked.data[12..].iter().all(|value| *value == 0.0));
+
+        let split: Vec<Tensor2D> = split_rows(&stacked, 3, 2);
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0].data, samples[0].data);
+        assert_eq!(split[1].data, samples[1].data);
+
+        assert!(matches!(
+            stack_rows(&samples, 2, 3, 1),
+            Err(GraphError::DimensionMismatch(_))
+        ));
+        assert!(matches!(
+            stack_rows(&[], 2, 3, 1),
+            Err(GraphError::DimensionMismatch(_))
+        ));
+        assert!(matches!(
+            stack_rows(&[sample(0.0, 3, 2)], 2, 3, 1),
+            Err(GraphError::DimensionMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn run_with_input() {
+        let mut graph_runner: GraphRunner =
+            GraphRunner::new(&mlp_graph(Tensor2D::new(0.0, 2, 4)), false).unwrap();
+
+        // The same runner gives the same result as a runner built for each input
+        for offset in [0.0, 10.0, 20.0] {
+            let input: Tensor2D = sample(offset, 2, 4);
+            let expected: Tensor2D = GraphRunner::new(&mlp_graph(input.clone()), false)
+                .unwrap()
+                .run()
+                .unwrap();
+            let output: Tensor2D = graph_runner.run_with_input(&input).unwrap();
+            assert_close(&expected, &output);
+        }
+
+        assert!(matches!(
+            graph_runner.run_with_input(&Tensor2D::new(0.0, 4, 2)),
+            Err(GraphError::DimensionMismatch(_))
+        ));
+
+        // With two inputs there is no telling which one to replace
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node(
+            "left",
+            GraphOperator::HostToDevice {
+                input: sample(0.0, 2, 3),
+            },
+            &[],
+        );
+        graph.add_node(
+            "right",
+            GraphOperator::HostToDevice {
+                input: sample(1.0, 2, 3),
+            },
+            &[],
+        );
+        graph.add_node("add", GraphOperator::Add, &["left", "right"]);
+        graph.add_node("output", GraphOperator::DeviceToHost, &["add"]);
+        let mut graph_runner: GraphRunner = GraphRunner::from_dag(&graph, false).unwrap();
+        assert!(matches!(
+            graph_runner.run_with_input(&sample(2.0, 2, 3)),
+            Err(GraphError::UnsupportedOperator(_))
+        ));
+    }
+
+    #[test]
+    fn run_batch() {
+        let inputs: Vec<Tensor2D> = (0..4)
+            .map(|index| sample(index as f32 * 10.0, 2, 4))
+            .collect();
+        let expected: Vec<Tensor2D> = inputs
+            .iter()
+            .map(|input| {
+                GraphRunner::new(&mlp_graph(input.clone()), false)
+                    .unwrap()
+                    .run()
+                    .unwrap()
+            })
+            .collect();
+
+        for fuse_operators in [false, true] {
+            let mut graph_runner: GraphRunner =
+                GraphRunner::batched(&mlp_graph(Tensor2D::new(0.0, 2, 4)), 4, fuse_operators)
+                    .unwrap();
+
+            let outputs: Vec<Tensor2D> = graph_runner.run_batch(&inputs).unwrap();
+            assert_eq!(outputs.len(), 4);
+            for (expected, output) in expected.iter().zip(outputs.iter()) {
+                assert_close(expected, output);
+            }
+
+            // A partial batch only returns the outputs of the samples it was given
+            let outputs: Vec<Tensor2D> = graph_runner.run_batch(&inputs[1..]).unwrap();
+            assert_eq!(outputs.len(), 3);
+            for (expected, output) in expected[1..].iter().zip(outputs.iter()) {
+                assert_close(expected, output);
+            }
+
+            let mut too_many: Vec<Tensor2D> = inputs.clone();
+            too_many.push(sample(0.0, 2, 4));
+            assert!(matches!(
+                graph_runner.run_batch(&too_many),
+                Err(GraphError::DimensionMismatch(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn batched_dag() {
+        // Fan-out, Add and Concat along the columns all keep the samples apart
+        let make_graph = |input: Tensor2D| -> GraphDAG {
+            let mut graph: GraphDAG = GraphDAG::new();
+            graph.add_node("input", GraphOperator::HostToDevice { input }, &[]);
+            graph.add_node(
+                "linear",
+                GraphOperator::LinearLayer {
+                    weights: sample(1.0, 3, 3),
+                    bias: sample(2.0, 2, 3),
+                },
+                &["input"],
+            );
+            graph.add_node("relu", GraphOperator::ReLU, &["linear"]);
+            graph.add_node("residual", GraphOperator::Add, &["relu", "input"]);
+            graph.add_node(
+                "concat",
+                GraphOperator::Concat { axis: 1 },
+                &["residual", "linear"],
+            );
+            graph.add_node("output", GraphOperator::DeviceToHost, &["concat"]);
+            graph
+        };
+
+        let inputs: Vec<Tensor2D> = vec![sample(0.0, 2, 3), sample(7.0, 2, 3)];
+        let mut graph_runner: GraphRunner =
+            GraphRunner::batched_from_dag(&make_graph(Tensor2D::new(0.0, 2, 3)), 2, false).unwrap();
+        let outputs: Vec<Tensor2D> = graph_runner.run_batch(&inputs).unwrap();
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            let expected: Tensor2D = GraphRunner::from_dag(&make_graph(input.clone()), false)
+                .unwrap()
+                .run()
+                .unwrap();
+            assert_close(&expected, output);
+        }
+    }
+
+    #[test]
+    fn row_softmax() {
+        // Every row is normalized by itself, so the samples stay apart
+        let make_graph = |input: Tensor2D| -> Vec<GraphOperator> {
+            let mut graph_operators: Vec<GraphOperator> = mlp_graph(input);
+            graph_operators.insert(4, GraphOperator::Softmax { axis: Some(1) });
+            graph_operators
+        };
+
+        let inputs: Vec<Tensor2D> = vec![sample(0.0, 2, 4), sample(7.0, 2, 4), sample(9.0, 2, 4)];
+        let mut graph_runner: GraphRunner =
+            GraphRunner::batched(&make_graph(Tensor2D::new(0.0, 2, 4)), 3, true).unwrap();
+        let outputs: Vec<Tensor2D> = graph_runner.run_batch(&inputs).unwrap();
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            let expected: Tensor2D = GraphRunner::new(&make_graph(input.clone()), false)
+                .unwrap()
+                .run()
+                .unwrap();
+            assert_close(&expected, output);
+        }
+    }
+
+    #[test]
+    fn unbatchable() {
+        let graph_operators: Vec<GraphOperator> = mlp_graph(sample(0.0, 2, 4));
+        assert!(matches!(
+            batch_graph(&GraphDAG::from_sequential(&graph_operators), 0),
+            Err(GraphError::DimensionMismatch(_))
+        ));
+
+        // Softmax normalizes over every sample in the batch at once,
+        // and along the columns it normalizes over every sample as well
+        for axis in [None, Some(0)] {
+            let mut graph_operators: Vec<GraphOperator> = graph_operators.clone();
+            graph_operators.insert(4, GraphOperator::Softmax { axis });
+            assert!(matches!(
+                GraphRunner::batched(&graph_operators, 2, false),
+                Err(GraphError::UnsupportedOperator(_))
+            ));
+        }
+
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node(
+            "input",
+            GraphOperator::HostToDevice {
+                input: sample(0.0, 2, 3),
+            },
+            &[],
+        );
+        graph.add_node("relu", GraphOperator::ReLU, &["input"]);
+        graph.add_node(
+            "concat",
+            GraphOperator::Concat { axis: 0 },
+            &["input", "relu"],
+        );
+        graph.add_node("output", GraphOperator::DeviceToHost, &["concat"]);
+        assert!(matches!(
+            GraphRunner::batched_from_dag(&graph, 2, false),
+            Err(GraphError::UnsupportedOperator(_))
+        ));
+    }
+}
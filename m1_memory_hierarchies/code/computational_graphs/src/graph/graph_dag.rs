@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use crate::shared::graph_operators::GraphOperator;
+use crate::shared::graph_operators::GraphOperator::*;
+
+use super::graph_error::GraphError;
+
+// A node in a computational graph described as a directed acyclic graph.
+// Every node produces exactly one tensor, which the other nodes
+// refer to by the name of the node producing it.
+// HostToDevice nodes are the inputs of the graph and have no inputs themselves,
+// DeviceToHost nodes are the outputs of the graph and have exactly one input.
+#[derive(Clone, Debug)]
+pub struct GraphNode {
+    pub name: String,
+    pub operator: GraphOperator,
+    pub inputs: Vec<String>,
+}
+
+// Unlike the sequential Vec<GraphOperator>, where every operator
+// implicitly takes the output of the previous one, the DAG can use
+// the same tensor in several operators (fan-out), combine branches
+// with Add or Concat (fan-in) and have several inputs and outputs.
+#[derive(Clone, Debug, Default)]
+pub struct GraphDAG {
+    pub nodes: Vec<GraphNode>,
+}
+
+impl GraphDAG {
+    pub fn new() -> Self {
+        GraphDAG {
+            nodes: Vec::<GraphNode>::new(),
+        }
+    }
+
+    // The node can be added before the nodes it takes as input,
+    // the order of execution is determined by topological_order.
+    pub fn add_node(&mut self, name: &str, operator: GraphOperator, inputs: &[&str]) {
+        self.nodes.push(GraphNode {
+            name: name.to_string(),
+            operator,
+            inputs: inputs.iter().map(|input| input.to_string()).collect(),
+        });
+    }
+
+    // Converts the sequential representation to a DAG where
+    // every operator takes the previous operator as input.
+    // Node names follow the same {operator}_{count} scheme as
+    // the node names in the graph runners, e.g. LinearLayer_0.
+    pub fn from_sequential(graph_operators: &Vec<GraphOperator>) -> Self {
+        let mut graph: GraphDAG = GraphDAG::new();
+        let mut operator_counts: HashMap<String, usize> = HashMap::<String, usize>::new();
+        let mut previous_name: Option<String> = None;
+
+        for operator in graph_operators {
+            let operator_name: &str = Self::operator_name(operator);
+            if operator_name == "Empty" {
+                continue;
+            }
+
+            let count: &mut usize = operator_counts
+                .entry(operator_name.to_string())
+                .or_insert(0);
+            let name: String = format!("{}_{}", operator_name, count);
+            *count += 1;
+
+            let inputs: Vec<String> = match (operator, &previous_name) {
+                (HostToDevice { input: _ }, _) => Vec::<String>::new(),
+                (_, Some(previous_name)) => vec![previous_name.clone()],
+                (_, None) => Vec::<String>::new(),
+            };
+
+            graph.nodes.push(GraphNode {
+                name: name.clone(),
+                operator: operator.clone(),
+                inputs,
+            });
+            previous_name = Some(name);
+        }
+
+        graph
+    }
+
+    pub fn operator_name(operator: &GraphOperator) -> &'static str {
+        match operator {
+            Empty => "Empty",
+            HostToDevice { input: _ } => "HostToDevice",
+            DeviceToHost => "DeviceToHost",
+            LinearLayer {
+                weights: _,
+                bias: _,
+            } => "LinearLayer",
+            ReLU => "ReLU",
+            Softmax { axis: _ } => "Softmax",
+            LogSoftmax => "LogSoftmax",
+            LinearReLUFused {
+                weights: _,
+                bias: _,
+            } => "LinearReLUFused",
+            LinearReLUSoftmaxFused {
+                weights: _,
+                bias: _,
+            } => "LinearReLUSoftmaxFused",
+            Add => "Add",
+            AddReLUFused => "AddReLUFused",
+            Concat { axis: _ } => "Concat",
+            Loss { kind: _, target: _ } => "Loss",
+        }
+    }
+
+    pub fn input_names(&self) -> Vec<String> {
+        self.nodes
+            .iter()
+            .filter(|node| matches!(node.operator, HostToDevice { input: _ }))
+            .map(|node| node.name.clone())
+            .collect()
+    }
+
+    pub fn output_names(&self) -> Vec<String> {
+        self.nodes
+            .iter()
+            .filter(|node| matches!(node.operator, DeviceToHost))
+            .map(|node| node.name.clone())
+            .collect()
+    }
+
+    pub fn node_index(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    // For every node, the indices of the nodes using its output
+    pub fn consumers(&self) -> Result<Vec<Vec<usize>>, GraphError> {
+        let mut indices: HashMap<&str, usize> = HashMap::<&str, usize>::new();
+        for (node_index, node) in self.nodes.iter().enumerate() {
+            if indices.insert(&node.name, node_index).is_some() {
+                return Err(GraphError::MalformedNode(format!(
+                    "The name {} is used by more than one node.",
+                    node.name
+                )));
+            }
+        }
+
+        let mut consumers: Vec<Vec<usize>> = vec![Vec::<usize>::new(); self.nodes.len()];
+        for (node_index, node) in self.nodes.iter().enumerate() {
+            for input in &node.inputs {
+                match indices.get(input.as_str()) {
+                    Some(input_index) => consumers[*input_index].push(node_index),
+                    None => {
+                        return Err(GraphError::MalformedNode(format!(
+                            "Node {} takes {} as input, but there is no node with that name.",
+                            node.name, input
+                        )));
+                    }
+                }
+            }
+        }
+
+        Ok(consumers)
+    }
+
+    // Kahn's algorithm. Nodes which are ready at the same time
+    // are executed in the order they were added to the graph,
+    // so a graph converted from a sequential graph keeps its order.
+    pub fn topological_order(&self) -> Result<Vec<usize>, GraphError> {
+        let consumers: Vec<Vec<usize>> = self.consumers()?;
+
+        let mut missing_inputs: Vec<usize> =
+            self.nodes.iter().map(|node| node.inputs.len()).collect();
+        let mut ready: Vec<usize> = (0..self.nodes.len())
+            .filter(|node_index| missing_inputs[*node_index] == 0)
+            .rev()
+            .collect();
+
+        let mut order: Vec<usize> = Vec::<usize>::with_capacity(self.nodes.len());
+        while let Some(node_index) = ready.pop() {
+            order.push(node_index);
+
+            let mut newly_ready: Vec<usize> = Vec::<usize>::new();
+            for consumer_index in &consumers[node_index] {
+                missing_inputs[*consumer_index] -= 1;
+                if missing_inputs[*consumer_index] == 0 {
+                    newly_ready.push(*consumer_index);
+                }
+            }
+
+            // Keep the ready list sorted in reverse, so pop returns the earliest added node
+            ready.extend(newly_ready);
+            ready.sort_unstable_by(|a, b| b.cmp(a));
+            ready.dedup();
+        }
+
+        if order.len() != self.nodes.len() {
+            let cycle: Vec<&str> = (0..self.nodes.len())
+                .filter(|node_index| 0 < missing_inputs[*node_index])
+                .map(|node_index| self.nodes[node_index].name.as_str())
+                .collect();
+            return Err(GraphError::MalformedNode(format!(
+                "The graph contains a cycle involving the nodes {:?}.",
+                cycle
+            )));
+        }
+
+        Ok(order)
+    }
+}
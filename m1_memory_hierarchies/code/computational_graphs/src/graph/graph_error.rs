@@ -0,0 +1,58 @@
+use std::fmt;
+
+// Everything that can go wrong when building or running a computational graph.
+// A malformed graph is user input, so instead of taking down the whole process
+// we hand the error back to the caller and let them decide what to do.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphError {
+    // Two tensors which have to agree on a dimension did not, or a tensor was empty
+    DimensionMismatch(String),
+    // HostToDevice/DeviceToHost in the wrong place, or an operator
+    // which wasn't preceded by a transfer
+    MisplacedTransfer(String),
+    // The operator isn't supported in this position, or by this runner
+    UnsupportedOperator(String),
+    // A node was built with the wrong number of buffers for its operator
+    MalformedNode(String),
+    // No adapter, or the adapter we got can't run compute shaders
+    GpuUnavailable(String),
+    // Mapping a staging buffer to read back results failed
+    BufferMapFailure(String),
+    // A node was asked for by a name which isn't in the graph, or isn't a node of the right kind
+    UnknownNode(String),
+    // Reading or writing a serialized graph failed, or the file
+    // was not a graph in a format and version we understand
+    Serialization(String),
+    // A tensor or a dispatch is larger than the device allows and can't be split in to chunks
+    LimitExceeded(String),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::DimensionMismatch(message) => {
+                write!(formatter, "Dimension mismatch: {}", message)
+            }
+            GraphError::MisplacedTransfer(message) => {
+                write!(formatter, "Misplaced transfer: {}", message)
+            }
+            GraphError::UnsupportedOperator(message) => {
+                write!(formatter, "Unsupported operator: {}", message)
+            }
+            GraphError::MalformedNode(message) => write!(formatter, "Malformed node: {}", message),
+            GraphError::GpuUnavailable(message) => {
+                write!(formatter, "GPU unavailable: {}", message)
+            }
+            GraphError::BufferMapFailure(message) => {
+                write!(formatter, "Buffer map failure: {}", message)
+            }
+            GraphError::UnknownNode(message) => write!(formatter, "Unknown node: {}", message),
+            GraphError::Serialization(message) => write!(formatter, "Serialization: {}", message),
+            GraphError::LimitExceeded(message) => {
+                write!(formatter, "Device limit exceeded: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::shared::graph_operators::GraphOperator;
+use crate::shared::graph_operators::GraphOperator::*;
+use crate::shared::tensor2d::Tensor2D;
+
+use super::graph_dag::{GraphDAG, GraphNode};
+use super::graph_error::GraphError;
+
+// Operator fusion as a rewrite of the graph, which happens before
+// either of the graph runners ever sees it. A pattern is a chain of operators,
+// where every node is the only consumer of the node before it.
+// If anything else reads one of the intermediate results, or it is an output
+// of the graph, the chain can't be fused as the intermediate result would be gone.
+//
+// The fused node takes over the name of the last node in the chain, so the
+// nodes reading the result of the chain don't need to be changed,
+// and the inputs of the first node in the chain.
+//
+// The pass is repeated until no pattern matches anymore, so patterns can
+// build on each other. Linear, ReLU, ReLU, Softmax first becomes LinearReLU, ReLU, Softmax,
+// then LinearReLU, Softmax and then LinearReLUSoftmax.
+pub struct FusionPattern {
+    pub name: &'static str,
+    // The operators of the chain, as named by GraphDAG::operator_name
+    pub chain: &'static [&'static str],
+    // Builds the fused operator from the operators in the chain.
+    // Returns None if it can't, like when a user supplied pattern names
+    // operators it doesn't know how to fuse, and the chain is left alone.
+    pub fuse: fn(&[&GraphOperator]) -> Option<GraphOperator>,
+}
+
+fn linear_parameters(operator: &GraphOperator) -> Option<(&Tensor2D, &Tensor2D)> {
+    match operator {
+        LinearLayer { weights, bias }
+        | LinearReLUFused { weights, bias }
+        | LinearReLUSoftmaxFused { weights, bias } => Some((weights, bias)),
+        _ => None,
+    }
+}
+
+fn fuse_linear_relu(chain: &[&GraphOperator]) -> Option<GraphOperator> {
+    let (weights, bias) = linear_parameters(chain.first()?)?;
+    Some(LinearReLUFused {
+        weights: weights.clone(),
+        bias: bias.clone(),
+    })
+}
+
+fn fuse_linear_relu_softmax(chain: &[&GraphOperator]) -> Option<GraphOperator> {
+    let (weights, bias) = linear_parameters(chain.first()?)?;
+    Some(LinearReLUSoftmaxFused {
+        weights: weights.clone(),
+        bias: bias.clone(),
+    })
+}
+
+fn fuse_add_relu(chain: &[&GraphOperator]) -> Option<GraphOperator> {
+    match chain.first()? {
+        Add | AddReLUFused => Some(AddReLUFused),
+        _ => None,
+    }
+}
+
+fn fuse_relu(_chain: &[&GraphOperator]) -> Option<GraphOperator> {
+    Some(ReLU)
+}
+
+// Longer patterns come first, so they get the first shot at a chain.
+// The linear layer carries its own bias, so Linear+Bias+ReLU is linear_relu.
+// The elementwise chains are ReLU after Add, and ReLU after anything ending in a ReLU,
+// as ReLU is idempotent.
+pub fn default_fusion_patterns() -> Vec<FusionPattern> {
+    vec![
+        FusionPattern {
+            name: "linear_relu_softmax",
+            chain: &["LinearLayer", "ReLU", "Softmax"],
+            fuse: fuse_linear_relu_softmax,
+        },
+        FusionPattern {
+            name: "linear_relu",
+            chain: &["LinearLayer", "ReLU"],
+            fuse: fuse_linear_relu,
+        },
+        FusionPattern {
+            name: "linear_relu_then_softmax",
+            chain: &["LinearReLUFused", "Softmax"],
+            fuse: fuse_linear_relu_softmax,
+        },
+        FusionPattern {
+            name: "linear_relu_then_relu",
+            chain: &["LinearReLUFused", "ReLU"],
+            fuse: fuse_linear_relu,
+        },
+        FusionPattern {
+            name: "add_relu",
+            chain: &["Add", "ReLU"],
+            fuse: fuse_add_relu,
+        },
+        FusionPattern {
+            name: "add_relu_then_relu",
+            chain: &["AddReLUFused", "ReLU"],
+            fuse: fuse_add_relu,
+        },
+        FusionPattern {
+            name: "relu_relu",
+            chain: &["ReLU", "ReLU"],
+            fuse: fuse_relu,
+        },
+    ]
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FusedNode {
+    pub name: String,
+    pub operator: &'static str,
+    // The names of the nodes in the original graph which became this node
+    pub original_nodes: Vec<String>,
+    // The patterns which were applied, in order
+    pub patterns: Vec<&'static str>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FusionReport {
+    pub node_count_before: usize,
+    pub node_count_after: usize,
+    pub fused_nodes: Vec<FusedNode>,
+}
+
+impl fmt::Display for FusionReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "Fusion - {} nodes before, {} nodes after",
+            self.node_count_before, self.node_count_after
+        )?;
+        for fused_node in &self.fused_nodes {
+            write!(
+                formatter,
+                "\n    {} ({}) <- {} [{}]",
+                fused_node.name,
+                fused_node.operator,
+                fused_node.original_nodes.join(", "),
+                fused_node.patterns.join(", ")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// Returns the consumer of the node if it is the only one.
+// If the output of a node is used anywhere else, it has to stay in its own buffer.
+fn single_consumer(consumers: &[Vec<usize>], node_index: usize) -> Option<usize> {
+    if consumers[node_index].len() == 1 {
+        Some(consumers[node_index][0])
+    } else {
+        None
+    }
+}
+
+// Finds the chain of nodes starting at node_index which matches the pattern
+fn match_pattern(
+    graph: &GraphDAG,
+    consumers: &[Vec<usize>],
+    fused: &[bool],
+    node_index: usize,
+    pattern: &FusionPattern,
+) -> Option<Vec<usize>> {
+    // Fusing fewer than two nodes would never shrink the graph, and the pass would never end
+    if pattern.chain.len() < 2 {
+        return None;
+    }
+
+    let mut chain: Vec<usize> = Vec::<usize>::with_capacity(pattern.chain.len());
+    let mut current_index: usize = node_index;
+    for (position, operator_name) in pattern.chain.iter().enumerate() {
+        if 0 < position {
+            current_index = single_consumer(consumers, current_index)?;
+        }
+        // The fused kernels only know the softmax over the whole tensor
+        if fused[current_index]
+            || GraphDAG::operator_name(&graph.nodes[current_index].operator) != *operator_name
+            || matches!(
+                graph.nodes[current_index].operator,
+                Softmax { axis: Some(_) }
+            )
+        {
+            return None;
+        }
+        chain.push(current_index);
+    }
+
+    Some(chain)
+}
+
+// A single pass over the graph, returns None if nothing was fused
+fn fuse_once(
+    graph: &GraphDAG,
+    patterns: &[FusionPattern],
+    fused_nodes: &mut HashMap<String, FusedNode>,
+) -> Result<Option<GraphDAG>, GraphError> {
+    let consumers: Vec<Vec<usize>> = graph.consumers()?;
+    let order: Vec<usize> = graph.topological_order()?;
+
+    // Nodes which have become part of a chain
+    let mut fused: Vec<bool> = vec![false; graph.nodes.len()];
+    // The fused node replacing the first node of each chain
+    let mut replacements: HashMap<usize, GraphNode> = HashMap::<usize, GraphNode>::new();
+
+    for node_index in order {
+        if fused[node_index] {
+            continue;
+        }
+
+        for pattern in patterns {
+            let chain: Vec<usize> =
+                match match_pattern(graph, &consumers, &fused, node_index, pattern) {
+                    Some(chain) => chain,
+                    None => continue,
+                };
+
+            let operators: Vec<&GraphOperator> = chain
+                .iter()
+                .map(|chain_index| &graph.nodes[*chain_index].operator)
+                .collect();
+            let operator: GraphOperator = match (pattern.fuse)(&operators) {
+                Some(operator) => operator,
+                None => continue,
+            };
+            let last_node: &GraphNode = &graph.nodes[*chain.last().unwrap()];
+            let fused_node: GraphNode = GraphNode {
+                name: last_node.name.clone(),
+                operator,
+                inputs: graph.nodes[chain[0]].inputs.clone(),
+            };
+
+            // Merge the history of nodes which were fused in an earlier pass
+            let mut original_nodes: Vec<String> = Vec::<String>::new();
+            let mut applied_patterns: Vec<&'static str> = Vec::<&'static str>::new();
+            for chain_index in &chain {
+                let name: &String = &graph.nodes[*chain_index].name;
+                match fused_nodes.remove(name) {
+                    Some(previous) => {
+                        original_nodes.extend(previous.original_nodes);
+                        applied_patterns.extend(previous.patterns);
+                    }
+                    None => original_nodes.push(name.clone()),
+                }
+            }
+            applied_patterns.push(pattern.name);
+            fused_nodes.insert(
+                fused_node.name.clone(),
+                FusedNode {
+                    name: fused_node.name.clone(),
+                    operator: GraphDAG::operator_name(&fused_node.operator),
+                    original_nodes,
+                    patterns: applied_patterns,
+                },
+            );
+
+            for chain_index in &chain {
+                fused[*chain_index] = true;
+            }
+            replacements.insert(chain[0], fused_node);
+            break;
+        }
+    }
+
+    if replacements.is_empty() {
+        return Ok(None);
+    }
+
+    // Keep the declaration order of the original graph,
+    // with the fused node in the place of the first node in its chain
+    let mut nodes: Vec<GraphNode> = Vec::<GraphNode>::with_capacity(graph.nodes.len());
+    for (node_index, node) in graph.nodes.iter().enumerate() {
+        if let Some(replacement) = replacements.remove(&node_index) {
+            nodes.push(replacement);
+        } else if !fused[node_index] {
+            nodes.push(node.clone());
+        }
+    }
+
+    Ok(Some(GraphDAG { nodes }))
+}
+
+pub fn fuse_graph(
+    graph: &GraphDAG,
+    patterns: &[FusionPattern],
+) -> Result<(GraphDAG, FusionReport), GraphError> {
+    let mut fused_graph: GraphDAG = graph.clone();
+    let mut fused_nodes: HashMap<String, FusedNode> = HashMap::<String, FusedNode>::new();
+
+    while let Some(next_graph) = fuse_once(&fused_graph, patterns, &mut fused_nodes)? {
+        fused_graph = next_graph;
+    }
+
+    // Report the fused nodes in the order they appear in the graph
+    let fused_nodes: Vec<FusedNode> = fused_graph
+        .nodes
+        .iter()
+        .filter_map(|node| fused_nodes.remove(&node.name))
+        .collect();
+
+    let report: FusionReport = FusionReport {
+        node_count_before: graph.nodes.len(),
+        node_count_after: fused_graph.nodes.len(),
+        fused_nodes,
+    };
+
+    Ok((fused_graph, report))
+}
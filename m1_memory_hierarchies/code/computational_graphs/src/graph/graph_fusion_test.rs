@@ -0,0 +1,205 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        graph::{
+            graph_dag::GraphDAG,
+            graph_fusion::{default_fusion_patterns, fuse_graph, FusionPattern, FusionReport},
+            graph_runner::GraphRunner,
+        },
+        shared::{graph_operators::GraphOperator, tensor2d::Tensor2D},
+    };
+
+    const ERROR_TOLERANCE: f32 = 0.00001;
+
+    fn operator_names(graph: &GraphDAG) -> Vec<&'static str> {
+        graph
+            .nodes
+            .iter()
+            .map(|node| GraphDAG::operator_name(&node.operator))
+            .collect()
+    }
+
+    #[test]
+    fn chained_patterns() {
+        let input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+        let weights: Tensor2D = Tensor2D::new(1.0, 4, 2);
+        let bias: Tensor2D = Tensor2D::new(0.1, 3, 2);
+
+        let expected_output: Tensor2D = Tensor2D::linear_layer(&input, &weights, &bias);
+        let expected_output: Tensor2D = Tensor2D::relu(&expected_output);
+        let expected_output: Tensor2D = Tensor2D::softmax(&expected_output);
+
+        // The second ReLU doesn't do anything, so the whole chain becomes one node
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice { input },
+            GraphOperator::LinearLayer { weights, bias },
+            GraphOperator::ReLU,
+            GraphOperator::ReLU,
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::DeviceToHost,
+        ];
+        let graph: GraphDAG = GraphDAG::from_sequential(&graph_operators);
+        let (fused_graph, report): (GraphDAG, FusionReport) =
+            fuse_graph(&graph, &default_fusion_patterns()).unwrap();
+
+        assert_eq!(
+            operator_names(&fused_graph),
+            vec!["HostToDevice", "LinearReLUSoftmaxFused", "DeviceToHost"]
+        );
+        assert_eq!((report.node_count_before, report.node_count_after), (6, 3));
+        assert_eq!(report.fused_nodes.len(), 1);
+        assert_eq!(report.fused_nodes[0].name, fused_graph.nodes[1].name);
+        assert_eq!(report.fused_nodes[0].original_nodes.len(), 4);
+        assert_eq!(
+            report.fused_nodes[0].patterns,
+            vec![
+                "linear_relu",
+                "linear_relu_then_relu",
+                "linear_relu_then_softmax"
+            ]
+        );
+
+        let mut graph_runner: GraphRunner = GraphRunner::new(&graph_operators, true).unwrap();
+        assert_eq!(*graph_runner.fusion_report(), report);
+        let output: Tensor2D = graph_runner.run().unwrap();
+        assert!(expected_output
+            .data
+            .iter()
+            .zip(output.data.iter())
+            .all(|(expected, actual)| (expected - actual).abs() < ERROR_TOLERANCE));
+    }
+
+    #[test]
+    fn shared_intermediates() {
+        let input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+        let weights: Tensor2D = Tensor2D::new(1.0, 4, 4);
+        let bias: Tensor2D = Tensor2D::new(0.1, 3, 4);
+
+        // The ReLU output is read by both the softmax and the add,
+        // so only the linear layer and the ReLU can be fused.
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node("input", GraphOperator::HostToDevice { input }, &[]);
+        graph.add_node(
+            "linear",
+            GraphOperator::LinearLayer { weights, bias },
+            &["input"],
+        );
+        graph.add_node("relu", GraphOperator::ReLU, &["linear"]);
+        graph.add_node("softmax", GraphOperator::Softmax { axis: None }, &["relu"]);
+        graph.add_node("sum", GraphOperator::Add, &["relu", "softmax"]);
+        graph.add_node("output", GraphOperator::DeviceToHost, &["sum"]);
+
+        let (fused_graph, report): (GraphDAG, FusionReport) =
+            fuse_graph(&graph, &default_fusion_patterns()).unwrap();
+        assert_eq!(
+            operator_names(&fused_graph),
+            vec![
+                "HostToDevice",
+                "LinearReLUFused",
+                "Softmax",
+                "Add",
+                "DeviceToHost"
+            ]
+        );
+        // The fused node keeps the name of the ReLU, so the softmax and add still find it
+        assert_eq!(fused_graph.nodes[1].name, "relu");
+        assert_eq!(report.fused_nodes[0].original_nodes, vec!["linear", "relu"]);
+
+        // Without any patterns the graph is left as it is
+        let (unfused_graph, report): (GraphDAG, FusionReport) = fuse_graph(&graph, &[]).unwrap();
+        assert_eq!(operator_names(&unfused_graph), operator_names(&graph));
+        assert!(report.fused_nodes.is_empty());
+
+        let graph_runner: GraphRunner = GraphRunner::from_dag(&graph, false).unwrap();
+        assert!(graph_runner.fusion_report().fused_nodes.is_empty());
+    }
+
+    // A residual connection, the ReLUs after the add become part of it
+    fn residual_graph() -> GraphDAG {
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node(
+            "input",
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.5, 3, 4),
+            },
+            &[],
+        );
+        graph.add_node(
+            "linear",
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(-0.3, 4, 4),
+                bias: Tensor2D::new(0.1, 3, 4),
+            },
+            &["input"],
+        );
+        graph.add_node("sum", GraphOperator::Add, &["input", "linear"]);
+        graph.add_node("relu", GraphOperator::ReLU, &["sum"]);
+        graph.add_node("relu_again", GraphOperator::ReLU, &["relu"]);
+        graph.add_node("output", GraphOperator::DeviceToHost, &["relu_again"]);
+        graph
+    }
+
+    #[test]
+    fn elementwise_chains() {
+        let graph: GraphDAG = residual_graph();
+        let (fused_graph, report): (GraphDAG, FusionReport) =
+            fuse_graph(&graph, &default_fusion_patterns()).unwrap();
+        assert_eq!(
+            operator_names(&fused_graph),
+            vec![
+                "HostToDevice",
+                "LinearLayer",
+                "AddReLUFused",
+                "DeviceToHost"
+            ]
+        );
+        assert_eq!(fused_graph.nodes[2].inputs, vec!["input", "linear"]);
+        assert_eq!(
+            report.fused_nodes[0].original_nodes,
+            vec!["sum", "relu", "relu_again"]
+        );
+        assert_eq!(
+            report.fused_nodes[0].patterns,
+            vec!["add_relu", "add_relu_then_relu"]
+        );
+
+        let expected: Tensor2D = GraphRunner::from_dag(&graph, false).unwrap().run().unwrap();
+        let output: Tensor2D = GraphRunner::from_dag(&graph, true).unwrap().run().unwrap();
+        assert!(expected.data.contains(&0.0));
+        assert!(expected
+            .data
+            .iter()
+            .zip(output.data.iter())
+            .all(|(expected, actual)| (expected - actual).abs() < ERROR_TOLERANCE));
+    }
+
+    // Patterns can come from the user, a pattern which doesn't fit its fuse function
+    // is skipped instead of taking down the pass
+    #[test]
+    fn malformed_patterns() {
+        let graph: GraphDAG = residual_graph();
+        let linear_fuse: FusionPattern = default_fusion_patterns().remove(0);
+        let patterns: Vec<FusionPattern> = vec![
+            FusionPattern {
+                name: "relu_as_linear",
+                chain: &["ReLU", "ReLU"],
+                fuse: linear_fuse.fuse,
+            },
+            FusionPattern {
+                name: "single",
+                chain: &["ReLU"],
+                fuse: linear_fuse.fuse,
+            },
+            FusionPattern {
+                name: "empty",
+                chain: &[],
+                fuse: linear_fuse.fuse,
+            },
+        ];
+
+        let (fused_graph, report): (GraphDAG, FusionReport) =
+            fuse_graph(&graph, &patterns).unwrap();
+        assert_eq!(operator_names(&fused_graph), operator_names(&graph));
+        assert!(report.fused_nodes.is_empty());
+    }
+}
@@ -1,9 +1,19 @@
 use std::collections::HashMap;
 
 use crate::shared::tensor2d::Tensor2D;
-
-use super::graph_validation::validate_graph_operators;
+use crate::shared::tensor2d_parallel::resolve_thread_count;
+
+use super::autograd::{
+    plan_backward, BackwardNode, BackwardOperator, BackwardPlan, ForwardRecord, Parameter,
+};
+use super::graph_batching::{batch_graph, split_rows, stack_rows};
+use super::graph_dag::{GraphDAG, GraphNode};
+use super::graph_error::GraphError;
+use super::graph_fusion::{default_fusion_patterns, fuse_graph, FusionReport};
+use super::graph_validation::{validate_graph_dag, validate_graph_operators};
+use super::memory_planner::{MemoryPlanner, MemoryReport};
 use super::nodes::{self, Node, NodeOperator};
+use super::optimizer::Optimizer;
 
 use crate::shared::graph_operators::GraphOperator;
 use crate::shared::graph_operators::GraphOperator::*;
@@ -15,10 +25,82 @@ pub struct GraphRunner {
     data_buffers: Vec<Tensor2D>,
     data_buffers_are_valid: bool,
     fuse_operators: bool,
+    // The name of every DeviceToHost node and the buffer it reads from,
+    // in the order they were added to the graph.
+    outputs: Vec<(String, usize)>,
+    memory_report: MemoryReport,
+    fusion_report: FusionReport,
+    // Built with for_training, every intermediate is kept for the backward pass
+    training: bool,
+    backward_plan: BackwardPlan,
+    forward_has_run: bool,
+    // How many samples are stacked in the input, 1 unless built with batched
+    batch_size: usize,
+    // Threads used by the CPU kernels, 1 unless set with set_thread_count
+    thread_count: usize,
 }
 
 impl GraphRunner {
-    pub fn new(graph_operators: &Vec<GraphOperator>, fuse_operators: bool) -> Self {
+    // The sequential graph is just a special case of the DAG,
+    // where every operator takes the previous one as input.
+    pub fn new(
+        graph_operators: &Vec<GraphOperator>,
+        fuse_operators: bool,
+    ) -> Result<Self, GraphError> {
+        validate_graph_operators(graph_operators)?;
+
+        Self::from_dag(&GraphDAG::from_sequential(graph_operators), fuse_operators)
+    }
+
+    pub fn from_dag(graph: &GraphDAG, fuse_operators: bool) -> Result<Self, GraphError> {
+        Self::build(graph, fuse_operators, false)
+    }
+
+    // A graph which can be trained with run followed by backward.
+    // The backward pass needs the result of every node, so the memory planner isn't
+    // allowed to reuse any buffers, nothing runs in place and nothing is fused.
+    // The graph needs at least one Loss node, whose loss is what gets minimized.
+    pub fn for_training(graph_operators: &Vec<GraphOperator>) -> Result<Self, GraphError> {
+        validate_graph_operators(graph_operators)?;
+
+        Self::from_dag_for_training(&GraphDAG::from_sequential(graph_operators))
+    }
+
+    pub fn from_dag_for_training(graph: &GraphDAG) -> Result<Self, GraphError> {
+        Self::build(graph, false, true)
+    }
+
+    // A graph which runs batch_size inputs at once with run_batch. The input and the
+    // biases are repeated batch_size times along the rows, see batch_graph.
+    pub fn batched(
+        graph_operators: &Vec<GraphOperator>,
+        batch_size: usize,
+        fuse_operators: bool,
+    ) -> Result<Self, GraphError> {
+        validate_graph_operators(graph_operators)?;
+
+        Self::batched_from_dag(
+            &GraphDAG::from_sequential(graph_operators),
+            batch_size,
+            fuse_operators,
+        )
+    }
+
+    pub fn batched_from_dag(
+        graph: &GraphDAG,
+        batch_size: usize,
+        fuse_operators: bool,
+    ) -> Result<Self, GraphError> {
+        validate_graph_dag(graph)?;
+
+        let mut runner: GraphRunner =
+            Self::build(&batch_graph(graph, batch_size)?, fuse_operators, false)?;
+        runner.batch_size = batch_size;
+
+        Ok(runner)
+    }
+
+    fn build(graph: &GraphDAG, fuse_operators: bool, training: bool) -> Result<Self, GraphError> {
         let mut runner: GraphRunner = GraphRunner {
             graph_operators_are_valid: false,
             nodes: Vec::<Node>::new(),
@@ -26,247 +108,409 @@ impl GraphRunner {
             data_buffers: Vec::<Tensor2D>::new(),
             data_buffers_are_valid: false,
             fuse_operators,
+            outputs: Vec::<(String, usize)>::new(),
+            memory_report: MemoryReport::default(),
+            fusion_report: FusionReport::default(),
+            training,
+            backward_plan: BackwardPlan::default(),
+            forward_has_run: false,
+            batch_size: 1,
+            thread_count: 1,
+        };
+        validate_graph_dag(graph)?;
+
+        // Fusion rewrites the graph before any nodes are made from it
+        let fused_graph: GraphDAG;
+        let graph: &GraphDAG = if runner.fuse_operators {
+            let (rewritten_graph, fusion_report) = fuse_graph(graph, &default_fusion_patterns())?;
+            fused_graph = rewritten_graph;
+            runner.fusion_report = fusion_report;
+            &fused_graph
+        } else {
+            graph
         };
-        runner.graph_operators_are_valid = validate_graph_operators(graph_operators);
+        let order: Vec<usize> = validate_graph_dag(graph)?;
+        runner.graph_operators_are_valid = true;
 
-        runner.compute_nodes(graph_operators, runner.fuse_operators);
+        runner.compute_nodes(graph, &order)?;
+        if runner.training {
+            runner.compute_backward_nodes()?;
+        }
         runner.data_buffers_are_valid = true;
 
-        runner
+        Ok(runner)
     }
 
-    fn get_new_key(operator_counts: &mut HashMap<NodeOperator, u32>, key: &NodeOperator) -> String {
-        let error_message: &str = "Failed to get value from hash map in graph_runner::get_new_key";
-        let value: &mut u32 = operator_counts
-            .get_mut(key)
-            .unwrap_or_else(|| panic!("{}", error_message));
-        let index: u32 = *value;
-        *value = index + 1;
-        format!("{:?}_{}", key, index)
-    }
-
-    fn verify_previous_node_and_get_index(nodes: &Vec<Node>, key: &NodeOperator) -> usize {
-        let previous_node: &Node = &nodes[nodes.len() - 1];
-        match previous_node.operator {
-            NodeOperator::Transfer => {}
-            _ => {
-                panic!("Invalid node operator! Was expecting DeviceToDevice before {:?}, but found {:?}", *key, previous_node.operator);
+    fn get_input_indices(
+        graph: &GraphDAG,
+        node: &GraphNode,
+        output_buffers: &[Option<usize>],
+    ) -> Result<Vec<usize>, GraphError> {
+        let mut input_indices: Vec<usize> = Vec::<usize>::with_capacity(node.inputs.len());
+        for input in &node.inputs {
+            let buffer_index: Option<usize> = graph
+                .node_index(input)
+                .and_then(|input_node_index| output_buffers[input_node_index]);
+            match buffer_index {
+                Some(buffer_index) => input_indices.push(buffer_index),
+                None => {
+                    return Err(GraphError::MalformedNode(format!(
+                        "Node {} takes {} as input, but it has not been computed yet.",
+                        node.name, input
+                    )));
+                }
             }
         }
 
-        if previous_node.buffer_indices.len() == 1 {
-            previous_node.buffer_indices[0]
-        } else {
-            panic!("There was more than one buffer index described in a DeviceToDevice operator. This is currently invalid behavior!");
-        }
+        Ok(input_indices)
     }
 
-    // This is made a lot more complicated by reusing buffers
-    // If each node owned its own buffers with no reusage
-    // We would need to keep less track of buffers
-    fn compute_nodes(&mut self, graph_operators: &Vec<GraphOperator>, fuse_operators: bool) {
+    // Nodes are created in topological order, so running them
+    // front to back always has every input ready.
+    // Intermediate results are placed in buffers by the memory planner,
+    // which reuses the buffers of results nobody needs anymore.
+    // Buffers are shared between nodes by index.
+    fn compute_nodes(&mut self, graph: &GraphDAG, order: &[usize]) -> Result<(), GraphError> {
         if !self.graph_operators_are_valid {
-            panic!("Invalid graph being sent to compute_nodes!");
+            return Err(GraphError::UnsupportedOperator(
+                "compute_nodes was given a graph which has not been validated".to_string(),
+            ));
         }
 
-        let mut operator_counts: HashMap<NodeOperator, u32> = HashMap::<NodeOperator, u32>::new();
-        operator_counts.insert(NodeOperator::Input, 0);
-        operator_counts.insert(NodeOperator::Output, 0);
-        operator_counts.insert(NodeOperator::Transfer, 0);
-        operator_counts.insert(NodeOperator::LinearLayer, 0);
-        operator_counts.insert(NodeOperator::ReLU, 0);
-        operator_counts.insert(NodeOperator::Softmax, 0);
-        operator_counts.insert(NodeOperator::LinearReLU, 0);
-        operator_counts.insert(NodeOperator::LinearReLUSoftmax, 0);
-
-        let mut operator_index: usize = 0;
-        while operator_index < graph_operators.len() {
-            let operator: &GraphOperator = &graph_operators[operator_index];
-
-            match operator {
-                Empty => {}
-                // Maybe put a device to device split in here for simpler code in the other operators
+        let consumers: Vec<Vec<usize>> = graph.consumers()?;
+        // The buffer holding the output of each node in the graph
+        let mut output_buffers: Vec<Option<usize>> = vec![None; graph.nodes.len()];
+        let mut memory_planner: MemoryPlanner = MemoryPlanner::new(std::mem::size_of::<f32>());
+
+        for node_index in order {
+            let node_index: usize = *node_index;
+            let graph_node: &GraphNode = &graph.nodes[node_index];
+            let input_indices: Vec<usize> =
+                Self::get_input_indices(graph, graph_node, &output_buffers)?;
+
+            match &graph_node.operator {
+                Empty => {
+                    return Err(GraphError::UnsupportedOperator(format!(
+                        "Node {} is Empty, which is only supported in sequential graphs.",
+                        graph_node.name
+                    )));
+                }
                 HostToDevice { input } => {
-                    let key: NodeOperator = NodeOperator::Input;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-
+                    memory_planner.track_fixed(input.row_count, input.column_count);
                     self.data_buffers.push(input.clone());
-                    let buffer_indices: Vec<usize> = vec![self.data_buffers.len() - 1];
+                    let buffer_index: usize = self.data_buffers.len() - 1;
 
-                    let node: Node = Node::new(new_key, key, buffer_indices.clone());
-                    self.nodes.push(node);
-
-                    let key: NodeOperator = NodeOperator::Transfer;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let node: Node = Node::new(new_key, NodeOperator::Transfer, buffer_indices);
+                    let node: Node = Node::new(
+                        graph_node.name.clone(),
+                        NodeOperator::Input,
+                        vec![buffer_index],
+                    );
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(buffer_index);
                 }
                 DeviceToHost => {
-                    let key: NodeOperator = NodeOperator::Output;
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-
-                    let buffer_indices: Vec<usize> = vec![input_index];
-                    let node: Node = Node::new(new_key, key, buffer_indices);
+                    let node: Node = Node::new(
+                        graph_node.name.clone(),
+                        NodeOperator::Output,
+                        input_indices.clone(),
+                    );
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(input_indices[0]);
                 }
-                LinearLayer { weights, bias } => {
-                    let mut key: NodeOperator = NodeOperator::LinearLayer;
-
-                    if fuse_operators {
-                        if let ReLU = graph_operators[operator_index + 1] {
-                            match graph_operators[operator_index + 2] {
-                                Softmax => {
-                                    key = NodeOperator::LinearReLUSoftmax;
-                                    operator_index += 2;
-                                }
-                                _ => {
-                                    key = NodeOperator::LinearReLU;
-                                    operator_index += 1;
-                                }
-                            }
-                        }
-                    }
-
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-
+                LinearLayer { weights, bias }
+                | LinearReLUFused { weights, bias }
+                | LinearReLUSoftmaxFused { weights, bias } => {
+                    let key: NodeOperator = match &graph_node.operator {
+                        LinearReLUFused {
+                            weights: _,
+                            bias: _,
+                        } => NodeOperator::LinearReLU,
+                        LinearReLUSoftmaxFused {
+                            weights: _,
+                            bias: _,
+                        } => NodeOperator::LinearReLUSoftmax,
+                        _ => NodeOperator::LinearLayer,
+                    };
+
+                    memory_planner.track_fixed(weights.row_count, weights.column_count);
                     self.data_buffers.push(weights.clone());
                     let weights_index: usize = self.data_buffers.len() - 1;
 
+                    memory_planner.track_fixed(bias.row_count, bias.column_count);
                     self.data_buffers.push(bias.clone());
                     let bias_index: usize = self.data_buffers.len() - 1;
 
-                    self.data_buffers
-                        .push(Tensor2D::new(0.0, bias.row_count, bias.column_count));
-                    let output_index: usize = self.data_buffers.len() - 1;
+                    let (use_count, pinned) =
+                        MemoryPlanner::output_uses(graph, &consumers, node_index);
+                    let output_index: usize = self.allocate_intermediate(
+                        &mut memory_planner,
+                        bias.row_count,
+                        bias.column_count,
+                        use_count,
+                        pinned,
+                    );
 
                     let buffer_indices: Vec<usize> =
-                        vec![input_index, weights_index, bias_index, output_index];
-                    let node: Node = Node::new(new_key.clone(), key, buffer_indices);
-                    self.nodes.push(node);
-
-                    let key: NodeOperator = NodeOperator::Transfer;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let buffer_indices: Vec<usize> = vec![output_index];
-                    let node: Node = Node::new(new_key, key, buffer_indices);
+                        vec![input_indices[0], weights_index, bias_index, output_index];
+                    let node: Node = Node::new(graph_node.name.clone(), key, buffer_indices);
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(output_index);
                 }
-                // Note this is not inplace
-                ReLU => {
-                    let key: NodeOperator = NodeOperator::ReLU;
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-
-                    let input_buffer: &Tensor2D = &self.data_buffers[input_index];
-                    self.data_buffers.push(Tensor2D::new(
-                        0.0,
-                        input_buffer.row_count,
-                        input_buffer.column_count,
-                    ));
-                    let output_index: usize = self.data_buffers.len() - 1;
-
-                    let buffer_indices: Vec<usize> = vec![input_index, output_index];
-                    let node: Node = Node::new(new_key.clone(), key, buffer_indices);
-                    self.nodes.push(node);
+                ReLU | Softmax { axis: _ } | LogSoftmax => {
+                    let (use_count, pinned) =
+                        MemoryPlanner::output_uses(graph, &consumers, node_index);
+
+                    // If this is the last node to ever read the input,
+                    // we can just overwrite it instead of using a separate output.
+                    // There is no in place LogSoftmax, it is only used for training,
+                    // and no in place softmax along an axis.
+                    if !matches!(graph_node.operator, LogSoftmax | Softmax { axis: Some(_) })
+                        && memory_planner.try_reuse_in_place(input_indices[0], use_count, pinned)
+                    {
+                        let key: NodeOperator = if let ReLU = graph_node.operator {
+                            NodeOperator::ReLUInPlace
+                        } else {
+                            NodeOperator::SoftmaxInPlace
+                        };
+
+                        let node: Node =
+                            Node::new(graph_node.name.clone(), key, vec![input_indices[0]]);
+                        self.nodes.push(node);
+                        output_buffers[node_index] = Some(input_indices[0]);
+
+                        // The input buffer now holds our output, so it mustn't be released
+                        continue;
+                    }
 
-                    let key: NodeOperator = NodeOperator::Transfer;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let buffer_indices: Vec<usize> = vec![output_index];
-                    let node: Node = Node::new(new_key, key, buffer_indices);
+                    let key: NodeOperator = match graph_node.operator {
+                        ReLU => NodeOperator::ReLU,
+                        LogSoftmax => NodeOperator::LogSoftmax,
+                        Softmax { axis: Some(axis) } => NodeOperator::SoftmaxAxis { axis },
+                        _ => NodeOperator::Softmax,
+                    };
+
+                    let input_buffer: &Tensor2D = &self.data_buffers[input_indices[0]];
+                    let (row_count, column_count) =
+                        (input_buffer.row_count, input_buffer.column_count);
+                    let output_index: usize = self.allocate_intermediate(
+                        &mut memory_planner,
+                        row_count,
+                        column_count,
+                        use_count,
+                        pinned,
+                    );
+
+                    let buffer_indices: Vec<usize> = vec![input_indices[0], output_index];
+                    let node: Node = Node::new(graph_node.name.clone(), key, buffer_indices);
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(output_index);
                 }
-                Softmax => {
-                    let key: NodeOperator = NodeOperator::Softmax;
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-
-                    let input_buffer: &Tensor2D = &self.data_buffers[input_index];
-                    // This should be more flexible, but Softmax always outputs a flattened vector
-                    self.data_buffers.push(Tensor2D::new(
-                        0.0,
-                        input_buffer.row_count,
-                        input_buffer.column_count,
-                    ));
-                    let output_index: usize = self.data_buffers.len() - 1;
-
-                    let buffer_indices: Vec<usize> = vec![input_index, output_index];
-                    let node: Node = Node::new(new_key.clone(), key, buffer_indices);
-                    self.nodes.push(node);
+                Add | AddReLUFused => {
+                    let key: NodeOperator = if let AddReLUFused = graph_node.operator {
+                        NodeOperator::AddReLU
+                    } else {
+                        NodeOperator::Add
+                    };
+                    let input_buffer: &Tensor2D = &self.data_buffers[input_indices[0]];
+                    let (row_count, column_count) =
+                        (input_buffer.row_count, input_buffer.column_count);
+                    let (use_count, pinned) =
+                        MemoryPlanner::output_uses(graph, &consumers, node_index);
+                    let output_index: usize = self.allocate_intermediate(
+                        &mut memory_planner,
+                        row_count,
+                        column_count,
+                        use_count,
+                        pinned,
+                    );
 
-                    let key: NodeOperator = NodeOperator::Transfer;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let buffer_indices: Vec<usize> = vec![output_index];
-                    let node: Node = Node::new(new_key, key, buffer_indices);
+                    let buffer_indices: Vec<usize> =
+                        vec![input_indices[0], input_indices[1], output_index];
+                    let node: Node = Node::new(graph_node.name.clone(), key, buffer_indices);
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(output_index);
                 }
-                LinearReLUFused { weights, bias } => {
-                    let key: NodeOperator = NodeOperator::LinearReLU;
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-
-                    self.data_buffers.push(weights.clone());
-                    let weights_index: usize = self.data_buffers.len() - 1;
-
-                    self.data_buffers.push(bias.clone());
-                    let bias_index: usize = self.data_buffers.len() - 1;
+                Loss { kind, target } => {
+                    memory_planner.track_fixed(target.row_count, target.column_count);
+                    self.data_buffers.push(target.clone());
+                    let target_index: usize = self.data_buffers.len() - 1;
 
-                    self.data_buffers
-                        .push(Tensor2D::new(0.0, bias.row_count, bias.column_count));
-                    let output_index: usize = self.data_buffers.len() - 1;
+                    let (use_count, pinned) =
+                        MemoryPlanner::output_uses(graph, &consumers, node_index);
+                    let output_index: usize =
+                        self.allocate_intermediate(&mut memory_planner, 1, 1, use_count, pinned);
 
                     let buffer_indices: Vec<usize> =
-                        vec![input_index, weights_index, bias_index, output_index];
-                    let node: Node = Node::new(new_key.clone(), key, buffer_indices);
+                        vec![input_indices[0], target_index, output_index];
+                    let node: Node = Node::new(
+                        graph_node.name.clone(),
+                        NodeOperator::Loss { kind: *kind },
+                        buffer_indices,
+                    );
                     self.nodes.push(node);
-
-                    let key: NodeOperator = NodeOperator::Transfer;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let buffer_indices: Vec<usize> = vec![output_index];
-                    let node: Node = Node::new(new_key, key, buffer_indices);
+                    output_buffers[node_index] = Some(output_index);
+                }
+                Concat { axis } => {
+                    let mut row_count: usize = self.data_buffers[input_indices[0]].row_count;
+                    let mut column_count: usize = self.data_buffers[input_indices[0]].column_count;
+                    for input_index in &input_indices[1..] {
+                        if *axis == 0 {
+                            row_count += self.data_buffers[*input_index].row_count;
+                        } else {
+                            column_count += self.data_buffers[*input_index].column_count;
+                        }
+                    }
+                    let (use_count, pinned) =
+                        MemoryPlanner::output_uses(graph, &consumers, node_index);
+                    let output_index: usize = self.allocate_intermediate(
+                        &mut memory_planner,
+                        row_count,
+                        column_count,
+                        use_count,
+                        pinned,
+                    );
+
+                    let mut buffer_indices: Vec<usize> = input_indices.clone();
+                    buffer_indices.push(output_index);
+                    let node: Node = Node::new(
+                        graph_node.name.clone(),
+                        NodeOperator::Concat { axis: *axis },
+                        buffer_indices,
+                    );
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(output_index);
                 }
-                LinearReLUSoftmaxFused { weights, bias } => {
-                    let key: NodeOperator = NodeOperator::LinearReLUSoftmax;
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
+            }
 
-                    self.data_buffers.push(weights.clone());
-                    let weights_index: usize = self.data_buffers.len() - 1;
+            // The output of the node has been allocated, so the buffers
+            // it reads can be handed out to the nodes after it
+            for input_index in &input_indices {
+                memory_planner.release(*input_index);
+            }
+        }
+        self.memory_report = memory_planner.report();
+
+        self.outputs = graph
+            .output_names()
+            .into_iter()
+            .map(|name| {
+                let node_index: usize = graph
+                    .node_index(&name)
+                    .expect("output_names only returns existing nodes");
+                (name, output_buffers[node_index].unwrap())
+            })
+            .collect();
+        self.nodes_are_valid = true;
+
+        Ok(())
+    }
 
-                    self.data_buffers.push(bias.clone());
-                    let bias_index: usize = self.data_buffers.len() - 1;
+    fn allocate_intermediate(
+        &mut self,
+        memory_planner: &mut MemoryPlanner,
+        row_count: usize,
+        column_count: usize,
+        use_count: usize,
+        pinned: bool,
+    ) -> usize {
+        // Pinned buffers are never reused or overwritten in place
+        let pinned: bool = pinned || self.training;
+        let data_buffers: &mut Vec<Tensor2D> = &mut self.data_buffers;
+        memory_planner.allocate(row_count, column_count, use_count, pinned, || {
+            data_buffers.push(Tensor2D::new(0.0, row_count, column_count));
+            data_buffers.len() - 1
+        })
+    }
 
-                    // This should be more flexible, but Softmax always outputs a flattened vector
-                    self.data_buffers
-                        .push(Tensor2D::new(0.0, bias.row_count, bias.column_count));
-                    let output_index: usize = self.data_buffers.len() - 1;
+    // Describes the forward nodes to the backward planner.
+    // The fused and in-place operators have no backward version, but
+    // a graph built for training never has any of those, unless the graph
+    // itself contains explicitly fused operators.
+    fn forward_records(&self) -> Result<Vec<ForwardRecord>, GraphError> {
+        let mut records: Vec<ForwardRecord> = Vec::<ForwardRecord>::new();
+        for node in &self.nodes {
+            let operator: BackwardOperator = match node.operator {
+                NodeOperator::Input | NodeOperator::Output => continue,
+                NodeOperator::LinearLayer => BackwardOperator::LinearLayer,
+                NodeOperator::ReLU => BackwardOperator::ReLU,
+                NodeOperator::Softmax => BackwardOperator::Softmax,
+                NodeOperator::SoftmaxAxis { axis } => BackwardOperator::SoftmaxAxis { axis },
+                NodeOperator::LogSoftmax => BackwardOperator::LogSoftmax,
+                NodeOperator::Loss { kind } => BackwardOperator::Loss { kind },
+                NodeOperator::Add => BackwardOperator::Add,
+                _ => {
+                    return Err(GraphError::UnsupportedOperator(format!(
+                        "Node {} is a {:?}, which can't be trained.",
+                        node.name, node.operator
+                    )));
+                }
+            };
+            records.push(ForwardRecord {
+                name: node.name.clone(),
+                operator,
+                buffer_indices: node.buffer_indices.clone(),
+            });
+        }
 
-                    let buffer_indices: Vec<usize> =
-                        vec![input_index, weights_index, bias_index, output_index];
-                    let node: Node = Node::new(new_key.clone(), key, buffer_indices);
-                    self.nodes.push(node);
+        Ok(records)
+    }
 
-                    let key: NodeOperator = NodeOperator::Transfer;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let buffer_indices: Vec<usize> = vec![output_index];
-                    let node: Node = Node::new(new_key, key, buffer_indices);
-                    self.nodes.push(node);
+    // The gradients get buffers of their own, after all of the forward buffers
+    fn compute_backward_nodes(&mut self) -> Result<(), GraphError> {
+        let records: Vec<ForwardRecord> = self.forward_records()?;
+        let shapes: Vec<(usize, usize)> = self
+            .data_buffers
+            .iter()
+            .map(|buffer| (buffer.row_count, buffer.column_count))
+            .collect();
+
+        let data_buffers: &mut Vec<Tensor2D> = &mut self.data_buffers;
+        self.backward_plan = plan_backward(&records, &shapes, |_, row_count, column_count| {
+            data_buffers.push(Tensor2D::new(0.0, row_count, column_count));
+            data_buffers.len() - 1
+        })?;
+
+        for seed_index in &self.backward_plan.seed_indices {
+            self.data_buffers[*seed_index].data[0] = 1.0;
+        }
+
+        Ok(())
+    }
+
+    fn submit_backward_commands(
+        backward_nodes: &Vec<BackwardNode>,
+        data_buffers: &mut [Tensor2D],
+    ) -> Result<(), GraphError> {
+        for node in backward_nodes {
+            match node.operator {
+                BackwardOperator::LinearLayer => {
+                    nodes::linear_layer_backward(node, data_buffers)?;
+                }
+                BackwardOperator::ReLU => {
+                    nodes::relu_backward(node, data_buffers)?;
+                }
+                BackwardOperator::Softmax => {
+                    nodes::softmax_backward(node, data_buffers)?;
+                }
+                BackwardOperator::SoftmaxAxis { axis } => {
+                    nodes::softmax_axis_backward(node, data_buffers, axis)?;
+                }
+                BackwardOperator::LogSoftmax => {
+                    nodes::log_softmax_backward(node, data_buffers)?;
+                }
+                BackwardOperator::Loss { kind } => {
+                    nodes::loss_backward(node, data_buffers, kind)?;
+                }
+                BackwardOperator::SoftmaxCrossEntropy => {
+                    nodes::softmax_cross_entropy_backward(node, data_buffers)?;
+                }
+                BackwardOperator::Add => {
+                    nodes::add_backward(node, data_buffers)?;
                 }
             }
-
-            operator_index += 1;
         }
-        self.graph_operators_are_valid = true;
+
+        Ok(())
     }
 
     // In a more correct system, not meant for teaching/learning
@@ -274,46 +518,281 @@ impl GraphRunner {
     // buffers explicitly to the functions. Or at the very least
     // enforce more correctness in the data passed along to
     // the CPUNodeOperator functions.
-    fn submit_operator_commands(node_vector: &Vec<Node>, data_buffers: &mut [Tensor2D]) {
+    fn submit_operator_commands(
+        node_vector: &Vec<Node>,
+        data_buffers: &mut [Tensor2D],
+        thread_count: usize,
+    ) -> Result<(), GraphError> {
         for node in node_vector {
             match node.operator {
                 NodeOperator::Input => {}
                 NodeOperator::Output => {}
-                NodeOperator::Transfer => {}
                 NodeOperator::LinearLayer => {
-                    nodes::linear_layer(node, data_buffers);
+                    nodes::linear_layer(node, data_buffers, thread_count)?;
                 }
                 NodeOperator::ReLU => {
-                    nodes::relu(node, data_buffers);
+                    nodes::relu(node, data_buffers)?;
                 }
                 NodeOperator::Softmax => {
-                    nodes::softmax(node, data_buffers);
+                    nodes::softmax(node, data_buffers, thread_count)?;
+                }
+                NodeOperator::SoftmaxAxis { axis } => {
+                    nodes::softmax_axis(node, data_buffers, axis)?;
+                }
+                NodeOperator::LogSoftmax => {
+                    nodes::log_softmax(node, data_buffers)?;
                 }
                 NodeOperator::LinearReLU => {
-                    nodes::linear_relu(node, data_buffers);
+                    nodes::linear_relu(node, data_buffers, thread_count)?;
                 }
                 NodeOperator::LinearReLUSoftmax => {
-                    nodes::linear_relu_softmax(node, data_buffers);
+                    nodes::linear_relu_softmax(node, data_buffers, thread_count)?;
+                }
+                NodeOperator::Add => {
+                    nodes::add(node, data_buffers)?;
+                }
+                NodeOperator::AddReLU => {
+                    nodes::add_relu(node, data_buffers)?;
+                }
+                NodeOperator::Concat { axis } => {
+                    nodes::concat(node, data_buffers, axis)?;
+                }
+                NodeOperator::Loss { kind } => {
+                    nodes::loss(node, data_buffers, kind)?;
+                }
+                NodeOperator::ReLUInPlace => {
+                    nodes::relu_inplace(node, data_buffers)?;
+                }
+                NodeOperator::SoftmaxInPlace => {
+                    nodes::softmax_inplace(node, data_buffers, thread_count)?;
                 }
             }
         }
+
+        Ok(())
     }
 
-    pub fn run(&mut self) -> Tensor2D {
-        if !self.graph_operators_are_valid {
-            panic!(
-                "Tried to run the a CPU computational graph with an unvalidated graph_operators!"
-            );
+    // Runs the graph without cloning any outputs, they can then be read with get_output
+    pub fn submit(&mut self) -> Result<(), GraphError> {
+        if !self.graph_operators_are_valid || !self.nodes_are_valid || !self.data_buffers_are_valid
+        {
+            return Err(GraphError::UnsupportedOperator(
+                "Tried to run a CPU computational graph which has not been validated".to_string(),
+            ));
+        }
+
+        Self::submit_operator_commands(&self.nodes, &mut self.data_buffers, self.thread_count)?;
+        self.forward_has_run = true;
+
+        Ok(())
+    }
+
+    // Computes the gradient of the sum of the losses with respect to
+    // every weight, bias and graph input, using the results of the last run.
+    pub fn backward(&mut self) -> Result<(), GraphError> {
+        if !self.training {
+            return Err(GraphError::UnsupportedOperator(
+                "Tried to run backward on a CPU graph which was not built for training".to_string(),
+            ));
+        }
+        if !self.forward_has_run {
+            return Err(GraphError::UnsupportedOperator(
+                "The graph has to be run before backward can be run".to_string(),
+            ));
+        }
+
+        for gradient_index in self.backward_plan.accumulated_indices() {
+            self.data_buffers[gradient_index].data.fill(0.0);
+        }
+
+        Self::submit_backward_commands(&self.backward_plan.nodes, &mut self.data_buffers)
+    }
+
+    // Updates every weight and bias with the gradients from the last backward
+    pub fn step(&mut self, optimizer: &mut dyn Optimizer) -> Result<(), GraphError> {
+        if !self.training {
+            return Err(GraphError::UnsupportedOperator(
+                "Tried to step a CPU graph which was not built for training".to_string(),
+            ));
+        }
+
+        optimizer.step(&self.backward_plan.parameters, &mut self.data_buffers)
+    }
+
+    // Every weight and bias in the graph, empty unless built for training
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.backward_plan.parameters
+    }
+
+    // The gradients computed by the last backward. Weights and biases are named
+    // by their node, like "LinearLayer_0.weights", graph inputs by their HostToDevice node.
+    pub fn gradients(&self) -> HashMap<String, Tensor2D> {
+        let mut gradients: HashMap<String, Tensor2D> = self
+            .backward_plan
+            .parameters
+            .iter()
+            .map(|parameter| {
+                (
+                    parameter.name(),
+                    self.data_buffers[parameter.gradient_index].clone(),
+                )
+            })
+            .collect();
+
+        for node in &self.nodes {
+            if node.operator != NodeOperator::Input {
+                continue;
+            }
+            if let Some(gradient_index) = self
+                .backward_plan
+                .gradient_indices
+                .get(&node.buffer_indices[0])
+            {
+                gradients.insert(
+                    node.name.clone(),
+                    self.data_buffers[*gradient_index].clone(),
+                );
+            }
+        }
+
+        gradients
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        self.memory_report
+    }
+
+    // Which nodes were fused, empty if fusion was turned off
+    pub fn fusion_report(&self) -> &FusionReport {
+        &self.fusion_report
+    }
+
+    // How many threads the CPU kernels may use, 0 uses every core available.
+    // Small nodes stay single-threaded no matter what, see tensor2d_parallel.
+    pub fn set_thread_count(&mut self, thread_count: usize) {
+        self.thread_count = resolve_thread_count(thread_count);
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.thread_count
+    }
+
+    // Returns the first output of the graph, which for a sequential graph is the only one
+    pub fn run(&mut self) -> Result<Tensor2D, GraphError> {
+        self.submit()?;
+
+        match self.outputs.first() {
+            Some((_, buffer_index)) => Ok(self.data_buffers[*buffer_index].clone()),
+            None => Err(GraphError::MisplacedTransfer(
+                "The graph has no DeviceToHost node to read the output from".to_string(),
+            )),
+        }
+    }
+
+    // The buffer of the HostToDevice node, for the graphs which have exactly one
+    fn input_index(&self) -> Result<usize, GraphError> {
+        let inputs: Vec<&Node> = self
+            .nodes
+            .iter()
+            .filter(|node| node.operator == NodeOperator::Input)
+            .collect();
+        match inputs[..] {
+            [node] => Ok(node.buffer_indices[0]),
+            _ => Err(GraphError::UnsupportedOperator(
+                "New input can only be given to a graph with exactly one HostToDevice node"
+                    .to_string(),
+            )),
         }
+    }
+
+    fn named_input_index(&self, name: &str) -> Result<usize, GraphError> {
+        self.nodes
+            .iter()
+            .find(|node| node.operator == NodeOperator::Input && node.name == name)
+            .map(|node| node.buffer_indices[0])
+            .ok_or_else(|| {
+                GraphError::UnknownNode(format!(
+                    "The graph has no HostToDevice node named {}",
+                    name
+                ))
+            })
+    }
+
+    fn write_input(&mut self, input_index: usize, input: &Tensor2D) -> Result<(), GraphError> {
+        let buffer: &mut Tensor2D = &mut self.data_buffers[input_index];
+        if buffer.row_count != input.row_count || buffer.column_count != input.column_count {
+            return Err(GraphError::DimensionMismatch(format!(
+                "The input has {} rows and {} columns, but the graph was built for {} rows and {} columns",
+                input.row_count, input.column_count, buffer.row_count, buffer.column_count
+            )));
+        }
+        buffer.data.copy_from_slice(&input.data);
+
+        // The intermediates kept for backward belong to the old input
+        self.forward_has_run = false;
+
+        Ok(())
+    }
+
+    // Replaces the input of the HostToDevice node called name,
+    // which keeps being used by every run after it
+    pub fn set_input(&mut self, name: &str, input: &Tensor2D) -> Result<(), GraphError> {
+        let input_index: usize = self.named_input_index(name)?;
+        self.write_input(input_index, input)
+    }
 
-        if !self.data_buffers_are_valid {
-            panic!("Tried to run the a CPU computational graph with an unvalidated data_buffers!");
+    // The output of the DeviceToHost node called name from the last run
+    pub fn get_output(&self, name: &str) -> Result<Tensor2D, GraphError> {
+        let buffer_index: usize = match self.outputs.iter().find(|(output, _)| output == name) {
+            Some((_, buffer_index)) => *buffer_index,
+            None => {
+                return Err(GraphError::UnknownNode(format!(
+                    "The graph has no DeviceToHost node named {}",
+                    name
+                )))
+            }
+        };
+        if !self.forward_has_run {
+            return Err(GraphError::UnsupportedOperator(
+                "The graph has to be run with its current input before reading an output"
+                    .to_string(),
+            ));
         }
 
-        Self::submit_operator_commands(&self.nodes, &mut self.data_buffers);
+        Ok(self.data_buffers[buffer_index].clone())
+    }
+
+    // Same as run, but with the input of the graph replaced by input first.
+    // Nothing is rebuilt, the input is copied into the existing buffer.
+    pub fn run_with_input(&mut self, input: &Tensor2D) -> Result<Tensor2D, GraphError> {
+        let input_index: usize = self.input_index()?;
+        self.write_input(input_index, input)?;
+        self.run()
+    }
+
+    // Runs up to batch_size inputs of a graph built with batched in one go
+    // and returns the first output of the graph for each of them
+    pub fn run_batch(&mut self, inputs: &[Tensor2D]) -> Result<Vec<Tensor2D>, GraphError> {
+        let input: &Tensor2D = &self.data_buffers[self.input_index()?];
+        let stacked: Tensor2D = stack_rows(
+            inputs,
+            input.row_count / self.batch_size,
+            input.column_count,
+            self.batch_size,
+        )?;
+
+        let output: Tensor2D = self.run_with_input(&stacked)?;
+        Ok(split_rows(&output, self.batch_size, inputs.len()))
+    }
+
+    // Returns every output of the graph by the name of its DeviceToHost node
+    pub fn run_all(&mut self) -> Result<HashMap<String, Tensor2D>, GraphError> {
+        self.submit()?;
 
-        // Based on the restrictions we have put on our graph, the last node has to be
-        // the output.
-        self.data_buffers[self.nodes[self.nodes.len() - 2].buffer_indices[0]].clone()
+        Ok(self
+            .outputs
+            .iter()
+            .map(|(name, buffer_index)| (name.clone(), self.data_buffers[*buffer_index].clone()))
+            .collect())
     }
 }
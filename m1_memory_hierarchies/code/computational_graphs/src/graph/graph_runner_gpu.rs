@@ -1,14 +1,94 @@
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-use wgpu::{BufferSlice, CommandEncoder, ComputePipeline, ShaderModule};
+use wgpu::{BufferSlice, CommandEncoder, ComputePipeline, DownlevelFlags, ShaderModule};
 
 use crate::shared::graph_operators::GraphOperator::*;
 use crate::shared::tensor2d::Tensor2D;
-use crate::shared::tensor2d_gpu::Tensor2DGPU;
+use crate::shared::tensor2d_gpu::{LinearLayerKernel, Tensor2DGPU};
 use crate::shared::{gpu_utilities::GPUHandles, graph_operators::GraphOperator};
 
-use super::graph_validation::validate_graph_operators;
-use super::nodes_gpu::{self, NodeGPU, NodeOperatorGPU};
+use super::autograd::{
+    plan_backward, BackwardNode, BackwardOperator, BackwardPlan, ForwardRecord, Parameter,
+};
+use super::graph_batching::{batch_graph, split_rows, stack_rows};
+use super::graph_dag::{GraphDAG, GraphNode};
+use super::graph_error::GraphError;
+use super::graph_fusion::{default_fusion_patterns, fuse_graph, FusionReport};
+use super::graph_validation::{validate_graph_dag, validate_graph_operators};
+use super::memory_planner::{MemoryPlanner, MemoryReport};
+use super::node_timing::{NodeTiming, NodeTimingReport, TimestampQueries, TimingSource};
+use super::nodes_gpu::{self, BoundNode, NodeGPU, NodeOperatorGPU};
+use super::optimizer::Optimizer;
+use super::readback_ring::{ReadbackHandle, ReadbackRing};
+
+// Double buffered, one output can be mapped while the next iteration is running
+const DEFAULT_READBACK_SLOT_COUNT: usize = 2;
+
+// How a GPU graph is built, passed to GraphRunnerGPU::new and from_dag.
+// Any combination works, e.g. bound nodes running the tiled linear layer kernel,
+// except the few which contradict each other, see GraphRunnerGPUOptions::validate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GraphRunnerGPUOptions {
+    pub fuse_operators: bool,
+    // Builds every shader and pipeline once up front, instead of in every submit
+    pub use_cache: bool,
+    // Same as GraphRunner::for_training, the parameters and
+    // their gradients never leave the GPU while training.
+    // Every intermediate is kept for the backward pass, so nothing can be fused.
+    pub training: bool,
+    // The uniforms and bind groups of every node are also made once when the node is
+    // created, instead of on every submit. Running the graph is then only a matter of
+    // recording the dispatches. The pipelines come from the cache, so it needs use_cache.
+    pub bind_once: bool,
+    // Which kernel in linear_layer.wgsl the linear layers, fused or not, are run with.
+    // The tiled kernel reuses the input and weights through workgroup memory,
+    // which pays off once the matrices are larger than a handful of workgroups.
+    pub linear_layer_kernel: LinearLayerKernel,
+    // Same as GraphRunner::batched, batch_size inputs are run with a single
+    // dispatch per node by run_batch. 1 runs a single input like any other graph.
+    pub batch_size: usize,
+}
+
+impl Default for GraphRunnerGPUOptions {
+    fn default() -> Self {
+        Self {
+            fuse_operators: false,
+            use_cache: false,
+            training: false,
+            bind_once: false,
+            linear_layer_kernel: LinearLayerKernel::Naive,
+            batch_size: 1,
+        }
+    }
+}
+
+impl GraphRunnerGPUOptions {
+    fn validate(&self) -> Result<(), GraphError> {
+        if self.training && self.fuse_operators {
+            return Err(GraphError::UnsupportedOperator(
+                "A GPU graph built for training can't fuse operators, the backward pass needs every intermediate".to_string(),
+            ));
+        }
+        if self.training && self.batch_size != 1 {
+            return Err(GraphError::UnsupportedOperator(
+                "A batched GPU graph can't be trained, every sample would get its own copy of the biases".to_string(),
+            ));
+        }
+        if self.bind_once && !self.use_cache {
+            return Err(GraphError::UnsupportedOperator(
+                "Bound nodes take their pipelines from the pipeline cache, so bind_once needs use_cache".to_string(),
+            ));
+        }
+        if self.batch_size == 0 {
+            return Err(GraphError::UnsupportedOperator(
+                "A batched GPU graph needs a batch size of at least 1".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
 
 pub struct GraphRunnerGPU {
     graph_operators_are_valid: bool,
@@ -20,24 +100,108 @@ pub struct GraphRunnerGPU {
     use_cache: bool,
     shader_cache: HashMap<String, ShaderModule>,
     pipeline_cache: HashMap<String, ComputePipeline>,
+    // The name of every DeviceToHost node and the buffer it reads from,
+    // in the order they were added to the graph.
+    outputs: Vec<(String, usize)>,
+    memory_report: MemoryReport,
+    fusion_report: FusionReport,
+    // Built for training, every intermediate is kept for the backward pass
+    training: bool,
+    backward_plan: BackwardPlan,
+    forward_has_run: bool,
+    // How many samples are stacked in the input, 1 unless built with a batch size
+    batch_size: usize,
+    // Built with bind_once, every node gets its bind groups once in compute_nodes
+    bind_once: bool,
+    // Which kernel in linear_layer.wgsl the linear layers are run with
+    linear_layer_kernel: LinearLayerKernel,
+    // The staging buffers submit_with_readback reads the first output back through,
+    // made the first time it is called
+    readback_ring: Option<ReadbackRing>,
+    readback_slot_count: usize,
 }
 
 impl GraphRunnerGPU {
+    // The sequential graph is just a special case of the DAG,
+    // where every operator takes the previous one as input.
     pub fn new(
         gpu_handles: &GPUHandles,
         graph_operators: &Vec<GraphOperator>,
-        fuse_operators: bool,
-        use_cache: bool,
-    ) -> Self {
+        options: &GraphRunnerGPUOptions,
+    ) -> Result<Self, GraphError> {
+        validate_graph_operators(graph_operators)?;
+
+        Self::from_dag(
+            gpu_handles,
+            &GraphDAG::from_sequential(graph_operators),
+            options,
+        )
+    }
+
+    pub fn from_dag(
+        gpu_handles: &GPUHandles,
+        graph: &GraphDAG,
+        options: &GraphRunnerGPUOptions,
+    ) -> Result<Self, GraphError> {
+        options.validate()?;
+        if options.batch_size == 1 {
+            return Self::build(gpu_handles, graph, options);
+        }
+
+        validate_graph_dag(graph)?;
+        let mut runner: GraphRunnerGPU = Self::build(
+            gpu_handles,
+            &batch_graph(graph, options.batch_size)?,
+            options,
+        )?;
+        runner.batch_size = options.batch_size;
+
+        Ok(runner)
+    }
+
+    fn build(
+        gpu_handles: &GPUHandles,
+        graph: &GraphDAG,
+        options: &GraphRunnerGPUOptions,
+    ) -> Result<Self, GraphError> {
+        // Some backends, like WebGL2 or older GL drivers, can't run compute shaders at all
+        if !gpu_handles
+            .adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(DownlevelFlags::COMPUTE_SHADERS)
+        {
+            return Err(GraphError::GpuUnavailable(format!(
+                "The adapter {} does not support compute shaders",
+                gpu_handles.adapter_info.name
+            )));
+        }
+
+        validate_graph_dag(graph)?;
+
+        // Fusion rewrites the graph before any nodes are made from it
+        let mut fusion_report: FusionReport = FusionReport::default();
+        let fused_graph: GraphDAG;
+        let graph: &GraphDAG = if options.fuse_operators {
+            let (rewritten_graph, report) = fuse_graph(graph, &default_fusion_patterns())?;
+            fused_graph = rewritten_graph;
+            fusion_report = report;
+            &fused_graph
+        } else {
+            graph
+        };
+        let order: Vec<usize> = validate_graph_dag(graph)?;
+
         let mut shader_cache: HashMap<String, ShaderModule> =
             HashMap::<String, ShaderModule>::new();
         let mut pipeline_cache: HashMap<String, ComputePipeline> =
             HashMap::<String, ComputePipeline>::new();
 
-        if use_cache {
+        if options.use_cache {
             Self::populate_caches(
                 gpu_handles,
-                fuse_operators,
+                options.training,
+                options.linear_layer_kernel,
                 &mut shader_cache,
                 &mut pipeline_cache,
             );
@@ -49,25 +213,49 @@ impl GraphRunnerGPU {
             nodes_are_valid: false,
             data_buffers: Vec::<Tensor2DGPU>::new(),
             data_buffers_are_valid: false,
-            fuse_operators,
-            use_cache,
+            fuse_operators: options.fuse_operators,
+            use_cache: options.use_cache,
             shader_cache,
             pipeline_cache,
+            outputs: Vec::<(String, usize)>::new(),
+            memory_report: MemoryReport::default(),
+            fusion_report,
+            training: options.training,
+            backward_plan: BackwardPlan::default(),
+            forward_has_run: false,
+            batch_size: 1,
+            bind_once: options.bind_once,
+            linear_layer_kernel: options.linear_layer_kernel,
+            readback_ring: None,
+            readback_slot_count: DEFAULT_READBACK_SLOT_COUNT,
         };
-        runner.graph_operators_are_valid = validate_graph_operators(graph_operators);
+        runner.graph_operators_are_valid = true;
+
+        runner.compute_nodes(gpu_handles, graph, &order)?;
+        if runner.training {
+            runner.compute_backward_nodes(gpu_handles)?;
+        }
+        runner.data_buffers_are_valid = true;
 
-        runner.compute_nodes(gpu_handles, graph_operators, fuse_operators);
-        runner
+        Ok(runner)
     }
 
     fn populate_caches(
         gpu_handles: &GPUHandles,
-        fuse_operators: bool,
+        training: bool,
+        linear_layer_kernel: LinearLayerKernel,
         shader_cache: &mut HashMap<String, ShaderModule>,
         pipeline_cache: &mut HashMap<String, ComputePipeline>,
     ) {
         //LinearLayer,
-        nodes_gpu::build_linear_layer_elements(gpu_handles, shader_cache, pipeline_cache, false);
+        //LinearReLU, a graph can contain explicitly fused operators even without fuse_operators
+        nodes_gpu::build_linear_layer_elements(
+            gpu_handles,
+            shader_cache,
+            pipeline_cache,
+            true,
+            linear_layer_kernel,
+        );
 
         //ReLU,
         nodes_gpu::build_relu_elements(gpu_handles, shader_cache, pipeline_cache);
@@ -75,301 +263,550 @@ impl GraphRunnerGPU {
         //Softmax,
         nodes_gpu::build_softmax_elements(gpu_handles, shader_cache, pipeline_cache);
 
-        if fuse_operators {
-            //LinearReLU,
-            nodes_gpu::build_linear_layer_elements(gpu_handles, shader_cache, pipeline_cache, true);
-        }
-    }
+        //Softmax along an axis, forward and backward are in the same shader
+        nodes_gpu::build_softmax_axis_elements(gpu_handles, shader_cache, pipeline_cache);
+
+        //Add,
+        nodes_gpu::build_add_elements(gpu_handles, shader_cache, pipeline_cache);
 
-    fn get_new_key(
-        operator_counts: &mut HashMap<NodeOperatorGPU, u32>,
-        key: &NodeOperatorGPU,
-    ) -> String {
-        let error_message: &str = "Failed to get value from hash map in graph_runner::get_new_key";
-        let value: &mut u32 = operator_counts
-            .get_mut(key)
-            .unwrap_or_else(|| panic!("{}", error_message));
-        let index: u32 = *value;
-        *value = index + 1;
-        format!("{:?}_{}", key, index)
+        //LogSoftmax, Loss and the backward operators, which include LogSoftmax and Loss
+        if training {
+            nodes_gpu::build_backward_elements(gpu_handles, shader_cache, pipeline_cache);
+        } else {
+            nodes_gpu::build_log_softmax_elements(gpu_handles, shader_cache, pipeline_cache);
+            nodes_gpu::build_loss_elements(gpu_handles, shader_cache, pipeline_cache);
+        }
     }
 
-    fn verify_previous_node_and_get_index(nodes: &Vec<NodeGPU>, key: &NodeOperatorGPU) -> usize {
-        let previous_node: &NodeGPU = &nodes[nodes.len() - 1];
-        match previous_node.operator {
-            NodeOperatorGPU::DeviceToDevice => {}
-            _ => {
-                panic!("Invalid node operator! Was expecting DeviceToDevice before {:?}, but found {:?}", *key, previous_node.operator);
+    fn get_input_indices(
+        graph: &GraphDAG,
+        node: &GraphNode,
+        output_buffers: &[Option<usize>],
+    ) -> Result<Vec<usize>, GraphError> {
+        let mut input_indices: Vec<usize> = Vec::<usize>::with_capacity(node.inputs.len());
+        for input in &node.inputs {
+            let buffer_index: Option<usize> = graph
+                .node_index(input)
+                .and_then(|input_node_index| output_buffers[input_node_index]);
+            match buffer_index {
+                Some(buffer_index) => input_indices.push(buffer_index),
+                None => {
+                    return Err(GraphError::MalformedNode(format!(
+                        "Node {} takes {} as input, but it has not been computed yet.",
+                        node.name, input
+                    )));
+                }
             }
         }
 
-        if previous_node.buffer_indices.len() == 1 {
-            previous_node.buffer_indices[0]
-        } else {
-            panic!("There was more than one buffer index described in a DeviceToDevice operator. This is currently invalid behavior!");
-        }
+        Ok(input_indices)
     }
 
-    // This is made a lot more complicated by reusing buffers
-    // If each node owned its own buffers with no reusage
-    // We would need to keep less track of buffers
+    // Nodes are created in topological order, so submitting them
+    // front to back always has every input ready.
+    // Intermediate results are placed in buffers by the memory planner,
+    // which reuses the buffers of results nobody needs anymore.
+    // Buffers are shared between nodes by index.
     fn compute_nodes(
         &mut self,
         gpu_handles: &GPUHandles,
-        graph_operators: &Vec<GraphOperator>,
-        fuse_operators: bool,
-    ) {
+        graph: &GraphDAG,
+        order: &[usize],
+    ) -> Result<(), GraphError> {
         if !self.graph_operators_are_valid {
-            panic!("Invalid graph being sent to compute_nodes!");
-        }
-
-        let mut operator_counts: HashMap<NodeOperatorGPU, u32> =
-            HashMap::<NodeOperatorGPU, u32>::new();
-        operator_counts.insert(NodeOperatorGPU::HostToDevice, 0);
-        operator_counts.insert(NodeOperatorGPU::DeviceToHost, 0);
-        operator_counts.insert(NodeOperatorGPU::DeviceToDevice, 0);
-        operator_counts.insert(NodeOperatorGPU::LinearLayer, 0);
-        operator_counts.insert(NodeOperatorGPU::ReLU, 0);
-        operator_counts.insert(NodeOperatorGPU::Softmax, 0);
-        operator_counts.insert(NodeOperatorGPU::LinearReLU, 0);
-        operator_counts.insert(NodeOperatorGPU::LinearReLUSoftmax, 0);
-
-        let mut operator_index: usize = 0;
-        while operator_index < graph_operators.len() {
-            let operator: &GraphOperator = &graph_operators[operator_index];
-
-            match operator {
-                Empty => {}
-                // Maybe put a device to device split in here for simpler code in the other operators
-                HostToDevice { input } => {
-                    let key: NodeOperatorGPU = NodeOperatorGPU::HostToDevice;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
+            return Err(GraphError::UnsupportedOperator(
+                "compute_nodes was given a graph which has not been validated".to_string(),
+            ));
+        }
+
+        let consumers: Vec<Vec<usize>> = graph.consumers()?;
+        // The buffer holding the output of each node in the graph
+        let mut output_buffers: Vec<Option<usize>> = vec![None; graph.nodes.len()];
+        let mut memory_planner: MemoryPlanner = MemoryPlanner::new(std::mem::size_of::<f32>());
 
+        for node_index in order {
+            let node_index: usize = *node_index;
+            let graph_node: &GraphNode = &graph.nodes[node_index];
+            let input_indices: Vec<usize> =
+                Self::get_input_indices(graph, graph_node, &output_buffers)?;
+
+            match &graph_node.operator {
+                Empty => {
+                    return Err(GraphError::UnsupportedOperator(format!(
+                        "Node {} is Empty, which is only supported in sequential graphs.",
+                        graph_node.name
+                    )));
+                }
+                HostToDevice { input } => {
+                    Self::buffer_size_check(
+                        gpu_handles,
+                        &graph_node.name,
+                        input.row_count,
+                        input.column_count,
+                    )?;
+                    memory_planner.track_fixed(input.row_count, input.column_count);
                     self.data_buffers.push(Tensor2DGPU::from_tensor2d(
                         gpu_handles,
-                        &format!("{}_{}", new_key, "input"),
+                        &format!("{}_{}", graph_node.name, "input"),
                         input,
                     ));
-                    let buffer_indices: Vec<usize> = vec![self.data_buffers.len() - 1];
+                    let buffer_index: usize = self.data_buffers.len() - 1;
 
-                    let node: NodeGPU = NodeGPU::new(new_key, key, buffer_indices.clone());
-                    self.nodes.push(node);
-
-                    let key: NodeOperatorGPU = NodeOperatorGPU::DeviceToDevice;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let node: NodeGPU = NodeGPU::new(new_key, key, buffer_indices);
+                    let node: NodeGPU = NodeGPU::new(
+                        graph_node.name.clone(),
+                        NodeOperatorGPU::HostToDevice,
+                        vec![buffer_index],
+                    );
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(buffer_index);
                 }
                 DeviceToHost => {
-                    let key: NodeOperatorGPU = NodeOperatorGPU::DeviceToHost;
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-
-                    let buffer_indices: Vec<usize> = vec![input_index];
-                    let node: NodeGPU =
-                        NodeGPU::new(new_key, NodeOperatorGPU::DeviceToHost, buffer_indices);
+                    let node: NodeGPU = NodeGPU::new(
+                        graph_node.name.clone(),
+                        NodeOperatorGPU::DeviceToHost,
+                        input_indices.clone(),
+                    );
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(input_indices[0]);
                 }
-                LinearLayer { weights, bias } => {
-                    let mut key: NodeOperatorGPU = NodeOperatorGPU::LinearLayer;
-
-                    if fuse_operators {
-                        if let ReLU = graph_operators[operator_index + 1] {
-                            match graph_operators[operator_index + 2] {
-                                Softmax => {
-                                    key = NodeOperatorGPU::LinearReLUSoftmax;
-                                    operator_index += 2;
-                                }
-                                _ => {
-                                    key = NodeOperatorGPU::LinearReLU;
-                                    operator_index += 1;
-                                }
-                            }
-                        }
-                    }
-
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
+                LinearLayer { weights, bias }
+                | LinearReLUFused { weights, bias }
+                | LinearReLUSoftmaxFused { weights, bias } => {
+                    let key: NodeOperatorGPU = match &graph_node.operator {
+                        LinearReLUFused {
+                            weights: _,
+                            bias: _,
+                        } => NodeOperatorGPU::LinearReLU,
+                        LinearReLUSoftmaxFused {
+                            weights: _,
+                            bias: _,
+                        } => NodeOperatorGPU::LinearReLUSoftmax,
+                        _ => NodeOperatorGPU::LinearLayer,
+                    };
+                    let name: String = graph_node.name.clone();
 
+                    Self::buffer_size_check(
+                        gpu_handles,
+                        &name,
+                        weights.row_count,
+                        weights.column_count,
+                    )?;
+                    Self::buffer_size_check(gpu_handles, &name, bias.row_count, bias.column_count)?;
+                    memory_planner.track_fixed(weights.row_count, weights.column_count);
                     self.data_buffers.push(Tensor2DGPU::from_tensor2d(
                         gpu_handles,
-                        &format!("{}_{}", new_key, "weights"),
+                        &format!("{}_{}", name, "weights"),
                         weights,
                     ));
                     let weights_index: usize = self.data_buffers.len() - 1;
 
+                    memory_planner.track_fixed(bias.row_count, bias.column_count);
                     self.data_buffers.push(Tensor2DGPU::from_tensor2d(
                         gpu_handles,
-                        &format!("{}_{}", new_key, "bias"),
+                        &format!("{}_{}", name, "bias"),
                         bias,
                     ));
                     let bias_index: usize = self.data_buffers.len() - 1;
 
-                    self.data_buffers.push(Tensor2DGPU::new(
+                    let (use_count, pinned) =
+                        MemoryPlanner::output_uses(graph, &consumers, node_index);
+                    let output_index: usize = self.allocate_intermediate(
                         gpu_handles,
-                        &format!("{}_{}", new_key, "output"),
-                        0.0,
+                        &mut memory_planner,
+                        &name,
                         bias.row_count,
                         bias.column_count,
-                    ));
-                    let output_index: usize = self.data_buffers.len() - 1;
+                        use_count,
+                        pinned,
+                    )?;
 
                     let buffer_indices: Vec<usize> =
-                        vec![input_index, weights_index, bias_index, output_index];
-                    let node: NodeGPU = NodeGPU::new(new_key.clone(), key, buffer_indices);
-                    self.nodes.push(node);
-
-                    let key: NodeOperatorGPU = NodeOperatorGPU::DeviceToDevice;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let buffer_indices: Vec<usize> = vec![output_index];
-                    let node: NodeGPU = NodeGPU::new(new_key, key, buffer_indices);
+                        vec![input_indices[0], weights_index, bias_index, output_index];
+                    let node: NodeGPU = NodeGPU::new(name, key, buffer_indices);
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(output_index);
                 }
                 // Note this is not inplace
-                ReLU => {
-                    let key: NodeOperatorGPU = NodeOperatorGPU::ReLU;
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
+                ReLU | Softmax { axis: _ } | LogSoftmax => {
+                    let key: NodeOperatorGPU = match graph_node.operator {
+                        ReLU => NodeOperatorGPU::ReLU,
+                        LogSoftmax => NodeOperatorGPU::LogSoftmax,
+                        Softmax { axis: Some(axis) } => NodeOperatorGPU::SoftmaxAxis { axis },
+                        _ => NodeOperatorGPU::Softmax,
+                    };
 
-                    let input_buffer: &Tensor2DGPU = &self.data_buffers[input_index];
-                    self.data_buffers.push(Tensor2DGPU::new(
+                    // Softmax used to write to a flattened vector, but it is the
+                    // same amount of memory, so we keep the shape of the input,
+                    // which softmax along an axis needs anyway.
+                    let input_buffer: &Tensor2DGPU = &self.data_buffers[input_indices[0]];
+                    let (row_count, column_count) =
+                        (input_buffer.row_count, input_buffer.column_count);
+                    let (use_count, pinned) =
+                        MemoryPlanner::output_uses(graph, &consumers, node_index);
+                    let output_index: usize = self.allocate_intermediate(
                         gpu_handles,
-                        &format!("{}_{}", new_key, "output"),
-                        0.0,
-                        input_buffer.row_count,
-                        input_buffer.column_count,
-                    ));
-                    let output_index: usize = self.data_buffers.len() - 1;
+                        &mut memory_planner,
+                        &graph_node.name,
+                        row_count,
+                        column_count,
+                        use_count,
+                        pinned,
+                    )?;
 
-                    let buffer_indices: Vec<usize> = vec![input_index, output_index];
-                    let node: NodeGPU = NodeGPU::new(new_key.clone(), key, buffer_indices);
-                    self.nodes.push(node);
-
-                    let key: NodeOperatorGPU = NodeOperatorGPU::DeviceToDevice;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let buffer_indices: Vec<usize> = vec![output_index];
-                    let node: NodeGPU = NodeGPU::new(new_key, key, buffer_indices);
+                    let buffer_indices: Vec<usize> = vec![input_indices[0], output_index];
+                    let node: NodeGPU = NodeGPU::new(graph_node.name.clone(), key, buffer_indices);
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(output_index);
                 }
-                Softmax => {
-                    let key: NodeOperatorGPU = NodeOperatorGPU::Softmax;
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-
-                    let input_buffer: &Tensor2DGPU = &self.data_buffers[input_index];
-                    // This should be more flexible, but Softmax always outputs a flattened vector
-                    self.data_buffers.push(Tensor2DGPU::new(
+                Add | AddReLUFused => {
+                    let key: NodeOperatorGPU = if let AddReLUFused = graph_node.operator {
+                        NodeOperatorGPU::AddReLU
+                    } else {
+                        NodeOperatorGPU::Add
+                    };
+                    let input_buffer: &Tensor2DGPU = &self.data_buffers[input_indices[0]];
+                    let (row_count, column_count) =
+                        (input_buffer.row_count, input_buffer.column_count);
+                    let (use_count, pinned) =
+                        MemoryPlanner::output_uses(graph, &consumers, node_index);
+                    let output_index: usize = self.allocate_intermediate(
                         gpu_handles,
-                        &format!("{}_{}", new_key, "output"),
-                        0.0,
-                        input_buffer.row_count * input_buffer.column_count,
-                        1,
-                    ));
-                    let output_index: usize = self.data_buffers.len() - 1;
+                        &mut memory_planner,
+                        &graph_node.name,
+                        row_count,
+                        column_count,
+                        use_count,
+                        pinned,
+                    )?;
 
-                    let buffer_indices: Vec<usize> = vec![input_index, output_index];
-                    let node: NodeGPU = NodeGPU::new(new_key.clone(), key, buffer_indices);
-                    self.nodes.push(node);
-
-                    let key: NodeOperatorGPU = NodeOperatorGPU::DeviceToDevice;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let buffer_indices: Vec<usize> = vec![output_index];
-                    let node: NodeGPU = NodeGPU::new(new_key, key, buffer_indices);
+                    let buffer_indices: Vec<usize> =
+                        vec![input_indices[0], input_indices[1], output_index];
+                    let node: NodeGPU = NodeGPU::new(graph_node.name.clone(), key, buffer_indices);
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(output_index);
                 }
-                LinearReLUFused { weights, bias } => {
-                    let key: NodeOperatorGPU = NodeOperatorGPU::LinearReLU;
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-
-                    self.data_buffers.push(Tensor2DGPU::from_tensor2d(
+                Loss { kind, target } => {
+                    Self::buffer_size_check(
                         gpu_handles,
-                        &format!("{}_{}", new_key, "weights"),
-                        weights,
-                    ));
-                    let weights_index: usize = self.data_buffers.len() - 1;
-
+                        &graph_node.name,
+                        target.row_count,
+                        target.column_count,
+                    )?;
+                    memory_planner.track_fixed(target.row_count, target.column_count);
                     self.data_buffers.push(Tensor2DGPU::from_tensor2d(
                         gpu_handles,
-                        &format!("{}_{}", new_key, "bias"),
-                        bias,
+                        &format!("{}_{}", graph_node.name, "target"),
+                        target,
                     ));
-                    let bias_index: usize = self.data_buffers.len() - 1;
+                    let target_index: usize = self.data_buffers.len() - 1;
 
-                    self.data_buffers.push(Tensor2DGPU::new(
+                    let (use_count, pinned) =
+                        MemoryPlanner::output_uses(graph, &consumers, node_index);
+                    let output_index: usize = self.allocate_intermediate(
                         gpu_handles,
-                        &format!("{}_{}", new_key, "output"),
-                        0.0,
-                        bias.row_count,
-                        bias.column_count,
-                    ));
-                    let output_index: usize = self.data_buffers.len() - 1;
+                        &mut memory_planner,
+                        &graph_node.name,
+                        1,
+                        1,
+                        use_count,
+                        pinned,
+                    )?;
 
                     let buffer_indices: Vec<usize> =
-                        vec![input_index, weights_index, bias_index, output_index];
-                    let node: NodeGPU = NodeGPU::new(new_key.clone(), key, buffer_indices);
+                        vec![input_indices[0], target_index, output_index];
+                    let node: NodeGPU = NodeGPU::new(
+                        graph_node.name.clone(),
+                        NodeOperatorGPU::Loss { kind: *kind },
+                        buffer_indices,
+                    );
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(output_index);
+                }
+                Concat { axis } => {
+                    let mut row_count: usize = self.data_buffers[input_indices[0]].row_count;
+                    let mut column_count: usize = self.data_buffers[input_indices[0]].column_count;
+                    for input_index in &input_indices[1..] {
+                        if *axis == 0 {
+                            row_count += self.data_buffers[*input_index].row_count;
+                        } else {
+                            column_count += self.data_buffers[*input_index].column_count;
+                        }
+                    }
+                    let (use_count, pinned) =
+                        MemoryPlanner::output_uses(graph, &consumers, node_index);
+                    let output_index: usize = self.allocate_intermediate(
+                        gpu_handles,
+                        &mut memory_planner,
+                        &graph_node.name,
+                        row_count,
+                        column_count,
+                        use_count,
+                        pinned,
+                    )?;
 
-                    let key: NodeOperatorGPU = NodeOperatorGPU::DeviceToDevice;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let buffer_indices: Vec<usize> = vec![output_index];
-                    let node: NodeGPU = NodeGPU::new(new_key, key, buffer_indices);
+                    let mut buffer_indices: Vec<usize> = input_indices.clone();
+                    buffer_indices.push(output_index);
+                    let node: NodeGPU = NodeGPU::new(
+                        graph_node.name.clone(),
+                        NodeOperatorGPU::Concat { axis: *axis },
+                        buffer_indices,
+                    );
                     self.nodes.push(node);
+                    output_buffers[node_index] = Some(output_index);
                 }
-                LinearReLUSoftmaxFused { weights, bias } => {
-                    let key: NodeOperatorGPU = NodeOperatorGPU::LinearReLUSoftmax;
-                    let input_index: usize =
-                        Self::verify_previous_node_and_get_index(&self.nodes, &key);
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
+            }
 
-                    self.data_buffers.push(Tensor2DGPU::from_tensor2d(
-                        gpu_handles,
-                        &format!("{}_{}", new_key, "weights"),
-                        weights,
-                    ));
-                    let weights_index: usize = self.data_buffers.len() - 1;
+            // The output of the node has been allocated, so the buffers
+            // it reads can be handed out to the nodes after it
+            for input_index in &input_indices {
+                memory_planner.release(*input_index);
+            }
+        }
+        self.memory_report = memory_planner.report();
 
-                    self.data_buffers.push(Tensor2DGPU::from_tensor2d(
-                        gpu_handles,
-                        &format!("{}_{}", new_key, "bias"),
-                        bias,
-                    ));
-                    let bias_index: usize = self.data_buffers.len() - 1;
+        if self.bind_once {
+            for node_index in 0..self.nodes.len() {
+                let bound: Option<BoundNode> = nodes_gpu::bind_node(
+                    gpu_handles,
+                    &self.pipeline_cache,
+                    &self.nodes[node_index],
+                    &self.data_buffers,
+                    self.linear_layer_kernel,
+                )?;
+                self.nodes[node_index].bound = bound;
+            }
+        }
 
-                    // This should be more flexible, but Softmax always outputs a flattened vector
-                    self.data_buffers.push(Tensor2DGPU::new(
-                        gpu_handles,
-                        &format!("{}_{}", new_key, "output"),
-                        0.0,
-                        bias.row_count,
-                        bias.column_count,
-                    ));
-                    let output_index: usize = self.data_buffers.len() - 1;
+        self.outputs = graph
+            .output_names()
+            .into_iter()
+            .map(|name| {
+                let node_index: usize = graph
+                    .node_index(&name)
+                    .expect("output_names only returns existing nodes");
+                (name, output_buffers[node_index].unwrap())
+            })
+            .collect();
+        self.nodes_are_valid = true;
 
-                    let buffer_indices: Vec<usize> =
-                        vec![input_index, weights_index, bias_index, output_index];
-                    let node: NodeGPU = NodeGPU::new(new_key.clone(), key, buffer_indices);
-                    self.nodes.push(node);
+        Ok(())
+    }
 
-                    let key: NodeOperatorGPU = NodeOperatorGPU::DeviceToDevice;
-                    let new_key: String = Self::get_new_key(&mut operator_counts, &key);
-                    let buffer_indices: Vec<usize> = vec![output_index];
-                    let node: NodeGPU = NodeGPU::new(new_key, key, buffer_indices);
-                    self.nodes.push(node);
+    // wgpu can't make a buffer larger than max_buffer_size at all, so unlike
+    // the bindings and dispatches the nodes make, there is nothing to split
+    fn buffer_size_check(
+        gpu_handles: &GPUHandles,
+        name: &str,
+        row_count: usize,
+        column_count: usize,
+    ) -> Result<(), GraphError> {
+        let size: u64 = (row_count * column_count * std::mem::size_of::<f32>()) as u64;
+        if gpu_handles.limits.max_buffer_size < size {
+            return Err(GraphError::LimitExceeded(format!(
+                "Node {} needs a {} x {} buffer of {} bytes, but {} only allows buffers of up to {} bytes",
+                name,
+                row_count,
+                column_count,
+                size,
+                gpu_handles.adapter_info.name,
+                gpu_handles.limits.max_buffer_size
+            )));
+        }
+
+        Ok(())
+    }
+
+    // A reused buffer keeps the label of the first node it was created for
+    fn allocate_intermediate(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        memory_planner: &mut MemoryPlanner,
+        name: &str,
+        row_count: usize,
+        column_count: usize,
+        use_count: usize,
+        pinned: bool,
+    ) -> Result<usize, GraphError> {
+        Self::buffer_size_check(gpu_handles, name, row_count, column_count)?;
+
+        // Pinned buffers are never reused
+        let pinned: bool = pinned || self.training;
+        let data_buffers: &mut Vec<Tensor2DGPU> = &mut self.data_buffers;
+        let buffer_index: usize =
+            memory_planner.allocate(row_count, column_count, use_count, pinned, || {
+                data_buffers.push(Tensor2DGPU::new(
+                    gpu_handles,
+                    &format!("{}_{}", name, "output"),
+                    0.0,
+                    row_count,
+                    column_count,
+                ));
+                data_buffers.len() - 1
+            });
+
+        Ok(buffer_index)
+    }
+
+    // Describes the forward nodes to the backward planner, see GraphRunner::forward_records
+    fn forward_records(&self) -> Result<Vec<ForwardRecord>, GraphError> {
+        let mut records: Vec<ForwardRecord> = Vec::<ForwardRecord>::new();
+        for node in &self.nodes {
+            let operator: BackwardOperator = match node.operator {
+                NodeOperatorGPU::HostToDevice | NodeOperatorGPU::DeviceToHost => continue,
+                NodeOperatorGPU::LinearLayer => BackwardOperator::LinearLayer,
+                NodeOperatorGPU::ReLU => BackwardOperator::ReLU,
+                NodeOperatorGPU::Softmax => BackwardOperator::Softmax,
+                NodeOperatorGPU::SoftmaxAxis { axis } => BackwardOperator::SoftmaxAxis { axis },
+                NodeOperatorGPU::LogSoftmax => BackwardOperator::LogSoftmax,
+                NodeOperatorGPU::Loss { kind } => BackwardOperator::Loss { kind },
+                NodeOperatorGPU::Add => BackwardOperator::Add,
+                _ => {
+                    return Err(GraphError::UnsupportedOperator(format!(
+                        "Node {} is a {:?}, which can't be trained.",
+                        node.name, node.operator
+                    )));
                 }
-            }
+            };
+            records.push(ForwardRecord {
+                name: node.name.clone(),
+                operator,
+                buffer_indices: node.buffer_indices.clone(),
+            });
+        }
+
+        Ok(records)
+    }
+
+    // The gradients get buffers of their own, after all of the forward buffers
+    fn compute_backward_nodes(&mut self, gpu_handles: &GPUHandles) -> Result<(), GraphError> {
+        let records: Vec<ForwardRecord> = self.forward_records()?;
+        let shapes: Vec<(usize, usize)> = self
+            .data_buffers
+            .iter()
+            .map(|buffer| (buffer.row_count, buffer.column_count))
+            .collect();
+
+        let data_buffers: &mut Vec<Tensor2DGPU> = &mut self.data_buffers;
+        self.backward_plan = plan_backward(&records, &shapes, |label, row_count, column_count| {
+            data_buffers.push(Tensor2DGPU::new(
+                gpu_handles,
+                label,
+                0.0,
+                row_count,
+                column_count,
+            ));
+            data_buffers.len() - 1
+        })?;
 
-            operator_index += 1;
+        for seed_index in &self.backward_plan.seed_indices {
+            let seed: &mut Tensor2DGPU = &mut self.data_buffers[*seed_index];
+            seed.data.data[0] = 1.0;
+            gpu_handles.queue.write_buffer(
+                &seed.storage_buffer,
+                0,
+                bytemuck::cast_slice(&seed.data.data),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn submit_backward_commands(
+        gpu_handles: &GPUHandles,
+        use_cache: bool,
+        pipeline_cache: &HashMap<String, ComputePipeline>,
+        backward_nodes: &[BackwardNode],
+        data_buffers: &[Tensor2DGPU],
+        encoder: &mut CommandEncoder,
+    ) -> Result<(), GraphError> {
+        for node in backward_nodes {
+            match node.operator {
+                BackwardOperator::LinearLayer => {
+                    nodes_gpu::linear_layer_backward(
+                        gpu_handles,
+                        use_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                    )?;
+                }
+                BackwardOperator::ReLU => {
+                    nodes_gpu::relu_backward(
+                        gpu_handles,
+                        use_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                    )?;
+                }
+                BackwardOperator::Softmax => {
+                    nodes_gpu::softmax_backward(
+                        gpu_handles,
+                        use_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                    )?;
+                }
+                BackwardOperator::SoftmaxAxis { axis } => {
+                    nodes_gpu::softmax_axis_backward(
+                        gpu_handles,
+                        use_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                        axis,
+                    )?;
+                }
+                BackwardOperator::LogSoftmax => {
+                    nodes_gpu::log_softmax_backward(
+                        gpu_handles,
+                        use_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                    )?;
+                }
+                BackwardOperator::Loss { kind } => {
+                    nodes_gpu::loss_backward(
+                        gpu_handles,
+                        use_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                        kind,
+                    )?;
+                }
+                BackwardOperator::SoftmaxCrossEntropy => {
+                    nodes_gpu::softmax_cross_entropy_backward(
+                        gpu_handles,
+                        use_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                    )?;
+                }
+                BackwardOperator::Add => {
+                    nodes_gpu::add_backward(
+                        gpu_handles,
+                        use_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                    )?;
+                }
+            }
         }
-        self.graph_operators_are_valid = true;
+
+        Ok(())
     }
 
     fn submit_operator_commands(
@@ -380,8 +817,14 @@ impl GraphRunnerGPU {
         node_vector: &[NodeGPU],
         data_buffers: &[Tensor2DGPU],
         encoder: &mut CommandEncoder,
-    ) {
+        linear_layer_kernel: LinearLayerKernel,
+    ) -> Result<(), GraphError> {
         for node in node_vector {
+            if let Some(bound) = &node.bound {
+                nodes_gpu::record_bound_node(pipeline_cache, node, bound, encoder)?;
+                continue;
+            }
+
             match node.operator {
                 NodeOperatorGPU::HostToDevice => {
                     // The graph runner handles transfers itself
@@ -389,9 +832,6 @@ impl GraphRunnerGPU {
                 NodeOperatorGPU::DeviceToHost => {
                     // The graph runner handles transfers itself
                 }
-                NodeOperatorGPU::DeviceToDevice => {
-                    // The graph runner handles transfers itself
-                }
                 NodeOperatorGPU::LinearLayer => {
                     nodes_gpu::linear_layer(
                         gpu_handles,
@@ -402,7 +842,8 @@ impl GraphRunnerGPU {
                         data_buffers,
                         encoder,
                         false,
-                    );
+                        linear_layer_kernel,
+                    )?;
                 }
                 NodeOperatorGPU::ReLU => {
                     nodes_gpu::relu(
@@ -413,18 +854,17 @@ impl GraphRunnerGPU {
                         node,
                         data_buffers,
                         encoder,
-                    );
+                    )?;
                 }
                 NodeOperatorGPU::Softmax => {
                     nodes_gpu::softmax(
                         gpu_handles,
                         use_cache,
-                        shader_cache,
                         pipeline_cache,
                         node,
                         data_buffers,
                         encoder,
-                    );
+                    )?;
                 }
                 NodeOperatorGPU::LinearReLU => {
                     nodes_gpu::linear_layer(
@@ -436,7 +876,8 @@ impl GraphRunnerGPU {
                         data_buffers,
                         encoder,
                         true,
-                    );
+                        linear_layer_kernel,
+                    )?;
                 }
                 NodeOperatorGPU::LinearReLUSoftmax => {
                     nodes_gpu::linear_relu_softmax(
@@ -447,13 +888,63 @@ impl GraphRunnerGPU {
                         node,
                         data_buffers,
                         encoder,
-                    );
+                        linear_layer_kernel,
+                    )?;
+                }
+                NodeOperatorGPU::Add | NodeOperatorGPU::AddReLU => {
+                    nodes_gpu::add(
+                        gpu_handles,
+                        use_cache,
+                        shader_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                        node.operator == NodeOperatorGPU::AddReLU,
+                    )?;
+                }
+                NodeOperatorGPU::Concat { axis } => {
+                    nodes_gpu::concat(node, data_buffers, encoder, axis)?;
+                }
+                NodeOperatorGPU::SoftmaxAxis { axis } => {
+                    nodes_gpu::softmax_axis(
+                        gpu_handles,
+                        use_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                        axis,
+                    )?;
+                }
+                NodeOperatorGPU::LogSoftmax => {
+                    nodes_gpu::log_softmax(
+                        gpu_handles,
+                        use_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                    )?;
+                }
+                NodeOperatorGPU::Loss { kind } => {
+                    nodes_gpu::loss(
+                        gpu_handles,
+                        use_cache,
+                        pipeline_cache,
+                        node,
+                        data_buffers,
+                        encoder,
+                        kind,
+                    )?;
                 }
             }
         }
+
+        Ok(())
     }
 
-    fn submit_operations(&mut self, gpu_handles: &GPUHandles) {
+    fn submit_operations(&mut self, gpu_handles: &GPUHandles) -> Result<(), GraphError> {
         let mut encoder: CommandEncoder = gpu_handles
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
@@ -467,39 +958,582 @@ impl GraphRunnerGPU {
                 &self.nodes,
                 &self.data_buffers,
                 &mut encoder,
-            );
+                self.linear_layer_kernel,
+            )?;
 
             // Submit commands
             gpu_handles.queue.submit(Some(encoder.finish()));
         }
+
+        Ok(())
     }
 
-    async fn retrieve_output(&mut self, gpu_handles: &GPUHandles) -> Tensor2D {
-        // Transfer result back
-        let last_index: usize = self.data_buffers.len() - 1;
-        let output: &mut Tensor2DGPU = &mut self.data_buffers[last_index];
-        let buffer_slice: BufferSlice = output.staging_buffer.slice(..);
-        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
-        buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
-        output.receiver = Some(receiver);
+    // Copies the buffers from their storage buffers to their staging buffers,
+    // maps the staging buffers and waits for the results, which end up in
+    // the data of each Tensor2DGPU.
+    async fn retrieve_buffers(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        mut buffer_indices: Vec<usize>,
+    ) -> Result<(), GraphError> {
+        // Several outputs can read from the same buffer, but it only needs to be transferred once.
+        buffer_indices.sort_unstable();
+        buffer_indices.dedup();
+
+        let mut encoder: CommandEncoder = gpu_handles
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for buffer_index in &buffer_indices {
+            self.data_buffers[*buffer_index].copy_from_gpu_mut(&mut encoder);
+        }
+        gpu_handles.queue.submit(Some(encoder.finish()));
+
+        for buffer_index in &buffer_indices {
+            let output: &mut Tensor2DGPU = &mut self.data_buffers[*buffer_index];
+            let buffer_slice: BufferSlice = output.staging_buffer.slice(..);
+            let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+            buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+            output.receiver = Some(receiver);
+        }
 
         gpu_handles.device.poll(wgpu::Maintain::Wait);
 
-        if output.live_data_on_device {
-            output.retrieve_results().await;
+        for buffer_index in &buffer_indices {
+            let output: &mut Tensor2DGPU = &mut self.data_buffers[*buffer_index];
+            if output.live_data_on_device {
+                output.try_retrieve_results().await.map_err(|error| {
+                    GraphError::BufferMapFailure(format!(
+                        "Failed to map the staging buffer of the output: {}",
+                        error
+                    ))
+                })?;
+            }
         }
 
-        output.data.clone()
+        Ok(())
     }
 
-    pub async fn run(&mut self, gpu_handles: &GPUHandles, iteration_count: usize) -> Tensor2D {
-        if !self.graph_operators_are_valid {
-            panic!("Failed to validate the computational graph!");
+    async fn retrieve_outputs(
+        &mut self,
+        gpu_handles: &GPUHandles,
+    ) -> Result<Vec<(String, Tensor2D)>, GraphError> {
+        let buffer_indices: Vec<usize> = self
+            .outputs
+            .iter()
+            .map(|(_, buffer_index)| *buffer_index)
+            .collect();
+        self.retrieve_buffers(gpu_handles, buffer_indices).await?;
+
+        Ok(self
+            .outputs
+            .iter()
+            .map(|(name, buffer_index)| {
+                (name.clone(), self.data_buffers[*buffer_index].data.clone())
+            })
+            .collect())
+    }
+
+    // Runs the graph iteration_count times without reading anything back,
+    // the outputs can then be read with get_output
+    pub fn submit(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        iteration_count: usize,
+    ) -> Result<(), GraphError> {
+        if !self.graph_operators_are_valid || !self.nodes_are_valid || !self.data_buffers_are_valid
+        {
+            return Err(GraphError::UnsupportedOperator(
+                "Tried to run a GPU computational graph which has not been validated".to_string(),
+            ));
         }
 
         for _ in 0..iteration_count {
-            self.submit_operations(gpu_handles);
+            self.submit_operations(gpu_handles)?;
+        }
+        self.forward_has_run = true;
+
+        Ok(())
+    }
+
+    async fn execute(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        iteration_count: usize,
+    ) -> Result<Vec<(String, Tensor2D)>, GraphError> {
+        self.submit(gpu_handles, iteration_count)?;
+        self.retrieve_outputs(gpu_handles).await
+    }
+
+    fn record_node(
+        &self,
+        gpu_handles: &GPUHandles,
+        node_index: usize,
+        encoder: &mut CommandEncoder,
+    ) -> Result<(), GraphError> {
+        Self::submit_operator_commands(
+            gpu_handles,
+            self.use_cache,
+            &self.shader_cache,
+            &self.pipeline_cache,
+            &self.nodes[node_index..(node_index + 1)],
+            &self.data_buffers,
+            encoder,
+            self.linear_layer_kernel,
+        )
+    }
+
+    // Runs the graph once, like submit, while timing every node which records any commands.
+    // The outputs can be read with get_output afterwards.
+    // Uses timestamps written by the GPU if the device has TIMESTAMP_QUERY, otherwise
+    // every node is submitted and waited for on its own, see TimingSource.
+    pub async fn time_nodes(
+        &mut self,
+        gpu_handles: &GPUHandles,
+    ) -> Result<NodeTimingReport, GraphError> {
+        if !self.graph_operators_are_valid || !self.nodes_are_valid || !self.data_buffers_are_valid
+        {
+            return Err(GraphError::UnsupportedOperator(
+                "Tried to time a GPU computational graph which has not been validated".to_string(),
+            ));
+        }
+
+        // The transfers are handled by the graph runner and don't record anything
+        let timed_nodes: Vec<usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| {
+                !matches!(
+                    node.operator,
+                    NodeOperatorGPU::HostToDevice | NodeOperatorGPU::DeviceToHost
+                )
+            })
+            .map(|(node_index, _)| node_index)
+            .collect();
+
+        // A timestamp before the first node and one after every node
+        let source: TimingSource = TimingSource::for_device(gpu_handles, timed_nodes.len() + 1);
+        let durations: Vec<Duration> = match source {
+            TimingSource::GpuTimestamps => {
+                let queries: TimestampQueries =
+                    TimestampQueries::new(gpu_handles, timed_nodes.len() + 1);
+                let mut encoder: CommandEncoder = gpu_handles
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                queries.write(&mut encoder, 0);
+                for (timestamp_index, node_index) in timed_nodes.iter().enumerate() {
+                    self.record_node(gpu_handles, *node_index, &mut encoder)?;
+                    queries.write(&mut encoder, timestamp_index + 1);
+                }
+                queries.resolve(&mut encoder);
+                gpu_handles.queue.submit(Some(encoder.finish()));
+
+                queries.durations(gpu_handles).await?
+            }
+            TimingSource::CpuTimers => {
+                let mut durations: Vec<Duration> = Vec::<Duration>::new();
+                for node_index in &timed_nodes {
+                    let now: Instant = Instant::now();
+                    let mut encoder: CommandEncoder = gpu_handles
+                        .device
+                        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                    self.record_node(gpu_handles, *node_index, &mut encoder)?;
+                    gpu_handles.queue.submit(Some(encoder.finish()));
+                    gpu_handles.device.poll(wgpu::Maintain::Wait);
+                    durations.push(now.elapsed());
+                }
+
+                durations
+            }
+        };
+        self.forward_has_run = true;
+
+        let nodes: Vec<NodeTiming> = timed_nodes
+            .iter()
+            .zip(durations)
+            .map(|(node_index, duration)| NodeTiming {
+                name: self.nodes[*node_index].name.clone(),
+                operator: format!("{:?}", self.nodes[*node_index].operator),
+                duration,
+            })
+            .collect();
+
+        Ok(NodeTimingReport { source, nodes })
+    }
+
+    // Computes the gradient of the sum of the losses with respect to
+    // every weight, bias and graph input, using the results of the last run.
+    // The gradients stay on the GPU, use gradients to read them back.
+    pub fn backward(&mut self, gpu_handles: &GPUHandles) -> Result<(), GraphError> {
+        if !self.training {
+            return Err(GraphError::UnsupportedOperator(
+                "Tried to run backward on a GPU graph which was not built for training".to_string(),
+            ));
         }
-        self.retrieve_output(gpu_handles).await
+        if !self.forward_has_run {
+            return Err(GraphError::UnsupportedOperator(
+                "The graph has to be run before backward can be run".to_string(),
+            ));
+        }
+
+        let mut encoder: CommandEncoder =
+            gpu_handles
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Backward"),
+                });
+        for gradient_index in self.backward_plan.accumulated_indices() {
+            encoder.clear_buffer(&self.data_buffers[gradient_index].storage_buffer, 0, None);
+        }
+        Self::submit_backward_commands(
+            gpu_handles,
+            self.use_cache,
+            &self.pipeline_cache,
+            &self.backward_plan.nodes,
+            &self.data_buffers,
+            &mut encoder,
+        )?;
+        gpu_handles.queue.submit(Some(encoder.finish()));
+
+        Ok(())
+    }
+
+    // Updates every weight and bias with the gradients from the last backward,
+    // without any of them leaving the GPU
+    pub fn step(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        optimizer: &mut dyn Optimizer,
+    ) -> Result<(), GraphError> {
+        if !self.training {
+            return Err(GraphError::UnsupportedOperator(
+                "Tried to step a GPU graph which was not built for training".to_string(),
+            ));
+        }
+
+        let mut encoder: CommandEncoder =
+            gpu_handles
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Optimizer"),
+                });
+        optimizer.step_gpu(
+            gpu_handles,
+            &self.backward_plan.parameters,
+            &self.data_buffers,
+            &mut encoder,
+        )?;
+        gpu_handles.queue.submit(Some(encoder.finish()));
+
+        Ok(())
+    }
+
+    // Every weight and bias in the graph, empty unless built for training
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.backward_plan.parameters
+    }
+
+    // Reads back the gradients computed by the last backward,
+    // named the same way as GraphRunner::gradients
+    pub async fn gradients(
+        &mut self,
+        gpu_handles: &GPUHandles,
+    ) -> Result<HashMap<String, Tensor2D>, GraphError> {
+        let mut named_gradients: Vec<(String, usize)> = self
+            .backward_plan
+            .parameters
+            .iter()
+            .map(|parameter| (parameter.name(), parameter.gradient_index))
+            .collect();
+        for node in &self.nodes {
+            if node.operator != NodeOperatorGPU::HostToDevice {
+                continue;
+            }
+            if let Some(gradient_index) = self
+                .backward_plan
+                .gradient_indices
+                .get(&node.buffer_indices[0])
+            {
+                named_gradients.push((node.name.clone(), *gradient_index));
+            }
+        }
+
+        let buffer_indices: Vec<usize> = named_gradients
+            .iter()
+            .map(|(_, buffer_index)| *buffer_index)
+            .collect();
+        self.retrieve_buffers(gpu_handles, buffer_indices).await?;
+
+        Ok(named_gradients
+            .into_iter()
+            .map(|(name, buffer_index)| (name, self.data_buffers[buffer_index].data.clone()))
+            .collect())
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        self.memory_report
+    }
+
+    // Which nodes were fused, empty if fusion was turned off
+    pub fn fusion_report(&self) -> &FusionReport {
+        &self.fusion_report
+    }
+
+    // Returns the first output of the graph, which for a sequential graph is the only one
+    pub async fn run(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        iteration_count: usize,
+    ) -> Result<Tensor2D, GraphError> {
+        let mut outputs: Vec<(String, Tensor2D)> =
+            self.execute(gpu_handles, iteration_count).await?;
+
+        if outputs.is_empty() {
+            return Err(GraphError::MisplacedTransfer(
+                "The graph has no DeviceToHost node to read the output from".to_string(),
+            ));
+        }
+        Ok(outputs.swap_remove(0).1)
+    }
+
+    // The buffer of the HostToDevice node, for the graphs which have exactly one
+    fn input_index(&self) -> Result<usize, GraphError> {
+        let inputs: Vec<&NodeGPU> = self
+            .nodes
+            .iter()
+            .filter(|node| node.operator == NodeOperatorGPU::HostToDevice)
+            .collect();
+        match inputs[..] {
+            [node] => Ok(node.buffer_indices[0]),
+            _ => Err(GraphError::UnsupportedOperator(
+                "New input can only be given to a graph with exactly one HostToDevice node"
+                    .to_string(),
+            )),
+        }
+    }
+
+    fn named_input_index(&self, name: &str) -> Result<usize, GraphError> {
+        self.nodes
+            .iter()
+            .find(|node| node.operator == NodeOperatorGPU::HostToDevice && node.name == name)
+            .map(|node| node.buffer_indices[0])
+            .ok_or_else(|| {
+                GraphError::UnknownNode(format!(
+                    "The graph has no HostToDevice node named {}",
+                    name
+                ))
+            })
+    }
+
+    // The write is queued and happens before the next submitted commands,
+    // so the input buffer, the bind groups and the pipelines are all kept.
+    fn write_input(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        input_index: usize,
+        input: &Tensor2D,
+    ) -> Result<(), GraphError> {
+        let buffer: &mut Tensor2DGPU = &mut self.data_buffers[input_index];
+        if buffer.row_count != input.row_count || buffer.column_count != input.column_count {
+            return Err(GraphError::DimensionMismatch(format!(
+                "The input has {} rows and {} columns, but the graph was built for {} rows and {} columns",
+                input.row_count, input.column_count, buffer.row_count, buffer.column_count
+            )));
+        }
+        gpu_handles.queue.write_buffer(
+            &buffer.storage_buffer,
+            0,
+            bytemuck::cast_slice(&input.data),
+        );
+        buffer.data.data.copy_from_slice(&input.data);
+
+        // The intermediates kept for backward belong to the old input
+        self.forward_has_run = false;
+
+        Ok(())
+    }
+
+    // Replaces the input of the HostToDevice node called name, which keeps
+    // being used by every run after it. Nothing is rebuilt.
+    pub fn set_input(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        name: &str,
+        input: &Tensor2D,
+    ) -> Result<(), GraphError> {
+        let input_index: usize = self.named_input_index(name)?;
+        self.write_input(gpu_handles, input_index, input)
+    }
+
+    // Reads back the output of the DeviceToHost node called name from the last run
+    pub async fn get_output(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        name: &str,
+    ) -> Result<Tensor2D, GraphError> {
+        let buffer_index: usize = match self.outputs.iter().find(|(output, _)| output == name) {
+            Some((_, buffer_index)) => *buffer_index,
+            None => {
+                return Err(GraphError::UnknownNode(format!(
+                    "The graph has no DeviceToHost node named {}",
+                    name
+                )))
+            }
+        };
+        if !self.forward_has_run {
+            return Err(GraphError::UnsupportedOperator(
+                "The graph has to be run with its current input before reading an output"
+                    .to_string(),
+            ));
+        }
+
+        self.retrieve_buffers(gpu_handles, vec![buffer_index])
+            .await?;
+        Ok(self.data_buffers[buffer_index].data.clone())
+    }
+
+    // Same as run with an iteration_count of 1, but with the input
+    // of the graph replaced by input first. Nothing is rebuilt.
+    pub async fn run_with_input(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        input: &Tensor2D,
+    ) -> Result<Tensor2D, GraphError> {
+        let input_index: usize = self.input_index()?;
+        self.write_input(gpu_handles, input_index, input)?;
+        self.run(gpu_handles, 1).await
+    }
+
+    // How many staging buffers submit_with_readback rotates through, 2 unless set.
+    // Can't be changed while a read back is waiting to be received.
+    pub fn set_readback_slot_count(&mut self, slot_count: usize) -> Result<(), GraphError> {
+        if slot_count == 0 {
+            return Err(GraphError::UnsupportedOperator(
+                "The readback ring needs at least one staging buffer".to_string(),
+            ));
+        }
+        if let Some(readback_ring) = &self.readback_ring {
+            if 0 < readback_ring.in_flight_count() {
+                return Err(GraphError::UnsupportedOperator(
+                    "Tried to resize the readback ring while read backs are in flight".to_string(),
+                ));
+            }
+        }
+        self.readback_slot_count = slot_count;
+        self.readback_ring = None;
+
+        Ok(())
+    }
+
+    // Runs the graph once, like submit, and starts reading back the first output of the graph
+    // without waiting for it. Receive it with receive_output, until then the next iteration
+    // can be submitted, as long as the readback ring has a free staging buffer.
+    pub fn submit_with_readback(
+        &mut self,
+        gpu_handles: &GPUHandles,
+    ) -> Result<ReadbackHandle, GraphError> {
+        let output_index: usize = match self.outputs.first() {
+            Some((_, buffer_index)) => *buffer_index,
+            None => {
+                return Err(GraphError::MisplacedTransfer(
+                    "The graph has no DeviceToHost node to read the output from".to_string(),
+                ))
+            }
+        };
+
+        let output: &Tensor2DGPU = &self.data_buffers[output_index];
+        let readback_ring: &mut ReadbackRing = self.readback_ring.get_or_insert_with(|| {
+            ReadbackRing::new(
+                gpu_handles,
+                "Graph Output",
+                output.row_count,
+                output.column_count,
+                self.readback_slot_count,
+            )
+        });
+        // Checked before running the graph, so a full ring doesn't cost an iteration
+        if readback_ring.in_flight_count() == readback_ring.slot_count() {
+            return Err(GraphError::UnsupportedOperator(format!(
+                "All {} staging buffers of the readback ring are in flight, receive one of them first",
+                readback_ring.slot_count()
+            )));
+        }
+
+        self.submit(gpu_handles, 1)?;
+        self.readback_ring
+            .as_mut()
+            .expect("The readback ring was made above")
+            .read_back(gpu_handles, &self.data_buffers[output_index])
+    }
+
+    // Same as submit_with_readback, but with the input of the graph replaced by input first
+    pub fn submit_with_input(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        input: &Tensor2D,
+    ) -> Result<ReadbackHandle, GraphError> {
+        let input_index: usize = self.input_index()?;
+        self.write_input(gpu_handles, input_index, input)?;
+        self.submit_with_readback(gpu_handles)
+    }
+
+    fn readback_ring(&mut self) -> Result<&mut ReadbackRing, GraphError> {
+        self.readback_ring.as_mut().ok_or_else(|| {
+            GraphError::UnsupportedOperator(
+                "Nothing has been read back with submit_with_readback".to_string(),
+            )
+        })
+    }
+
+    // Whether the output of handle can be received without waiting. Never blocks.
+    pub fn output_ready(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        handle: &ReadbackHandle,
+    ) -> Result<bool, GraphError> {
+        self.readback_ring()?.is_ready(gpu_handles, handle)
+    }
+
+    // The output of the iteration which returned handle. Only waits for that iteration,
+    // the ones submitted after it keep running. Yields to the executor while waiting
+    // instead of blocking it, see ReadbackRing::receive.
+    pub async fn receive_output(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        handle: &ReadbackHandle,
+    ) -> Result<Tensor2D, GraphError> {
+        self.readback_ring()?.receive(gpu_handles, handle).await
+    }
+
+    // Runs up to batch_size inputs of a graph built with a batch_size in one submission
+    // and returns the first output of the graph for each of them
+    pub async fn run_batch(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        inputs: &[Tensor2D],
+    ) -> Result<Vec<Tensor2D>, GraphError> {
+        let input: &Tensor2DGPU = &self.data_buffers[self.input_index()?];
+        let stacked: Tensor2D = stack_rows(
+            inputs,
+            input.row_count / self.batch_size,
+            input.column_count,
+            self.batch_size,
+        )?;
+
+        let output: Tensor2D = self.run_with_input(gpu_handles, &stacked).await?;
+        Ok(split_rows(&output, self.batch_size, inputs.len()))
+    }
+
+    // Returns every output of the graph by the name of its DeviceToHost node
+    pub async fn run_all(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        iteration_count: usize,
+    ) -> Result<HashMap<String, Tensor2D>, GraphError> {
+        Ok(self
+            .execute(gpu_handles, iteration_count)
+            .await?
+            .into_iter()
+            .collect())
     }
 }
@@ -1,16 +1,60 @@
+// The tests which use initialize_gpu want a hardware adapter, the rest run on the
+// software adapter. Without a GPU, like in CI, set WGPU_FORCE_FALLBACK_ADAPTER=1
+// to run every test on the software adapter.
 #[cfg(test)]
 mod tests {
+    use std::collections::{HashMap, VecDeque};
+
+    use wgpu::Features;
+
     use crate::{
-        graph::graph_runner_gpu::GraphRunnerGPU,
+        graph::{
+            graph_dag::GraphDAG,
+            graph_error::GraphError,
+            graph_runner::GraphRunner,
+            graph_runner_gpu::{GraphRunnerGPU, GraphRunnerGPUOptions},
+            node_timing::{NodeTimingReport, TimingSource},
+            optimizer::{Adam, Optimizer, Sgd},
+            readback_ring::ReadbackHandle,
+        },
         shared::{
-            gpu_utilities::{initialize_gpu, GPUHandles},
-            graph_operators::GraphOperator,
+            gpu_utilities::{initialize_fallback_gpu, initialize_gpu, GPUHandles},
+            graph_operators::{GraphOperator, LossKind},
             tensor2d::Tensor2D,
+            tensor2d_gpu::LinearLayerKernel,
         },
     };
 
     const ERROR_TOLERANCE: f32 = 0.00001;
 
+    // Uncached, cached and with bound nodes, which should all give the same results
+    const RUNNER_OPTIONS: [GraphRunnerGPUOptions; 3] = [
+        GraphRunnerGPUOptions {
+            fuse_operators: false,
+            use_cache: false,
+            training: false,
+            bind_once: false,
+            linear_layer_kernel: LinearLayerKernel::Naive,
+            batch_size: 1,
+        },
+        GraphRunnerGPUOptions {
+            fuse_operators: false,
+            use_cache: true,
+            training: false,
+            bind_once: false,
+            linear_layer_kernel: LinearLayerKernel::Naive,
+            batch_size: 1,
+        },
+        GraphRunnerGPUOptions {
+            fuse_operators: false,
+            use_cache: true,
+            training: false,
+            bind_once: true,
+            linear_layer_kernel: LinearLayerKernel::Naive,
+            batch_size: 1,
+        },
+    ];
+
     // This is for verification purposes only
     // we don't care about making this fast
     fn subtract_tensors(left: &Tensor2D, right: &Tensor2D) -> Tensor2D {
@@ -65,10 +109,15 @@ mod tests {
                     let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
                         &gpu_handles,
                         &graph_operators,
-                        fuse_operators,
-                        cache_elements,
-                    );
-                    let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1));
+                        &GraphRunnerGPUOptions {
+                            fuse_operators,
+                            use_cache: cache_elements,
+                            ..Default::default()
+                        },
+                    )
+                    .unwrap();
+                    let output: Tensor2D =
+                        pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
 
                     let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
                     println!("{:?}", difference);
@@ -103,10 +152,15 @@ mod tests {
                 let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
                     &gpu_handles,
                     &graph_operators,
-                    fuse_operators,
-                    cache_elements,
-                );
-                let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1));
+                    &GraphRunnerGPUOptions {
+                        fuse_operators,
+                        use_cache: cache_elements,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                let output: Tensor2D =
+                    pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
 
                 let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
                 println!("{:?}", difference);
@@ -131,7 +185,7 @@ mod tests {
 
                 let graph_operators: Vec<GraphOperator> = vec![
                     GraphOperator::HostToDevice { input },
-                    GraphOperator::Softmax,
+                    GraphOperator::Softmax { axis: None },
                     GraphOperator::DeviceToHost,
                 ];
 
@@ -140,10 +194,15 @@ mod tests {
                 let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
                     &gpu_handles,
                     &graph_operators,
-                    fuse_operators,
-                    cache_elements,
-                );
-                let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1));
+                    &GraphRunnerGPUOptions {
+                        fuse_operators,
+                        use_cache: cache_elements,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                let output: Tensor2D =
+                    pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
 
                 let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
                 println!("{:?}", difference);
@@ -152,6 +211,575 @@ mod tests {
         }
     }
 
+    // Large enough for the reductions to use every workgroup, checked on the software adapter
+    #[test]
+    fn softmax_multi_workgroup() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu()).expect(
+            "Failed to get GPU handles in graph_runner_test::softmax_multi_workgroup() test",
+        );
+
+        let mut input: Tensor2D = Tensor2D::new(0.0, 300, 250);
+        for (index, value) in input.data.iter_mut().enumerate() {
+            *value = (index as f32 * 1.7).sin() * 4.0;
+        }
+        let output_cpu: Tensor2D = Tensor2D::softmax(&input);
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice { input },
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::DeviceToHost,
+        ];
+
+        let mut graph_runners: Vec<GraphRunnerGPU> = RUNNER_OPTIONS
+            .iter()
+            .map(|options| GraphRunnerGPU::new(&gpu_handles, &graph_operators, options).unwrap())
+            .collect();
+        for graph_runner in &mut graph_runners {
+            let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+            assert!((1.0 - output.sum()).abs() < 0.001);
+            let difference: Tensor2D = subtract_tensors(&output_cpu, &output);
+            assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+        }
+    }
+
+    // The tiled linear layer kernel, fused and unfused, matches the CPU graph
+    #[test]
+    fn linear_layer_tiled() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::linear_layer_tiled() test");
+
+        let mut input: Tensor2D = Tensor2D::new(0.0, 45, 70);
+        for (index, value) in input.data.iter_mut().enumerate() {
+            *value = (index as f32 * 0.7).sin();
+        }
+        let mut weights: Tensor2D = Tensor2D::new(0.0, 70, 33);
+        for (index, value) in weights.data.iter_mut().enumerate() {
+            *value = (index as f32 * 1.3).cos() * 0.1;
+        }
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice { input },
+            GraphOperator::LinearLayer {
+                weights,
+                bias: Tensor2D::new(0.1, 45, 33),
+            },
+            GraphOperator::ReLU,
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::DeviceToHost,
+        ];
+        let output_cpu: Tensor2D = GraphRunner::new(&graph_operators, false)
+            .unwrap()
+            .run()
+            .unwrap();
+
+        for (fuse_operators, use_cache) in [(false, false), (false, true), (true, true)] {
+            let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+                &gpu_handles,
+                &graph_operators,
+                &GraphRunnerGPUOptions {
+                    fuse_operators,
+                    use_cache,
+                    linear_layer_kernel: LinearLayerKernel::Tiled,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+            let difference: Tensor2D = subtract_tensors(&output_cpu, &output);
+            assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+        }
+    }
+
+    // Limits small enough that every node has to be split in to several chunks,
+    // which is the same thing a tensor larger than a real device allows goes through
+    fn chunking_gpu_handles(test_name: &str) -> GPUHandles {
+        let mut gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .unwrap_or_else(|| {
+                panic!(
+                    "Failed to get GPU handles in graph_runner_test::{}() test",
+                    test_name
+                )
+            });
+        gpu_handles.limits.max_storage_buffer_binding_size = 4096;
+        gpu_handles.limits.max_compute_workgroups_per_dimension = 4;
+        gpu_handles
+    }
+
+    #[test]
+    fn chunked_dispatches() {
+        let gpu_handles: GPUHandles = chunking_gpu_handles("chunked_dispatches");
+
+        let mut input: Tensor2D = Tensor2D::new(0.0, 100, 12);
+        for (index, value) in input.data.iter_mut().enumerate() {
+            *value = (index as f32 * 0.7).sin();
+        }
+        let mut weights: Tensor2D = Tensor2D::new(0.0, 12, 20);
+        for (index, value) in weights.data.iter_mut().enumerate() {
+            *value = (index as f32 * 1.3).cos();
+        }
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice { input },
+            GraphOperator::LinearLayer {
+                weights,
+                bias: Tensor2D::new(0.01, 100, 20),
+            },
+            GraphOperator::ReLU,
+            GraphOperator::DeviceToHost,
+        ];
+        let output_cpu: Tensor2D = GraphRunner::new(&graph_operators, false)
+            .unwrap()
+            .run()
+            .unwrap();
+
+        for kernel in [LinearLayerKernel::Naive, LinearLayerKernel::Tiled] {
+            for (fuse_operators, use_cache) in [(false, false), (false, true), (true, true)] {
+                let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+                    &gpu_handles,
+                    &graph_operators,
+                    &GraphRunnerGPUOptions {
+                        fuse_operators,
+                        use_cache,
+                        linear_layer_kernel: kernel,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                let output: Tensor2D =
+                    pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+                let difference: Tensor2D = subtract_tensors(&output_cpu, &output);
+                assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+            }
+        }
+        let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+            &gpu_handles,
+            &graph_operators,
+            &GraphRunnerGPUOptions {
+                use_cache: true,
+                bind_once: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+        let difference: Tensor2D = subtract_tensors(&output_cpu, &output);
+        assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+
+        // The softmax reductions need all of their input in one binding, but the map is split
+        let mut input: Tensor2D = Tensor2D::new(0.0, 30, 20);
+        for (index, value) in input.data.iter_mut().enumerate() {
+            *value = (index as f32 * 1.7).sin() * 4.0;
+        }
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node("left", GraphOperator::HostToDevice { input }, &[]);
+        graph.add_node(
+            "right",
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.001, 30, 20),
+            },
+            &[],
+        );
+        graph.add_node("sum", GraphOperator::Add, &["left", "right"]);
+        graph.add_node("softmax", GraphOperator::Softmax { axis: None }, &["sum"]);
+        graph.add_node("output", GraphOperator::DeviceToHost, &["softmax"]);
+        let expected: HashMap<String, Tensor2D> = GraphRunner::from_dag(&graph, false)
+            .unwrap()
+            .run_all()
+            .unwrap();
+
+        let mut graph_runners: Vec<GraphRunnerGPU> = RUNNER_OPTIONS
+            .iter()
+            .map(|options| GraphRunnerGPU::from_dag(&gpu_handles, &graph, options).unwrap())
+            .collect();
+        for graph_runner in &mut graph_runners {
+            let outputs: HashMap<String, Tensor2D> =
+                pollster::block_on(graph_runner.run_all(&gpu_handles, 1)).unwrap();
+            let difference: Tensor2D = subtract_tensors(&expected["output"], &outputs["output"]);
+            assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+        }
+    }
+
+    #[test]
+    fn chunked_training() {
+        let mut gpu_handles: GPUHandles = chunking_gpu_handles("chunked_training");
+
+        // Softmax along axis 1 splits its rows, two rows at a time to keep the chunks aligned.
+        // The backward nodes and the optimizer steps are split like the elementwise nodes.
+        let mut input: Tensor2D = Tensor2D::new(0.0, 24, 8);
+        for (index, value) in input.data.iter_mut().enumerate() {
+            *value = (index as f32 * 0.7).sin();
+        }
+        let mut weights: Tensor2D = Tensor2D::new(0.0, 8, 32);
+        for (index, value) in weights.data.iter_mut().enumerate() {
+            *value = (index as f32 * 1.3).cos() * 0.1;
+        }
+        let mut target: Tensor2D = Tensor2D::new(0.0, 24, 32);
+        for row in 0..24 {
+            target.data[row * 32 + row % 32] = 1.0;
+        }
+        let linear_layer: GraphOperator = GraphOperator::LinearLayer {
+            weights,
+            bias: Tensor2D::new(0.02, 24, 32),
+        };
+        let cross_entropy: GraphOperator = GraphOperator::Loss {
+            kind: LossKind::CrossEntropy,
+            target: target.clone(),
+        };
+        let graphs: Vec<Vec<GraphOperator>> = vec![
+            vec![
+                GraphOperator::HostToDevice {
+                    input: input.clone(),
+                },
+                linear_layer.clone(),
+                GraphOperator::Softmax { axis: Some(1) },
+                cross_entropy.clone(),
+                GraphOperator::DeviceToHost,
+            ],
+            vec![
+                GraphOperator::HostToDevice {
+                    input: input.clone(),
+                },
+                linear_layer.clone(),
+                GraphOperator::ReLU,
+                GraphOperator::Softmax { axis: None },
+                cross_entropy,
+                GraphOperator::DeviceToHost,
+            ],
+            vec![
+                GraphOperator::HostToDevice {
+                    input: input.clone(),
+                },
+                linear_layer.clone(),
+                GraphOperator::LogSoftmax,
+                GraphOperator::Loss {
+                    kind: LossKind::NegativeLogLikelihood,
+                    target,
+                },
+                GraphOperator::DeviceToHost,
+            ],
+            vec![
+                GraphOperator::HostToDevice { input },
+                linear_layer,
+                GraphOperator::Loss {
+                    kind: LossKind::MeanSquaredError,
+                    target: Tensor2D::new(0.1, 24, 32),
+                },
+                GraphOperator::DeviceToHost,
+            ],
+        ];
+        let training_options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+            use_cache: true,
+            training: true,
+            ..Default::default()
+        };
+
+        for graph_operators in &graphs {
+            let mut graph_runner: GraphRunner = GraphRunner::for_training(graph_operators).unwrap();
+            let loss_cpu: Tensor2D = graph_runner.run().unwrap();
+            graph_runner.backward().unwrap();
+            let gradients_cpu: HashMap<String, Tensor2D> = graph_runner.gradients();
+
+            for options in &RUNNER_OPTIONS {
+                let mut graph_runner: GraphRunnerGPU =
+                    GraphRunnerGPU::new(&gpu_handles, graph_operators, options).unwrap();
+                let loss: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+                assert!((loss_cpu.data[0] - loss.data[0]).abs() < 0.0001);
+            }
+
+            let mut graph_runner: GraphRunnerGPU =
+                GraphRunnerGPU::new(&gpu_handles, graph_operators, &training_options).unwrap();
+            pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+            graph_runner.backward(&gpu_handles).unwrap();
+            let gradients: HashMap<String, Tensor2D> =
+                pollster::block_on(graph_runner.gradients(&gpu_handles)).unwrap();
+            for (name, gradient_cpu) in &gradients_cpu {
+                let difference: Tensor2D = subtract_tensors(gradient_cpu, &gradients[name]);
+                assert!(difference.data.iter().all(|x| x.abs() < 0.0001), "{}", name);
+            }
+        }
+
+        let make_optimizers = || -> Vec<Box<dyn Optimizer>> {
+            vec![
+                Box::new(Sgd::new(0.5)),
+                Box::new(Sgd::with_momentum(0.2, 0.9)),
+                Box::new(Adam::new(0.05)),
+            ]
+        };
+        for (mut optimizer_cpu, mut optimizer_gpu) in
+            make_optimizers().into_iter().zip(make_optimizers())
+        {
+            let mut graph_runner_cpu: GraphRunner = GraphRunner::for_training(&graphs[0]).unwrap();
+            let mut graph_runner_gpu: GraphRunnerGPU =
+                GraphRunnerGPU::new(&gpu_handles, &graphs[0], &training_options).unwrap();
+            for _ in 0..3 {
+                let loss_cpu: Tensor2D = graph_runner_cpu.run().unwrap();
+                let loss_gpu: Tensor2D =
+                    pollster::block_on(graph_runner_gpu.run(&gpu_handles, 1)).unwrap();
+                assert!((loss_cpu.data[0] - loss_gpu.data[0]).abs() < 0.0001);
+
+                graph_runner_cpu.backward().unwrap();
+                graph_runner_cpu.step(optimizer_cpu.as_mut()).unwrap();
+                graph_runner_gpu.backward(&gpu_handles).unwrap();
+                graph_runner_gpu
+                    .step(&gpu_handles, optimizer_gpu.as_mut())
+                    .unwrap();
+            }
+        }
+
+        // Softmax along axis 0 splits its columns, which takes more than 4 workgroups
+        // to keep the chunks aligned
+        gpu_handles.limits.max_storage_buffer_binding_size = 8192;
+        gpu_handles.limits.max_compute_workgroups_per_dimension = 64;
+        let mut target: Tensor2D = Tensor2D::new(0.0, 10, 130);
+        for column in 0..130 {
+            target.data[(column % 10) * 130 + column] = 1.0;
+        }
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.1, 10, 8),
+            },
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(-0.05, 8, 130),
+                bias: Tensor2D::new(0.02, 10, 130),
+            },
+            GraphOperator::Softmax { axis: Some(0) },
+            GraphOperator::Loss {
+                kind: LossKind::CrossEntropy,
+                target,
+            },
+            GraphOperator::DeviceToHost,
+        ];
+        let mut graph_runner: GraphRunner = GraphRunner::for_training(&graph_operators).unwrap();
+        let loss_cpu: Tensor2D = graph_runner.run().unwrap();
+        graph_runner.backward().unwrap();
+        let gradients_cpu: HashMap<String, Tensor2D> = graph_runner.gradients();
+        for options in &RUNNER_OPTIONS {
+            let mut graph_runner: GraphRunnerGPU =
+                GraphRunnerGPU::new(&gpu_handles, &graph_operators, options).unwrap();
+            let loss: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+            // The loss sums over every column, so it is compared relative to its size
+            assert!(
+                (loss_cpu.data[0] - loss.data[0]).abs() < loss_cpu.data[0].abs() * ERROR_TOLERANCE
+            );
+        }
+        let mut graph_runner: GraphRunnerGPU =
+            GraphRunnerGPU::new(&gpu_handles, &graph_operators, &training_options).unwrap();
+        pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+        graph_runner.backward(&gpu_handles).unwrap();
+        let gradients: HashMap<String, Tensor2D> =
+            pollster::block_on(graph_runner.gradients(&gpu_handles)).unwrap();
+        for (name, gradient_cpu) in &gradients_cpu {
+            let difference: Tensor2D = subtract_tensors(gradient_cpu, &gradients[name]);
+            assert!(difference.data.iter().all(|x| x.abs() < 0.0001), "{}", name);
+        }
+    }
+
+    #[test]
+    fn limit_exceeded() {
+        let mut gpu_handles: GPUHandles = chunking_gpu_handles("limit_exceeded");
+
+        // 100 x 20 is more than the softmax reductions can bind at once
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.1, 100, 20),
+            },
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::DeviceToHost,
+        ];
+        let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+            &gpu_handles,
+            &graph_operators,
+            &GraphRunnerGPUOptions {
+                use_cache: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(matches!(
+            pollster::block_on(graph_runner.run(&gpu_handles, 1)),
+            Err(GraphError::LimitExceeded(_))
+        ));
+        assert!(matches!(
+            GraphRunnerGPU::new(
+                &gpu_handles,
+                &graph_operators,
+                &GraphRunnerGPUOptions {
+                    use_cache: true,
+                    bind_once: true,
+                    ..Default::default()
+                }
+            ),
+            Err(GraphError::LimitExceeded(_))
+        ));
+
+        // Buffers can't be split at all
+        gpu_handles.limits.max_buffer_size = 4096;
+        assert!(matches!(
+            GraphRunnerGPU::new(
+                &gpu_handles,
+                &graph_operators,
+                &GraphRunnerGPUOptions {
+                    use_cache: true,
+                    ..Default::default()
+                }
+            ),
+            Err(GraphError::LimitExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn time_nodes() {
+        let mut gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::time_nodes() test");
+
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.1, 10, 12),
+            },
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(-0.05, 12, 8),
+                bias: Tensor2D::new(0.1, 10, 8),
+            },
+            GraphOperator::ReLU,
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::DeviceToHost,
+        ];
+        let output_cpu: Tensor2D = GraphRunner::new(&graph_operators, false)
+            .unwrap()
+            .run()
+            .unwrap();
+
+        // The second time around the CPU timers are used, even if the device has timestamps
+        for timestamps in [true, false] {
+            if !timestamps {
+                gpu_handles.features.remove(Features::TIMESTAMP_QUERY);
+            }
+            let expected_source: TimingSource =
+                if gpu_handles.features.contains(Features::TIMESTAMP_QUERY) {
+                    TimingSource::GpuTimestamps
+                } else {
+                    TimingSource::CpuTimers
+                };
+
+            let mut graph_runners: Vec<GraphRunnerGPU> = RUNNER_OPTIONS
+                .iter()
+                .map(|options| {
+                    GraphRunnerGPU::new(&gpu_handles, &graph_operators, options).unwrap()
+                })
+                .collect();
+            for graph_runner in &mut graph_runners {
+                let report: NodeTimingReport =
+                    pollster::block_on(graph_runner.time_nodes(&gpu_handles)).unwrap();
+                assert_eq!(report.source, expected_source);
+                // Everything but the transfers
+                assert_eq!(
+                    report
+                        .nodes
+                        .iter()
+                        .map(|node| (node.name.as_str(), node.operator.as_str()))
+                        .collect::<Vec<(&str, &str)>>(),
+                    vec![
+                        ("LinearLayer_0", "LinearLayer"),
+                        ("ReLU_0", "ReLU"),
+                        ("Softmax_0", "Softmax")
+                    ]
+                );
+                assert_eq!(
+                    report.total(),
+                    report.nodes.iter().map(|node| node.duration).sum()
+                );
+
+                // Timing the nodes is still a run of the graph
+                let output: Tensor2D =
+                    pollster::block_on(graph_runner.get_output(&gpu_handles, "DeviceToHost_0"))
+                        .unwrap();
+                let difference: Tensor2D = subtract_tensors(&output_cpu, &output);
+                assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+            }
+        }
+    }
+
+    #[test]
+    fn softmax_axis() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::softmax_axis() test");
+
+        // Lanes both shorter and longer than a workgroup
+        for (row_count, column_count) in [(1, 1), (3, 5), (70, 2), (4, 70)] {
+            let mut input: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+            for (index, value) in input.data.iter_mut().enumerate() {
+                *value = (index as f32 * 1.7).sin() * 4.0;
+            }
+
+            for axis in [0, 1] {
+                let output_cpu: Tensor2D = Tensor2D::softmax_axis(&input, axis);
+                let graph_operators: Vec<GraphOperator> = vec![
+                    GraphOperator::HostToDevice {
+                        input: input.clone(),
+                    },
+                    GraphOperator::Softmax { axis: Some(axis) },
+                    GraphOperator::DeviceToHost,
+                ];
+
+                let mut graph_runners: Vec<GraphRunnerGPU> = RUNNER_OPTIONS
+                    .iter()
+                    .map(|options| {
+                        GraphRunnerGPU::new(&gpu_handles, &graph_operators, options).unwrap()
+                    })
+                    .collect();
+                for graph_runner in &mut graph_runners {
+                    let output: Tensor2D =
+                        pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+                    let difference: Tensor2D = subtract_tensors(&output_cpu, &output);
+                    assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+                }
+            }
+        }
+
+        // The backward pass matches the CPU as well
+        let mut target: Tensor2D = Tensor2D::new(0.0, 5, 3);
+        target.data[4] = 1.0;
+        for axis in [0, 1] {
+            let graph_operators: Vec<GraphOperator> = vec![
+                GraphOperator::HostToDevice {
+                    input: Tensor2D::new(0.1, 5, 6),
+                },
+                GraphOperator::LinearLayer {
+                    weights: Tensor2D::new(-0.05, 6, 3),
+                    bias: Tensor2D::new(0.02, 5, 3),
+                },
+                GraphOperator::Softmax { axis: Some(axis) },
+                GraphOperator::Loss {
+                    kind: LossKind::CrossEntropy,
+                    target: target.clone(),
+                },
+                GraphOperator::DeviceToHost,
+            ];
+
+            let mut graph_runner: GraphRunner =
+                GraphRunner::for_training(&graph_operators).unwrap();
+            graph_runner.run().unwrap();
+            graph_runner.backward().unwrap();
+            let gradients_cpu: HashMap<String, Tensor2D> = graph_runner.gradients();
+
+            let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+                &gpu_handles,
+                &graph_operators,
+                &GraphRunnerGPUOptions {
+                    use_cache: true,
+                    training: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+            graph_runner.backward(&gpu_handles).unwrap();
+            let gradients: HashMap<String, Tensor2D> =
+                pollster::block_on(graph_runner.gradients(&gpu_handles)).unwrap();
+            for (name, gradient_cpu) in &gradients_cpu {
+                let difference: Tensor2D = subtract_tensors(gradient_cpu, &gradients[name]);
+                assert!(difference.data.iter().all(|x| x.abs() < 0.0001), "{}", name);
+            }
+        }
+    }
+
     #[test]
     fn linear_relu() {
         let gpu_handles: GPUHandles = pollster::block_on(initialize_gpu(true))
@@ -184,10 +812,15 @@ mod tests {
                     let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
                         &gpu_handles,
                         &graph_operators,
-                        fuse_operators,
-                        cache_elements,
-                    );
-                    let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1));
+                        &GraphRunnerGPUOptions {
+                            fuse_operators,
+                            use_cache: cache_elements,
+                            ..Default::default()
+                        },
+                    )
+                    .unwrap();
+                    let output: Tensor2D =
+                        pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
 
                     let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
                     println!("{:?}", difference);
@@ -230,10 +863,15 @@ mod tests {
                     let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
                         &gpu_handles,
                         &graph_operators,
-                        fuse_operators,
-                        cache_elements,
-                    );
-                    let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1));
+                        &GraphRunnerGPUOptions {
+                            fuse_operators,
+                            use_cache: cache_elements,
+                            ..Default::default()
+                        },
+                    )
+                    .unwrap();
+                    let output: Tensor2D =
+                        pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
 
                     let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
                     println!("{:?}", difference);
@@ -267,10 +905,15 @@ mod tests {
                 let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
                     &gpu_handles,
                     &graph_operators,
-                    fuse_operators,
-                    cache_elements,
-                );
-                let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1));
+                    &GraphRunnerGPUOptions {
+                        fuse_operators,
+                        use_cache: cache_elements,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                let output: Tensor2D =
+                    pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
 
                 let difference: Tensor2D = Tensor2D::subtraction(&expected_output, &output);
                 println!("{:?}", difference);
@@ -278,4 +921,737 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn residual_block() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::residual_block() test");
+
+        let input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+        let weights: Tensor2D = Tensor2D::new(1.0, 4, 4);
+        let bias: Tensor2D = Tensor2D::new(0.1, 3, 4);
+
+        let output_cpu: Tensor2D = Tensor2D::linear_layer(&input, &weights, &bias);
+        let output_cpu: Tensor2D = Tensor2D::relu(&output_cpu);
+        let mut residual: Tensor2D = Tensor2D::new(0.0, 3, 4);
+        Tensor2D::add_preallocated(&output_cpu, &input, &mut residual);
+        let output_cpu: Tensor2D = residual;
+
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node("input", GraphOperator::HostToDevice { input }, &[]);
+        graph.add_node(
+            "linear",
+            GraphOperator::LinearLayer { weights, bias },
+            &["input"],
+        );
+        graph.add_node("relu", GraphOperator::ReLU, &["linear"]);
+        graph.add_node("residual", GraphOperator::Add, &["relu", "input"]);
+        graph.add_node("output", GraphOperator::DeviceToHost, &["residual"]);
+
+        let fuse_operators: bool = true;
+        let cache_elements: bool = true;
+        let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::from_dag(
+            &gpu_handles,
+            &graph,
+            &GraphRunnerGPUOptions {
+                fuse_operators,
+                use_cache: cache_elements,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+
+        let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
+        println!("{:?}", difference);
+        println!("{:?}", difference.data.iter().map(|x| x.abs()).sum::<f32>());
+    }
+
+    #[test]
+    fn add_relu() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::add_relu() test");
+
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node(
+            "input",
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.5, 3, 4),
+            },
+            &[],
+        );
+        graph.add_node(
+            "linear",
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(-0.3, 4, 4),
+                bias: Tensor2D::new(0.1, 3, 4),
+            },
+            &["input"],
+        );
+        graph.add_node("residual", GraphOperator::Add, &["input", "linear"]);
+        graph.add_node("relu", GraphOperator::ReLU, &["residual"]);
+        graph.add_node("output", GraphOperator::DeviceToHost, &["relu"]);
+
+        let expected: Tensor2D = GraphRunner::from_dag(&graph, false).unwrap().run().unwrap();
+        for cache_elements in [false, true] {
+            let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::from_dag(
+                &gpu_handles,
+                &graph,
+                &GraphRunnerGPUOptions {
+                    fuse_operators: true,
+                    use_cache: cache_elements,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+            let difference: Tensor2D = subtract_tensors(&expected, &output);
+            assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+        }
+    }
+
+    #[test]
+    fn backward() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::backward() test");
+
+        let mut target: Tensor2D = Tensor2D::new(0.0, 5, 3);
+        target.data[4] = 1.0;
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.1, 5, 6),
+            },
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(-0.05, 6, 7),
+                bias: Tensor2D::new(0.02, 5, 7),
+            },
+            GraphOperator::ReLU,
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(0.03, 7, 3),
+                bias: Tensor2D::new(-0.01, 5, 3),
+            },
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::Loss {
+                kind: LossKind::CrossEntropy,
+                target,
+            },
+            GraphOperator::DeviceToHost,
+        ];
+
+        let mut graph_runner: GraphRunner = GraphRunner::for_training(&graph_operators).unwrap();
+        let loss_cpu: Tensor2D = graph_runner.run().unwrap();
+        graph_runner.backward().unwrap();
+        let gradients_cpu: HashMap<String, Tensor2D> = graph_runner.gradients();
+
+        for cache_elements in [false, true] {
+            let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+                &gpu_handles,
+                &graph_operators,
+                &GraphRunnerGPUOptions {
+                    use_cache: cache_elements,
+                    training: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let loss: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+            assert!((loss_cpu.data[0] - loss.data[0]).abs() < ERROR_TOLERANCE);
+
+            graph_runner.backward(&gpu_handles).unwrap();
+            let gradients: HashMap<String, Tensor2D> =
+                pollster::block_on(graph_runner.gradients(&gpu_handles)).unwrap();
+            assert_eq!(gradients.len(), gradients_cpu.len());
+            for (name, gradient_cpu) in &gradients_cpu {
+                let difference: Tensor2D = subtract_tensors(gradient_cpu, &gradients[name]);
+                assert!(difference.data.iter().all(|x| x.abs() < 0.0001), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn losses() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::losses() test");
+
+        let mut probabilities_target: Tensor2D = Tensor2D::new(0.0, 5, 3);
+        probabilities_target.data[4] = 1.0;
+        let mut regression_target: Tensor2D = Tensor2D::new(0.0, 5, 3);
+        for (index, value) in regression_target.data.iter_mut().enumerate() {
+            *value = (index as f32 * 1.7).sin() * 0.1;
+        }
+
+        for (kind, target) in [
+            (LossKind::NegativeLogLikelihood, probabilities_target),
+            (LossKind::MeanSquaredError, regression_target.clone()),
+            (LossKind::MeanAbsoluteError, regression_target),
+        ] {
+            let mut graph_operators: Vec<GraphOperator> = vec![
+                GraphOperator::HostToDevice {
+                    input: Tensor2D::new(0.1, 5, 6),
+                },
+                GraphOperator::LinearLayer {
+                    weights: Tensor2D::new(-0.05, 6, 3),
+                    bias: Tensor2D::new(0.02, 5, 3),
+                },
+            ];
+            if kind == LossKind::NegativeLogLikelihood {
+                graph_operators.push(GraphOperator::LogSoftmax);
+            }
+            graph_operators.push(GraphOperator::Loss { kind, target });
+            graph_operators.push(GraphOperator::DeviceToHost);
+
+            let mut graph_runner: GraphRunner =
+                GraphRunner::for_training(&graph_operators).unwrap();
+            let loss_cpu: Tensor2D = graph_runner.run().unwrap();
+            graph_runner.backward().unwrap();
+            let gradients_cpu: HashMap<String, Tensor2D> = graph_runner.gradients();
+
+            // The loss is also usable when not training
+            let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+                &gpu_handles,
+                &graph_operators,
+                &GraphRunnerGPUOptions {
+                    use_cache: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let loss: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+            assert!(
+                (loss_cpu.data[0] - loss.data[0]).abs() < ERROR_TOLERANCE,
+                "{}",
+                kind.name()
+            );
+
+            let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+                &gpu_handles,
+                &graph_operators,
+                &GraphRunnerGPUOptions {
+                    use_cache: true,
+                    training: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let loss: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+            assert!(
+                (loss_cpu.data[0] - loss.data[0]).abs() < ERROR_TOLERANCE,
+                "{}",
+                kind.name()
+            );
+
+            graph_runner.backward(&gpu_handles).unwrap();
+            let gradients: HashMap<String, Tensor2D> =
+                pollster::block_on(graph_runner.gradients(&gpu_handles)).unwrap();
+            for (name, gradient_cpu) in &gradients_cpu {
+                let difference: Tensor2D = subtract_tensors(gradient_cpu, &gradients[name]);
+                assert!(
+                    difference.data.iter().all(|x| x.abs() < 0.0001),
+                    "{} {}",
+                    kind.name(),
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn training() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::training() test");
+
+        let mut target: Tensor2D = Tensor2D::new(0.0, 4, 2);
+        target.data[3] = 1.0;
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.1, 4, 3),
+            },
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(-0.05, 3, 2),
+                bias: Tensor2D::new(0.02, 4, 2),
+            },
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::Loss {
+                kind: LossKind::CrossEntropy,
+                target,
+            },
+            GraphOperator::DeviceToHost,
+        ];
+
+        // The same optimizer on both, the parameters must end up in the same place
+        let make_optimizers = || -> Vec<Box<dyn Optimizer>> {
+            vec![
+                Box::new(Sgd::new(0.5)),
+                Box::new(Sgd::with_momentum(0.2, 0.9)),
+                Box::new(Adam::new(0.05)),
+            ]
+        };
+        for (mut optimizer_cpu, mut optimizer_gpu) in
+            make_optimizers().into_iter().zip(make_optimizers())
+        {
+            let mut graph_runner_cpu: GraphRunner =
+                GraphRunner::for_training(&graph_operators).unwrap();
+            let mut graph_runner_gpu: GraphRunnerGPU = GraphRunnerGPU::new(
+                &gpu_handles,
+                &graph_operators,
+                &GraphRunnerGPUOptions {
+                    use_cache: true,
+                    training: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+            for _ in 0..5 {
+                let loss_cpu: Tensor2D = graph_runner_cpu.run().unwrap();
+                let loss_gpu: Tensor2D =
+                    pollster::block_on(graph_runner_gpu.run(&gpu_handles, 1)).unwrap();
+                assert!((loss_cpu.data[0] - loss_gpu.data[0]).abs() < 0.0001);
+
+                graph_runner_cpu.backward().unwrap();
+                graph_runner_cpu.step(optimizer_cpu.as_mut()).unwrap();
+                graph_runner_gpu.backward(&gpu_handles).unwrap();
+                graph_runner_gpu
+                    .step(&gpu_handles, optimizer_gpu.as_mut())
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn new_input() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::new_input() test");
+
+        let make_graph = |input: Tensor2D| -> Vec<GraphOperator> {
+            vec![
+                GraphOperator::HostToDevice { input },
+                GraphOperator::LinearLayer {
+                    weights: Tensor2D::new(-0.05, 6, 7),
+                    bias: Tensor2D::new(0.02, 5, 7),
+                },
+                GraphOperator::ReLU,
+                GraphOperator::DeviceToHost,
+            ]
+        };
+        let inputs: Vec<Tensor2D> = (0..3)
+            .map(|index| {
+                let mut input: Tensor2D = Tensor2D::new(0.0, 5, 6);
+                for (element_index, value) in input.data.iter_mut().enumerate() {
+                    *value = ((element_index + index * 30) as f32 * 1.7).sin();
+                }
+                input
+            })
+            .collect();
+        let expected: Vec<Tensor2D> = inputs
+            .iter()
+            .map(|input| {
+                GraphRunner::new(&make_graph(input.clone()), false)
+                    .unwrap()
+                    .run()
+                    .unwrap()
+            })
+            .collect();
+
+        for cache_elements in [false, true] {
+            let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+                &gpu_handles,
+                &make_graph(Tensor2D::new(0.0, 5, 6)),
+                &GraphRunnerGPUOptions {
+                    use_cache: cache_elements,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            for (input, expected) in inputs.iter().zip(expected.iter()) {
+                let output: Tensor2D =
+                    pollster::block_on(graph_runner.run_with_input(&gpu_handles, input)).unwrap();
+                let difference: Tensor2D = subtract_tensors(expected, &output);
+                assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+            }
+
+            let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+                &gpu_handles,
+                &make_graph(Tensor2D::new(0.0, 5, 6)),
+                &GraphRunnerGPUOptions {
+                    use_cache: cache_elements,
+                    batch_size: 4,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let outputs: Vec<Tensor2D> =
+                pollster::block_on(graph_runner.run_batch(&gpu_handles, &inputs)).unwrap();
+            assert_eq!(outputs.len(), inputs.len());
+            for (expected, output) in expected.iter().zip(outputs.iter()) {
+                let difference: Tensor2D = subtract_tensors(expected, output);
+                assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+            }
+        }
+    }
+
+    // Several inputs in flight at once through the readback ring come back in the right order
+    #[test]
+    fn streaming_readback() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::streaming_readback() test");
+
+        let make_graph = |input: Tensor2D| -> Vec<GraphOperator> {
+            vec![
+                GraphOperator::HostToDevice { input },
+                GraphOperator::LinearLayer {
+                    weights: Tensor2D::new(-0.05, 6, 7),
+                    bias: Tensor2D::new(0.02, 5, 7),
+                },
+                GraphOperator::ReLU,
+                GraphOperator::DeviceToHost,
+            ]
+        };
+        let inputs: Vec<Tensor2D> = (0..7)
+            .map(|index| {
+                let mut input: Tensor2D = Tensor2D::new(0.0, 5, 6);
+                for (element_index, value) in input.data.iter_mut().enumerate() {
+                    *value = ((element_index + index * 30) as f32 * 1.7).sin();
+                }
+                input
+            })
+            .collect();
+        let expected: Vec<Tensor2D> = inputs
+            .iter()
+            .map(|input| {
+                GraphRunner::new(&make_graph(input.clone()), false)
+                    .unwrap()
+                    .run()
+                    .unwrap()
+            })
+            .collect();
+
+        let graph_operators: Vec<GraphOperator> = make_graph(Tensor2D::new(0.0, 5, 6));
+        for slot_count in [1, 2, 3] {
+            let mut graph_runners: Vec<GraphRunnerGPU> = RUNNER_OPTIONS
+                .iter()
+                .map(|options| {
+                    GraphRunnerGPU::new(&gpu_handles, &graph_operators, options).unwrap()
+                })
+                .collect();
+            for graph_runner in &mut graph_runners {
+                graph_runner.set_readback_slot_count(slot_count).unwrap();
+
+                let mut in_flight: VecDeque<ReadbackHandle> = VecDeque::<ReadbackHandle>::new();
+                let mut outputs: Vec<Tensor2D> = Vec::<Tensor2D>::new();
+                for input in &inputs {
+                    if in_flight.len() == slot_count {
+                        // The ring is full, another iteration can't be submitted
+                        assert!(matches!(
+                            graph_runner.submit_with_input(&gpu_handles, input),
+                            Err(GraphError::UnsupportedOperator(_))
+                        ));
+                        let handle: ReadbackHandle = in_flight.pop_front().unwrap();
+                        outputs.push(
+                            pollster::block_on(graph_runner.receive_output(&gpu_handles, &handle))
+                                .unwrap(),
+                        );
+                    }
+                    in_flight
+                        .push_back(graph_runner.submit_with_input(&gpu_handles, input).unwrap());
+                }
+                while let Some(handle) = in_flight.pop_front() {
+                    outputs.push(
+                        pollster::block_on(graph_runner.receive_output(&gpu_handles, &handle))
+                            .unwrap(),
+                    );
+                }
+
+                assert_eq!(outputs.len(), expected.len());
+                for (expected, output) in expected.iter().zip(outputs.iter()) {
+                    let difference: Tensor2D = subtract_tensors(expected, output);
+                    assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+                }
+            }
+        }
+
+        let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+            &gpu_handles,
+            &graph_operators,
+            &GraphRunnerGPUOptions {
+                use_cache: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(graph_runner.set_readback_slot_count(0).is_err());
+        let handle: ReadbackHandle = graph_runner.submit_with_readback(&gpu_handles).unwrap();
+        assert!(graph_runner.set_readback_slot_count(3).is_err());
+        pollster::block_on(graph_runner.receive_output(&gpu_handles, &handle)).unwrap();
+        assert!(pollster::block_on(graph_runner.receive_output(&gpu_handles, &handle)).is_err());
+        assert!(graph_runner.set_readback_slot_count(3).is_ok());
+    }
+
+    #[test]
+    fn named_inputs_and_outputs() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu()).expect(
+            "Failed to get GPU handles in graph_runner_test::named_inputs_and_outputs() test",
+        );
+
+        // Two inputs and two outputs, each output only depends on its own input
+        let make_graph = |left: Tensor2D, right: Tensor2D| -> GraphDAG {
+            let mut graph: GraphDAG = GraphDAG::new();
+            graph.add_node("left", GraphOperator::HostToDevice { input: left }, &[]);
+            graph.add_node("right", GraphOperator::HostToDevice { input: right }, &[]);
+            graph.add_node("relu", GraphOperator::ReLU, &["left"]);
+            graph.add_node("sum", GraphOperator::Add, &["left", "right"]);
+            graph.add_node("relu_output", GraphOperator::DeviceToHost, &["relu"]);
+            graph.add_node("sum_output", GraphOperator::DeviceToHost, &["sum"]);
+            graph
+        };
+        let left: Tensor2D = Tensor2D::new(0.3, 3, 4);
+        let right: Tensor2D = Tensor2D::new(-0.7, 3, 4);
+
+        let mut expected: GraphRunner =
+            GraphRunner::from_dag(&make_graph(left.clone(), right.clone()), false).unwrap();
+        let expected: HashMap<String, Tensor2D> = expected.run_all().unwrap();
+
+        for cache_elements in [false, true] {
+            let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::from_dag(
+                &gpu_handles,
+                &make_graph(Tensor2D::new(0.0, 3, 4), Tensor2D::new(0.0, 3, 4)),
+                &GraphRunnerGPUOptions {
+                    use_cache: cache_elements,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+            // Several runs with new input through the same runner
+            for _ in 0..2 {
+                graph_runner.set_input(&gpu_handles, "left", &left).unwrap();
+                graph_runner
+                    .set_input(&gpu_handles, "right", &right)
+                    .unwrap();
+                graph_runner.submit(&gpu_handles, 1).unwrap();
+                for name in ["relu_output", "sum_output"] {
+                    let output: Tensor2D =
+                        pollster::block_on(graph_runner.get_output(&gpu_handles, name)).unwrap();
+                    let difference: Tensor2D = subtract_tensors(&expected[name], &output);
+                    assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+                }
+
+                graph_runner
+                    .set_input(&gpu_handles, "right", &Tensor2D::new(0.0, 3, 4))
+                    .unwrap();
+                graph_runner.submit(&gpu_handles, 1).unwrap();
+                let output: Tensor2D =
+                    pollster::block_on(graph_runner.get_output(&gpu_handles, "sum_output"))
+                        .unwrap();
+                let difference: Tensor2D = subtract_tensors(&left, &output);
+                assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+            }
+
+            assert!(matches!(
+                graph_runner.set_input(&gpu_handles, "sum", &left),
+                Err(GraphError::UnknownNode(_))
+            ));
+            assert!(matches!(
+                pollster::block_on(graph_runner.get_output(&gpu_handles, "left")),
+                Err(GraphError::UnknownNode(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn bound_nodes() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::bound_nodes() test");
+
+        let mut target: Tensor2D = Tensor2D::new(0.0, 5, 3);
+        target.data[4] = 1.0;
+        let graphs: Vec<Vec<GraphOperator>> = vec![
+            vec![
+                GraphOperator::HostToDevice {
+                    input: Tensor2D::new(0.1, 5, 6),
+                },
+                GraphOperator::LinearLayer {
+                    weights: Tensor2D::new(-0.05, 6, 7),
+                    bias: Tensor2D::new(0.02, 5, 7),
+                },
+                GraphOperator::ReLU,
+                GraphOperator::LinearLayer {
+                    weights: Tensor2D::new(0.03, 7, 3),
+                    bias: Tensor2D::new(-0.01, 5, 3),
+                },
+                GraphOperator::Softmax { axis: None },
+                GraphOperator::DeviceToHost,
+            ],
+            vec![
+                GraphOperator::HostToDevice {
+                    input: Tensor2D::new(0.1, 5, 6),
+                },
+                GraphOperator::LinearLayer {
+                    weights: Tensor2D::new(-0.05, 6, 3),
+                    bias: Tensor2D::new(0.02, 5, 3),
+                },
+                GraphOperator::LogSoftmax,
+                GraphOperator::Loss {
+                    kind: LossKind::NegativeLogLikelihood,
+                    target,
+                },
+                GraphOperator::DeviceToHost,
+            ],
+        ];
+
+        for graph_operators in &graphs {
+            for fuse_operators in [false, true] {
+                let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+                    &gpu_handles,
+                    graph_operators,
+                    &GraphRunnerGPUOptions {
+                        fuse_operators,
+                        use_cache: true,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                let expected: Tensor2D =
+                    pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+
+                let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+                    &gpu_handles,
+                    graph_operators,
+                    &GraphRunnerGPUOptions {
+                        fuse_operators,
+                        use_cache: true,
+                        bind_once: true,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                // Running several times reuses the same bind groups
+                for iteration_count in [1, 3] {
+                    let output: Tensor2D =
+                        pollster::block_on(graph_runner.run(&gpu_handles, iteration_count))
+                            .unwrap();
+                    let difference: Tensor2D = subtract_tensors(&expected, &output);
+                    assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+                }
+            }
+        }
+
+        // The bind groups point at the input buffer, so new input is picked up
+        let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+            &gpu_handles,
+            &graphs[0],
+            &GraphRunnerGPUOptions {
+                use_cache: true,
+                bind_once: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let input: Tensor2D = Tensor2D::new(-0.2, 5, 6);
+        let output: Tensor2D =
+            pollster::block_on(graph_runner.run_with_input(&gpu_handles, &input)).unwrap();
+        let mut graph_operators: Vec<GraphOperator> = graphs[0].clone();
+        graph_operators[0] = GraphOperator::HostToDevice { input };
+        let expected: Tensor2D = GraphRunner::new(&graph_operators, false)
+            .unwrap()
+            .run()
+            .unwrap();
+        let difference: Tensor2D = subtract_tensors(&expected, &output);
+        assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+    }
+
+    // Combinations which used to have no constructor, and the ones which are rejected
+    #[test]
+    fn options() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in graph_runner_test::options() test");
+
+        let mut target: Tensor2D = Tensor2D::new(0.0, 5, 3);
+        target.data[4] = 1.0;
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.1, 5, 6),
+            },
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(-0.05, 6, 7),
+                bias: Tensor2D::new(0.02, 5, 7),
+            },
+            GraphOperator::ReLU,
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(0.03, 7, 3),
+                bias: Tensor2D::new(-0.01, 5, 3),
+            },
+            GraphOperator::LogSoftmax,
+            GraphOperator::Loss {
+                kind: LossKind::NegativeLogLikelihood,
+                target,
+            },
+            GraphOperator::DeviceToHost,
+        ];
+        let expected: Tensor2D = GraphRunner::new(&graph_operators, false)
+            .unwrap()
+            .run()
+            .unwrap();
+
+        for fuse_operators in [false, true] {
+            let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+                &gpu_handles,
+                &graph_operators,
+                &GraphRunnerGPUOptions {
+                    fuse_operators,
+                    use_cache: true,
+                    bind_once: true,
+                    linear_layer_kernel: LinearLayerKernel::Tiled,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let output: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+            let difference: Tensor2D = subtract_tensors(&expected, &output);
+            assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+        }
+
+        let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
+            &gpu_handles,
+            &graph_operators,
+            &GraphRunnerGPUOptions {
+                use_cache: true,
+                training: true,
+                bind_once: true,
+                linear_layer_kernel: LinearLayerKernel::Tiled,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let loss: Tensor2D = pollster::block_on(graph_runner.run(&gpu_handles, 1)).unwrap();
+        assert!((expected.data[0] - loss.data[0]).abs() < ERROR_TOLERANCE);
+        graph_runner.backward(&gpu_handles).unwrap();
+
+        let conflicting_options: [GraphRunnerGPUOptions; 4] = [
+            GraphRunnerGPUOptions {
+                fuse_operators: true,
+                training: true,
+                ..Default::default()
+            },
+            GraphRunnerGPUOptions {
+                training: true,
+                batch_size: 2,
+                ..Default::default()
+            },
+            GraphRunnerGPUOptions {
+                bind_once: true,
+                ..Default::default()
+            },
+            GraphRunnerGPUOptions {
+                batch_size: 0,
+                ..Default::default()
+            },
+        ];
+        for options in &conflicting_options {
+            assert!(matches!(
+                GraphRunnerGPU::new(&gpu_handles, &graph_operators, options),
+                Err(GraphError::UnsupportedOperator(_))
+            ));
+        }
+    }
 }
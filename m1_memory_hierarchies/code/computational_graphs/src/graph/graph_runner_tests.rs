@@ -1,9 +1,17 @@
 #[cfg(test)]
 mod tests {
 
+    use std::collections::HashMap;
+
     use crate::{
-        graph::graph_runner::GraphRunner,
-        shared::{graph_operators::GraphOperator, tensor2d::Tensor2D},
+        graph::{
+            graph_dag::GraphDAG, graph_error::GraphError, graph_runner::GraphRunner,
+            memory_planner::MemoryReport,
+        },
+        shared::{
+            graph_operators::{GraphOperator, LossKind},
+            tensor2d::Tensor2D,
+        },
     };
 
     const ERROR_TOLERANCE: f32 = 0.00001;
@@ -56,8 +64,8 @@ mod tests {
 
                     let fuse_operators: bool = false;
                     let mut graph_runner: GraphRunner =
-                        GraphRunner::new(&graph_operators, fuse_operators);
-                    let output: Tensor2D = graph_runner.run();
+                        GraphRunner::new(&graph_operators, fuse_operators).unwrap();
+                    let output: Tensor2D = graph_runner.run().unwrap();
 
                     let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
                     println!("{:?}", difference);
@@ -86,8 +94,8 @@ mod tests {
 
                 let fuse_operators: bool = false;
                 let mut graph_runner: GraphRunner =
-                    GraphRunner::new(&graph_operators, fuse_operators);
-                let output: Tensor2D = graph_runner.run();
+                    GraphRunner::new(&graph_operators, fuse_operators).unwrap();
+                let output: Tensor2D = graph_runner.run().unwrap();
 
                 let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
                 println!("{:?}", difference);
@@ -109,14 +117,14 @@ mod tests {
 
                 let graph_operators: Vec<GraphOperator> = vec![
                     GraphOperator::HostToDevice { input },
-                    GraphOperator::Softmax,
+                    GraphOperator::Softmax { axis: None },
                     GraphOperator::DeviceToHost,
                 ];
 
                 let fuse_operators: bool = false;
                 let mut graph_runner: GraphRunner =
-                    GraphRunner::new(&graph_operators, fuse_operators);
-                let output: Tensor2D = graph_runner.run();
+                    GraphRunner::new(&graph_operators, fuse_operators).unwrap();
+                let output: Tensor2D = graph_runner.run().unwrap();
 
                 let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
                 println!("{:?}", difference);
@@ -125,6 +133,50 @@ mod tests {
         }
     }
 
+    #[test]
+    fn softmax_axis() {
+        let input: Tensor2D = training_tensor(0.0, 3, 4);
+        let weights: Tensor2D = training_tensor(1.0, 4, 5);
+        let bias: Tensor2D = training_tensor(2.0, 3, 5);
+
+        for axis in [0, 1] {
+            let mut expected: Tensor2D = Tensor2D::new(0.0, 3, 5);
+            Tensor2D::linear_layer_optimized_relu(&input, &weights, &bias, &mut expected);
+            let expected: Tensor2D = Tensor2D::softmax_axis(&expected, axis);
+
+            // Linear, ReLU, Softmax would be fused into the global softmax if the axis was ignored
+            let graph_operators: Vec<GraphOperator> = vec![
+                GraphOperator::HostToDevice {
+                    input: input.clone(),
+                },
+                GraphOperator::LinearLayer {
+                    weights: weights.clone(),
+                    bias: bias.clone(),
+                },
+                GraphOperator::ReLU,
+                GraphOperator::Softmax { axis: Some(axis) },
+                GraphOperator::DeviceToHost,
+            ];
+            for fuse_operators in [false, true] {
+                let mut graph_runner: GraphRunner =
+                    GraphRunner::new(&graph_operators, fuse_operators).unwrap();
+                let output: Tensor2D = graph_runner.run().unwrap();
+                let difference: Tensor2D = subtract_tensors(&expected, &output);
+                assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+            }
+        }
+
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice { input },
+            GraphOperator::Softmax { axis: Some(2) },
+            GraphOperator::DeviceToHost,
+        ];
+        assert!(matches!(
+            GraphRunner::new(&graph_operators, false),
+            Err(GraphError::UnsupportedOperator(_))
+        ));
+    }
+
     #[test]
     fn linear_relu() {
         let outer_dimension_range: usize = 8;
@@ -151,8 +203,8 @@ mod tests {
 
                     let fuse_operators: bool = false;
                     let mut graph_runner: GraphRunner =
-                        GraphRunner::new(&graph_operators, fuse_operators);
-                    let output: Tensor2D = graph_runner.run();
+                        GraphRunner::new(&graph_operators, fuse_operators).unwrap();
+                    let output: Tensor2D = graph_runner.run().unwrap();
 
                     let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
                     println!("{:?}", difference);
@@ -189,8 +241,8 @@ mod tests {
 
                     let fuse_operators: bool = false;
                     let mut graph_runner: GraphRunner =
-                        GraphRunner::new(&graph_operators, fuse_operators);
-                    let output: Tensor2D = graph_runner.run();
+                        GraphRunner::new(&graph_operators, fuse_operators).unwrap();
+                    let output: Tensor2D = graph_runner.run().unwrap();
 
                     let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
                     println!("{:?}", difference);
@@ -200,6 +252,45 @@ mod tests {
         }
     }
 
+    // Large enough for the CPU kernels to actually split the work between threads
+    #[test]
+    fn thread_count() {
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: training_tensor(0.0, 97, 128),
+            },
+            GraphOperator::LinearLayer {
+                weights: training_tensor(1.0, 128, 80),
+                bias: training_tensor(2.0, 97, 80),
+            },
+            GraphOperator::ReLU,
+            GraphOperator::LinearLayer {
+                weights: training_tensor(3.0, 80, 90),
+                bias: training_tensor(4.0, 97, 90),
+            },
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::DeviceToHost,
+        ];
+
+        for fuse_operators in [false, true] {
+            let mut graph_runner: GraphRunner =
+                GraphRunner::new(&graph_operators, fuse_operators).unwrap();
+            assert_eq!(graph_runner.thread_count(), 1);
+            let expected: Tensor2D = graph_runner.run().unwrap();
+
+            for thread_count in [0, 3] {
+                let mut graph_runner: GraphRunner =
+                    GraphRunner::new(&graph_operators, fuse_operators).unwrap();
+                graph_runner.set_thread_count(thread_count);
+                assert!(0 < graph_runner.thread_count());
+                let output: Tensor2D = graph_runner.run().unwrap();
+
+                let difference: Tensor2D = subtract_tensors(&expected, &output);
+                assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+            }
+        }
+    }
+
     #[test]
     fn transfers() {
         let outer_dimension_range: usize = 8;
@@ -218,8 +309,8 @@ mod tests {
 
                 let fuse_operators: bool = false;
                 let mut graph_runner: GraphRunner =
-                    GraphRunner::new(&graph_operators, fuse_operators);
-                let output: Tensor2D = graph_runner.run();
+                    GraphRunner::new(&graph_operators, fuse_operators).unwrap();
+                let output: Tensor2D = graph_runner.run().unwrap();
 
                 let difference: Tensor2D = Tensor2D::subtraction(&expected_output, &output);
                 println!("{:?}", difference);
@@ -227,4 +318,759 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn dimension_mismatch() {
+        let input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+        let weights: Tensor2D = Tensor2D::new(1.0, 5, 2);
+        let bias: Tensor2D = Tensor2D::new(0.1, 3, 2);
+
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice { input },
+            GraphOperator::LinearLayer { weights, bias },
+            GraphOperator::DeviceToHost,
+        ];
+
+        let result: Result<GraphRunner, GraphError> = GraphRunner::new(&graph_operators, false);
+        assert!(matches!(result, Err(GraphError::DimensionMismatch(_))));
+
+        let input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+        let weights: Tensor2D = Tensor2D::new(1.0, 4, 2);
+        let bias: Tensor2D = Tensor2D::new(0.1, 2, 2);
+
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice { input },
+            GraphOperator::LinearLayer { weights, bias },
+            GraphOperator::DeviceToHost,
+        ];
+
+        let result: Result<GraphRunner, GraphError> = GraphRunner::new(&graph_operators, false);
+        assert!(matches!(result, Err(GraphError::DimensionMismatch(_))));
+    }
+
+    #[test]
+    fn misplaced_transfer() {
+        let input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+
+        let missing_device_to_host: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: input.clone(),
+            },
+            GraphOperator::ReLU,
+        ];
+        let result: Result<GraphRunner, GraphError> =
+            GraphRunner::new(&missing_device_to_host, true);
+        assert!(matches!(result, Err(GraphError::MisplacedTransfer(_))));
+
+        let late_host_to_device: Vec<GraphOperator> = vec![
+            GraphOperator::ReLU,
+            GraphOperator::HostToDevice { input },
+            GraphOperator::DeviceToHost,
+        ];
+        let result: Result<GraphRunner, GraphError> = GraphRunner::new(&late_host_to_device, false);
+        assert!(matches!(result, Err(GraphError::MisplacedTransfer(_))));
+
+        let result: Result<GraphRunner, GraphError> = GraphRunner::new(&Vec::new(), false);
+        assert!(matches!(result, Err(GraphError::MisplacedTransfer(_))));
+    }
+
+    #[test]
+    fn residual_block() {
+        let input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+        let weights: Tensor2D = Tensor2D::new(1.0, 4, 4);
+        let bias: Tensor2D = Tensor2D::new(0.1, 3, 4);
+
+        let expected_output: Tensor2D = Tensor2D::linear_layer(&input, &weights, &bias);
+        let expected_output: Tensor2D = Tensor2D::relu(&expected_output);
+        let mut residual: Tensor2D = Tensor2D::new(0.0, 3, 4);
+        Tensor2D::add_preallocated(&expected_output, &input, &mut residual);
+        let expected_output: Tensor2D = residual;
+
+        // The input is used by both the linear layer and the add, so neither
+        // the input nor the linear layer can be overwritten.
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node("input", GraphOperator::HostToDevice { input }, &[]);
+        graph.add_node(
+            "linear",
+            GraphOperator::LinearLayer { weights, bias },
+            &["input"],
+        );
+        graph.add_node("relu", GraphOperator::ReLU, &["linear"]);
+        graph.add_node("residual", GraphOperator::Add, &["relu", "input"]);
+        graph.add_node("output", GraphOperator::DeviceToHost, &["residual"]);
+
+        for fuse_operators in [false, true] {
+            let mut graph_runner: GraphRunner =
+                GraphRunner::from_dag(&graph, fuse_operators).unwrap();
+            let output: Tensor2D = graph_runner.run().unwrap();
+
+            let difference: Tensor2D = subtract_tensors(&expected_output, &output);
+            assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+        }
+    }
+
+    #[test]
+    fn concat() {
+        let mut left: Tensor2D = Tensor2D::new(0.0, 2, 2);
+        left.data = vec![1.0, 2.0, 3.0, 4.0];
+        let mut right: Tensor2D = Tensor2D::new(0.0, 2, 2);
+        right.data = vec![5.0, 6.0, 7.0, 8.0];
+
+        let expected_rows: Vec<f32> = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        let expected_columns: Vec<f32> = vec![1.0, 2.0, 5.0, 6.0, 3.0, 4.0, 7.0, 8.0];
+
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node("left", GraphOperator::HostToDevice { input: left }, &[]);
+        graph.add_node("right", GraphOperator::HostToDevice { input: right }, &[]);
+        graph.add_node(
+            "rows",
+            GraphOperator::Concat { axis: 0 },
+            &["left", "right"],
+        );
+        graph.add_node(
+            "columns",
+            GraphOperator::Concat { axis: 1 },
+            &["left", "right"],
+        );
+        graph.add_node("rows_output", GraphOperator::DeviceToHost, &["rows"]);
+        graph.add_node("columns_output", GraphOperator::DeviceToHost, &["columns"]);
+
+        let mut graph_runner: GraphRunner = GraphRunner::from_dag(&graph, false).unwrap();
+        let outputs: HashMap<String, Tensor2D> = graph_runner.run_all().unwrap();
+
+        let rows: &Tensor2D = &outputs["rows_output"];
+        assert_eq!((rows.row_count, rows.column_count), (4, 2));
+        assert_eq!(rows.data, expected_rows);
+
+        let columns: &Tensor2D = &outputs["columns_output"];
+        assert_eq!((columns.row_count, columns.column_count), (2, 4));
+        assert_eq!(columns.data, expected_columns);
+    }
+
+    #[test]
+    fn fan_out() {
+        let input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+        let weights: Tensor2D = Tensor2D::new(1.0, 4, 2);
+        let bias: Tensor2D = Tensor2D::new(0.1, 3, 2);
+
+        let expected_linear: Tensor2D = Tensor2D::linear_layer(&input, &weights, &bias);
+        let expected_relu: Tensor2D = Tensor2D::relu(&expected_linear);
+        let expected_softmax: Tensor2D = Tensor2D::softmax(&expected_relu);
+
+        // The ReLU output is both an output of the graph and the input of the
+        // softmax, so the linear layer can't be fused with the softmax.
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node("input", GraphOperator::HostToDevice { input }, &[]);
+        graph.add_node(
+            "linear",
+            GraphOperator::LinearLayer { weights, bias },
+            &["input"],
+        );
+        graph.add_node("relu", GraphOperator::ReLU, &["linear"]);
+        graph.add_node("softmax", GraphOperator::Softmax { axis: None }, &["relu"]);
+        graph.add_node("relu_output", GraphOperator::DeviceToHost, &["relu"]);
+        graph.add_node("softmax_output", GraphOperator::DeviceToHost, &["softmax"]);
+
+        for fuse_operators in [false, true] {
+            let mut graph_runner: GraphRunner =
+                GraphRunner::from_dag(&graph, fuse_operators).unwrap();
+            let outputs: HashMap<String, Tensor2D> = graph_runner.run_all().unwrap();
+            assert_eq!(outputs.len(), 2);
+
+            let difference: Tensor2D = subtract_tensors(&expected_relu, &outputs["relu_output"]);
+            assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+
+            let difference: Tensor2D =
+                subtract_tensors(&expected_softmax, &outputs["softmax_output"]);
+            assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+        }
+    }
+
+    #[test]
+    fn malformed_dag() {
+        let input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+
+        let mut cycle: GraphDAG = GraphDAG::new();
+        cycle.add_node(
+            "input",
+            GraphOperator::HostToDevice {
+                input: input.clone(),
+            },
+            &[],
+        );
+        cycle.add_node("a", GraphOperator::Add, &["input", "b"]);
+        cycle.add_node("b", GraphOperator::ReLU, &["a"]);
+        cycle.add_node("output", GraphOperator::DeviceToHost, &["b"]);
+        let result: Result<GraphRunner, GraphError> = GraphRunner::from_dag(&cycle, false);
+        assert!(matches!(result, Err(GraphError::MalformedNode(_))));
+
+        let mut unknown_input: GraphDAG = GraphDAG::new();
+        unknown_input.add_node(
+            "input",
+            GraphOperator::HostToDevice {
+                input: input.clone(),
+            },
+            &[],
+        );
+        unknown_input.add_node("relu", GraphOperator::ReLU, &["missing"]);
+        unknown_input.add_node("output", GraphOperator::DeviceToHost, &["relu"]);
+        let result: Result<GraphRunner, GraphError> = GraphRunner::from_dag(&unknown_input, false);
+        assert!(matches!(result, Err(GraphError::MalformedNode(_))));
+
+        let mut shape_mismatch: GraphDAG = GraphDAG::new();
+        shape_mismatch.add_node("a", GraphOperator::HostToDevice { input }, &[]);
+        shape_mismatch.add_node(
+            "b",
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.5, 4, 3),
+            },
+            &[],
+        );
+        shape_mismatch.add_node("sum", GraphOperator::Add, &["a", "b"]);
+        shape_mismatch.add_node("output", GraphOperator::DeviceToHost, &["sum"]);
+        let result: Result<GraphRunner, GraphError> = GraphRunner::from_dag(&shape_mismatch, false);
+        assert!(matches!(result, Err(GraphError::DimensionMismatch(_))));
+    }
+
+    #[test]
+    fn memory_planning() {
+        let layer_count: usize = 16;
+        let input: Tensor2D = Tensor2D::new(0.01, 4, 4);
+        let weights: Tensor2D = Tensor2D::new(0.02, 4, 4);
+        let bias: Tensor2D = Tensor2D::new(0.01, 4, 4);
+
+        let mut expected_output: Tensor2D = input.clone();
+        let mut graph_operators: Vec<GraphOperator> = vec![GraphOperator::HostToDevice { input }];
+        for _ in 0..layer_count {
+            expected_output = Tensor2D::linear_layer(&expected_output, &weights, &bias);
+            expected_output = Tensor2D::relu(&expected_output);
+            graph_operators.push(GraphOperator::LinearLayer {
+                weights: weights.clone(),
+                bias: bias.clone(),
+            });
+            graph_operators.push(GraphOperator::ReLU);
+        }
+        graph_operators.push(GraphOperator::DeviceToHost);
+
+        let mut graph_runner: GraphRunner = GraphRunner::new(&graph_operators, false).unwrap();
+        let output: Tensor2D = graph_runner.run().unwrap();
+        let difference: Tensor2D = subtract_tensors(&expected_output, &output);
+        assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+
+        // Every layer reads the previous result and writes a new one,
+        // so two buffers are enough no matter how deep the graph is.
+        let report: MemoryReport = graph_runner.memory_report();
+        let tensor_bytes: usize = 4 * 4 * std::mem::size_of::<f32>();
+        assert_eq!(report.intermediate_count, 2 * layer_count);
+        assert_eq!(report.intermediate_buffer_count, 2);
+        assert_eq!(report.fixed_bytes, (1 + 2 * layer_count) * tensor_bytes);
+        assert_eq!(
+            report.unplanned_intermediate_bytes,
+            2 * layer_count * tensor_bytes
+        );
+        assert_eq!(report.planned_intermediate_bytes, 2 * tensor_bytes);
+        assert!(report.peak_bytes() < report.unplanned_peak_bytes());
+    }
+
+    #[test]
+    fn in_place() {
+        let input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+        let weights: Tensor2D = Tensor2D::new(1.0, 4, 4);
+        let bias: Tensor2D = Tensor2D::new(0.1, 3, 4);
+
+        // Overwriting the input would give a different result the second time
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: input.clone(),
+            },
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::DeviceToHost,
+        ];
+        let expected_output: Tensor2D = Tensor2D::softmax(&input);
+        let mut graph_runner: GraphRunner = GraphRunner::new(&graph_operators, false).unwrap();
+        for _ in 0..2 {
+            let output: Tensor2D = graph_runner.run().unwrap();
+            let difference: Tensor2D = subtract_tensors(&expected_output, &output);
+            assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+        }
+
+        // Both the ReLU and the softmax can work directly on the output of the linear layer
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: input.clone(),
+            },
+            GraphOperator::LinearLayer {
+                weights: weights.clone(),
+                bias: bias.clone(),
+            },
+            GraphOperator::ReLU,
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::DeviceToHost,
+        ];
+        let expected_output: Tensor2D = Tensor2D::linear_layer(&input, &weights, &bias);
+        let expected_output: Tensor2D = Tensor2D::relu(&expected_output);
+        let expected_output: Tensor2D = Tensor2D::softmax(&expected_output);
+        let mut graph_runner: GraphRunner = GraphRunner::new(&graph_operators, false).unwrap();
+        for _ in 0..2 {
+            let output: Tensor2D = graph_runner.run().unwrap();
+            let difference: Tensor2D = subtract_tensors(&expected_output, &output);
+            assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+        }
+        assert_eq!(graph_runner.memory_report().intermediate_buffer_count, 1);
+
+        // The add still needs the output of the linear layer after the ReLU has run
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node(
+            "input",
+            GraphOperator::HostToDevice {
+                input: input.clone(),
+            },
+            &[],
+        );
+        graph.add_node(
+            "linear",
+            GraphOperator::LinearLayer {
+                weights: weights.clone(),
+                bias: bias.clone(),
+            },
+            &["input"],
+        );
+        graph.add_node("relu", GraphOperator::ReLU, &["linear"]);
+        graph.add_node("sum", GraphOperator::Add, &["relu", "linear"]);
+        graph.add_node("output", GraphOperator::DeviceToHost, &["sum"]);
+
+        let linear: Tensor2D = Tensor2D::linear_layer(&input, &weights, &bias);
+        let mut expected_output: Tensor2D = Tensor2D::new(0.0, 3, 4);
+        Tensor2D::add_preallocated(&Tensor2D::relu(&linear), &linear, &mut expected_output);
+
+        let mut graph_runner: GraphRunner = GraphRunner::from_dag(&graph, false).unwrap();
+        let output: Tensor2D = graph_runner.run().unwrap();
+        let difference: Tensor2D = subtract_tensors(&expected_output, &output);
+        assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+    }
+
+    // Values with both signs, so the ReLUs actually cut something off
+    fn training_tensor(offset: f32, row_count: usize, column_count: usize) -> Tensor2D {
+        let mut tensor: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+        for (index, value) in tensor.data.iter_mut().enumerate() {
+            *value = ((index as f32 + offset) * 1.7).sin();
+        }
+        tensor
+    }
+
+    fn training_target(row_count: usize, column_count: usize) -> Tensor2D {
+        let mut target: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+        target.data[1] = 1.0;
+        target
+    }
+
+    fn mlp_graph(tensors: &[Tensor2D]) -> Vec<GraphOperator> {
+        vec![
+            GraphOperator::HostToDevice {
+                input: tensors[0].clone(),
+            },
+            GraphOperator::LinearLayer {
+                weights: tensors[1].clone(),
+                bias: tensors[2].clone(),
+            },
+            GraphOperator::ReLU,
+            GraphOperator::LinearLayer {
+                weights: tensors[3].clone(),
+                bias: tensors[4].clone(),
+            },
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::Loss {
+                kind: LossKind::CrossEntropy,
+                target: training_target(2, 3),
+            },
+            GraphOperator::DeviceToHost,
+        ]
+    }
+
+    // The gradient of the loss of the graph made by make_graph, one element
+    // of one tensor at a time, using central differences. The step is large,
+    // as the loss is only computed in f32.
+    fn numerical_gradient(
+        tensors: &[Tensor2D],
+        tensor_index: usize,
+        make_graph: impl Fn(&[Tensor2D]) -> Vec<GraphOperator>,
+    ) -> Tensor2D {
+        let step: f32 = 0.001;
+        let loss = |tensors: &[Tensor2D]| -> f32 {
+            let mut graph_runner: GraphRunner =
+                GraphRunner::new(&make_graph(tensors), false).unwrap();
+            graph_runner.run().unwrap().data[0]
+        };
+
+        let mut shifted: Vec<Tensor2D> = tensors.to_vec();
+        let mut gradient: Tensor2D = Tensor2D::new(
+            0.0,
+            tensors[tensor_index].row_count,
+            tensors[tensor_index].column_count,
+        );
+        for index in 0..gradient.len() {
+            let value: f32 = tensors[tensor_index].data[index];
+            shifted[tensor_index].data[index] = value + step;
+            let above: f32 = loss(&shifted);
+            shifted[tensor_index].data[index] = value - step;
+            let below: f32 = loss(&shifted);
+            shifted[tensor_index].data[index] = value;
+            gradient.data[index] = (above - below) / (2.0 * step);
+        }
+        gradient
+    }
+
+    fn assert_gradients_close(expected: &Tensor2D, actual: &Tensor2D) {
+        assert_eq!(
+            (expected.row_count, expected.column_count),
+            (actual.row_count, actual.column_count)
+        );
+        for (expected, actual) in expected.data.iter().zip(actual.data.iter()) {
+            assert!(
+                (expected - actual).abs() < 0.005,
+                "expected {} but got {}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn backward() {
+        let tensors: Vec<Tensor2D> = vec![
+            training_tensor(0.0, 2, 4),
+            training_tensor(1.0, 4, 5),
+            training_tensor(2.0, 2, 5),
+            training_tensor(3.0, 5, 3),
+            training_tensor(4.0, 2, 3),
+        ];
+        let names: [&str; 5] = [
+            "HostToDevice_0",
+            "LinearLayer_0.weights",
+            "LinearLayer_0.bias",
+            "LinearLayer_1.weights",
+            "LinearLayer_1.bias",
+        ];
+
+        let mut graph_runner: GraphRunner =
+            GraphRunner::for_training(&mlp_graph(&tensors)).unwrap();
+        assert_eq!(graph_runner.parameters().len(), 4);
+
+        // Running backward twice must not add up the gradients of both runs
+        for _ in 0..2 {
+            let loss: Tensor2D = graph_runner.run().unwrap();
+            assert_eq!((loss.row_count, loss.column_count), (1, 1));
+            graph_runner.backward().unwrap();
+        }
+
+        let gradients: HashMap<String, Tensor2D> = graph_runner.gradients();
+        assert_eq!(gradients.len(), names.len());
+        for (tensor_index, name) in names.iter().enumerate() {
+            let expected: Tensor2D = numerical_gradient(&tensors, tensor_index, mlp_graph);
+            assert_gradients_close(&expected, &gradients[*name]);
+        }
+    }
+
+    #[test]
+    fn residual_backward() {
+        // The input is read by both the linear layer and the add,
+        // so its gradient is the sum of what comes back through each of them
+        let make_graph = |tensors: &[Tensor2D]| -> GraphDAG {
+            let mut graph: GraphDAG = GraphDAG::new();
+            graph.add_node(
+                "input",
+                GraphOperator::HostToDevice {
+                    input: tensors[0].clone(),
+                },
+                &[],
+            );
+            graph.add_node(
+                "linear",
+                GraphOperator::LinearLayer {
+                    weights: tensors[1].clone(),
+                    bias: tensors[2].clone(),
+                },
+                &["input"],
+            );
+            graph.add_node("relu", GraphOperator::ReLU, &["linear"]);
+            graph.add_node("residual", GraphOperator::Add, &["relu", "input"]);
+            graph.add_node(
+                "softmax",
+                GraphOperator::Softmax { axis: None },
+                &["residual"],
+            );
+            graph.add_node(
+                "loss",
+                GraphOperator::Loss {
+                    kind: LossKind::CrossEntropy,
+                    target: training_target(3, 4),
+                },
+                &["softmax"],
+            );
+            graph.add_node("output", GraphOperator::DeviceToHost, &["loss"]);
+            graph
+        };
+
+        let tensors: Vec<Tensor2D> = vec![
+            training_tensor(0.0, 3, 4),
+            training_tensor(1.0, 4, 4),
+            training_tensor(2.0, 3, 4),
+        ];
+        let mut graph_runner: GraphRunner =
+            GraphRunner::from_dag_for_training(&make_graph(&tensors)).unwrap();
+        graph_runner.run().unwrap();
+        graph_runner.backward().unwrap();
+        let gradients: HashMap<String, Tensor2D> = graph_runner.gradients();
+
+        let step: f32 = 0.001;
+        let loss = |tensors: &[Tensor2D]| -> f32 {
+            let mut graph_runner: GraphRunner =
+                GraphRunner::from_dag(&make_graph(tensors), false).unwrap();
+            graph_runner.run().unwrap().data[0]
+        };
+        for (tensor_index, name) in ["input", "linear.weights", "linear.bias"]
+            .iter()
+            .enumerate()
+        {
+            let mut shifted: Vec<Tensor2D> = tensors.clone();
+            let mut expected: Tensor2D = Tensor2D::new(
+                0.0,
+                tensors[tensor_index].row_count,
+                tensors[tensor_index].column_count,
+            );
+            for index in 0..expected.len() {
+                let value: f32 = tensors[tensor_index].data[index];
+                shifted[tensor_index].data[index] = value + step;
+                let above: f32 = loss(&shifted);
+                shifted[tensor_index].data[index] = value - step;
+                let below: f32 = loss(&shifted);
+                shifted[tensor_index].data[index] = value;
+                expected.data[index] = (above - below) / (2.0 * step);
+            }
+            assert_gradients_close(&expected, &gradients[*name]);
+        }
+    }
+
+    // The mlp with its softmax and cross entropy swapped out for each of the other losses
+    fn loss_graph(tensors: &[Tensor2D], kind: LossKind) -> Vec<GraphOperator> {
+        let mut graph_operators: Vec<GraphOperator> = mlp_graph(tensors);
+        match kind {
+            LossKind::CrossEntropy => {}
+            LossKind::NegativeLogLikelihood => {
+                graph_operators[4] = GraphOperator::LogSoftmax;
+                graph_operators[5] = GraphOperator::Loss {
+                    kind,
+                    target: training_target(2, 3),
+                };
+            }
+            LossKind::MeanSquaredError | LossKind::MeanAbsoluteError => {
+                graph_operators.remove(4);
+                graph_operators[4] = GraphOperator::Loss {
+                    kind,
+                    target: training_tensor(5.0, 2, 3),
+                };
+            }
+        }
+        graph_operators
+    }
+
+    #[test]
+    fn loss_backward() {
+        let tensors: Vec<Tensor2D> = vec![
+            training_tensor(0.0, 2, 4),
+            training_tensor(1.0, 4, 5),
+            training_tensor(2.0, 2, 5),
+            training_tensor(3.0, 5, 3),
+            training_tensor(4.0, 2, 3),
+        ];
+
+        for kind in [
+            LossKind::NegativeLogLikelihood,
+            LossKind::MeanSquaredError,
+            LossKind::MeanAbsoluteError,
+        ] {
+            let make_graph =
+                |tensors: &[Tensor2D]| -> Vec<GraphOperator> { loss_graph(tensors, kind) };
+
+            let mut graph_runner: GraphRunner =
+                GraphRunner::for_training(&make_graph(&tensors)).unwrap();
+            let loss: Tensor2D = graph_runner.run().unwrap();
+            assert_eq!((loss.row_count, loss.column_count), (1, 1));
+            graph_runner.backward().unwrap();
+
+            let gradients: HashMap<String, Tensor2D> = graph_runner.gradients();
+            let expected: Tensor2D = numerical_gradient(&tensors, 3, make_graph);
+            assert_gradients_close(&expected, &gradients["LinearLayer_1.weights"]);
+            let expected: Tensor2D = numerical_gradient(&tensors, 0, make_graph);
+            assert_gradients_close(&expected, &gradients["HostToDevice_0"]);
+        }
+
+        // LogSoftmax followed by NegativeLogLikelihood is the stable way to get cross entropy
+        let cross_entropy: f32 = GraphRunner::new(&mlp_graph(&tensors), false)
+            .unwrap()
+            .run()
+            .unwrap()
+            .data[0];
+        let negative_log_likelihood: f32 = GraphRunner::new(
+            &loss_graph(&tensors, LossKind::NegativeLogLikelihood),
+            false,
+        )
+        .unwrap()
+        .run()
+        .unwrap()
+        .data[0];
+        assert!((cross_entropy - negative_log_likelihood).abs() < 0.0001);
+    }
+
+    #[test]
+    fn softmax_axis_backward() {
+        let tensors: Vec<Tensor2D> = vec![
+            training_tensor(0.0, 2, 4),
+            training_tensor(1.0, 4, 5),
+            training_tensor(2.0, 2, 5),
+            training_tensor(3.0, 5, 3),
+            training_tensor(4.0, 2, 3),
+        ];
+
+        // Not merged with the cross entropy, which only knows the global softmax
+        for axis in [0, 1] {
+            let make_graph = |tensors: &[Tensor2D]| -> Vec<GraphOperator> {
+                let mut graph_operators: Vec<GraphOperator> = mlp_graph(tensors);
+                graph_operators[4] = GraphOperator::Softmax { axis: Some(axis) };
+                graph_operators
+            };
+
+            let mut graph_runner: GraphRunner =
+                GraphRunner::for_training(&make_graph(&tensors)).unwrap();
+            graph_runner.run().unwrap();
+            graph_runner.backward().unwrap();
+
+            let gradients: HashMap<String, Tensor2D> = graph_runner.gradients();
+            let expected: Tensor2D = numerical_gradient(&tensors, 3, make_graph);
+            assert_gradients_close(&expected, &gradients["LinearLayer_1.weights"]);
+            let expected: Tensor2D = numerical_gradient(&tensors, 0, make_graph);
+            assert_gradients_close(&expected, &gradients["HostToDevice_0"]);
+        }
+    }
+
+    #[test]
+    fn backward_errors() {
+        let tensors: Vec<Tensor2D> = vec![
+            training_tensor(0.0, 2, 4),
+            training_tensor(1.0, 4, 5),
+            training_tensor(2.0, 2, 5),
+            training_tensor(3.0, 5, 3),
+            training_tensor(4.0, 2, 3),
+        ];
+
+        // Without for_training the intermediates are gone by the time backward would run
+        let mut graph_runner: GraphRunner = GraphRunner::new(&mlp_graph(&tensors), false).unwrap();
+        graph_runner.run().unwrap();
+        assert!(matches!(
+            graph_runner.backward(),
+            Err(GraphError::UnsupportedOperator(_))
+        ));
+        assert!(graph_runner.parameters().is_empty());
+
+        let mut graph_runner: GraphRunner =
+            GraphRunner::for_training(&mlp_graph(&tensors)).unwrap();
+        assert!(matches!(
+            graph_runner.backward(),
+            Err(GraphError::UnsupportedOperator(_))
+        ));
+
+        // Nothing to differentiate
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: tensors[0].clone(),
+            },
+            GraphOperator::ReLU,
+            GraphOperator::DeviceToHost,
+        ];
+        assert!(matches!(
+            GraphRunner::for_training(&graph_operators),
+            Err(GraphError::UnsupportedOperator(_))
+        ));
+
+        // The target has to match the probabilities
+        let mut graph_operators: Vec<GraphOperator> = mlp_graph(&tensors);
+        graph_operators[5] = GraphOperator::Loss {
+            kind: LossKind::CrossEntropy,
+            target: training_target(3, 2),
+        };
+        assert!(GraphRunner::for_training(&graph_operators).is_err());
+
+        // Same for every other kind of loss, also when not training
+        let mut graph_operators: Vec<GraphOperator> =
+            loss_graph(&tensors, LossKind::MeanSquaredError);
+        graph_operators[4] = GraphOperator::Loss {
+            kind: LossKind::MeanSquaredError,
+            target: training_tensor(0.0, 2, 4),
+        };
+        assert!(matches!(
+            GraphRunner::new(&graph_operators, false),
+            Err(GraphError::DimensionMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn named_inputs_and_outputs() {
+        // Two inputs and two outputs, each output only depends on its own input
+        let make_graph = |left: Tensor2D, right: Tensor2D| -> GraphDAG {
+            let mut graph: GraphDAG = GraphDAG::new();
+            graph.add_node("left", GraphOperator::HostToDevice { input: left }, &[]);
+            graph.add_node("right", GraphOperator::HostToDevice { input: right }, &[]);
+            graph.add_node("relu", GraphOperator::ReLU, &["left"]);
+            graph.add_node("sum", GraphOperator::Add, &["left", "right"]);
+            graph.add_node("relu_output", GraphOperator::DeviceToHost, &["relu"]);
+            graph.add_node("sum_output", GraphOperator::DeviceToHost, &["sum"]);
+            graph
+        };
+        let left: Tensor2D = training_tensor(0.0, 3, 4);
+        let right: Tensor2D = training_tensor(5.0, 3, 4);
+
+        let mut graph_runner: GraphRunner = GraphRunner::from_dag(
+            &make_graph(Tensor2D::new(0.0, 3, 4), Tensor2D::new(0.0, 3, 4)),
+            false,
+        )
+        .unwrap();
+        // Nothing has been run yet
+        assert!(matches!(
+            graph_runner.get_output("sum_output"),
+            Err(GraphError::UnsupportedOperator(_))
+        ));
+
+        graph_runner.set_input("left", &left).unwrap();
+        graph_runner.set_input("right", &right).unwrap();
+        graph_runner.submit().unwrap();
+        let mut expected: GraphRunner =
+            GraphRunner::from_dag(&make_graph(left.clone(), right.clone()), false).unwrap();
+        let expected: HashMap<String, Tensor2D> = expected.run_all().unwrap();
+        for name in ["relu_output", "sum_output"] {
+            let output: Tensor2D = graph_runner.get_output(name).unwrap();
+            let difference: Tensor2D = subtract_tensors(&expected[name], &output);
+            assert!(difference.data.iter().all(|x| x.abs() < ERROR_TOLERANCE));
+        }
+
+        // The inputs stay set until they are replaced
+        graph_runner.set_input("right", &left).unwrap();
+        graph_runner.submit().unwrap();
+        let sum: Tensor2D = graph_runner.get_output("sum_output").unwrap();
+        for (value, left) in sum.data.iter().zip(left.data.iter()) {
+            assert!((value - 2.0 * left).abs() < ERROR_TOLERANCE);
+        }
+
+        assert!(matches!(
+            graph_runner.set_input("relu", &left),
+            Err(GraphError::UnknownNode(_))
+        ));
+        assert!(matches!(
+            graph_runner.set_input("left", &Tensor2D::new(0.0, 4, 3)),
+            Err(GraphError::DimensionMismatch(_))
+        ));
+        assert!(matches!(
+            graph_runner.get_output("sum"),
+            Err(GraphError::UnknownNode(_))
+        ));
+    }
 }
@@ -0,0 +1,685 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::shared::graph_operators::GraphOperator::*;
+use crate::shared::graph_operators::{GraphOperator, LossKind};
+use crate::shared::tensor2d::Tensor2D;
+
+use super::graph_dag::{GraphDAG, GraphNode};
+use super::graph_error::GraphError;
+use super::graph_validation::{validate_graph_dag, validate_graph_operators};
+
+// A plain text format for saving graphs, so a graph built in one tool
+// can be run by another without rebuilding it in Rust code.
+// Empty lines and lines starting with # are ignored. A sequential graph looks like this:
+//
+//     computational-graph 1
+//     graph sequential 4
+//     weights inline
+//     operator HostToDevice
+//     tensor input 2 2 inline 0 0.5 1 1.5
+//     operator LinearLayer
+//     tensor weights 2 1 inline 0 1
+//     tensor bias 2 1 inline 0 0.1
+//     operator ReLU
+//     operator DeviceToHost
+//
+// A GraphDAG uses node lines instead, with the name of the node and its inputs.
+// A softmax without an axis is written as just Softmax.
+//
+//     node residual Add inputs relu input
+//     node stacked Concat 0 inputs left right
+//     node probabilities Softmax 1 inputs logits
+//     node loss Loss CrossEntropy inputs probabilities
+//     tensor target 1 2 inline 0 1
+//
+// With WeightStorage::Sidecar, the tensors are written as little endian f32's
+// to a binary file next to the graph, and the tensor lines hold the byte offset instead
+//
+//     weights sidecar 48
+//     tensor weights 2 1 sidecar 16
+//
+// The version number has to be bumped whenever a change would make
+// an older loader misread a file. Loading a newer version is an error.
+pub const GRAPH_FORMAT_VERSION: u32 = 1;
+const GRAPH_FORMAT_MAGIC: &str = "computational-graph";
+const SIDECAR_EXTENSION: &str = "bin";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WeightStorage {
+    Inline,
+    Sidecar,
+}
+
+pub struct SerializedGraph {
+    pub text: String,
+    // Empty unless the graph was serialized with WeightStorage::Sidecar
+    pub sidecar: Vec<u8>,
+}
+
+// The operators of a sequential graph are unnamed, the nodes of a DAG aren't.
+struct GraphEntry {
+    name: Option<String>,
+    operator: GraphOperator,
+    inputs: Vec<String>,
+}
+
+fn format_error(line_number: usize, message: String) -> GraphError {
+    GraphError::Serialization(format!("line {}: {}", line_number, message))
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> GraphError {
+    GraphError::Serialization(format!("{}: {}", path.display(), error))
+}
+
+// The sidecar always lives next to the graph file
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    path.with_extension(SIDECAR_EXTENSION)
+}
+
+fn check_name(name: &str) -> Result<(), GraphError> {
+    if name.is_empty() || name.contains(char::is_whitespace) || name.starts_with('#') {
+        return Err(GraphError::Serialization(format!(
+            "The name {:?} can't be serialized, names can't be empty, contain whitespace or start with #.",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+fn write_tensor(
+    text: &mut String,
+    sidecar: &mut Vec<u8>,
+    weight_storage: WeightStorage,
+    name: &str,
+    tensor: &Tensor2D,
+) -> Result<(), GraphError> {
+    if tensor.data.len() < tensor.len() {
+        return Err(GraphError::DimensionMismatch(format!(
+            "The tensor {} is {}x{}, but only holds {} elements.",
+            name,
+            tensor.row_count,
+            tensor.column_count,
+            tensor.data.len()
+        )));
+    }
+
+    write!(
+        text,
+        "tensor {} {} {}",
+        name, tensor.row_count, tensor.column_count
+    )
+    .unwrap();
+    match weight_storage {
+        WeightStorage::Inline => {
+            text.push_str(" inline");
+            // Display for floats prints the shortest representation which
+            // parses back to the exact same value, so nothing is lost.
+            for value in &tensor.data[..tensor.len()] {
+                write!(text, " {}", value).unwrap();
+            }
+        }
+        WeightStorage::Sidecar => {
+            write!(text, " sidecar {}", sidecar.len()).unwrap();
+            for value in &tensor.data[..tensor.len()] {
+                sidecar.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    text.push('\n');
+
+    Ok(())
+}
+
+fn write_graph(
+    kind: &str,
+    entries: &[GraphEntry],
+    weight_storage: WeightStorage,
+) -> Result<SerializedGraph, GraphError> {
+    let mut body: String = String::new();
+    let mut sidecar: Vec<u8> = Vec::<u8>::new();
+
+    for entry in entries {
+        match &entry.name {
+            Some(name) => {
+                check_name(name)?;
+                write!(body, "node {} ", name).unwrap();
+            }
+            None => body.push_str("operator "),
+        }
+
+        body.push_str(GraphDAG::operator_name(&entry.operator));
+        match &entry.operator {
+            Concat { axis } => write!(body, " {}", axis).unwrap(),
+            Softmax { axis: Some(axis) } => write!(body, " {}", axis).unwrap(),
+            Loss { kind, target: _ } => write!(body, " {}", kind.name()).unwrap(),
+            _ => {}
+        }
+
+        if !entry.inputs.is_empty() {
+            body.push_str(" inputs");
+            for input in &entry.inputs {
+                check_name(input)?;
+                write!(body, " {}", input).unwrap();
+            }
+        }
+        body.push('\n');
+
+        match &entry.operator {
+            HostToDevice { input } => {
+                write_tensor(&mut body, &mut sidecar, weight_storage, "input", input)?;
+            }
+            LinearLayer { weights, bias }
+            | LinearReLUFused { weights, bias }
+            | LinearReLUSoftmaxFused { weights, bias } => {
+                write_tensor(&mut body, &mut sidecar, weight_storage, "weights", weights)?;
+                write_tensor(&mut body, &mut sidecar, weight_storage, "bias", bias)?;
+            }
+            Loss { kind: _, target } => {
+                write_tensor(&mut body, &mut sidecar, weight_storage, "target", target)?;
+            }
+            Empty
+            | DeviceToHost
+            | ReLU
+            | Softmax { axis: _ }
+            | LogSoftmax
+            | Add
+            | AddReLUFused
+            | Concat { axis: _ } => {}
+        }
+    }
+
+    let mut text: String = String::new();
+    writeln!(text, "{} {}", GRAPH_FORMAT_MAGIC, GRAPH_FORMAT_VERSION).unwrap();
+    writeln!(text, "graph {} {}", kind, entries.len()).unwrap();
+    match weight_storage {
+        WeightStorage::Inline => writeln!(text, "weights inline").unwrap(),
+        WeightStorage::Sidecar => writeln!(text, "weights sidecar {}", sidecar.len()).unwrap(),
+    }
+    text.push_str(&body);
+
+    Ok(SerializedGraph { text, sidecar })
+}
+
+struct GraphReader<'a> {
+    lines: Vec<(usize, Vec<&'a str>)>,
+    position: usize,
+    sidecar: Option<&'a [u8]>,
+}
+
+impl<'a> GraphReader<'a> {
+    fn new(text: &'a str, sidecar: Option<&'a [u8]>) -> Self {
+        let lines: Vec<(usize, Vec<&'a str>)> = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line_number, line)| (line_number, line.split_whitespace().collect()))
+            .collect();
+
+        GraphReader {
+            lines,
+            position: 0,
+            sidecar,
+        }
+    }
+
+    fn next_line(&mut self) -> Result<(usize, Vec<&'a str>), GraphError> {
+        match self.lines.get(self.position) {
+            Some(line) => {
+                self.position += 1;
+                Ok(line.clone())
+            }
+            None => Err(GraphError::Serialization(
+                "The graph ended unexpectedly.".to_string(),
+            )),
+        }
+    }
+
+    fn parse<T: std::str::FromStr>(
+        line_number: usize,
+        token: Option<&&str>,
+        what: &str,
+    ) -> Result<T, GraphError> {
+        match token {
+            Some(token) => token.parse::<T>().map_err(|_| {
+                format_error(line_number, format!("{} {:?} is not valid", what, token))
+            }),
+            None => Err(format_error(line_number, format!("missing {}", what))),
+        }
+    }
+
+    // Returns the kind of graph and the number of entries
+    fn read_header(&mut self) -> Result<(String, usize), GraphError> {
+        let (line_number, tokens) = self.next_line()?;
+        if tokens.first() != Some(&GRAPH_FORMAT_MAGIC) {
+            return Err(format_error(
+                line_number,
+                format!("expected {} <version>", GRAPH_FORMAT_MAGIC),
+            ));
+        }
+        let version: u32 = Self::parse(line_number, tokens.get(1), "version")?;
+        if version != GRAPH_FORMAT_VERSION {
+            return Err(format_error(
+                line_number,
+                format!(
+                    "version {} is not supported, this loader reads version {}",
+                    version, GRAPH_FORMAT_VERSION
+                ),
+            ));
+        }
+
+        let (line_number, tokens) = self.next_line()?;
+        if tokens.len() != 3 || tokens[0] != "graph" {
+            return Err(format_error(
+                line_number,
+                "expected graph <sequential|dag> <count>".to_string(),
+            ));
+        }
+        let kind: String = tokens[1].to_string();
+        let count: usize = Self::parse(line_number, tokens.get(2), "entry count")?;
+
+        let (line_number, tokens) = self.next_line()?;
+        match tokens.as_slice() {
+            ["weights", "inline"] => {}
+            ["weights", "sidecar", byte_count] => {
+                let byte_count: usize =
+                    Self::parse(line_number, Some(byte_count), "sidecar byte count")?;
+                match self.sidecar {
+                    Some(sidecar) if sidecar.len() == byte_count => {}
+                    Some(sidecar) => {
+                        return Err(format_error(
+                            line_number,
+                            format!(
+                                "expected a sidecar of {} bytes, but it has {} bytes",
+                                byte_count,
+                                sidecar.len()
+                            ),
+                        ));
+                    }
+                    None => {
+                        return Err(format_error(
+                            line_number,
+                            "the weights are in a sidecar file, but no sidecar was given"
+                                .to_string(),
+                        ));
+                    }
+                }
+            }
+            _ => {
+                return Err(format_error(
+                    line_number,
+                    "expected weights inline or weights sidecar <byte count>".to_string(),
+                ));
+            }
+        }
+
+        Ok((kind, count))
+    }
+
+    fn read_tensor(&mut self, expected_name: &str) -> Result<Tensor2D, GraphError> {
+        let (line_number, tokens) = self.next_line()?;
+        if tokens.len() < 5 || tokens[0] != "tensor" || tokens[1] != expected_name {
+            return Err(format_error(
+                line_number,
+                format!(
+                    "expected tensor {} <rows> <columns> <inline|sidecar> ...",
+                    expected_name
+                ),
+            ));
+        }
+        let row_count: usize = Self::parse(line_number, tokens.get(2), "row count")?;
+        let column_count: usize = Self::parse(line_number, tokens.get(3), "column count")?;
+        // The shape comes from the file, so it can claim more values than fit in a usize
+        let element_count: usize = row_count.checked_mul(column_count).ok_or_else(|| {
+            format_error(
+                line_number,
+                format!("a {}x{} tensor is too large", row_count, column_count),
+            )
+        })?;
+
+        let data: Vec<f32> = match tokens[4] {
+            "inline" => {
+                if tokens.len() - 5 != element_count {
+                    return Err(format_error(
+                        line_number,
+                        format!(
+                            "a {}x{} tensor needs {} values, but {} were given",
+                            row_count,
+                            column_count,
+                            element_count,
+                            tokens.len() - 5
+                        ),
+                    ));
+                }
+                tokens[5..]
+                    .iter()
+                    .map(|token| Self::parse(line_number, Some(token), "value"))
+                    .collect::<Result<Vec<f32>, GraphError>>()?
+            }
+            "sidecar" => {
+                let offset: usize = Self::parse(line_number, tokens.get(5), "sidecar offset")?;
+                let end: usize = element_count
+                    .checked_mul(std::mem::size_of::<f32>())
+                    .and_then(|byte_count| offset.checked_add(byte_count))
+                    .ok_or_else(|| {
+                        format_error(
+                            line_number,
+                            format!(
+                                "a {}x{} tensor at byte {} ends past the end of any sidecar",
+                                row_count, column_count, offset
+                            ),
+                        )
+                    })?;
+                let bytes: &[u8] = match self.sidecar {
+                    Some(sidecar) if end <= sidecar.len() => &sidecar[offset..end],
+                    _ => {
+                        return Err(format_error(
+                            line_number,
+                            format!("bytes {} to {} are outside the sidecar", offset, end),
+                        ));
+                    }
+                };
+                bytes
+                    .chunks_exact(std::mem::size_of::<f32>())
+                    .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                    .collect()
+            }
+            storage => {
+                return Err(format_error(
+                    line_number,
+                    format!("unknown tensor storage {}", storage),
+                ));
+            }
+        };
+
+        Ok(Tensor2D {
+            data,
+            row_count,
+            column_count,
+        })
+    }
+
+    fn read_entry(&mut self, kind: &str) -> Result<GraphEntry, GraphError> {
+        let (line_number, tokens) = self.next_line()?;
+
+        let (name, mut index): (Option<String>, usize) = match (kind, tokens.first()) {
+            ("sequential", Some(&"operator")) => (None, 1),
+            ("dag", Some(&"node")) => match tokens.get(1) {
+                Some(name) => (Some(name.to_string()), 2),
+                None => return Err(format_error(line_number, "missing node name".to_string())),
+            },
+            ("sequential", _) => {
+                return Err(format_error(
+                    line_number,
+                    "expected operator <operator>".to_string(),
+                ));
+            }
+            _ => {
+                return Err(format_error(
+                    line_number,
+                    "expected node <name> <operator>".to_string(),
+                ));
+            }
+        };
+
+        let operator_name: &str = match tokens.get(index) {
+            Some(operator_name) => operator_name,
+            None => return Err(format_error(line_number, "missing operator".to_string())),
+        };
+        index += 1;
+
+        let operator: GraphOperator = match operator_name {
+            "Empty" => Empty,
+            "HostToDevice" => HostToDevice {
+                input: Tensor2D::default(),
+            },
+            "DeviceToHost" => DeviceToHost,
+            "LinearLayer" | "LinearReLUFused" | "LinearReLUSoftmaxFused" => LinearLayer {
+                weights: Tensor2D::default(),
+                bias: Tensor2D::default(),
+            },
+            "ReLU" => ReLU,
+            // Without an axis the softmax is over the whole tensor
+            "Softmax" => match tokens.get(index) {
+                Some(&"inputs") | None => Softmax { axis: None },
+                token => {
+                    let axis: usize = Self::parse(line_number, token, "axis")?;
+                    index += 1;
+                    Softmax { axis: Some(axis) }
+                }
+            },
+            "LogSoftmax" => LogSoftmax,
+            "Add" => Add,
+            "AddReLUFused" => AddReLUFused,
+            "Loss" => {
+                let kind: LossKind = match tokens.get(index) {
+                    Some(kind_name) => LossKind::from_name(kind_name).ok_or_else(|| {
+                        format_error(line_number, format!("unknown loss {}", kind_name))
+                    })?,
+                    None => return Err(format_error(line_number, "missing loss".to_string())),
+                };
+                index += 1;
+                Loss {
+                    kind,
+                    target: Tensor2D::default(),
+                }
+            }
+            "Concat" => {
+                let axis: usize = Self::parse(line_number, tokens.get(index), "axis")?;
+                index += 1;
+                Concat { axis }
+            }
+            _ => {
+                return Err(format_error(
+                    line_number,
+                    format!("unknown operator {}", operator_name),
+                ));
+            }
+        };
+
+        let inputs: Vec<String> = match tokens.get(index) {
+            Some(&"inputs") => tokens[index + 1..]
+                .iter()
+                .map(|input| input.to_string())
+                .collect(),
+            Some(token) => {
+                return Err(format_error(
+                    line_number,
+                    format!("unexpected {} after the operator", token),
+                ));
+            }
+            None => Vec::<String>::new(),
+        };
+        if name.is_none() && !inputs.is_empty() {
+            return Err(format_error(
+                line_number,
+                "operators in a sequential graph can't have inputs".to_string(),
+            ));
+        }
+
+        // The tensors belonging to the operator follow on the next lines
+        let operator: GraphOperator = match operator {
+            HostToDevice { input: _ } => HostToDevice {
+                input: self.read_tensor("input")?,
+            },
+            LinearLayer {
+                weights: _,
+                bias: _,
+            } => {
+                let weights: Tensor2D = self.read_tensor("weights")?;
+                let bias: Tensor2D = self.read_tensor("bias")?;
+                match operator_name {
+                    "LinearReLUFused" => LinearReLUFused { weights, bias },
+                    "LinearReLUSoftmaxFused" => LinearReLUSoftmaxFused { weights, bias },
+                    _ => LinearLayer { weights, bias },
+                }
+            }
+            Loss { kind, target: _ } => Loss {
+                kind,
+                target: self.read_tensor("target")?,
+            },
+            operator => operator,
+        };
+
+        Ok(GraphEntry {
+            name,
+            operator,
+            inputs,
+        })
+    }
+
+    fn read_graph(&mut self, expected_kind: &str) -> Result<Vec<GraphEntry>, GraphError> {
+        let (kind, count) = self.read_header()?;
+        if kind != expected_kind {
+            return Err(GraphError::Serialization(format!(
+                "expected a {} graph, but the file holds a {} graph",
+                expected_kind, kind
+            )));
+        }
+
+        // The count comes from the file, so it is only compared against, never allocated for
+        let mut entries: Vec<GraphEntry> = Vec::<GraphEntry>::new();
+        while entries.len() < count && self.position < self.lines.len() {
+            entries.push(self.read_entry(&kind)?);
+        }
+        if entries.len() != count {
+            return Err(GraphError::Serialization(format!(
+                "The graph ended after {} of the {} entries in its header.",
+                entries.len(),
+                count
+            )));
+        }
+
+        if let Some((line_number, _)) = self.lines.get(self.position) {
+            return Err(format_error(
+                *line_number,
+                format!("the graph should have ended after {} entries", count),
+            ));
+        }
+
+        Ok(entries)
+    }
+}
+
+pub fn serialize_graph_operators(
+    graph_operators: &[GraphOperator],
+    weight_storage: WeightStorage,
+) -> Result<SerializedGraph, GraphError> {
+    let entries: Vec<GraphEntry> = graph_operators
+        .iter()
+        .map(|operator| GraphEntry {
+            name: None,
+            operator: operator.clone(),
+            inputs: Vec::<String>::new(),
+        })
+        .collect();
+
+    write_graph("sequential", &entries, weight_storage)
+}
+
+// The loaded graph is validated before it is handed back,
+// so it is ready to be given to a graph runner.
+pub fn deserialize_graph_operators(
+    text: &str,
+    sidecar: Option<&[u8]>,
+) -> Result<Vec<GraphOperator>, GraphError> {
+    let graph_operators: Vec<GraphOperator> = GraphReader::new(text, sidecar)
+        .read_graph("sequential")?
+        .into_iter()
+        .map(|entry| entry.operator)
+        .collect();
+
+    validate_graph_operators(&graph_operators)?;
+
+    Ok(graph_operators)
+}
+
+pub fn serialize_graph_dag(
+    graph: &GraphDAG,
+    weight_storage: WeightStorage,
+) -> Result<SerializedGraph, GraphError> {
+    let entries: Vec<GraphEntry> = graph
+        .nodes
+        .iter()
+        .map(|node| GraphEntry {
+            name: Some(node.name.clone()),
+            operator: node.operator.clone(),
+            inputs: node.inputs.clone(),
+        })
+        .collect();
+
+    write_graph("dag", &entries, weight_storage)
+}
+
+pub fn deserialize_graph_dag(text: &str, sidecar: Option<&[u8]>) -> Result<GraphDAG, GraphError> {
+    let nodes: Vec<GraphNode> = GraphReader::new(text, sidecar)
+        .read_graph("dag")?
+        .into_iter()
+        .map(|entry| GraphNode {
+            name: entry.name.unwrap_or_default(),
+            operator: entry.operator,
+            inputs: entry.inputs,
+        })
+        .collect();
+    let graph: GraphDAG = GraphDAG { nodes };
+
+    validate_graph_dag(&graph)?;
+
+    Ok(graph)
+}
+
+fn save_serialized_graph(path: &Path, serialized: &SerializedGraph) -> Result<(), GraphError> {
+    fs::write(path, &serialized.text).map_err(|error| io_error(path, error))?;
+    if !serialized.sidecar.is_empty() {
+        let sidecar_path: PathBuf = sidecar_path(path);
+        fs::write(&sidecar_path, &serialized.sidecar)
+            .map_err(|error| io_error(&sidecar_path, error))?;
+    }
+
+    Ok(())
+}
+
+// Returns the text of the graph and the contents of the sidecar, if there is one
+fn load_serialized_graph(path: &Path) -> Result<(String, Option<Vec<u8>>), GraphError> {
+    let text: String = fs::read_to_string(path).map_err(|error| io_error(path, error))?;
+    let sidecar_path: PathBuf = sidecar_path(path);
+    let sidecar: Option<Vec<u8>> = if sidecar_path.exists() {
+        Some(fs::read(&sidecar_path).map_err(|error| io_error(&sidecar_path, error))?)
+    } else {
+        None
+    };
+
+    Ok((text, sidecar))
+}
+
+pub fn save_graph_operators(
+    path: &Path,
+    graph_operators: &[GraphOperator],
+    weight_storage: WeightStorage,
+) -> Result<(), GraphError> {
+    save_serialized_graph(
+        path,
+        &serialize_graph_operators(graph_operators, weight_storage)?,
+    )
+}
+
+pub fn load_graph_operators(path: &Path) -> Result<Vec<GraphOperator>, GraphError> {
+    let (text, sidecar) = load_serialized_graph(path)?;
+    deserialize_graph_operators(&text, sidecar.as_deref())
+}
+
+pub fn save_graph_dag(
+    path: &Path,
+    graph: &GraphDAG,
+    weight_storage: WeightStorage,
+) -> Result<(), GraphError> {
+    save_serialized_graph(path, &serialize_graph_dag(graph, weight_storage)?)
+}
+
+pub fn load_graph_dag(path: &Path) -> Result<GraphDAG, GraphError> {
+    let (text, sidecar) = load_serialized_graph(path)?;
+    deserialize_graph_dag(&text, sidecar.as_deref())
+}
@@ -0,0 +1,326 @@
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        graph::{
+            graph_dag::GraphDAG,
+            graph_error::GraphError,
+            graph_runner::GraphRunner,
+            graph_serialization::{
+                deserialize_graph_dag, deserialize_graph_operators, load_graph_operators,
+                save_graph_operators, serialize_graph_dag, serialize_graph_operators, sidecar_path,
+                SerializedGraph, WeightStorage,
+            },
+        },
+        shared::{
+            graph_operators::{GraphOperator, LossKind},
+            tensor2d::Tensor2D,
+        },
+    };
+
+    fn build_graph() -> Vec<GraphOperator> {
+        let mut input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+        // Make sure values without a short decimal representation survive as well
+        input.data[1] = 0.1 + 0.2;
+        input.data[2] = -1.0 / 3.0;
+        let weights: Tensor2D = Tensor2D::new(1.0, 4, 2);
+        let bias: Tensor2D = Tensor2D::new(0.1, 3, 2);
+
+        vec![
+            GraphOperator::HostToDevice { input },
+            GraphOperator::LinearReLUFused {
+                weights,
+                bias: bias.clone(),
+            },
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(1.0, 2, 2),
+                bias,
+            },
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::DeviceToHost,
+        ]
+    }
+
+    fn assert_same_output(left: &Vec<GraphOperator>, right: &Vec<GraphOperator>) {
+        let left: Tensor2D = GraphRunner::new(left, false).unwrap().run().unwrap();
+        let right: Tensor2D = GraphRunner::new(right, false).unwrap().run().unwrap();
+        assert_eq!(left.row_count, right.row_count);
+        assert_eq!(left.column_count, right.column_count);
+        assert_eq!(left.data, right.data);
+    }
+
+    #[test]
+    fn inline_round_trip() {
+        let graph_operators: Vec<GraphOperator> = build_graph();
+
+        let serialized: SerializedGraph =
+            serialize_graph_operators(&graph_operators, WeightStorage::Inline).unwrap();
+        assert!(serialized.sidecar.is_empty());
+
+        let loaded: Vec<GraphOperator> =
+            deserialize_graph_operators(&serialized.text, None).unwrap();
+        assert_eq!(loaded.len(), graph_operators.len());
+        assert!(matches!(loaded[1], GraphOperator::LinearReLUFused { .. }));
+        assert_same_output(&graph_operators, &loaded);
+    }
+
+    #[test]
+    fn sidecar_round_trip() {
+        let graph_operators: Vec<GraphOperator> = build_graph();
+
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "computational_graphs_sidecar_round_trip_{}.graph",
+            std::process::id()
+        ));
+        save_graph_operators(&path, &graph_operators, WeightStorage::Sidecar).unwrap();
+        let loaded: Vec<GraphOperator> = load_graph_operators(&path).unwrap();
+
+        // A sidecar graph can't be loaded without its weights
+        std::fs::remove_file(sidecar_path(&path)).unwrap();
+        let missing_sidecar: Result<Vec<GraphOperator>, GraphError> = load_graph_operators(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_same_output(&graph_operators, &loaded);
+        assert!(matches!(missing_sidecar, Err(GraphError::Serialization(_))));
+    }
+
+    #[test]
+    fn dag_round_trip() {
+        let input: Tensor2D = Tensor2D::new(0.5, 3, 4);
+        let weights: Tensor2D = Tensor2D::new(1.0, 4, 4);
+        let bias: Tensor2D = Tensor2D::new(0.1, 3, 4);
+
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node("input", GraphOperator::HostToDevice { input }, &[]);
+        graph.add_node(
+            "linear",
+            GraphOperator::LinearLayer { weights, bias },
+            &["input"],
+        );
+        graph.add_node("relu", GraphOperator::ReLU, &["linear"]);
+        graph.add_node("residual", GraphOperator::Add, &["relu", "input"]);
+        graph.add_node(
+            "stacked",
+            GraphOperator::Concat { axis: 1 },
+            &["residual", "input"],
+        );
+        graph.add_node("output", GraphOperator::DeviceToHost, &["stacked"]);
+
+        for weight_storage in [WeightStorage::Inline, WeightStorage::Sidecar] {
+            let serialized: SerializedGraph = serialize_graph_dag(&graph, weight_storage).unwrap();
+            let loaded: GraphDAG =
+                deserialize_graph_dag(&serialized.text, Some(&serialized.sidecar)).unwrap();
+
+            for (original, loaded) in graph.nodes.iter().zip(loaded.nodes.iter()) {
+                assert_eq!(original.name, loaded.name);
+                assert_eq!(original.inputs, loaded.inputs);
+            }
+
+            let expected: Tensor2D = GraphRunner::from_dag(&graph, false).unwrap().run().unwrap();
+            let output: Tensor2D = GraphRunner::from_dag(&loaded, false)
+                .unwrap()
+                .run()
+                .unwrap();
+            assert_eq!(expected.data, output.data);
+        }
+    }
+
+    #[test]
+    fn loss_round_trip() {
+        let mut target: Tensor2D = Tensor2D::new(0.0, 3, 4);
+        target.data[5] = 1.0;
+        let graph_operators: Vec<GraphOperator> = vec![
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.5, 3, 4),
+            },
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::Loss {
+                kind: LossKind::CrossEntropy,
+                target,
+            },
+            GraphOperator::DeviceToHost,
+        ];
+
+        let serialized: SerializedGraph =
+            serialize_graph_operators(&graph_operators, WeightStorage::Inline).unwrap();
+        let loaded: Vec<GraphOperator> =
+            deserialize_graph_operators(&serialized.text, None).unwrap();
+        assert!(matches!(loaded[2], GraphOperator::Loss { .. }));
+        assert_same_output(&graph_operators, &loaded);
+
+        // The kind of the loss survives the round trip
+        for kind in [
+            LossKind::NegativeLogLikelihood,
+            LossKind::MeanSquaredError,
+            LossKind::MeanAbsoluteError,
+        ] {
+            let mut graph_operators: Vec<GraphOperator> = graph_operators.clone();
+            graph_operators[1] = GraphOperator::LogSoftmax;
+            if let GraphOperator::Loss {
+                kind: loss_kind, ..
+            } = &mut graph_operators[2]
+            {
+                *loss_kind = kind;
+            }
+
+            let serialized: SerializedGraph =
+                serialize_graph_operators(&graph_operators, WeightStorage::Inline).unwrap();
+            let loaded: Vec<GraphOperator> =
+                deserialize_graph_operators(&serialized.text, None).unwrap();
+            assert!(matches!(loaded[1], GraphOperator::LogSoftmax));
+            assert!(
+                matches!(loaded[2], GraphOperator::Loss { kind: loaded_kind, .. } if loaded_kind == kind)
+            );
+            assert_same_output(&graph_operators, &loaded);
+        }
+
+        let text: String = serialized.text.replace("CrossEntropy", "Hinge");
+        assert!(deserialize_graph_operators(&text, None).is_err());
+    }
+
+    #[test]
+    fn softmax_axis_round_trip() {
+        // In a DAG the axis is followed by the inputs, and the global softmax has no axis
+        let mut graph: GraphDAG = GraphDAG::new();
+        graph.add_node(
+            "input",
+            GraphOperator::HostToDevice {
+                input: Tensor2D::new(0.5, 3, 4),
+            },
+            &[],
+        );
+        graph.add_node(
+            "linear",
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(0.3, 4, 4),
+                bias: Tensor2D::new(0.1, 3, 4),
+            },
+            &["input"],
+        );
+        graph.add_node(
+            "rows",
+            GraphOperator::Softmax { axis: Some(1) },
+            &["linear"],
+        );
+        graph.add_node(
+            "columns",
+            GraphOperator::Softmax { axis: Some(0) },
+            &["rows"],
+        );
+        graph.add_node(
+            "global",
+            GraphOperator::Softmax { axis: None },
+            &["columns"],
+        );
+        graph.add_node("output", GraphOperator::DeviceToHost, &["global"]);
+
+        let serialized: SerializedGraph =
+            serialize_graph_dag(&graph, WeightStorage::Inline).unwrap();
+        let loaded: GraphDAG = deserialize_graph_dag(&serialized.text, None).unwrap();
+        for (original, loaded) in graph.nodes.iter().zip(loaded.nodes.iter()) {
+            assert_eq!(original.name, loaded.name);
+            assert_eq!(original.inputs, loaded.inputs);
+        }
+        assert!(matches!(
+            loaded.nodes[2].operator,
+            GraphOperator::Softmax { axis: Some(1) }
+        ));
+        assert!(matches!(
+            loaded.nodes[3].operator,
+            GraphOperator::Softmax { axis: Some(0) }
+        ));
+        assert!(matches!(
+            loaded.nodes[4].operator,
+            GraphOperator::Softmax { axis: None }
+        ));
+
+        let expected: Tensor2D = GraphRunner::from_dag(&graph, false).unwrap().run().unwrap();
+        let output: Tensor2D = GraphRunner::from_dag(&loaded, false)
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(expected.data, output.data);
+
+        let text: String = serialized.text.replace("Softmax 1", "Softmax rows");
+        assert!(deserialize_graph_dag(&text, None).is_err());
+    }
+
+    #[test]
+    fn invalid_files() {
+        let serialized: SerializedGraph =
+            serialize_graph_operators(&build_graph(), WeightStorage::Inline).unwrap();
+
+        // A newer version than we know how to read
+        let newer_version: String =
+            serialized
+                .text
+                .replacen("computational-graph 1", "computational-graph 2", 1);
+        let result: Result<Vec<GraphOperator>, GraphError> =
+            deserialize_graph_operators(&newer_version, None);
+        assert!(matches!(result, Err(GraphError::Serialization(_))));
+
+        // A sequential graph isn't a DAG
+        let result: Result<GraphDAG, GraphError> = deserialize_graph_dag(&serialized.text, None);
+        assert!(matches!(result, Err(GraphError::Serialization(_))));
+
+        let truncated: String = serialized
+            .text
+            .lines()
+            .take(5)
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let result: Result<Vec<GraphOperator>, GraphError> =
+            deserialize_graph_operators(&truncated, None);
+        assert!(matches!(result, Err(GraphError::Serialization(_))));
+
+        // Well formed, but the graph itself is invalid, which the loader should catch
+        let text: &str = "computational-graph 1
+graph sequential 3
+weights inline
+# The weights don't match the input
+operator HostToDevice
+tensor input 1 2 inline 1 2
+operator LinearLayer
+tensor weights 3 1 inline 1 2 3
+tensor bias 1 1 inline 0
+operator DeviceToHost
+";
+        let result: Result<Vec<GraphOperator>, GraphError> =
+            deserialize_graph_operators(text, None);
+        assert!(matches!(result, Err(GraphError::DimensionMismatch(_))));
+
+        // Sizes from the file which don't fit in a usize shouldn't panic or allocate
+        let text: &str = "computational-graph 1
+graph sequential 18446744073709551615
+weights inline
+operator HostToDevice
+tensor input 1 2 inline 1 2
+";
+        let result: Result<Vec<GraphOperator>, GraphError> =
+            deserialize_graph_operators(text, None);
+        assert!(matches!(result, Err(GraphError::Serialization(_))));
+
+        let text: &str = "computational-graph 1
+graph sequential 2
+weights inline
+operator HostToDevice
+tensor input 4294967296 4294967296 inline
+operator DeviceToHost
+";
+        let result: Result<Vec<GraphOperator>, GraphError> =
+            deserialize_graph_operators(text, None);
+        assert!(matches!(result, Err(GraphError::Serialization(_))));
+
+        let text: &str = "computational-graph 1
+graph sequential 2
+weights sidecar 8
+operator HostToDevice
+tensor input 1 2 sidecar 18446744073709551615
+operator DeviceToHost
+";
+        let result: Result<Vec<GraphOperator>, GraphError> =
+            deserialize_graph_operators(text, Some(&[0; 8]));
+        assert!(matches!(result, Err(GraphError::Serialization(_))));
+    }
+}
@@ -1,85 +1,123 @@
-use crate::shared::graph_operators::GraphOperator;
 use crate::shared::graph_operators::GraphOperator::*;
+use crate::shared::graph_operators::{GraphOperator, LossKind};
 use crate::shared::tensor2d::Tensor2D;
 
-pub fn linear_layer_dimension_check(input: &Tensor2D, weights: &Tensor2D, bias: &Tensor2D) {
-    assert!(
-        0 < input.row_count,
-        "\ninput.row_count must be larger than 0. Current value: {}.",
-        input.row_count
-    );
-
-    assert!(
-        0 < input.column_count,
-        "\ninput.column_count must be larger than 0. Current value: {}.",
-        input.column_count
-    );
-
-    assert!(
-        0 < weights.row_count,
-        "\nweights.row_count must be larger than 0. Current value: {}.",
-        weights.row_count
-    );
-
-    assert!(
-        0 < weights.column_count,
-        "\nweights.column_count must be larger than 0. Current value: {}.",
-        weights.column_count
-    );
-
-    assert!(
-        0 < bias.row_count,
-        "\nbias.row_count must be larger than 0. Current value: {}.",
-        bias.row_count
-    );
-
-    assert!(
-        0 < bias.column_count,
-        "\nbias.column_count must be larger than 0. Current value: {}.",
-        bias.column_count
-    );
-
-    assert_eq!(
-        input.column_count,
-        weights.row_count,
-        "\nMismatch - input.column_count & weights.row_count\ninput - rows: {} columns: {}.\n weights - rows: {} columns: {}.", 
-        input.row_count,
-        input.column_count,
-        weights.row_count,
-        weights.column_count
-    );
+use super::graph_dag::{GraphDAG, GraphNode};
+use super::graph_error::GraphError;
+
+fn nonzero_dimension_check(name: &str, tensor: &Tensor2D) -> Result<(), GraphError> {
+    if tensor.row_count == 0 || tensor.column_count == 0 {
+        return Err(GraphError::DimensionMismatch(format!(
+            "{} must have more than 0 rows and columns. Current value - rows: {} columns: {}.",
+            name, tensor.row_count, tensor.column_count
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn linear_layer_dimension_check(
+    input: &Tensor2D,
+    weights: &Tensor2D,
+    bias: &Tensor2D,
+) -> Result<(), GraphError> {
+    nonzero_dimension_check("input", input)?;
+    nonzero_dimension_check("weights", weights)?;
+    nonzero_dimension_check("bias", bias)?;
+
+    if input.column_count != weights.row_count {
+        return Err(GraphError::DimensionMismatch(format!(
+            "input.column_count & weights.row_count\ninput - rows: {} columns: {}.\n weights - rows: {} columns: {}.",
+            input.row_count,
+            input.column_count,
+            weights.row_count,
+            weights.column_count
+        )));
+    }
+
+    // The output of the linear layer is written in to a buffer the size of the bias
+    if input.row_count != bias.row_count || weights.column_count != bias.column_count {
+        return Err(GraphError::DimensionMismatch(format!(
+            "bias has to be input.row_count x weights.column_count\ninput - rows: {} columns: {}.\n weights - rows: {} columns: {}.\n bias - rows: {} columns: {}.",
+            input.row_count,
+            input.column_count,
+            weights.row_count,
+            weights.column_count,
+            bias.row_count,
+            bias.column_count
+        )));
+    }
+
+    Ok(())
+}
+
+// The target is compared element by element with the input,
+// so they need the exact same shape
+fn loss_dimension_check(
+    name: &str,
+    kind: LossKind,
+    input_shape: (usize, usize),
+    target: &Tensor2D,
+) -> Result<(), GraphError> {
+    nonzero_dimension_check("target", target)?;
+
+    if input_shape != (target.row_count, target.column_count) {
+        return Err(GraphError::DimensionMismatch(format!(
+            "{} loss {} received an input with shape {:?}, but the target has shape {:?}.",
+            kind.name(),
+            name,
+            input_shape,
+            (target.row_count, target.column_count)
+        )));
+    }
+
+    Ok(())
 }
 
 // Every dimension is legal in this operator, it is up to the other operators to reject
-fn validate_host_to_device(current_index: usize, graph: &[GraphOperator]) -> bool {
+fn validate_host_to_device(
+    current_index: usize,
+    graph: &[GraphOperator],
+) -> Result<(), GraphError> {
     if let HostToDevice { input: _ } = &graph[current_index] {
         if current_index != 0 {
-            println!("Something went wrong in validate_host_to_device. The HostToDevice operator was not at the beginning of the graph.");
-            return false;
+            return Err(GraphError::MisplacedTransfer(format!(
+                "The HostToDevice operator was at index {}, not at the beginning of the graph.",
+                current_index
+            )));
         }
     } else {
-        println!("Something went wrong in validate_host_to_device. Current operator was not HostToDevice");
-        return false;
+        return Err(GraphError::UnsupportedOperator(format!(
+            "validate_host_to_device was called on {:?}.",
+            graph[current_index]
+        )));
     }
 
-    true
+    Ok(())
 }
 
 // Every dimension is legal in this operator, it is up to the other operators to reject.
 // Normally this wouldn't be, but we have elected to overwrite the existing data whenever
 // an output is transferred back to the host.
-fn validate_device_to_host(current_index: usize, graph: &Vec<GraphOperator>) -> bool {
+fn validate_device_to_host(
+    current_index: usize,
+    graph: &[GraphOperator],
+) -> Result<(), GraphError> {
     if let DeviceToHost = &graph[current_index] {
         if current_index != (graph.len() - 1) {
-            println!("Something went wrong in validate_device_to_host. Current operator was not DeviceToHost");
-            return false;
+            return Err(GraphError::MisplacedTransfer(format!(
+                "The DeviceToHost operator was at index {}, not at the end of the graph.",
+                current_index
+            )));
         }
     } else {
-        println!("Something went wrong in validate_device_to_host. Current operator was not DeviceToHost");
-        return false;
+        return Err(GraphError::UnsupportedOperator(format!(
+            "validate_device_to_host was called on {:?}.",
+            graph[current_index]
+        )));
     }
 
-    true
+    Ok(())
 }
 
 fn validate_linear_dimensions(
@@ -87,31 +125,41 @@ fn validate_linear_dimensions(
     graph: &[GraphOperator],
     current_weights: &Tensor2D,
     current_bias: &Tensor2D,
-) -> bool {
+) -> Result<(), GraphError> {
     // Search for nearest dimension dictating operation
     for predecessor_index in (0..current_index).rev() {
         match &graph[predecessor_index] {
             HostToDevice { input } => {
-                linear_layer_dimension_check(input, current_weights, current_bias);
-                return true;
+                return linear_layer_dimension_check(input, current_weights, current_bias);
             }
             LinearLayer { weights: _, bias } => {
-                linear_layer_dimension_check(bias, current_weights, current_bias);
-                return true;
+                return linear_layer_dimension_check(bias, current_weights, current_bias);
             }
             LinearReLUFused { weights: _, bias } => {
-                linear_layer_dimension_check(bias, current_weights, current_bias);
-                return true;
+                return linear_layer_dimension_check(bias, current_weights, current_bias);
             }
             LinearReLUSoftmaxFused { weights: _, bias } => {
-                linear_layer_dimension_check(bias, current_weights, current_bias);
-                return true;
+                return linear_layer_dimension_check(bias, current_weights, current_bias);
+            }
+            Loss { kind: _, target: _ } => {
+                let loss: Tensor2D = Tensor2D {
+                    data: Vec::<f32>::new(),
+                    row_count: 1,
+                    column_count: 1,
+                };
+                return linear_layer_dimension_check(&loss, current_weights, current_bias);
             }
             DeviceToHost => {
-                panic!("Found a DeviceToHost node before a linear layer node. This wasn't part of the contrived example!");
+                return Err(GraphError::MisplacedTransfer(format!(
+                    "Found a DeviceToHost operator at index {} before the linear layer at index {}.",
+                    predecessor_index, current_index
+                )));
             }
             Empty => {
-                panic!("Found an Empty node before a linear layer node. This wasn't part of the contrived example!");
+                return Err(GraphError::UnsupportedOperator(format!(
+                    "Found an Empty operator at index {} before the linear layer at index {}.",
+                    predecessor_index, current_index
+                )));
             }
             _ => {
                 //Predecessor operator was probably ReLU or Softmax
@@ -119,34 +167,78 @@ fn validate_linear_dimensions(
         }
     }
 
-    true
+    Ok(())
 }
 
-fn validate_relu(current_index: usize, graph: &[GraphOperator]) -> bool {
-    if let ReLU {} = &graph[current_index] {
+fn validate_relu(current_index: usize, graph: &[GraphOperator]) -> Result<(), GraphError> {
+    if let ReLU = &graph[current_index] {
     } else {
-        println!("Something went wrong in validate_relu. Current operator was not HostToDevice");
-        return false;
+        return Err(GraphError::UnsupportedOperator(format!(
+            "validate_relu was called on {:?}.",
+            graph[current_index]
+        )));
     }
 
-    true
+    Ok(())
 }
 
-fn validate_softmax(current_index: usize, graph: &[GraphOperator]) -> bool {
-    if let Softmax {} = &graph[current_index] {
+fn validate_softmax(current_index: usize, graph: &[GraphOperator]) -> Result<(), GraphError> {
+    if let Softmax { axis: Some(axis) } = &graph[current_index] {
+        softmax_axis_check(&format!("at index {}", current_index), *axis)?;
+    } else if let Softmax { axis: None } | LogSoftmax = &graph[current_index] {
     } else {
-        println!("Something went wrong in validate_softmax. Current operator was not HostToDevice");
-        return false;
+        return Err(GraphError::UnsupportedOperator(format!(
+            "validate_softmax was called on {:?}.",
+            graph[current_index]
+        )));
     }
 
-    true
+    Ok(())
+}
+
+fn softmax_axis_check(location: &str, axis: usize) -> Result<(), GraphError> {
+    if 1 < axis {
+        return Err(GraphError::UnsupportedOperator(format!(
+            "Softmax {} has axis {}, only 0 and 1 are supported for 2D tensors.",
+            location, axis
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_loss(
+    current_index: usize,
+    graph: &[GraphOperator],
+    kind: LossKind,
+    target: &Tensor2D,
+) -> Result<(), GraphError> {
+    // Search for nearest dimension dictating operation, the activations keep the shape
+    for predecessor_index in (0..current_index).rev() {
+        let input_shape: (usize, usize) = match &graph[predecessor_index] {
+            HostToDevice { input } => (input.row_count, input.column_count),
+            LinearLayer { weights: _, bias }
+            | LinearReLUFused { weights: _, bias }
+            | LinearReLUSoftmaxFused { weights: _, bias } => (bias.row_count, bias.column_count),
+            Loss { kind: _, target: _ } => (1, 1),
+            _ => continue,
+        };
+        return loss_dimension_check(
+            &format!("at index {}", current_index),
+            kind,
+            input_shape,
+            target,
+        );
+    }
+
+    Ok(())
 }
 
 // For our contrived example, for a graph to be valid it has to begin
-// with HostToDevice and end with DeviceToHost, perhaps later
-// we will support running the same input in a loop, or
-// running a graph with new input every time.
-fn validate_transfers(graph: &Vec<GraphOperator>) -> bool {
+// with HostToDevice and end with DeviceToHost. The input of HostToDevice
+// is only the initial input, the runners can be given new input of the
+// same shape every time with run_with_input and run_batch.
+fn validate_transfers(graph: &[GraphOperator]) -> Result<(), GraphError> {
     let mut found_valid_host_to_device: bool = false;
     let mut found_valid_device_to_host: bool = false;
 
@@ -154,64 +246,268 @@ fn validate_transfers(graph: &Vec<GraphOperator>) -> bool {
         let operator: &GraphOperator = &graph[index];
         match operator {
             HostToDevice { input } => {
-                if index == 0 {
-                    if 0 < input.row_count && 0 < input.column_count {
-                        found_valid_host_to_device = true;
-                    }
-                } else {
-                    return false;
+                if index != 0 {
+                    return Err(GraphError::MisplacedTransfer(format!(
+                        "HostToDevice has to be the first operator, but was found at index {}.",
+                        index
+                    )));
                 }
+                nonzero_dimension_check("HostToDevice input", input)?;
+                found_valid_host_to_device = true;
             }
             DeviceToHost => {
-                if index == graph.len() - 1 {
-                    found_valid_device_to_host = true;
-                } else {
-                    return false;
+                if index != graph.len() - 1 {
+                    return Err(GraphError::MisplacedTransfer(format!(
+                        "DeviceToHost has to be the last operator, but was found at index {} of {}.",
+                        index,
+                        graph.len()
+                    )));
                 }
+                found_valid_device_to_host = true;
             }
             _ => {}
         }
     }
 
-    found_valid_device_to_host && found_valid_host_to_device
+    if !found_valid_host_to_device {
+        return Err(GraphError::MisplacedTransfer(
+            "The graph has to begin with a HostToDevice operator.".to_string(),
+        ));
+    }
+
+    if !found_valid_device_to_host {
+        return Err(GraphError::MisplacedTransfer(
+            "The graph has to end with a DeviceToHost operator.".to_string(),
+        ));
+    }
+
+    Ok(())
 }
 
 // Just for learning purposes the only real requirements we will have will be
 // matching dimensions and each graph beginning with a transfer to device
 // and ending with a transfer from device
 // All validation is retrospective, each operator will look for valid predecessors.
-pub fn validate_graph_operators(graph: &Vec<GraphOperator>) -> bool {
-    let graph_length: usize = graph.len();
-    let mut graph_is_validated: bool = true;
-
-    graph_is_validated = graph_is_validated && validate_transfers(graph);
+// The first problem found is returned.
+pub fn validate_graph_operators(graph: &[GraphOperator]) -> Result<(), GraphError> {
+    validate_transfers(graph)?;
 
     // Scanning graph for valid sizes
-    for current_index in 0..graph_length {
+    for current_index in 0..graph.len() {
         let current: &GraphOperator = &graph[current_index];
 
-        let valid_operator: bool = match current {
+        match current {
             GraphOperator::Empty => {
                 continue;
             }
             GraphOperator::HostToDevice { input: _ } => {
-                validate_host_to_device(current_index, graph)
+                validate_host_to_device(current_index, graph)?
             }
-            GraphOperator::DeviceToHost => validate_device_to_host(current_index, graph),
+            GraphOperator::DeviceToHost => validate_device_to_host(current_index, graph)?,
             GraphOperator::LinearLayer { weights, bias } => {
-                validate_linear_dimensions(current_index, graph, weights, bias)
+                validate_linear_dimensions(current_index, graph, weights, bias)?
+            }
+            GraphOperator::ReLU => validate_relu(current_index, graph)?,
+            GraphOperator::Softmax { axis: _ } | GraphOperator::LogSoftmax => {
+                validate_softmax(current_index, graph)?
             }
-            GraphOperator::ReLU => validate_relu(current_index, graph),
-            GraphOperator::Softmax => validate_softmax(current_index, graph),
             GraphOperator::LinearReLUFused { weights, bias } => {
-                validate_linear_dimensions(current_index, graph, weights, bias)
+                validate_linear_dimensions(current_index, graph, weights, bias)?
             }
             GraphOperator::LinearReLUSoftmaxFused { weights, bias } => {
-                validate_linear_dimensions(current_index, graph, weights, bias)
+                validate_linear_dimensions(current_index, graph, weights, bias)?
+            }
+            GraphOperator::Loss { kind, target } => {
+                validate_loss(current_index, graph, *kind, target)?
+            }
+            GraphOperator::Add
+            | GraphOperator::AddReLUFused
+            | GraphOperator::Concat { axis: _ } => {
+                return Err(GraphError::UnsupportedOperator(format!(
+                    "{:?} at index {} takes more than one input, which requires a GraphDAG.",
+                    current, current_index
+                )));
             }
         };
-        graph_is_validated = graph_is_validated && valid_operator;
     }
 
-    graph_is_validated
+    Ok(())
+}
+
+fn input_count_check(node: &GraphNode, expected: usize) -> Result<(), GraphError> {
+    if node.inputs.len() != expected {
+        return Err(GraphError::MalformedNode(format!(
+            "Node {} with operator {} expected {} inputs, received {:?}.",
+            node.name,
+            GraphDAG::operator_name(&node.operator),
+            expected,
+            node.inputs
+        )));
+    }
+
+    Ok(())
+}
+
+// The DAG version of validate_graph_operators. Instead of looking back
+// for the previous linear layer, the shapes of every tensor are propagated
+// through the graph in topological order. Every input has to be a HostToDevice
+// and every tensor which leaves the graph has to go through a DeviceToHost.
+// Returns the order in which the nodes should be executed.
+pub fn validate_graph_dag(graph: &GraphDAG) -> Result<Vec<usize>, GraphError> {
+    let order: Vec<usize> = graph.topological_order()?;
+    let consumers: Vec<Vec<usize>> = graph.consumers()?;
+
+    if graph.input_names().is_empty() {
+        return Err(GraphError::MisplacedTransfer(
+            "The graph needs at least one HostToDevice operator.".to_string(),
+        ));
+    }
+
+    if graph.output_names().is_empty() {
+        return Err(GraphError::MisplacedTransfer(
+            "The graph needs at least one DeviceToHost operator.".to_string(),
+        ));
+    }
+
+    // (row_count, column_count) of the tensor produced by each node
+    let mut shapes: Vec<(usize, usize)> = vec![(0, 0); graph.nodes.len()];
+    for node_index in &order {
+        let node: &GraphNode = &graph.nodes[*node_index];
+        let input_shapes: Vec<(usize, usize)> = node
+            .inputs
+            .iter()
+            .map(|input| {
+                shapes[graph
+                    .node_index(input)
+                    .expect("topological_order already checked the input names")]
+            })
+            .collect();
+
+        // Nothing is allowed to read from a DeviceToHost, it is the end of the line
+        for input in &node.inputs {
+            let input_index: usize = graph.node_index(input).unwrap();
+            if let DeviceToHost = graph.nodes[input_index].operator {
+                return Err(GraphError::MisplacedTransfer(format!(
+                    "Node {} takes the DeviceToHost node {} as input.",
+                    node.name, input
+                )));
+            }
+        }
+
+        shapes[*node_index] = match &node.operator {
+            Empty => {
+                return Err(GraphError::UnsupportedOperator(format!(
+                    "Node {} is Empty, which is only supported in sequential graphs.",
+                    node.name
+                )));
+            }
+            HostToDevice { input } => {
+                input_count_check(node, 0)?;
+                nonzero_dimension_check(&node.name, input)?;
+                (input.row_count, input.column_count)
+            }
+            DeviceToHost => {
+                input_count_check(node, 1)?;
+                input_shapes[0]
+            }
+            LinearLayer { weights, bias }
+            | LinearReLUFused { weights, bias }
+            | LinearReLUSoftmaxFused { weights, bias } => {
+                input_count_check(node, 1)?;
+                let input: Tensor2D = Tensor2D {
+                    data: Vec::<f32>::new(),
+                    row_count: input_shapes[0].0,
+                    column_count: input_shapes[0].1,
+                };
+                linear_layer_dimension_check(&input, weights, bias).map_err(|error| {
+                    GraphError::DimensionMismatch(format!("In node {} - {}", node.name, error))
+                })?;
+                (bias.row_count, bias.column_count)
+            }
+            ReLU | LogSoftmax => {
+                input_count_check(node, 1)?;
+                input_shapes[0]
+            }
+            Softmax { axis } => {
+                input_count_check(node, 1)?;
+                if let Some(axis) = axis {
+                    softmax_axis_check(&format!("node {}", node.name), *axis)?;
+                }
+                input_shapes[0]
+            }
+            Loss { kind, target } => {
+                input_count_check(node, 1)?;
+                loss_dimension_check(&node.name, *kind, input_shapes[0], target)?;
+                (1, 1)
+            }
+            Add | AddReLUFused => {
+                input_count_check(node, 2)?;
+                if input_shapes[0] != input_shapes[1] {
+                    return Err(GraphError::DimensionMismatch(format!(
+                        "{} node {} received {} with shape {:?} and {} with shape {:?}.",
+                        GraphDAG::operator_name(&node.operator),
+                        node.name,
+                        node.inputs[0],
+                        input_shapes[0],
+                        node.inputs[1],
+                        input_shapes[1]
+                    )));
+                }
+                input_shapes[0]
+            }
+            Concat { axis } => {
+                if node.inputs.len() < 2 {
+                    return Err(GraphError::MalformedNode(format!(
+                        "Concat node {} needs at least 2 inputs, received {:?}.",
+                        node.name, node.inputs
+                    )));
+                }
+                if 1 < *axis {
+                    return Err(GraphError::UnsupportedOperator(format!(
+                        "Concat node {} has axis {}, only 0 and 1 are supported for 2D tensors.",
+                        node.name, axis
+                    )));
+                }
+
+                // Every dimension other than the concatenated one has to match
+                let kept: usize = if *axis == 0 {
+                    input_shapes[0].1
+                } else {
+                    input_shapes[0].0
+                };
+                let mut concatenated: usize = 0;
+                for (input_index, shape) in input_shapes.iter().enumerate() {
+                    let (shape_kept, shape_concatenated): (usize, usize) = if *axis == 0 {
+                        (shape.1, shape.0)
+                    } else {
+                        (shape.0, shape.1)
+                    };
+                    if shape_kept != kept {
+                        return Err(GraphError::DimensionMismatch(format!(
+                            "Concat node {} along axis {} received {} with shape {:?}, which does not match {} with shape {:?}.",
+                            node.name, axis, node.inputs[input_index], shape, node.inputs[0], input_shapes[0]
+                        )));
+                    }
+                    concatenated += shape_concatenated;
+                }
+
+                if *axis == 0 {
+                    (concatenated, kept)
+                } else {
+                    (kept, concatenated)
+                }
+            }
+        };
+
+        // A tensor with no consumers would be computed and thrown away,
+        // which is most likely a mistake in the graph description.
+        if consumers[*node_index].is_empty() && !matches!(node.operator, DeviceToHost) {
+            return Err(GraphError::MisplacedTransfer(format!(
+                "The output of node {} is never used. Connect it to a DeviceToHost operator.",
+                node.name
+            )));
+        }
+    }
+
+    Ok(order)
 }
@@ -0,0 +1,238 @@
+use std::fmt;
+
+use crate::shared::graph_operators::GraphOperator;
+
+use super::graph_dag::GraphDAG;
+
+// Without planning, every node in the graph gets its own output buffer,
+// so the memory needed grows linearly with the depth of the graph.
+// But once every consumer of an intermediate result has run, its buffer
+// is dead and can be handed to a node further down the graph.
+//
+// The planner is given the nodes in the order they will run. Every
+// intermediate gets the number of nodes which will read it. Each read releases
+// one use, and when there are no uses left the buffer goes back in the pool.
+// The next intermediate with the same shape gets that buffer instead of a new one.
+//
+// We only reuse buffers of the exact same shape. The kernels get their dimensions
+// from the buffers, so this way a buffer never has to be resized or reshaped,
+// which keeps the planner usable for both Tensor2D and Tensor2DGPU.
+// Graph inputs and weights are never pooled, and neither are the graph outputs,
+// as they have to survive until they are read back after the whole graph has run.
+//
+// The planner only decides which buffer index to use, creating the
+// actual buffers is left to the graph runner.
+#[derive(Clone, Debug)]
+struct PooledBuffer {
+    buffer_index: usize,
+    row_count: usize,
+    column_count: usize,
+    remaining_uses: usize,
+    // Outputs of the graph are never released
+    pinned: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MemoryReport {
+    // Bytes in inputs and weights, which can't be planned
+    pub fixed_bytes: usize,
+    // The number of intermediate results the graph produces
+    pub intermediate_count: usize,
+    // The number of buffers needed to hold them
+    pub intermediate_buffer_count: usize,
+    // Bytes of every intermediate result added together,
+    // which is what the graph needed before planning
+    pub unplanned_intermediate_bytes: usize,
+    // Bytes of the buffers actually allocated for intermediates
+    pub planned_intermediate_bytes: usize,
+    // The largest amount of memory holding live intermediate
+    // results at any point, no plan can do better than this
+    pub live_intermediate_bytes: usize,
+}
+
+impl MemoryReport {
+    // Total bytes allocated by the graph runner
+    pub fn peak_bytes(&self) -> usize {
+        self.fixed_bytes + self.planned_intermediate_bytes
+    }
+
+    pub fn unplanned_peak_bytes(&self) -> usize {
+        self.fixed_bytes + self.unplanned_intermediate_bytes
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "peak {} bytes (unplanned {} bytes) - {} intermediates in {} buffers, {} bytes of inputs and weights, {} bytes of live intermediates at most",
+            self.peak_bytes(),
+            self.unplanned_peak_bytes(),
+            self.intermediate_count,
+            self.intermediate_buffer_count,
+            self.fixed_bytes,
+            self.live_intermediate_bytes,
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryPlanner {
+    element_size: usize,
+    pool: Vec<PooledBuffer>,
+    live_bytes: usize,
+    report: MemoryReport,
+}
+
+impl MemoryPlanner {
+    // element_size is the size in bytes of a single element in the buffers
+    pub fn new(element_size: usize) -> Self {
+        MemoryPlanner {
+            element_size,
+            pool: Vec::<PooledBuffer>::new(),
+            live_bytes: 0,
+            report: MemoryReport::default(),
+        }
+    }
+
+    fn byte_count(&self, row_count: usize, column_count: usize) -> usize {
+        row_count * column_count * self.element_size
+    }
+
+    // Inputs and weights, which always get their own buffer
+    pub fn track_fixed(&mut self, row_count: usize, column_count: usize) {
+        self.report.fixed_bytes += self.byte_count(row_count, column_count);
+    }
+
+    // Returns the index of the buffer to write an intermediate result to.
+    // use_count is the number of nodes reading the result. If no free buffer
+    // of the right shape exists, create_buffer is called to make a new one,
+    // and has to return its index.
+    pub fn allocate(
+        &mut self,
+        row_count: usize,
+        column_count: usize,
+        use_count: usize,
+        pinned: bool,
+        create_buffer: impl FnOnce() -> usize,
+    ) -> usize {
+        let byte_count: usize = self.byte_count(row_count, column_count);
+        self.report.intermediate_count += 1;
+        self.report.unplanned_intermediate_bytes += byte_count;
+        self.live_bytes += byte_count;
+        self.report.live_intermediate_bytes =
+            self.report.live_intermediate_bytes.max(self.live_bytes);
+
+        let free_buffer: Option<&mut PooledBuffer> = self.pool.iter_mut().find(|buffer| {
+            !buffer.pinned
+                && buffer.remaining_uses == 0
+                && buffer.row_count == row_count
+                && buffer.column_count == column_count
+        });
+
+        let buffer_index: usize = match free_buffer {
+            Some(buffer) => {
+                buffer.remaining_uses = use_count;
+                buffer.pinned = pinned;
+                buffer.buffer_index
+            }
+            None => {
+                let buffer_index: usize = create_buffer();
+                self.pool.push(PooledBuffer {
+                    buffer_index,
+                    row_count,
+                    column_count,
+                    remaining_uses: use_count,
+                    pinned,
+                });
+                self.report.intermediate_buffer_count += 1;
+                self.report.planned_intermediate_bytes += byte_count;
+                buffer_index
+            }
+        };
+
+        // Nobody reads the result, so it is dead right away
+        if use_count == 0 && !pinned {
+            self.live_bytes -= byte_count;
+        }
+
+        buffer_index
+    }
+
+    // Called once for every node reading the buffer, after the
+    // node's own output has been allocated. Buffers which aren't
+    // intermediates, like inputs and weights, are ignored.
+    pub fn release(&mut self, buffer_index: usize) {
+        let element_size: usize = self.element_size;
+        if let Some(buffer) = self
+            .pool
+            .iter_mut()
+            .find(|buffer| buffer.buffer_index == buffer_index)
+        {
+            if buffer.remaining_uses == 0 {
+                return;
+            }
+
+            buffer.remaining_uses -= 1;
+            if buffer.remaining_uses == 0 && !buffer.pinned {
+                self.live_bytes -= buffer.row_count * buffer.column_count * element_size;
+            }
+        }
+    }
+
+    // An operator with a single input can write its result directly in to
+    // its input, if it is the last node which will ever read that input.
+    // Graph inputs and outputs are never overwritten.
+    // Returns whether the buffer now holds the result of the operator,
+    // in which case the input should not be released.
+    pub fn try_reuse_in_place(
+        &mut self,
+        buffer_index: usize,
+        use_count: usize,
+        pinned: bool,
+    ) -> bool {
+        let byte_count: usize;
+        match self
+            .pool
+            .iter_mut()
+            .find(|buffer| buffer.buffer_index == buffer_index)
+        {
+            Some(buffer) if buffer.remaining_uses == 1 && !buffer.pinned => {
+                buffer.remaining_uses = use_count;
+                buffer.pinned = pinned;
+                byte_count = buffer.row_count * buffer.column_count * self.element_size;
+            }
+            _ => return false,
+        }
+
+        // The input dies as the result is born, so the live memory stays the same
+        self.report.intermediate_count += 1;
+        self.report.unplanned_intermediate_bytes += byte_count;
+        if use_count == 0 && !pinned {
+            self.live_bytes -= byte_count;
+        }
+
+        true
+    }
+
+    pub fn report(&self) -> MemoryReport {
+        self.report
+    }
+
+    // The number of nodes reading the output of a node, and whether
+    // it is an output of the graph, which means it has to be pinned.
+    pub fn output_uses(
+        graph: &GraphDAG,
+        consumers: &[Vec<usize>],
+        node_index: usize,
+    ) -> (usize, bool) {
+        let pinned: bool = consumers[node_index].iter().any(|consumer_index| {
+            matches!(
+                graph.nodes[*consumer_index].operator,
+                GraphOperator::DeviceToHost
+            )
+        });
+
+        (consumers[node_index].len(), pinned)
+    }
+}
@@ -1,8 +1,23 @@
+pub mod autograd;
+pub mod graph_batching;
+pub mod graph_batching_test;
+pub mod graph_dag;
+pub mod graph_error;
+pub mod graph_fusion;
+pub mod graph_fusion_test;
 pub mod graph_runner;
 pub mod graph_runner_gpu;
 pub mod graph_runner_gpu_test;
 pub mod graph_runner_tests;
+pub mod graph_serialization;
+pub mod graph_serialization_test;
 pub mod graph_validation;
+pub mod memory_planner;
+pub mod node_timing;
 pub mod nodes;
 pub mod nodes_gpu;
+pub mod optimizer;
+pub mod optimizer_test;
+pub mod readback_ring;
+pub mod readback_ring_test;
 pub mod runner;
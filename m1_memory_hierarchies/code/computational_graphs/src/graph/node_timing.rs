@@ -0,0 +1,168 @@
+use std::{fmt, time::Duration};
+
+use wgpu::{Buffer, BufferSlice, BufferView, CommandEncoder, Features, QuerySet};
+
+use super::graph_error::GraphError;
+use crate::shared::gpu_utilities::GPUHandles;
+
+// Where the durations in a NodeTimingReport came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimingSource {
+    // Timestamps written by the GPU itself between the nodes, all in a single submission.
+    // Only the time the commands of each node took to run on the GPU.
+    GpuTimestamps,
+    // Every node recorded, submitted and waited for on its own with a CPU timer.
+    // Includes the cost of all three, which for small nodes is most of it.
+    CpuTimers,
+}
+
+impl TimingSource {
+    // Timestamps need TIMESTAMP_QUERY, which initialize_gpu asks for when the adapter has it
+    pub fn for_device(gpu_handles: &GPUHandles, query_count: usize) -> Self {
+        if gpu_handles.features.contains(Features::TIMESTAMP_QUERY)
+            && query_count <= wgpu::QUERY_SET_MAX_QUERIES as usize
+        {
+            TimingSource::GpuTimestamps
+        } else {
+            TimingSource::CpuTimers
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeTiming {
+    pub name: String,
+    pub operator: String,
+    pub duration: Duration,
+}
+
+// The nodes which don't record any commands, like the transfers, are left out
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeTimingReport {
+    pub source: TimingSource,
+    pub nodes: Vec<NodeTiming>,
+}
+
+impl NodeTimingReport {
+    pub fn total(&self) -> Duration {
+        self.nodes.iter().map(|node| node.duration).sum()
+    }
+}
+
+impl fmt::Display for NodeTimingReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "Timing - {:?}, {} us in {} nodes",
+            self.source,
+            self.total().as_micros(),
+            self.nodes.len()
+        )?;
+        for node in &self.nodes {
+            write!(
+                formatter,
+                "\n    {} ({}) {} us",
+                node.name,
+                node.operator,
+                node.duration.as_micros()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// A query set of timestamps, along with the buffers they are resolved and read back through.
+// Timestamp index + 1 is written after the commands of node index,
+// so every duration is the difference between two neighbouring timestamps.
+pub struct TimestampQueries {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    staging_buffer: Buffer,
+    count: u32,
+}
+
+impl TimestampQueries {
+    pub fn new(gpu_handles: &GPUHandles, count: usize) -> Self {
+        let count: u32 = count as u32;
+        let query_set: QuerySet = gpu_handles
+            .device
+            .create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Node Timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count,
+            });
+
+        // Every timestamp is a u64
+        let size: u64 = count as u64 * std::mem::size_of::<u64>() as u64;
+        let resolve_buffer: Buffer = gpu_handles.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Node Timestamps Resolve"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer: Buffer = gpu_handles.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Node Timestamps Staging"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            query_set,
+            resolve_buffer,
+            staging_buffer,
+            count,
+        }
+    }
+
+    pub fn write(&self, encoder: &mut CommandEncoder, index: usize) {
+        encoder.write_timestamp(&self.query_set, index as u32);
+    }
+
+    // Has to be recorded after the last timestamp was written
+    pub fn resolve(&self, encoder: &mut CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..self.count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.staging_buffer,
+            0,
+            self.resolve_buffer.size(),
+        );
+    }
+
+    // The time between every pair of neighbouring timestamps,
+    // once the submission which resolved them has been submitted
+    pub async fn durations(&self, gpu_handles: &GPUHandles) -> Result<Vec<Duration>, GraphError> {
+        let buffer_slice: BufferSlice = self.staging_buffer.slice(..);
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+        gpu_handles.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .receive()
+            .await
+            .unwrap_or(Err(wgpu::BufferAsyncError))
+            .map_err(|error| {
+                GraphError::BufferMapFailure(format!(
+                    "Failed to map the staging buffer of the node timestamps: {}",
+                    error
+                ))
+            })?;
+
+        let data: BufferView = buffer_slice.get_mapped_range();
+        let timestamps: Vec<u64> = bytemuck::cast_slice(&data).to_vec();
+        drop(data);
+        self.staging_buffer.unmap();
+
+        // The period is the number of nanoseconds per tick.
+        // Some GPUs can reorder the timestamps slightly, which should never become a huge duration.
+        let period: f64 = gpu_handles.queue.get_timestamp_period() as f64;
+        Ok(timestamps
+            .windows(2)
+            .map(|pair| {
+                Duration::from_nanos((pair[1].saturating_sub(pair[0]) as f64 * period) as u64)
+            })
+            .collect())
+    }
+}
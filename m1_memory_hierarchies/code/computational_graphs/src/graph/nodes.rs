@@ -1,17 +1,27 @@
-use std::vec::Drain;
-
+use crate::shared::graph_operators::LossKind;
 use crate::shared::tensor2d::Tensor2D;
 
+use super::autograd::BackwardNode;
+use super::graph_error::GraphError;
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum NodeOperator {
     Input,
     Output,
-    Transfer,
     LinearLayer,
     ReLU,
     Softmax,
+    SoftmaxAxis { axis: usize },
+    LogSoftmax,
     LinearReLU,
     LinearReLUSoftmax,
+    Add,
+    AddReLU,
+    Concat { axis: usize },
+    Loss { kind: LossKind },
+    // Overwrites its single buffer with the result
+    ReLUInPlace,
+    SoftmaxInPlace,
 }
 
 #[derive(Debug)]
@@ -31,145 +41,429 @@ impl Node {
     }
 }
 
-// Due to Rust's borrowing rules, this is slightly complicated as we need
-// multiple shared references and mutable references. If we guaranteed
-// that buffer_indices[0..N] were sequential we could do with just one split
-// but we can't, so we have to guarantee the borrow checker that we don't have overlaps.
-// The drain is not the result from this function as it needs to have the references vector
-// survive.
-fn sorted_mutable_references<'a>(
-    node: &'a Node,
+// Due to Rust's borrowing rules, we can't just index data_buffers once for
+// every buffer a node needs, as the output has to be mutable while the inputs are shared.
+// The last buffer of a node is always its output. Splitting the buffer list
+// around the output gives us a mutable reference to the output and the
+// rest of the list on either side of it, which every input is taken from.
+// The inputs may refer to the same buffer several times, e.g. x + x,
+// but none of them may be the output.
+fn inputs_and_output<'a>(
+    function_name: &str,
+    node: &Node,
     data_buffers: &'a mut [Tensor2D],
-) -> Vec<(usize, &'a mut Tensor2D)> {
-    let indices: Vec<(usize, usize)> = node
+    minimum_buffer_count: usize,
+    maximum_buffer_count: usize,
+) -> Result<(Vec<&'a Tensor2D>, &'a mut Tensor2D), GraphError> {
+    let buffer_count: usize = node.buffer_indices.len();
+    if buffer_count < minimum_buffer_count || maximum_buffer_count < buffer_count {
+        return Err(GraphError::MalformedNode(format!(
+            "{} expected {} to {} buffers, received {} in node {}.",
+            function_name, minimum_buffer_count, maximum_buffer_count, buffer_count, node.name
+        )));
+    }
+
+    let output_index: usize = node.buffer_indices[buffer_count - 1];
+    let input_indices: &[usize] = &node.buffer_indices[0..(buffer_count - 1)];
+    if node
         .buffer_indices
         .iter()
-        .enumerate()
-        .map(|data| (data.0, *data.1))
-        .collect();
+        .any(|buffer_index| data_buffers.len() <= *buffer_index)
+        || input_indices.contains(&output_index)
+    {
+        return Err(GraphError::MalformedNode(format!(
+            "{} received the invalid buffer indices {:?} in node {}.",
+            function_name, node.buffer_indices, node.name
+        )));
+    }
 
-    // Only engage with the relevant buffers
-    let filtered_buffers: Vec<(usize, &mut Tensor2D)> = data_buffers
-        .iter_mut()
-        .enumerate()
-        .filter(|pair| node.buffer_indices.contains(&pair.0))
-        .collect();
-    let mut references: Vec<(usize, &mut Tensor2D)> =
-        Vec::<(usize, &mut Tensor2D)>::with_capacity(indices.len());
-
-    // We know filtered_buffers only contain the buffers that
-    // are relevant to us. Now we just have to pair the buffers with the
-    // correct ordering and then sort, for the output to be drained correctly
-    // afterwards.
-    for (data_index, buffer) in filtered_buffers {
-        for (buffer_enumeration, buffer_index) in &indices {
-            if data_index == *buffer_index {
-                references.push((*buffer_enumeration, buffer));
-                break;
+    let (before, rest): (&'a mut [Tensor2D], &'a mut [Tensor2D]) =
+        data_buffers.split_at_mut(output_index);
+    let (output, after): (&'a mut Tensor2D, &'a mut [Tensor2D]) = rest.split_first_mut().unwrap();
+    let before: &'a [Tensor2D] = before;
+    let after: &'a [Tensor2D] = after;
+
+    let inputs: Vec<&'a Tensor2D> = input_indices
+        .iter()
+        .map(|buffer_index| {
+            if *buffer_index < output_index {
+                &before[*buffer_index]
+            } else {
+                &after[*buffer_index - output_index - 1]
             }
+        })
+        .collect();
+
+    Ok((inputs, output))
+}
+
+// In-place nodes only have the one buffer, which is both input and output
+fn in_place_buffer<'a>(
+    function_name: &str,
+    node: &Node,
+    data_buffers: &'a mut [Tensor2D],
+) -> Result<&'a mut Tensor2D, GraphError> {
+    match node.buffer_indices.as_slice() {
+        [buffer_index] if *buffer_index < data_buffers.len() => {
+            Ok(&mut data_buffers[*buffer_index])
         }
+        _ => Err(GraphError::MalformedNode(format!(
+            "{} expected a single valid buffer, received {:?} in node {}.",
+            function_name, node.buffer_indices, node.name
+        ))),
     }
+}
+
+pub fn linear_layer(
+    node: &Node,
+    data_buffers: &mut [Tensor2D],
+    thread_count: usize,
+) -> Result<(), GraphError> {
+    let (inputs, output): (Vec<&Tensor2D>, &mut Tensor2D) =
+        inputs_and_output("nodes::linear_layer", node, data_buffers, 4, 4)?;
+
+    let input: &Tensor2D = inputs[0];
+    let weights: &Tensor2D = inputs[1];
+    let bias: &Tensor2D = inputs[2];
+
+    // With a single thread, or a small layer, this is just linear_layer_optimized
+    Tensor2D::linear_layer_parallel(input, weights, bias, output, thread_count);
 
-    // Resort the references by their original ordering to match it to the correct buffer name
-    references.sort_by(|a, b| a.0.cmp(&b.0));
-    references
+    Ok(())
 }
 
-pub fn linear_layer(node: &Node, data_buffers: &mut [Tensor2D]) {
-    if node.buffer_indices.len() != 4 {
-        panic!(
-            "cpu_nodes::linear_layer function expected 1 input buffer, received {}",
-            node.buffer_indices.len()
-        );
-    }
+pub fn relu(node: &Node, data_buffers: &mut [Tensor2D]) -> Result<(), GraphError> {
+    let (inputs, output): (Vec<&Tensor2D>, &mut Tensor2D) =
+        inputs_and_output("nodes::relu", node, data_buffers, 2, 2)?;
+
+    // When nobody else needs the input, the graph runner uses relu_inplace instead,
+    // which is what the immediate CPU version does as well.
+    Tensor2D::relu_preallocated(inputs[0], output);
 
-    let mut references: Vec<(usize, &mut Tensor2D)> = sorted_mutable_references(node, data_buffers);
-    let mut drain: Drain<(usize, &mut Tensor2D)> = references.drain(0..references.len());
+    Ok(())
+}
+
+pub fn relu_inplace(node: &Node, data_buffers: &mut [Tensor2D]) -> Result<(), GraphError> {
+    let data: &mut Tensor2D = in_place_buffer("nodes::relu_inplace", node, data_buffers)?;
 
-    let input: &Tensor2D = drain.next().unwrap().1;
-    let weights: &Tensor2D = drain.next().unwrap().1;
-    let bias: &Tensor2D = drain.next().unwrap().1;
-    let output: &mut Tensor2D = drain.next().unwrap().1;
+    Tensor2D::relu_inplace_inline(data);
 
-    Tensor2D::linear_layer_optimized(input, weights, bias, output)
+    Ok(())
 }
 
-pub fn relu(node: &Node, data_buffers: &mut [Tensor2D]) {
-    if node.buffer_indices.len() != 2 {
-        panic!(
-            "nodes::relu function expected 1 input buffer, received {}",
-            node.buffer_indices.len()
-        );
-    }
+pub fn softmax(
+    node: &Node,
+    data_buffers: &mut [Tensor2D],
+    thread_count: usize,
+) -> Result<(), GraphError> {
+    let (inputs, output): (Vec<&Tensor2D>, &mut Tensor2D) =
+        inputs_and_output("nodes::softmax", node, data_buffers, 2, 2)?;
+
+    // When nobody else needs the input, the graph runner uses softmax_inplace instead.
+    Tensor2D::softmax_parallel(inputs[0], output, thread_count);
+
+    Ok(())
+}
 
-    let mut references: Vec<(usize, &mut Tensor2D)> = sorted_mutable_references(node, data_buffers);
-    let mut drain: Drain<(usize, &mut Tensor2D)> = references.drain(0..references.len());
+pub fn softmax_inplace(
+    node: &Node,
+    data_buffers: &mut [Tensor2D],
+    thread_count: usize,
+) -> Result<(), GraphError> {
+    let data: &mut Tensor2D = in_place_buffer("nodes::softmax_inplace", node, data_buffers)?;
 
-    let input: &Tensor2D = drain.next().unwrap().1;
-    let output: &mut Tensor2D = drain.next().unwrap().1;
+    Tensor2D::softmax_inplace_parallel(data, thread_count);
 
-    // Note that in the immediate CPU version we are using relu_inplace_inline
-    // which has better performance and works on directly on the given input, which has to be mutable.
-    // Due to the way the graph is currently setup, this isn't implemented for the CPU graph,
-    // but it could be a possible optimization. Wink. Wink.
-    Tensor2D::relu_preallocated(input, output);
+    Ok(())
 }
 
-pub fn softmax(node: &Node, data_buffers: &mut [Tensor2D]) {
-    if node.buffer_indices.len() != 2 {
-        panic!(
-            "nodes::softmax function expected 1 input buffer, received {}",
-            node.buffer_indices.len()
-        );
-    }
+pub fn softmax_axis(
+    node: &Node,
+    data_buffers: &mut [Tensor2D],
+    axis: usize,
+) -> Result<(), GraphError> {
+    let (inputs, output): (Vec<&Tensor2D>, &mut Tensor2D) =
+        inputs_and_output("nodes::softmax_axis", node, data_buffers, 2, 2)?;
 
-    let mut references: Vec<(usize, &mut Tensor2D)> = sorted_mutable_references(node, data_buffers);
-    let mut drain: Drain<(usize, &mut Tensor2D)> = references.drain(0..references.len());
+    Tensor2D::softmax_axis_preallocated(inputs[0], axis, output);
 
-    let input: &Tensor2D = drain.next().unwrap().1;
-    let output: &mut Tensor2D = drain.next().unwrap().1;
+    Ok(())
+}
+
+pub fn log_softmax(node: &Node, data_buffers: &mut [Tensor2D]) -> Result<(), GraphError> {
+    let (inputs, output): (Vec<&Tensor2D>, &mut Tensor2D) =
+        inputs_and_output("nodes::log_softmax", node, data_buffers, 2, 2)?;
+
+    Tensor2D::log_softmax_preallocated(inputs[0], output);
 
-    // Note that in the immediate CPU version we are using softmax_optimized
-    // which has better performance and works on directly on the given input, which has to be mutable.
-    // Due to the way the graph is currently setup, this isn't implemented for the CPU graph,
-    // but it could be a possible optimization. Wink. Wink.
-    Tensor2D::softmax_preallocated(input, output);
+    Ok(())
 }
 
-pub fn linear_relu(node: &Node, data_buffers: &mut [Tensor2D]) {
-    if node.buffer_indices.len() != 4 {
-        panic!(
-            "cpu_nodes::linear_relu function expected 1 input buffer, received {}",
-            node.buffer_indices.len()
-        );
-    }
+pub fn linear_relu(
+    node: &Node,
+    data_buffers: &mut [Tensor2D],
+    thread_count: usize,
+) -> Result<(), GraphError> {
+    let (inputs, output): (Vec<&Tensor2D>, &mut Tensor2D) =
+        inputs_and_output("nodes::linear_relu", node, data_buffers, 4, 4)?;
 
-    let mut references: Vec<(usize, &mut Tensor2D)> = sorted_mutable_references(node, data_buffers);
-    let mut drain: Drain<(usize, &mut Tensor2D)> = references.drain(0..references.len());
+    let input: &Tensor2D = inputs[0];
+    let weights: &Tensor2D = inputs[1];
+    let bias: &Tensor2D = inputs[2];
 
-    let input: &Tensor2D = drain.next().unwrap().1;
-    let weights: &Tensor2D = drain.next().unwrap().1;
-    let bias: &Tensor2D = drain.next().unwrap().1;
-    let output: &mut Tensor2D = drain.next().unwrap().1;
+    Tensor2D::linear_layer_parallel_relu(input, weights, bias, output, thread_count);
 
-    Tensor2D::linear_layer_optimized_relu(input, weights, bias, output);
+    Ok(())
 }
 
-pub fn linear_relu_softmax(node: &Node, data_buffers: &mut [Tensor2D]) {
-    if node.buffer_indices.len() != 4 {
-        panic!(
-            "cpu_nodes::linear_relu_softmax function expected 1 input buffer, received {}",
-            node.buffer_indices.len()
+pub fn linear_relu_softmax(
+    node: &Node,
+    data_buffers: &mut [Tensor2D],
+    thread_count: usize,
+) -> Result<(), GraphError> {
+    let (inputs, output): (Vec<&Tensor2D>, &mut Tensor2D) =
+        inputs_and_output("nodes::linear_relu_softmax", node, data_buffers, 4, 4)?;
+
+    let input: &Tensor2D = inputs[0];
+    let weights: &Tensor2D = inputs[1];
+    let bias: &Tensor2D = inputs[2];
+
+    Tensor2D::linear_relu_softmax_fused_parallel(input, weights, bias, output, thread_count);
+
+    Ok(())
+}
+
+pub fn add(node: &Node, data_buffers: &mut [Tensor2D]) -> Result<(), GraphError> {
+    let (inputs, output): (Vec<&Tensor2D>, &mut Tensor2D) =
+        inputs_and_output("nodes::add", node, data_buffers, 3, 3)?;
+
+    Tensor2D::add_preallocated(inputs[0], inputs[1], output);
+
+    Ok(())
+}
+
+pub fn add_relu(node: &Node, data_buffers: &mut [Tensor2D]) -> Result<(), GraphError> {
+    let (inputs, output): (Vec<&Tensor2D>, &mut Tensor2D) =
+        inputs_and_output("nodes::add_relu", node, data_buffers, 3, 3)?;
+
+    Tensor2D::add_relu_preallocated(inputs[0], inputs[1], output);
+
+    Ok(())
+}
+
+pub fn concat(node: &Node, data_buffers: &mut [Tensor2D], axis: usize) -> Result<(), GraphError> {
+    let (inputs, output): (Vec<&Tensor2D>, &mut Tensor2D) =
+        inputs_and_output("nodes::concat", node, data_buffers, 3, usize::MAX)?;
+
+    Tensor2D::concat_preallocated(&inputs, axis, output);
+
+    Ok(())
+}
+
+pub fn loss(node: &Node, data_buffers: &mut [Tensor2D], kind: LossKind) -> Result<(), GraphError> {
+    let (inputs, output): (Vec<&Tensor2D>, &mut Tensor2D) =
+        inputs_and_output("nodes::loss", node, data_buffers, 3, 3)?;
+
+    output.data[0] = Tensor2D::loss(kind, inputs[0], inputs[1]);
+
+    Ok(())
+}
+
+// The backward nodes write several gradients at once, so inputs_and_output
+// won't do. Instead the gradients, which are always the last gradient_count buffers,
+// are moved out of data_buffers while the node runs, leaving the rest free to be read.
+// They have to be handed back with restore_gradients afterwards.
+fn take_gradients(
+    function_name: &str,
+    node: &BackwardNode,
+    data_buffers: &mut [Tensor2D],
+    buffer_count: usize,
+    gradient_count: usize,
+) -> Result<Vec<Tensor2D>, GraphError> {
+    let indices: &[usize] = &node.buffer_indices;
+    let invalid_indices: bool = indices.len() != buffer_count
+        || indices
+            .iter()
+            .any(|buffer_index| data_buffers.len() <= *buffer_index);
+    // A gradient can't be read by the node, or be written twice
+    let first_gradient: usize = buffer_count - gradient_count;
+    if invalid_indices
+        || (first_gradient..buffer_count)
+            .any(|position| indices[..position].contains(&indices[position]))
+    {
+        return Err(GraphError::MalformedNode(format!(
+            "{} received the invalid buffer indices {:?} in node {}.",
+            function_name, node.buffer_indices, node.name
+        )));
+    }
+
+    Ok(indices[first_gradient..]
+        .iter()
+        .map(|gradient_index| std::mem::take(&mut data_buffers[*gradient_index]))
+        .collect())
+}
+
+fn restore_gradients(node: &BackwardNode, data_buffers: &mut [Tensor2D], gradients: Vec<Tensor2D>) {
+    let first_gradient: usize = node.buffer_indices.len() - gradients.len();
+    for (gradient_index, gradient) in node.buffer_indices[first_gradient..].iter().zip(gradients) {
+        data_buffers[*gradient_index] = gradient;
+    }
+}
+
+pub fn linear_layer_backward(
+    node: &BackwardNode,
+    data_buffers: &mut [Tensor2D],
+) -> Result<(), GraphError> {
+    let mut gradients: Vec<Tensor2D> =
+        take_gradients("nodes::linear_layer_backward", node, data_buffers, 6, 3)?;
+
+    if let [input_gradient, weights_gradient, bias_gradient] = gradients.as_mut_slice() {
+        Tensor2D::linear_layer_backward(
+            &data_buffers[node.buffer_indices[0]],
+            &data_buffers[node.buffer_indices[1]],
+            &data_buffers[node.buffer_indices[2]],
+            input_gradient,
+            weights_gradient,
+            bias_gradient,
         );
     }
+    restore_gradients(node, data_buffers, gradients);
+
+    Ok(())
+}
+
+pub fn relu_backward(node: &BackwardNode, data_buffers: &mut [Tensor2D]) -> Result<(), GraphError> {
+    let mut gradients: Vec<Tensor2D> =
+        take_gradients("nodes::relu_backward", node, data_buffers, 3, 1)?;
+
+    Tensor2D::relu_backward(
+        &data_buffers[node.buffer_indices[0]],
+        &data_buffers[node.buffer_indices[1]],
+        &mut gradients[0],
+    );
+    restore_gradients(node, data_buffers, gradients);
+
+    Ok(())
+}
+
+pub fn softmax_backward(
+    node: &BackwardNode,
+    data_buffers: &mut [Tensor2D],
+) -> Result<(), GraphError> {
+    let mut gradients: Vec<Tensor2D> =
+        take_gradients("nodes::softmax_backward", node, data_buffers, 3, 1)?;
+
+    Tensor2D::softmax_backward(
+        &data_buffers[node.buffer_indices[0]],
+        &data_buffers[node.buffer_indices[1]],
+        &mut gradients[0],
+    );
+    restore_gradients(node, data_buffers, gradients);
+
+    Ok(())
+}
+
+pub fn softmax_axis_backward(
+    node: &BackwardNode,
+    data_buffers: &mut [Tensor2D],
+    axis: usize,
+) -> Result<(), GraphError> {
+    let mut gradients: Vec<Tensor2D> =
+        take_gradients("nodes::softmax_axis_backward", node, data_buffers, 3, 1)?;
 
-    let mut references: Vec<(usize, &mut Tensor2D)> = sorted_mutable_references(node, data_buffers);
-    let mut drain: Drain<(usize, &mut Tensor2D)> = references.drain(0..references.len());
+    Tensor2D::softmax_axis_backward(
+        &data_buffers[node.buffer_indices[0]],
+        &data_buffers[node.buffer_indices[1]],
+        axis,
+        &mut gradients[0],
+    );
+    restore_gradients(node, data_buffers, gradients);
+
+    Ok(())
+}
+
+pub fn log_softmax_backward(
+    node: &BackwardNode,
+    data_buffers: &mut [Tensor2D],
+) -> Result<(), GraphError> {
+    let mut gradients: Vec<Tensor2D> =
+        take_gradients("nodes::log_softmax_backward", node, data_buffers, 3, 1)?;
+
+    Tensor2D::log_softmax_backward(
+        &data_buffers[node.buffer_indices[0]],
+        &data_buffers[node.buffer_indices[1]],
+        &mut gradients[0],
+    );
+    restore_gradients(node, data_buffers, gradients);
+
+    Ok(())
+}
 
-    let input: &Tensor2D = drain.next().unwrap().1;
-    let weights: &Tensor2D = drain.next().unwrap().1;
-    let bias: &Tensor2D = drain.next().unwrap().1;
-    let output: &mut Tensor2D = drain.next().unwrap().1;
+pub fn loss_backward(
+    node: &BackwardNode,
+    data_buffers: &mut [Tensor2D],
+    kind: LossKind,
+) -> Result<(), GraphError> {
+    let mut gradients: Vec<Tensor2D> =
+        take_gradients("nodes::loss_backward", node, data_buffers, 4, 1)?;
+
+    let input: &Tensor2D = &data_buffers[node.buffer_indices[0]];
+    let target: &Tensor2D = &data_buffers[node.buffer_indices[1]];
+    let loss_gradient: f32 = data_buffers[node.buffer_indices[2]].data[0];
+    Tensor2D::loss_backward(kind, input, target, loss_gradient, &mut gradients[0]);
+    restore_gradients(node, data_buffers, gradients);
+
+    Ok(())
+}
+
+pub fn softmax_cross_entropy_backward(
+    node: &BackwardNode,
+    data_buffers: &mut [Tensor2D],
+) -> Result<(), GraphError> {
+    let mut gradients: Vec<Tensor2D> = take_gradients(
+        "nodes::softmax_cross_entropy_backward",
+        node,
+        data_buffers,
+        4,
+        1,
+    )?;
+
+    let softmax_output: &Tensor2D = &data_buffers[node.buffer_indices[0]];
+    let target: &Tensor2D = &data_buffers[node.buffer_indices[1]];
+    let loss_gradient: f32 = data_buffers[node.buffer_indices[2]].data[0];
+    Tensor2D::softmax_cross_entropy_backward(
+        softmax_output,
+        target,
+        loss_gradient,
+        &mut gradients[0],
+    );
+    restore_gradients(node, data_buffers, gradients);
+
+    Ok(())
+}
+
+// Both inputs get the whole output gradient. For x + x both
+// gradients are the same buffer, which then gets it twice.
+pub fn add_backward(node: &BackwardNode, data_buffers: &mut [Tensor2D]) -> Result<(), GraphError> {
+    if node.buffer_indices.len() != 3 {
+        return Err(GraphError::MalformedNode(format!(
+            "nodes::add_backward expected 3 buffers, received {} in node {}.",
+            node.buffer_indices.len(),
+            node.name
+        )));
+    }
+
+    for gradient_index in &node.buffer_indices[1..] {
+        let single: BackwardNode = BackwardNode {
+            name: node.name.clone(),
+            operator: node.operator,
+            buffer_indices: vec![node.buffer_indices[0], *gradient_index],
+        };
+        let mut gradients: Vec<Tensor2D> =
+            take_gradients("nodes::add_backward", &single, data_buffers, 2, 1)?;
+        Tensor2D::accumulate(&data_buffers[node.buffer_indices[0]], &mut gradients[0]);
+        restore_gradients(&single, data_buffers, gradients);
+    }
 
-    Tensor2D::linear_relu_softmax_fused(input, weights, bias, output);
+    Ok(())
 }
@@ -1,26 +1,36 @@
-use core::panic;
 use std::collections::HashMap;
 
 use wgpu::{
-    BindGroup, BindGroupLayout, BindingResource, CommandEncoder, ComputePass, ComputePipeline,
-    ShaderModule,
+    BindGroup, BindGroupLayout, BindingResource, Buffer, BufferBinding, BufferSize, CommandEncoder,
+    ComputePass, ComputePipeline, Limits, ShaderModule,
 };
 
+use super::autograd::BackwardNode;
+use super::graph_error::GraphError;
 use crate::shared::{
     gpu_utilities::{create_bind_group, create_compute_pipeline, create_shader_module, GPUHandles},
-    tensor2d_gpu::{LinearLayerUniform, ReluUniform, SoftmaxUniform, Tensor2DGPU},
+    graph_operators::LossKind,
+    tensor2d_gpu::{
+        dispatch_chunks, DispatchChunk, LinearLayerKernel, LinearLayerUniform, LossUniform,
+        ReluUniform, SoftmaxAxisUniform, SoftmaxUniform, Tensor2DGPU,
+    },
 };
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum NodeOperatorGPU {
     HostToDevice,
     DeviceToHost,
-    DeviceToDevice,
     LinearLayer,
     ReLU,
     Softmax,
+    SoftmaxAxis { axis: usize },
+    LogSoftmax,
     LinearReLU,
     LinearReLUSoftmax,
+    Add,
+    AddReLU,
+    Concat { axis: usize },
+    Loss { kind: LossKind },
 }
 
 #[derive(Debug)]
@@ -28,6 +38,9 @@ pub struct NodeGPU {
     pub name: String,
     pub operator: NodeOperatorGPU,
     pub buffer_indices: Vec<usize>,
+    // Everything needed to record the node, made once by bind_node.
+    // If it is None, the functions below create it all again on every submit.
+    pub bound: Option<BoundNode>,
 }
 
 impl NodeGPU {
@@ -36,16 +49,38 @@ impl NodeGPU {
             name,
             operator,
             buffer_indices,
+            bound: None,
         }
     }
 }
 
+// A compute pass which is recorded exactly the same way on every submit.
+// A bind group made from the layout of a pipeline only works with that exact
+// pipeline, so the pipeline is looked up by its key in the pipeline cache
+// instead of being created again.
+#[derive(Debug)]
+pub struct BoundPass {
+    pub label: &'static str,
+    pub pipeline_key: String,
+    pub bind_group: BindGroup,
+    pub launch_blocks: (u32, u32, u32),
+}
+
+#[derive(Debug, Default)]
+pub struct BoundNode {
+    pub passes: Vec<BoundPass>,
+    // The uniforms and scratch buffers the bind groups point to,
+    // which have to live as long as the bind groups do
+    pub buffers: Vec<Buffer>,
+}
+
 // Linear Layer
 pub fn build_linear_layer_elements(
     gpu_handles: &GPUHandles,
     shader_cache: &mut HashMap<String, ShaderModule>,
     pipeline_cache: &mut HashMap<String, ComputePipeline>,
     use_fused_with_relu: bool,
+    kernel: LinearLayerKernel,
 ) {
     let key: String = "LinearLayer".to_string();
 
@@ -54,7 +89,7 @@ pub fn build_linear_layer_elements(
         include_str!("../shared/shaders/linear_layer.wgsl"),
     );
 
-    let entry_point: &str = "main";
+    let entry_point: &str = kernel.entry_point(false);
     let compute_pipeline: ComputePipeline =
         create_compute_pipeline(gpu_handles, &cs_module, entry_point);
 
@@ -67,7 +102,7 @@ pub fn build_linear_layer_elements(
             include_str!("../shared/shaders/linear_layer.wgsl"),
         );
         let key: String = "LinearReLU".to_string();
-        let entry_point: &str = "main_with_relu";
+        let entry_point: &str = kernel.entry_point(true);
         let compute_pipeline: ComputePipeline =
             create_compute_pipeline(gpu_handles, &cs_module, entry_point);
 
@@ -85,12 +120,14 @@ pub fn linear_layer(
     data_buffers: &[Tensor2DGPU],
     encoder: &mut CommandEncoder,
     use_fused_with_relu: bool,
-) {
+    kernel: LinearLayerKernel,
+) -> Result<(), GraphError> {
     if node.buffer_indices.len() != 4 {
-        panic!(
-            "nodes::linear_layer function expected 1 input buffer, received {}",
-            node.buffer_indices.len()
-        );
+        return Err(GraphError::MalformedNode(format!(
+            "nodes::linear_layer function expected 4 buffers, received {} in node {}",
+            node.buffer_indices.len(),
+            node.name
+        )));
     }
 
     let input: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
@@ -101,18 +138,8 @@ pub fn linear_layer(
     // Normally these would be right next to the lines where they are used
     // but this section is based on user input and can cause errors.
     // It is placed here for visibility.
-    let block_size: usize = 8;
-    let launch_blocks_x: u32 = ((output.row_count + block_size - 1) / block_size) as u32;
-    let launch_blocks_y: u32 = ((output.column_count + block_size - 1) / block_size) as u32;
-
-    let uniform_device: LinearLayerUniform = LinearLayerUniform::from_tensor_2d_gpu(
-        gpu_handles,
-        "Linear Layer Uniform",
-        input,
-        weights,
-        bias,
-        output,
-    );
+    let chunks: Vec<DispatchChunk> =
+        linear_layer_chunks(gpu_handles, &node.name, kernel, input, output)?;
 
     let shader_module: Option<ShaderModule> = if use_cache {
         None
@@ -132,7 +159,7 @@ pub fn linear_layer(
         if shader_cache.contains_key(key) {
             &shader_cache[key]
         } else {
-            panic!("Tried to get a cached {} shader in graph::nodes::linear_layer(), but failed to find it in the shader cache!", key);
+            return Err(GraphError::UnsupportedOperator(format!("Tried to get a cached {} shader in graph::nodes::linear_layer(), but failed to find it in the shader cache!", key)));
         }
     } else {
         shader_module
@@ -141,11 +168,7 @@ pub fn linear_layer(
     };
 
     let pipeline: Option<ComputePipeline> = if !use_cache {
-        let entry_point: &str = if use_fused_with_relu {
-            "main_with_relu"
-        } else {
-            "main"
-        };
+        let entry_point: &str = kernel.entry_point(use_fused_with_relu);
         Some(create_compute_pipeline(gpu_handles, cs_module, entry_point))
     } else {
         None
@@ -160,7 +183,7 @@ pub fn linear_layer(
         if pipeline_cache.contains_key(key) {
             &pipeline_cache[key]
         } else {
-            panic!("Tried to get a cached {} pipeline in graph::nodes::linear_layer(), but failed to find it in the pipeline cache!", key);
+            return Err(GraphError::UnsupportedOperator(format!("Tried to get a cached {} pipeline in graph::nodes::linear_layer(), but failed to find it in the pipeline cache!", key)));
         }
     } else {
         pipeline
@@ -168,30 +191,33 @@ pub fn linear_layer(
             .expect("Failed to get a reference to compute pipeline in graph::nodes::linear_layer")
     };
 
-    // Instantiates the bind group, once again specifying the binding of buffers.
-    let bind_group_layout: BindGroupLayout = compute_pipeline.get_bind_group_layout(0);
-    let to_be_bound: Vec<(u32, BindingResource)> = vec![
-        (0, uniform_device.storage_buffer.as_entire_binding()),
-        (1, input.storage_buffer.as_entire_binding()),
-        (2, weights.storage_buffer.as_entire_binding()),
-        (3, bias.storage_buffer.as_entire_binding()),
-        (4, output.storage_buffer.as_entire_binding()),
-    ];
-    let bind_group: BindGroup = create_bind_group(gpu_handles, &bind_group_layout, to_be_bound);
-
-    {
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some(if use_fused_with_relu {
-                "linear_relu_graph"
-            } else {
-                "linear_layer_graph"
-            }),
-        });
-        cpass.set_pipeline(compute_pipeline);
-        cpass.set_bind_group(0, &bind_group, &[]);
-        cpass.insert_debug_marker("linear_layer_graph");
-        cpass.dispatch_workgroups(launch_blocks_x, launch_blocks_y, 1); // Number of cells to run, the (x,y,z) size of item being processed
+    // A dispatch per chunk, which for anything which fits on the device is just one
+    let label: &str = if use_fused_with_relu {
+        "linear_relu_graph"
+    } else {
+        "linear_layer_graph"
+    };
+    for chunk in &chunks {
+        let uniform_device: LinearLayerUniform = LinearLayerUniform::from_chunk(
+            gpu_handles,
+            "Linear Layer Uniform",
+            input,
+            weights,
+            bias,
+            output,
+            chunk,
+        );
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            linear_layer_chunk_bindings(&uniform_device, input, weights, bias, output, chunk),
+            label,
+            kernel.launch_blocks(chunk.row_count, output.column_count),
+        )?;
     }
+
+    Ok(())
 }
 
 // ReLU
@@ -221,18 +247,19 @@ pub fn relu(
     node: &NodeGPU,
     data_buffers: &[Tensor2DGPU],
     encoder: &mut CommandEncoder,
-) {
+) -> Result<(), GraphError> {
     if node.buffer_indices.len() != 2 {
-        panic!(
-            "nodes::relu function expected 1 input buffer, received {}",
-            node.buffer_indices.len()
-        );
+        return Err(GraphError::MalformedNode(format!(
+            "nodes::relu function expected 2 buffers, received {} in node {}",
+            node.buffer_indices.len(),
+            node.name
+        )));
     }
 
     let input: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
     let output: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
 
-    let uniform: ReluUniform = ReluUniform::new(gpu_handles, "Relu Uniform", &input.data);
+    let chunks: Vec<DispatchChunk> = elementwise_chunks(gpu_handles, &node.name, input)?;
 
     // let cs_module: ShaderModule =
     //     create_shader_module(gpu_handles, include_str!("../shared/shaders/relu.wgsl"));
@@ -253,7 +280,7 @@ pub fn relu(
         if shader_cache.contains_key(key) {
             &shader_cache[key]
         } else {
-            panic!("Tried to get a cached {} shader in graph::nodes::relu(), but failed to find it in the shader cache!", key);
+            return Err(GraphError::UnsupportedOperator(format!("Tried to get a cached {} shader in graph::nodes::relu(), but failed to find it in the shader cache!", key)));
         }
     } else {
         shader_module
@@ -271,7 +298,7 @@ pub fn relu(
         if pipeline_cache.contains_key(key) {
             &pipeline_cache[key]
         } else {
-            panic!("Tried to get a cached {} pipeline in graph::nodes::relu(), but failed to find it in the pipeline cache!", key);
+            return Err(GraphError::UnsupportedOperator(format!("Tried to get a cached {} pipeline in graph::nodes::relu(), but failed to find it in the pipeline cache!", key)));
         }
     } else {
         pipeline
@@ -279,233 +306,232 @@ pub fn relu(
             .expect("Failed to get a reference to compute pipeline in graph::nodes::relu")
     };
 
-    // Instantiates the bind group, once again specifying the binding of buffers.
-    let bind_group_layout: BindGroupLayout = compute_pipeline.get_bind_group_layout(0);
-    let to_be_bound: Vec<(u32, BindingResource)> = vec![
-        (0, uniform.storage_buffer.as_entire_binding()),
-        (1, input.storage_buffer.as_entire_binding()),
-        (2, output.storage_buffer.as_entire_binding()),
-    ];
-    let bind_group: BindGroup = create_bind_group(gpu_handles, &bind_group_layout, to_be_bound);
-
-    {
-        let mut cpass: ComputePass =
-            encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
-        cpass.set_pipeline(compute_pipeline);
-        cpass.set_bind_group(0, &bind_group, &[]);
-        cpass.insert_debug_marker("Relu Graph");
-        cpass.dispatch_workgroups(
-            (input.row_count + 31 / 32) as u32,
-            (input.column_count + 31 / 32) as u32,
-            1,
-        ); // Number of cells to run, the (x,y,z) size of item being processed
+    for chunk in &chunks {
+        let uniform: ReluUniform =
+            ReluUniform::from_dimensions(gpu_handles, "Relu Uniform", chunk.row_count, 1);
+        let to_be_bound: Vec<(u32, BindingResource)> = vec![
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (1, chunk.binding(input, 1)),
+            (2, chunk.binding(output, 1)),
+        ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "Relu Graph",
+            chunk_launch_blocks(chunk),
+        )?;
     }
+
+    Ok(())
 }
 
 // Softmax
+// The max and the sum are both reduced in two passes, up to REDUCTION_BLOCK_SIZE workgroups
+// writing partial results, followed by a single workgroup reducing those.
+// Entry point, bindings and label of every pass, in the order they are dispatched.
+const SOFTMAX_PASSES: [(&str, &[u32], &str); 5] = [
+    ("partial_max", &[0, 1, 5], "Softmax - Partial Max"),
+    ("final_max", &[0, 2, 5], "Softmax - Final Max"),
+    ("partial_sum", &[0, 1, 2, 5], "Softmax - Partial Sum"),
+    ("final_sum", &[0, 2, 3, 5], "Softmax - Final Sum"),
+    ("map", &[0, 1, 3, 4], "Softmax - Map"),
+];
+
 pub fn build_softmax_elements(
     gpu_handles: &GPUHandles,
     shader_cache: &mut HashMap<String, ShaderModule>,
     pipeline_cache: &mut HashMap<String, ComputePipeline>,
 ) {
-    let cs_module: ShaderModule =
-        create_shader_module(gpu_handles, include_str!("../shared/shaders/softmax.wgsl"));
-    let entry_point: &str = "single_pass_max";
-    let key: String = format!("Softmax_{}", entry_point);
-    let compute_pipeline: ComputePipeline =
-        create_compute_pipeline(gpu_handles, &cs_module, entry_point);
+    let entry_points: Vec<&str> = SOFTMAX_PASSES
+        .iter()
+        .map(|(entry_point, _, _)| *entry_point)
+        .collect();
+    build_elements(
+        gpu_handles,
+        shader_cache,
+        pipeline_cache,
+        "Softmax",
+        include_str!("../shared/shaders/softmax.wgsl"),
+        &entry_points,
+    );
+}
 
-    pipeline_cache.insert(key, compute_pipeline);
+// The uniform and scratch buffers shared by the softmax passes
+struct SoftmaxBuffers {
+    uniform: SoftmaxUniform,
+    global_max: Tensor2DGPU,
+    global_offset: Tensor2DGPU,
+    partials: Tensor2DGPU,
+    // The map is elementwise and split in to chunks like ReLU, with a uniform per chunk.
+    // The reductions need all of the input and can't be split.
+    map_chunks: Vec<(DispatchChunk, SoftmaxUniform)>,
+}
 
-    let entry_point: &str = "single_pass_sum";
-    let key: String = format!("Softmax_{}", entry_point);
-    let compute_pipeline: ComputePipeline =
-        create_compute_pipeline(gpu_handles, &cs_module, entry_point);
+impl SoftmaxBuffers {
+    fn new(gpu_handles: &GPUHandles, name: &str, input: &Tensor2DGPU) -> Result<Self, GraphError> {
+        let uniform: SoftmaxUniform =
+            SoftmaxUniform::new(gpu_handles, "Softmax Uniform", input.len());
+        let global_max: Tensor2DGPU =
+            Tensor2DGPU::new(gpu_handles, "Softmax Global Max", 0.0, 1, 1);
+        let global_offset: Tensor2DGPU =
+            Tensor2DGPU::new(gpu_handles, "Softmax Global Offset", 0.0, 1, 1);
+        let partials: Tensor2DGPU = Tensor2DGPU::new(
+            gpu_handles,
+            "Softmax Partials",
+            0.0,
+            uniform.block_count() as usize,
+            1,
+        );
+        let map_chunks: Vec<(DispatchChunk, SoftmaxUniform)> =
+            elementwise_chunks(gpu_handles, name, input)?
+                .into_iter()
+                .map(|chunk| {
+                    let uniform: SoftmaxUniform =
+                        SoftmaxUniform::new(gpu_handles, "Softmax Map Uniform", chunk.row_count);
+                    (chunk, uniform)
+                })
+                .collect();
+
+        Ok(Self {
+            uniform,
+            global_max,
+            global_offset,
+            partials,
+            map_chunks,
+        })
+    }
 
-    pipeline_cache.insert(key, compute_pipeline);
+    // The map passes bind just their chunk of the input and output
+    fn to_be_bound<'a>(
+        &'a self,
+        input: &'a Tensor2DGPU,
+        output: &'a Tensor2DGPU,
+        bindings: &[u32],
+        map_chunk: Option<&'a (DispatchChunk, SoftmaxUniform)>,
+    ) -> Vec<(u32, BindingResource<'a>)> {
+        bindings
+            .iter()
+            .map(|binding| {
+                let resource: BindingResource = match (binding, map_chunk) {
+                    (0, Some((_, uniform))) => uniform.storage_buffer.as_entire_binding(),
+                    (0, None) => self.uniform.storage_buffer.as_entire_binding(),
+                    (1, Some((chunk, _))) => chunk.binding(input, 1),
+                    (1, None) => input.storage_buffer.as_entire_binding(),
+                    (2, _) => self.global_max.storage_buffer.as_entire_binding(),
+                    (3, _) => self.global_offset.storage_buffer.as_entire_binding(),
+                    (4, Some((chunk, _))) => chunk.binding(output, 1),
+                    (4, None) => output.storage_buffer.as_entire_binding(),
+                    _ => self.partials.storage_buffer.as_entire_binding(),
+                };
+                (*binding, resource)
+            })
+            .collect()
+    }
 
-    let entry_point: &str = "map";
-    let key: String = format!("Softmax_{}", entry_point);
-    let compute_pipeline: ComputePipeline =
-        create_compute_pipeline(gpu_handles, &cs_module, entry_point);
+    // Every pass in the order they are dispatched, with a map pass per chunk
+    fn passes<'a>(
+        &'a self,
+        input: &'a Tensor2DGPU,
+        output: &'a Tensor2DGPU,
+    ) -> Vec<SoftmaxPass<'a>> {
+        let mut passes: Vec<SoftmaxPass<'a>> = Vec::<SoftmaxPass<'a>>::new();
+        for (entry_point, bindings, label) in SOFTMAX_PASSES {
+            match entry_point {
+                "partial_max" | "partial_sum" => passes.push((
+                    entry_point,
+                    label,
+                    self.to_be_bound(input, output, bindings, None),
+                    (self.uniform.block_count(), 1, 1),
+                )),
+                "final_max" | "final_sum" => passes.push((
+                    entry_point,
+                    label,
+                    self.to_be_bound(input, output, bindings, None),
+                    (1, 1, 1),
+                )),
+                _ => {
+                    for map_chunk in &self.map_chunks {
+                        passes.push((
+                            entry_point,
+                            label,
+                            self.to_be_bound(input, output, bindings, Some(map_chunk)),
+                            chunk_launch_blocks(&map_chunk.0),
+                        ));
+                    }
+                }
+            }
+        }
 
-    pipeline_cache.insert(key, compute_pipeline);
+        passes
+    }
+}
+
+// Entry point, label, bindings and launch of a single softmax pass
+type SoftmaxPass<'a> = (
+    &'static str,
+    &'static str,
+    Vec<(u32, BindingResource<'a>)>,
+    (u32, u32, u32),
+);
+
+fn record_softmax_passes(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    name: &str,
+    input: &Tensor2DGPU,
+    output: &Tensor2DGPU,
+    encoder: &mut CommandEncoder,
+) -> Result<(), GraphError> {
+    let buffers: SoftmaxBuffers = SoftmaxBuffers::new(gpu_handles, name, input)?;
+    for (entry_point, label, to_be_bound, launch_blocks) in buffers.passes(input, output) {
+        let mut uncached_pipeline: Option<ComputePipeline> = None;
+        let compute_pipeline: &ComputePipeline = get_pipeline(
+            gpu_handles,
+            use_cache,
+            pipeline_cache,
+            "Softmax",
+            include_str!("../shared/shaders/softmax.wgsl"),
+            entry_point,
+            &mut uncached_pipeline,
+        )?;
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            label,
+            launch_blocks,
+        )?;
+    }
 
-    shader_cache.insert("Softmax".to_string(), cs_module);
+    Ok(())
 }
 
 pub fn softmax(
     gpu_handles: &GPUHandles,
     use_cache: bool,
-    shader_cache: &HashMap<String, ShaderModule>,
     pipeline_cache: &HashMap<String, ComputePipeline>,
     node: &NodeGPU,
     data_buffers: &[Tensor2DGPU],
     encoder: &mut CommandEncoder,
-) {
-    if node.buffer_indices.len() != 2 {
-        panic!(
-            "nodes::softmax function expected 2 input buffer, received {}",
-            node.buffer_indices.len()
-        );
-    }
+) -> Result<(), GraphError> {
+    buffer_count_check("nodes::softmax", &node.name, &node.buffer_indices, 2)?;
 
     let input: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
     let output: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
 
-    let uniform: SoftmaxUniform = SoftmaxUniform::new(gpu_handles, "Softmax Uniform", input.len());
-    let global_max: Tensor2DGPU = Tensor2DGPU::new(gpu_handles, "Softmax Global Max", 0.0, 1, 1);
-    let global_offset: Tensor2DGPU =
-        Tensor2DGPU::new(gpu_handles, "Softmax Global Offset", 0.0, 1, 1);
-
-    let shader_module: Option<ShaderModule> = if use_cache {
-        None
-    } else {
-        Some(create_shader_module(
-            gpu_handles,
-            include_str!("../shared/shaders/softmax.wgsl"),
-        ))
-    };
-    let cs_module: &ShaderModule = if use_cache {
-        let key: &str = "Softmax";
-        if shader_cache.contains_key(key) {
-            &shader_cache[key]
-        } else {
-            panic!("Tried to get a cached {} shader in graph::nodes::softmax(), but failed to find it in the shader cache!", key);
-        }
-    } else {
-        shader_module
-            .as_ref()
-            .expect("Failed to get a reference to compute shader module")
-    };
-
-    let to_be_bound: Vec<(u32, BindingResource)> = vec![
-        (0, uniform.storage_buffer.as_entire_binding()),
-        (1, input.storage_buffer.as_entire_binding()),
-        (2, global_max.storage_buffer.as_entire_binding()),
-    ];
-
-    // Instantiates the bind group, once again specifying the binding of buffers.
-    {
-        let max_pipeline: Option<ComputePipeline> = if use_cache {
-            None
-        } else {
-            Some(create_compute_pipeline(
-                gpu_handles,
-                cs_module,
-                "single_pass_max",
-            ))
-        };
-        let max_compute_pipeline: &ComputePipeline = if use_cache {
-            let key: &str = "Softmax_single_pass_max";
-            if pipeline_cache.contains_key(key) {
-                &pipeline_cache[key]
-            } else {
-                panic!("Tried to get a cached {} pipeline in graph::nodes::softmax(), but failed to find it in the pipeline cache!", key);
-            }
-        } else {
-            max_pipeline
-                .as_ref()
-                .expect("Failed to get a reference to compute pipeline in graph::nodes::softmax")
-        };
-
-        let max_bind_group_layout: BindGroupLayout = max_compute_pipeline.get_bind_group_layout(0);
-        let max_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &max_bind_group_layout, to_be_bound);
-
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Max"),
-        });
-        cpass.set_pipeline(max_compute_pipeline);
-        cpass.set_bind_group(0, &max_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Max");
-        cpass.dispatch_workgroups(1, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
-    }
-
-    let to_be_bound: Vec<(u32, BindingResource)> = vec![
-        (0, uniform.storage_buffer.as_entire_binding()),
-        (1, input.storage_buffer.as_entire_binding()),
-        (2, global_max.storage_buffer.as_entire_binding()),
-        (3, global_offset.storage_buffer.as_entire_binding()),
-    ];
-    {
-        let sum_pipeline: Option<ComputePipeline> = if use_cache {
-            None
-        } else {
-            Some(create_compute_pipeline(
-                gpu_handles,
-                cs_module,
-                "single_pass_sum",
-            ))
-        };
-        let sum_compute_pipeline: &ComputePipeline = if use_cache {
-            let key: &str = "Softmax_single_pass_sum";
-            if pipeline_cache.contains_key(key) {
-                &pipeline_cache[key]
-            } else {
-                panic!("Tried to get a cached {} pipeline in graph::nodes::softmax(), but failed to find it in the pipeline cache!", key);
-            }
-        } else {
-            sum_pipeline
-                .as_ref()
-                .expect("Failed to get a reference to compute pipeline in graph::nodes::softmax")
-        };
-
-        let sum_bind_group_layout: BindGroupLayout = sum_compute_pipeline.get_bind_group_layout(0);
-        let sum_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &sum_bind_group_layout, to_be_bound);
-
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Sum"),
-        });
-        cpass.set_pipeline(sum_compute_pipeline);
-        cpass.set_bind_group(0, &sum_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Sum");
-        cpass.dispatch_workgroups(1, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
-    }
-
-    let to_be_bound: Vec<(u32, BindingResource)> = vec![
-        (0, uniform.storage_buffer.as_entire_binding()),
-        (1, input.storage_buffer.as_entire_binding()),
-        (3, global_offset.storage_buffer.as_entire_binding()),
-        (4, output.storage_buffer.as_entire_binding()),
-    ];
-    let block_size: usize = 32;
-    {
-        // let map_compute_pipeline: ComputePipeline =
-        //     create_compute_pipeline(gpu_handles, &cs_module, "map");
-
-        let map_pipeline: Option<ComputePipeline> = if use_cache {
-            None
-        } else {
-            Some(create_compute_pipeline(gpu_handles, cs_module, "map"))
-        };
-        let map_compute_pipeline: &ComputePipeline = if use_cache {
-            let key: &str = "Softmax_map";
-            if pipeline_cache.contains_key(key) {
-                &pipeline_cache[key]
-            } else {
-                panic!("Tried to get a cached {} pipeline in graph::nodes::softmax(), but failed to find it in the pipeline cache!", key);
-            }
-        } else {
-            map_pipeline
-                .as_ref()
-                .expect("Failed to get a reference to compute pipeline in graph::nodes::softmax")
-        };
-
-        let map_bind_group_layout: BindGroupLayout = map_compute_pipeline.get_bind_group_layout(0);
-        let map_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &map_bind_group_layout, to_be_bound);
-
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Map"),
-        });
-        cpass.set_pipeline(map_compute_pipeline);
-        cpass.set_bind_group(0, &map_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Map");
-        cpass.dispatch_workgroups(((input.len() + block_size - 1) / block_size) as u32, 1, 1);
-        // Number of cells to run, the (x,y,z) size of item being processed
-    }
+    record_softmax_passes(
+        gpu_handles,
+        use_cache,
+        pipeline_cache,
+        &node.name,
+        input,
+        output,
+        encoder,
+    )
 }
 
 // LinearReLUSoftmax
@@ -517,12 +543,14 @@ pub fn linear_relu_softmax(
     node: &NodeGPU,
     data_buffers: &[Tensor2DGPU],
     encoder: &mut CommandEncoder,
-) {
+    kernel: LinearLayerKernel,
+) -> Result<(), GraphError> {
     if node.buffer_indices.len() != 4 {
-        panic!(
-            "nodes::linear_relu_softmax function expected 4 buffers, received {}",
-            node.buffer_indices.len()
-        );
+        return Err(GraphError::MalformedNode(format!(
+            "nodes::linear_relu_softmax function expected 4 buffers, received {} in node {}",
+            node.buffer_indices.len(),
+            node.name
+        )));
     }
 
     let input: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
@@ -538,26 +566,8 @@ pub fn linear_relu_softmax(
         bias.column_count,
     );
 
-    let linear_block_size: usize = 8;
-    let linear_launch_blocks_x: u32 =
-        ((intermediate.row_count + linear_block_size - 1) / linear_block_size) as u32;
-    let linear_launch_blocks_y: u32 =
-        ((intermediate.column_count + linear_block_size - 1) / linear_block_size) as u32;
-
-    let linear_uniform: LinearLayerUniform = LinearLayerUniform::from_tensor_2d_gpu(
-        gpu_handles,
-        "Linear Layer Uniform",
-        input,
-        weights,
-        bias,
-        &intermediate,
-    );
-    let softmax_uniform: SoftmaxUniform =
-        SoftmaxUniform::new(gpu_handles, "Softmax Uniform", intermediate.len());
-    let softmax_global_max: Tensor2DGPU =
-        Tensor2DGPU::new(gpu_handles, "Softmax Global Max", 0.0, 1, 1);
-    let softmax_global_offset: Tensor2DGPU =
-        Tensor2DGPU::new(gpu_handles, "Softmax Global Offset", 0.0, 1, 1);
+    let chunks: Vec<DispatchChunk> =
+        linear_layer_chunks(gpu_handles, &node.name, kernel, input, &intermediate)?;
 
     let linear_shader_module: Option<ShaderModule> = if use_cache {
         None
@@ -573,7 +583,7 @@ pub fn linear_relu_softmax(
         if shader_cache.contains_key(key) {
             &shader_cache[key]
         } else {
-            panic!("Tried to get a cached {} shader in graph::nodes::linear_relu_softmax(), but failed to find it!", key);
+            return Err(GraphError::UnsupportedOperator(format!("Tried to get a cached {} shader in graph::nodes::linear_relu_softmax(), but failed to find it!", key)));
         }
     } else {
         linear_shader_module.as_ref().expect(
@@ -581,27 +591,6 @@ pub fn linear_relu_softmax(
         )
     };
 
-    let softmax_shader_module: Option<ShaderModule> = if use_cache {
-        None
-    } else {
-        Some(create_shader_module(
-            gpu_handles,
-            include_str!("../shared/shaders/softmax.wgsl"),
-        ))
-    };
-    let softmax_cs_module: &ShaderModule = if use_cache {
-        let key: &str = "Softmax";
-        if shader_cache.contains_key(key) {
-            &shader_cache[key]
-        } else {
-            panic!("Tried to get a cached {} shader in graph::nodes::linear_relu_softmax(), but failed to find it in the shader cache!", key);
-        }
-    } else {
-        softmax_shader_module.as_ref().expect(
-            "Failed to get a reference to compute pipeline in graph::nodes::linear_relu_softmax",
-        )
-    };
-
     {
         let linear_pipeline: Option<ComputePipeline> = if use_cache {
             None
@@ -609,7 +598,7 @@ pub fn linear_relu_softmax(
             Some(create_compute_pipeline(
                 gpu_handles,
                 linear_cs_module,
-                "main_with_relu",
+                kernel.entry_point(true),
             ))
         };
         let linear_compute_pipeline: &ComputePipeline = if use_cache {
@@ -617,144 +606,1614 @@ pub fn linear_relu_softmax(
             if pipeline_cache.contains_key(key) {
                 &pipeline_cache[key]
             } else {
-                panic!("Tried to get a cached {} pipeline in graph::nodes::linear_relu_softmax(), but failed to find it!", key);
+                return Err(GraphError::UnsupportedOperator(format!("Tried to get a cached {} pipeline in graph::nodes::linear_relu_softmax(), but failed to find it!", key)));
             }
         } else {
             linear_pipeline.as_ref().expect("Failed to get a reference to compute pipeline in graph::nodes::linear_relu_softmax")
         };
 
-        let to_be_bound: Vec<(u32, BindingResource)> = vec![
-            (0, linear_uniform.storage_buffer.as_entire_binding()),
-            (1, input.storage_buffer.as_entire_binding()),
-            (2, weights.storage_buffer.as_entire_binding()),
-            (3, bias.storage_buffer.as_entire_binding()),
-            (4, intermediate.storage_buffer.as_entire_binding()),
-        ];
-
-        let linear_bind_group_layout: BindGroupLayout =
-            linear_compute_pipeline.get_bind_group_layout(0);
-        let linear_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &linear_bind_group_layout, to_be_bound);
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("linear_layer_immediate"),
-        });
-        cpass.set_pipeline(linear_compute_pipeline);
-        cpass.set_bind_group(0, &linear_bind_group, &[]);
-        cpass.insert_debug_marker("linear_layer_immediate");
-        cpass.dispatch_workgroups(linear_launch_blocks_x, linear_launch_blocks_y, 1);
-        // Number of cells to run, the (x,y,z) size of item being processed
+        for chunk in &chunks {
+            let linear_uniform: LinearLayerUniform = LinearLayerUniform::from_chunk(
+                gpu_handles,
+                "Linear Layer Uniform",
+                input,
+                weights,
+                bias,
+                &intermediate,
+                chunk,
+            );
+            dispatch(
+                gpu_handles,
+                encoder,
+                linear_compute_pipeline,
+                linear_layer_chunk_bindings(
+                    &linear_uniform,
+                    input,
+                    weights,
+                    bias,
+                    &intermediate,
+                    chunk,
+                ),
+                "linear_layer_immediate",
+                kernel.launch_blocks(chunk.row_count, intermediate.column_count),
+            )?;
+        }
     }
 
-    {
-        let to_be_bound: Vec<(u32, BindingResource)> = vec![
-            (0, softmax_uniform.storage_buffer.as_entire_binding()),
-            (1, intermediate.storage_buffer.as_entire_binding()),
-            (2, softmax_global_max.storage_buffer.as_entire_binding()),
-        ];
+    record_softmax_passes(
+        gpu_handles,
+        use_cache,
+        pipeline_cache,
+        &node.name,
+        &intermediate,
+        output,
+        encoder,
+    )
+}
 
-        let max_pipeline: Option<ComputePipeline> = if use_cache {
-            None
-        } else {
-            Some(create_compute_pipeline(
-                gpu_handles,
-                softmax_cs_module,
-                "single_pass_max",
-            ))
-        };
-        let max_compute_pipeline: &ComputePipeline = if use_cache {
-            let key: &str = "Softmax_single_pass_max";
-            if pipeline_cache.contains_key(key) {
-                &pipeline_cache[key]
-            } else {
-                panic!("Tried to get a cached {} pipeline in graph::nodes::softmax(), but failed to find it in the pipeline cache!", key);
-            }
-        } else {
-            max_pipeline
-                .as_ref()
-                .expect("Failed to get a reference to compute pipeline in graph::nodes::softmax")
-        };
+// Add
+pub fn build_add_elements(
+    gpu_handles: &GPUHandles,
+    shader_cache: &mut HashMap<String, ShaderModule>,
+    pipeline_cache: &mut HashMap<String, ComputePipeline>,
+) {
+    for (key, entry_point) in [("Add", "main"), ("AddReLU", "main_with_relu")] {
+        let cs_module: ShaderModule =
+            create_shader_module(gpu_handles, include_str!("../shared/shaders/add.wgsl"));
 
-        let max_bind_group_layout: BindGroupLayout = max_compute_pipeline.get_bind_group_layout(0);
-        let max_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &max_bind_group_layout, to_be_bound);
+        let compute_pipeline: ComputePipeline =
+            create_compute_pipeline(gpu_handles, &cs_module, entry_point);
 
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Max"),
-        });
-        cpass.set_pipeline(max_compute_pipeline);
-        cpass.set_bind_group(0, &max_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Max");
-        cpass.dispatch_workgroups(1, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
+        shader_cache.insert(key.to_string(), cs_module);
+        pipeline_cache.insert(key.to_string(), compute_pipeline);
     }
+}
 
-    {
-        let to_be_bound: Vec<(u32, BindingResource)> = vec![
-            (0, softmax_uniform.storage_buffer.as_entire_binding()),
-            (1, intermediate.storage_buffer.as_entire_binding()),
-            (2, softmax_global_max.storage_buffer.as_entire_binding()),
-            (3, softmax_global_offset.storage_buffer.as_entire_binding()),
-        ];
-
-        let sum_compute_pipeline: ComputePipeline =
-            create_compute_pipeline(gpu_handles, softmax_cs_module, "single_pass_sum");
-        let sum_bind_group_layout: BindGroupLayout = sum_compute_pipeline.get_bind_group_layout(0);
-        let sum_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &sum_bind_group_layout, to_be_bound);
-
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Sum"),
-        });
-        cpass.set_pipeline(&sum_compute_pipeline);
-        cpass.set_bind_group(0, &sum_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Sum");
-        cpass.dispatch_workgroups(1, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
+// The pipeline key and entry point of add.wgsl
+fn add_pipeline(use_fused_with_relu: bool) -> (&'static str, &'static str) {
+    if use_fused_with_relu {
+        ("AddReLU", "main_with_relu")
+    } else {
+        ("Add", "main")
     }
+}
 
-    let block_size: usize = 32;
+pub fn add(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    shader_cache: &HashMap<String, ShaderModule>,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &NodeGPU,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+    use_fused_with_relu: bool,
+) -> Result<(), GraphError> {
+    if node.buffer_indices.len() != 3 {
+        return Err(GraphError::MalformedNode(format!(
+            "nodes::add function expected 3 buffers, received {} in node {}",
+            node.buffer_indices.len(),
+            node.name
+        )));
+    }
+
+    let tensor_a: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    let tensor_b: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
+    let output: &Tensor2DGPU = &data_buffers[node.buffer_indices[2]];
+
+    let chunks: Vec<DispatchChunk> = elementwise_chunks(gpu_handles, &node.name, output)?;
+    let (key, entry_point) = add_pipeline(use_fused_with_relu);
+
+    let shader_module: Option<ShaderModule> = if use_cache {
+        None
+    } else {
+        Some(create_shader_module(
+            gpu_handles,
+            include_str!("../shared/shaders/add.wgsl"),
+        ))
+    };
+
+    let pipeline: Option<ComputePipeline> = shader_module
+        .as_ref()
+        .map(|cs_module| create_compute_pipeline(gpu_handles, cs_module, entry_point));
+    let compute_pipeline: &ComputePipeline = if use_cache {
+        if pipeline_cache.contains_key(key) && shader_cache.contains_key(key) {
+            &pipeline_cache[key]
+        } else {
+            return Err(GraphError::UnsupportedOperator(format!("Tried to get a cached {} pipeline in graph::nodes::add(), but failed to find it in the pipeline cache!", key)));
+        }
+    } else {
+        pipeline
+            .as_ref()
+            .expect("Failed to get a reference to compute pipeline in graph::nodes::add")
+    };
+
+    // The add shader uses the same row and column layout as the ReLU shader
+    for chunk in &chunks {
+        let uniform: ReluUniform =
+            ReluUniform::from_dimensions(gpu_handles, "Add Uniform", chunk.row_count, 1);
+        let to_be_bound: Vec<(u32, BindingResource)> = vec![
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (1, chunk.binding(tensor_a, 1)),
+            (2, chunk.binding(tensor_b, 1)),
+            (3, chunk.binding(output, 1)),
+        ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "add_graph",
+            chunk_launch_blocks(chunk),
+        )?;
+    }
+
+    Ok(())
+}
+
+// Concat
+// No shader needed, the inputs are just copied in to the right place in the output.
+// Stacking rows is a single copy per input, placing columns side by side
+// is a copy per row per input.
+pub fn concat(
+    node: &NodeGPU,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+    axis: usize,
+) -> Result<(), GraphError> {
+    if node.buffer_indices.len() < 3 {
+        return Err(GraphError::MalformedNode(format!(
+            "nodes::concat function expected at least 3 buffers, received {} in node {}",
+            node.buffer_indices.len(),
+            node.name
+        )));
+    }
+
+    let output: &Tensor2DGPU = &data_buffers[node.buffer_indices[node.buffer_indices.len() - 1]];
+    let element_size: u64 = output.element_size as u64;
+
+    if axis == 0 {
+        let mut output_offset: u64 = 0;
+        for input_index in &node.buffer_indices[0..(node.buffer_indices.len() - 1)] {
+            let input: &Tensor2DGPU = &data_buffers[*input_index];
+            encoder.copy_buffer_to_buffer(
+                &input.storage_buffer,
+                0,
+                &output.storage_buffer,
+                output_offset,
+                input.size(),
+            );
+            output_offset += input.size();
+        }
+    } else {
+        for row_index in 0..output.row_count {
+            let mut output_offset: u64 = (row_index * output.column_count) as u64 * element_size;
+            for input_index in &node.buffer_indices[0..(node.buffer_indices.len() - 1)] {
+                let input: &Tensor2DGPU = &data_buffers[*input_index];
+                let row_size: u64 = input.column_count as u64 * element_size;
+                encoder.copy_buffer_to_buffer(
+                    &input.storage_buffer,
+                    row_index as u64 * row_size,
+                    &output.storage_buffer,
+                    output_offset,
+                    row_size,
+                );
+                output_offset += row_size;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// The loss and backward operators below have several entry points each.
+// Instead of repeating the cache lookup for every single one of them,
+// they share get_pipeline and dispatch.
+// Pipelines are cached under "<shader key>_<entry point>".
+fn get_pipeline<'a>(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &'a HashMap<String, ComputePipeline>,
+    shader_key: &str,
+    shader_source: &str,
+    entry_point: &str,
+    uncached_pipeline: &'a mut Option<ComputePipeline>,
+) -> Result<&'a ComputePipeline, GraphError> {
+    if use_cache {
+        let key: String = format!("{}_{}", shader_key, entry_point);
+        return pipeline_cache.get(&key).ok_or_else(|| {
+            GraphError::UnsupportedOperator(format!(
+                "Tried to get a cached {} pipeline in graph::nodes_gpu, but failed to find it in the pipeline cache!",
+                key
+            ))
+        });
+    }
+
+    let cs_module: ShaderModule = create_shader_module(gpu_handles, shader_source);
+    Ok(uncached_pipeline.insert(create_compute_pipeline(
+        gpu_handles,
+        &cs_module,
+        entry_point,
+    )))
+}
+
+fn build_elements(
+    gpu_handles: &GPUHandles,
+    shader_cache: &mut HashMap<String, ShaderModule>,
+    pipeline_cache: &mut HashMap<String, ComputePipeline>,
+    shader_key: &str,
+    shader_source: &str,
+    entry_points: &[&str],
+) {
+    let cs_module: ShaderModule = create_shader_module(gpu_handles, shader_source);
+    for entry_point in entry_points {
+        let compute_pipeline: ComputePipeline =
+            create_compute_pipeline(gpu_handles, &cs_module, entry_point);
+        pipeline_cache.insert(format!("{}_{}", shader_key, entry_point), compute_pipeline);
+    }
+    shader_cache.insert(shader_key.to_string(), cs_module);
+}
+
+pub fn dispatch(
+    gpu_handles: &GPUHandles,
+    encoder: &mut CommandEncoder,
+    compute_pipeline: &ComputePipeline,
+    to_be_bound: Vec<(u32, BindingResource)>,
+    label: &str,
+    launch_blocks: (u32, u32, u32),
+) -> Result<(), GraphError> {
+    limits_check(gpu_handles, label, &to_be_bound, launch_blocks)?;
+
+    let bind_group_layout: BindGroupLayout = compute_pipeline.get_bind_group_layout(0);
+    let bind_group: BindGroup = create_bind_group(gpu_handles, &bind_group_layout, to_be_bound);
+
+    let mut cpass: ComputePass =
+        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some(label) });
+    cpass.set_pipeline(compute_pipeline);
+    cpass.set_bind_group(0, &bind_group, &[]);
+    cpass.insert_debug_marker(label);
+    cpass.dispatch_workgroups(launch_blocks.0, launch_blocks.1, launch_blocks.2);
+
+    Ok(())
+}
+
+// wgpu only complains about a binding or a launch larger than the device allows once
+// it is too late to do anything about it, so every pass is checked against the limits first.
+// Most operators are split in to chunks which stay within the limits, see dispatch_chunks.
+// The ones which aren't, and fail here instead, are
+//   the reductions of the softmax, log softmax, loss and their backward nodes,
+//     which bind the whole tensor for a single workgroup,
+//   the backward pass of the linear layer, which binds whole tensors,
+//   softmax along axis 0, which splits its columns but still binds nearly the whole tensor.
+fn limits_check(
+    gpu_handles: &GPUHandles,
+    label: &str,
+    to_be_bound: &[(u32, BindingResource)],
+    launch_blocks: (u32, u32, u32),
+) -> Result<(), GraphError> {
+    let limits: &Limits = &gpu_handles.limits;
+
+    for (binding, resource) in to_be_bound {
+        if let BindingResource::Buffer(buffer_binding) = resource {
+            let size: u64 = match buffer_binding.size {
+                Some(size) => size.get(),
+                None => buffer_binding.buffer.size() - buffer_binding.offset,
+            };
+            if (limits.max_storage_buffer_binding_size as u64) < size {
+                return Err(GraphError::LimitExceeded(format!(
+                    "Binding {} of {} is {} bytes, but {} only allows storage buffer bindings of up to {} bytes",
+                    binding, label, size, gpu_handles.adapter_info.name, limits.max_storage_buffer_binding_size
+                )));
+            }
+        }
+    }
+
+    let max_workgroups: u32 = limits.max_compute_workgroups_per_dimension;
+    if max_workgroups < launch_blocks.0
+        || max_workgroups < launch_blocks.1
+        || max_workgroups < launch_blocks.2
     {
+        return Err(GraphError::LimitExceeded(format!(
+            "{} launches {:?} workgroups, but {} only allows up to {} per dimension",
+            label, launch_blocks, gpu_handles.adapter_info.name, max_workgroups
+        )));
+    }
+
+    Ok(())
+}
+
+// Splits the rows of the tensors of a node in to chunks the device can bind and dispatch,
+// see dispatch_chunks
+fn chunks(
+    gpu_handles: &GPUHandles,
+    name: &str,
+    row_count: usize,
+    row_lengths: &[usize],
+    rows_per_workgroup: usize,
+) -> Result<Vec<DispatchChunk>, GraphError> {
+    dispatch_chunks(
+        &gpu_handles.limits,
+        row_count,
+        row_lengths,
+        rows_per_workgroup,
+    )
+    .ok_or_else(|| {
+        GraphError::LimitExceeded(format!(
+            "The rows of node {} can't be split in to chunks {} can bind, which are at most {} bytes",
+            name, gpu_handles.adapter_info.name, gpu_handles.limits.max_storage_buffer_binding_size
+        ))
+    })
+}
+
+// ReLU, Add, the maps of the softmax and the backward passes and the optimizer steps
+// see their tensors as rows of a single element, 32 to a workgroup.
+// The ReLU and Add shaders handle those as a tensor with a single column.
+pub fn elementwise_chunks(
+    gpu_handles: &GPUHandles,
+    name: &str,
+    tensor: &Tensor2DGPU,
+) -> Result<Vec<DispatchChunk>, GraphError> {
+    chunks(gpu_handles, name, tensor.len(), &[1], 32)
+}
+
+pub fn chunk_launch_blocks(chunk: &DispatchChunk) -> (u32, u32, u32) {
+    let block_size: usize = 32;
+    (chunk.row_count.div_ceil(block_size) as u32, 1, 1)
+}
+
+// Chunks of the rows of the output, along with the same rows of the input and bias
+fn linear_layer_chunks(
+    gpu_handles: &GPUHandles,
+    name: &str,
+    kernel: LinearLayerKernel,
+    input: &Tensor2DGPU,
+    output: &Tensor2DGPU,
+) -> Result<Vec<DispatchChunk>, GraphError> {
+    chunks(
+        gpu_handles,
+        name,
+        output.row_count,
+        &[input.column_count, output.column_count],
+        kernel.block_size(),
+    )
+}
+
+// The weights are needed by every row and are always bound whole
+fn linear_layer_chunk_bindings<'a>(
+    uniform: &'a LinearLayerUniform,
+    input: &'a Tensor2DGPU,
+    weights: &'a Tensor2DGPU,
+    bias: &'a Tensor2DGPU,
+    output: &'a Tensor2DGPU,
+    chunk: &DispatchChunk,
+) -> Vec<(u32, BindingResource<'a>)> {
+    vec![
+        (0, uniform.storage_buffer.as_entire_binding()),
+        (1, chunk.binding(input, input.column_count)),
+        (2, weights.storage_buffer.as_entire_binding()),
+        (3, chunk.binding(bias, bias.column_count)),
+        (4, chunk.binding(output, output.column_count)),
+    ]
+}
+
+fn buffer_count_check(
+    function_name: &str,
+    name: &str,
+    buffer_indices: &[usize],
+    expected: usize,
+) -> Result<(), GraphError> {
+    if buffer_indices.len() != expected {
+        return Err(GraphError::MalformedNode(format!(
+            "{} function expected {} buffers, received {} in node {}",
+            function_name,
+            expected,
+            buffer_indices.len(),
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+// LogSoftmax
+pub fn build_log_softmax_elements(
+    gpu_handles: &GPUHandles,
+    shader_cache: &mut HashMap<String, ShaderModule>,
+    pipeline_cache: &mut HashMap<String, ComputePipeline>,
+) {
+    build_elements(
+        gpu_handles,
+        shader_cache,
+        pipeline_cache,
+        "LogSoftmax",
+        include_str!("../shared/shaders/log_softmax.wgsl"),
+        &["forward", "single_pass_sum", "backward"],
+    );
+}
+
+pub fn log_softmax(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &NodeGPU,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+) -> Result<(), GraphError> {
+    buffer_count_check("nodes::log_softmax", &node.name, &node.buffer_indices, 2)?;
+
+    let input: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    let output: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
+
+    let uniform: SoftmaxUniform =
+        SoftmaxUniform::new(gpu_handles, "Log Softmax Uniform", input.len());
+
+    let mut uncached_pipeline: Option<ComputePipeline> = None;
+    let compute_pipeline: &ComputePipeline = get_pipeline(
+        gpu_handles,
+        use_cache,
+        pipeline_cache,
+        "LogSoftmax",
+        include_str!("../shared/shaders/log_softmax.wgsl"),
+        "forward",
+        &mut uncached_pipeline,
+    )?;
+    let to_be_bound: Vec<(u32, BindingResource)> = vec![
+        (0, uniform.storage_buffer.as_entire_binding()),
+        (1, input.storage_buffer.as_entire_binding()),
+        (2, output.storage_buffer.as_entire_binding()),
+    ];
+    dispatch(
+        gpu_handles,
+        encoder,
+        compute_pipeline,
+        to_be_bound,
+        "Log Softmax",
+        (1, 1, 1),
+    )?;
+
+    Ok(())
+}
+
+// Softmax along an axis
+// Unlike Softmax, every lane has a workgroup of its own,
+// so the max, sum and map all happen in a single dispatch.
+pub fn build_softmax_axis_elements(
+    gpu_handles: &GPUHandles,
+    shader_cache: &mut HashMap<String, ShaderModule>,
+    pipeline_cache: &mut HashMap<String, ComputePipeline>,
+) {
+    build_elements(
+        gpu_handles,
+        shader_cache,
+        pipeline_cache,
+        "SoftmaxAxis",
+        include_str!("../shared/shaders/softmax_axis.wgsl"),
+        &["forward", "backward"],
+    );
+}
+
+pub fn softmax_axis(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &NodeGPU,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+    axis: usize,
+) -> Result<(), GraphError> {
+    buffer_count_check("nodes::softmax_axis", &node.name, &node.buffer_indices, 2)?;
+
+    let input: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    let output: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
+
+    let mut uncached_pipeline: Option<ComputePipeline> = None;
+    let compute_pipeline: &ComputePipeline = get_pipeline(
+        gpu_handles,
+        use_cache,
+        pipeline_cache,
+        "SoftmaxAxis",
+        include_str!("../shared/shaders/softmax_axis.wgsl"),
+        "forward",
+        &mut uncached_pipeline,
+    )?;
+    for chunk in softmax_axis_chunks(gpu_handles, &node.name, input, axis)? {
+        let uniform: SoftmaxAxisUniform = SoftmaxAxisUniform::from_chunk(
+            gpu_handles,
+            "Softmax Axis Uniform",
+            input.row_count,
+            input.column_count,
+            axis,
+            &chunk,
+        );
         let to_be_bound: Vec<(u32, BindingResource)> = vec![
-            (0, softmax_uniform.storage_buffer.as_entire_binding()),
-            (1, intermediate.storage_buffer.as_entire_binding()),
-            (3, softmax_global_offset.storage_buffer.as_entire_binding()),
-            (4, output.storage_buffer.as_entire_binding()),
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (1, softmax_axis_binding(input, &chunk, axis)),
+            (2, softmax_axis_binding(output, &chunk, axis)),
         ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "Softmax Axis",
+            (uniform.lane_count(), 1, 1),
+        )?;
+    }
 
-        let map_pipeline: Option<ComputePipeline> = if use_cache {
-            None
-        } else {
-            Some(create_compute_pipeline(
+    Ok(())
+}
+
+// Every lane gets a workgroup, so a chunk can have at most max_compute_workgroups_per_dimension
+// lanes. Along axis 1 the lanes are rows, which are chunked like the rows of any other node.
+// Along axis 0 the lanes are columns, so a chunk is a range of columns in every row.
+fn softmax_axis_chunks(
+    gpu_handles: &GPUHandles,
+    name: &str,
+    tensor: &Tensor2DGPU,
+    axis: usize,
+) -> Result<Vec<DispatchChunk>, GraphError> {
+    if axis == 0 {
+        chunks(gpu_handles, name, tensor.column_count, &[1], 1)
+    } else {
+        chunks(
+            gpu_handles,
+            name,
+            tensor.row_count,
+            &[tensor.column_count],
+            1,
+        )
+    }
+}
+
+// Along axis 0 the binding starts at the first column of the chunk and ends after its last
+// column in the last row. That is nearly the whole tensor, so a tensor too large to be bound
+// at once is still rejected by limits_check, only the lanes are split.
+fn softmax_axis_binding<'a>(
+    tensor: &'a Tensor2DGPU,
+    chunk: &DispatchChunk,
+    axis: usize,
+) -> BindingResource<'a> {
+    if axis != 0 {
+        return chunk.binding(tensor, tensor.column_count);
+    }
+
+    let element_count: usize = (tensor.row_count - 1) * tensor.column_count + chunk.row_count;
+    BindingResource::Buffer(BufferBinding {
+        buffer: &tensor.storage_buffer,
+        offset: (chunk.row_offset * tensor.element_size) as u64,
+        size: BufferSize::new((element_count * tensor.element_size) as u64),
+    })
+}
+
+// Loss
+pub fn build_loss_elements(
+    gpu_handles: &GPUHandles,
+    shader_cache: &mut HashMap<String, ShaderModule>,
+    pipeline_cache: &mut HashMap<String, ComputePipeline>,
+) {
+    build_elements(
+        gpu_handles,
+        shader_cache,
+        pipeline_cache,
+        "Loss",
+        include_str!("../shared/shaders/loss.wgsl"),
+        &[
+            "forward",
+            "backward",
+            "single_pass_target_sum",
+            "softmax_backward",
+        ],
+    );
+}
+
+// Every kind of loss is the same shader, the kind is passed in the uniform
+pub fn loss(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &NodeGPU,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+    kind: LossKind,
+) -> Result<(), GraphError> {
+    buffer_count_check("nodes::loss", &node.name, &node.buffer_indices, 3)?;
+
+    let input: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    let target: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
+    let output: &Tensor2DGPU = &data_buffers[node.buffer_indices[2]];
+
+    let uniform: LossUniform = LossUniform::new(gpu_handles, "Loss Uniform", input.len(), kind);
+
+    let mut uncached_pipeline: Option<ComputePipeline> = None;
+    let compute_pipeline: &ComputePipeline = get_pipeline(
+        gpu_handles,
+        use_cache,
+        pipeline_cache,
+        "Loss",
+        include_str!("../shared/shaders/loss.wgsl"),
+        "forward",
+        &mut uncached_pipeline,
+    )?;
+    let to_be_bound: Vec<(u32, BindingResource)> = vec![
+        (0, uniform.storage_buffer.as_entire_binding()),
+        (1, input.storage_buffer.as_entire_binding()),
+        (2, target.storage_buffer.as_entire_binding()),
+        (3, output.storage_buffer.as_entire_binding()),
+    ];
+    dispatch(
+        gpu_handles,
+        encoder,
+        compute_pipeline,
+        to_be_bound,
+        "Loss",
+        (1, 1, 1),
+    )?;
+
+    Ok(())
+}
+
+// Backward
+pub fn build_backward_elements(
+    gpu_handles: &GPUHandles,
+    shader_cache: &mut HashMap<String, ShaderModule>,
+    pipeline_cache: &mut HashMap<String, ComputePipeline>,
+) {
+    build_elements(
+        gpu_handles,
+        shader_cache,
+        pipeline_cache,
+        "LinearLayerBackward",
+        include_str!("../shared/shaders/linear_layer_backward.wgsl"),
+        &["backward_input", "backward_weights"],
+    );
+    build_elements(
+        gpu_handles,
+        shader_cache,
+        pipeline_cache,
+        "Accumulate",
+        include_str!("../shared/shaders/accumulate.wgsl"),
+        &["main"],
+    );
+    build_elements(
+        gpu_handles,
+        shader_cache,
+        pipeline_cache,
+        "ReLUBackward",
+        include_str!("../shared/shaders/relu_backward.wgsl"),
+        &["main"],
+    );
+    build_elements(
+        gpu_handles,
+        shader_cache,
+        pipeline_cache,
+        "SoftmaxBackward",
+        include_str!("../shared/shaders/softmax_backward.wgsl"),
+        &["single_pass_dot", "map"],
+    );
+    // The backward passes of the losses and of log softmax live with their forward passes
+    build_loss_elements(gpu_handles, shader_cache, pipeline_cache);
+    build_log_softmax_elements(gpu_handles, shader_cache, pipeline_cache);
+}
+
+// accumulated += gradient
+fn accumulate(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    gradient: &Tensor2DGPU,
+    accumulated: &Tensor2DGPU,
+    encoder: &mut CommandEncoder,
+) -> Result<(), GraphError> {
+    let mut uncached_pipeline: Option<ComputePipeline> = None;
+    let compute_pipeline: &ComputePipeline = get_pipeline(
+        gpu_handles,
+        use_cache,
+        pipeline_cache,
+        "Accumulate",
+        include_str!("../shared/shaders/accumulate.wgsl"),
+        "main",
+        &mut uncached_pipeline,
+    )?;
+    // The same chunks as relu
+    for chunk in elementwise_chunks(gpu_handles, "Accumulate", gradient)? {
+        let uniform: ReluUniform =
+            ReluUniform::from_dimensions(gpu_handles, "Accumulate Uniform", chunk.row_count, 1);
+        let to_be_bound: Vec<(u32, BindingResource)> = vec![
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (1, chunk.binding(gradient, 1)),
+            (2, chunk.binding(accumulated, 1)),
+        ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "Accumulate",
+            chunk_launch_blocks(&chunk),
+        )?;
+    }
+
+    Ok(())
+}
+
+// See graph::autograd for the buffer layout of the backward nodes
+pub fn linear_layer_backward(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &BackwardNode,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+) -> Result<(), GraphError> {
+    buffer_count_check(
+        "nodes::linear_layer_backward",
+        &node.name,
+        &node.buffer_indices,
+        6,
+    )?;
+
+    let input: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    let weights: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
+    let output_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[2]];
+    let input_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[3]];
+    let weights_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[4]];
+    let bias_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[5]];
+
+    // The output gradient has the shape of the bias and the output
+    let uniform: LinearLayerUniform = LinearLayerUniform::from_tensor_2d_gpu(
+        gpu_handles,
+        "Linear Layer Backward Uniform",
+        input,
+        weights,
+        output_gradient,
+        output_gradient,
+    );
+
+    let block_size: usize = 8;
+    {
+        let mut uncached_pipeline: Option<ComputePipeline> = None;
+        let compute_pipeline: &ComputePipeline = get_pipeline(
+            gpu_handles,
+            use_cache,
+            pipeline_cache,
+            "LinearLayerBackward",
+            include_str!("../shared/shaders/linear_layer_backward.wgsl"),
+            "backward_input",
+            &mut uncached_pipeline,
+        )?;
+        let to_be_bound: Vec<(u32, BindingResource)> = vec![
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (2, weights.storage_buffer.as_entire_binding()),
+            (3, output_gradient.storage_buffer.as_entire_binding()),
+            (4, input_gradient.storage_buffer.as_entire_binding()),
+        ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "Linear Layer Backward - Input",
+            (
+                input.row_count.div_ceil(block_size) as u32,
+                input.column_count.div_ceil(block_size) as u32,
+                1,
+            ),
+        )?;
+    }
+
+    {
+        let mut uncached_pipeline: Option<ComputePipeline> = None;
+        let compute_pipeline: &ComputePipeline = get_pipeline(
+            gpu_handles,
+            use_cache,
+            pipeline_cache,
+            "LinearLayerBackward",
+            include_str!("../shared/shaders/linear_layer_backward.wgsl"),
+            "backward_weights",
+            &mut uncached_pipeline,
+        )?;
+        let to_be_bound: Vec<(u32, BindingResource)> = vec![
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (1, input.storage_buffer.as_entire_binding()),
+            (3, output_gradient.storage_buffer.as_entire_binding()),
+            (5, weights_gradient.storage_buffer.as_entire_binding()),
+        ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "Linear Layer Backward - Weights",
+            (
+                weights.row_count.div_ceil(block_size) as u32,
+                weights.column_count.div_ceil(block_size) as u32,
+                1,
+            ),
+        )?;
+    }
+
+    // The bias is added to every output element once, so its gradient is the output gradient
+    accumulate(
+        gpu_handles,
+        use_cache,
+        pipeline_cache,
+        output_gradient,
+        bias_gradient,
+        encoder,
+    )
+}
+
+pub fn relu_backward(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &BackwardNode,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+) -> Result<(), GraphError> {
+    buffer_count_check("nodes::relu_backward", &node.name, &node.buffer_indices, 3)?;
+
+    let input: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    let output_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
+    let input_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[2]];
+
+    let mut uncached_pipeline: Option<ComputePipeline> = None;
+    let compute_pipeline: &ComputePipeline = get_pipeline(
+        gpu_handles,
+        use_cache,
+        pipeline_cache,
+        "ReLUBackward",
+        include_str!("../shared/shaders/relu_backward.wgsl"),
+        "main",
+        &mut uncached_pipeline,
+    )?;
+    for chunk in elementwise_chunks(gpu_handles, &node.name, input)? {
+        let uniform: ReluUniform =
+            ReluUniform::from_dimensions(gpu_handles, "Relu Backward Uniform", chunk.row_count, 1);
+        let to_be_bound: Vec<(u32, BindingResource)> = vec![
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (1, chunk.binding(input, 1)),
+            (2, chunk.binding(output_gradient, 1)),
+            (3, chunk.binding(input_gradient, 1)),
+        ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "Relu Backward",
+            chunk_launch_blocks(&chunk),
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn softmax_backward(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &BackwardNode,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+) -> Result<(), GraphError> {
+    buffer_count_check(
+        "nodes::softmax_backward",
+        &node.name,
+        &node.buffer_indices,
+        3,
+    )?;
+
+    let output: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    let output_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
+    let input_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[2]];
+
+    let uniform: SoftmaxUniform =
+        SoftmaxUniform::new(gpu_handles, "Softmax Backward Uniform", output.len());
+    let global_dot: Tensor2DGPU =
+        Tensor2DGPU::new(gpu_handles, "Softmax Backward Global Dot", 0.0, 1, 1);
+
+    {
+        let mut uncached_pipeline: Option<ComputePipeline> = None;
+        let compute_pipeline: &ComputePipeline = get_pipeline(
+            gpu_handles,
+            use_cache,
+            pipeline_cache,
+            "SoftmaxBackward",
+            include_str!("../shared/shaders/softmax_backward.wgsl"),
+            "single_pass_dot",
+            &mut uncached_pipeline,
+        )?;
+        let to_be_bound: Vec<(u32, BindingResource)> = vec![
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (1, output.storage_buffer.as_entire_binding()),
+            (2, output_gradient.storage_buffer.as_entire_binding()),
+            (3, global_dot.storage_buffer.as_entire_binding()),
+        ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "Softmax Backward - Dot",
+            (1, 1, 1),
+        )?;
+    }
+
+    {
+        let mut uncached_pipeline: Option<ComputePipeline> = None;
+        let compute_pipeline: &ComputePipeline = get_pipeline(
+            gpu_handles,
+            use_cache,
+            pipeline_cache,
+            "SoftmaxBackward",
+            include_str!("../shared/shaders/softmax_backward.wgsl"),
+            "map",
+            &mut uncached_pipeline,
+        )?;
+        for chunk in elementwise_chunks(gpu_handles, &node.name, output)? {
+            let map_uniform: SoftmaxUniform =
+                SoftmaxUniform::new(gpu_handles, "Softmax Backward Map Uniform", chunk.row_count);
+            let to_be_bound: Vec<(u32, BindingResource)> = vec![
+                (0, map_uniform.storage_buffer.as_entire_binding()),
+                (1, chunk.binding(output, 1)),
+                (2, chunk.binding(output_gradient, 1)),
+                (3, global_dot.storage_buffer.as_entire_binding()),
+                (4, chunk.binding(input_gradient, 1)),
+            ];
+            dispatch(
                 gpu_handles,
-                softmax_cs_module,
-                "map",
-            ))
-        };
-        let map_compute_pipeline: &ComputePipeline = if use_cache {
-            let key: &str = "Softmax_map";
-            if pipeline_cache.contains_key(key) {
-                &pipeline_cache[key]
+                encoder,
+                compute_pipeline,
+                to_be_bound,
+                "Softmax Backward - Map",
+                chunk_launch_blocks(&chunk),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn softmax_axis_backward(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &BackwardNode,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+    axis: usize,
+) -> Result<(), GraphError> {
+    buffer_count_check(
+        "nodes::softmax_axis_backward",
+        &node.name,
+        &node.buffer_indices,
+        3,
+    )?;
+
+    let output: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    let output_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
+    let input_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[2]];
+
+    let mut uncached_pipeline: Option<ComputePipeline> = None;
+    let compute_pipeline: &ComputePipeline = get_pipeline(
+        gpu_handles,
+        use_cache,
+        pipeline_cache,
+        "SoftmaxAxis",
+        include_str!("../shared/shaders/softmax_axis.wgsl"),
+        "backward",
+        &mut uncached_pipeline,
+    )?;
+    for chunk in softmax_axis_chunks(gpu_handles, &node.name, output, axis)? {
+        let uniform: SoftmaxAxisUniform = SoftmaxAxisUniform::from_chunk(
+            gpu_handles,
+            "Softmax Axis Backward Uniform",
+            output.row_count,
+            output.column_count,
+            axis,
+            &chunk,
+        );
+        let to_be_bound: Vec<(u32, BindingResource)> = vec![
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (2, softmax_axis_binding(output, &chunk, axis)),
+            (3, softmax_axis_binding(output_gradient, &chunk, axis)),
+            (4, softmax_axis_binding(input_gradient, &chunk, axis)),
+        ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "Softmax Axis Backward",
+            (uniform.lane_count(), 1, 1),
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn log_softmax_backward(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &BackwardNode,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+) -> Result<(), GraphError> {
+    buffer_count_check(
+        "nodes::log_softmax_backward",
+        &node.name,
+        &node.buffer_indices,
+        3,
+    )?;
+
+    let output: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    let output_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
+    let input_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[2]];
+
+    let uniform: SoftmaxUniform =
+        SoftmaxUniform::new(gpu_handles, "Log Softmax Backward Uniform", output.len());
+    let global_sum: Tensor2DGPU =
+        Tensor2DGPU::new(gpu_handles, "Log Softmax Backward Global Sum", 0.0, 1, 1);
+
+    {
+        let mut uncached_pipeline: Option<ComputePipeline> = None;
+        let compute_pipeline: &ComputePipeline = get_pipeline(
+            gpu_handles,
+            use_cache,
+            pipeline_cache,
+            "LogSoftmax",
+            include_str!("../shared/shaders/log_softmax.wgsl"),
+            "single_pass_sum",
+            &mut uncached_pipeline,
+        )?;
+        let to_be_bound: Vec<(u32, BindingResource)> = vec![
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (3, output_gradient.storage_buffer.as_entire_binding()),
+            (4, global_sum.storage_buffer.as_entire_binding()),
+        ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "Log Softmax Backward - Sum",
+            (1, 1, 1),
+        )?;
+    }
+
+    {
+        let mut uncached_pipeline: Option<ComputePipeline> = None;
+        let compute_pipeline: &ComputePipeline = get_pipeline(
+            gpu_handles,
+            use_cache,
+            pipeline_cache,
+            "LogSoftmax",
+            include_str!("../shared/shaders/log_softmax.wgsl"),
+            "backward",
+            &mut uncached_pipeline,
+        )?;
+        for chunk in elementwise_chunks(gpu_handles, &node.name, output)? {
+            let map_uniform: SoftmaxUniform = SoftmaxUniform::new(
+                gpu_handles,
+                "Log Softmax Backward Map Uniform",
+                chunk.row_count,
+            );
+            let to_be_bound: Vec<(u32, BindingResource)> = vec![
+                (0, map_uniform.storage_buffer.as_entire_binding()),
+                (2, chunk.binding(output, 1)),
+                (3, chunk.binding(output_gradient, 1)),
+                (4, global_sum.storage_buffer.as_entire_binding()),
+                (5, chunk.binding(input_gradient, 1)),
+            ];
+            dispatch(
+                gpu_handles,
+                encoder,
+                compute_pipeline,
+                to_be_bound,
+                "Log Softmax Backward - Map",
+                chunk_launch_blocks(&chunk),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn loss_backward(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &BackwardNode,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+    kind: LossKind,
+) -> Result<(), GraphError> {
+    buffer_count_check("nodes::loss_backward", &node.name, &node.buffer_indices, 4)?;
+
+    let input: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    let target: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
+    let output_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[2]];
+    let input_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[3]];
+
+    let mut uncached_pipeline: Option<ComputePipeline> = None;
+    let compute_pipeline: &ComputePipeline = get_pipeline(
+        gpu_handles,
+        use_cache,
+        pipeline_cache,
+        "Loss",
+        include_str!("../shared/shaders/loss.wgsl"),
+        "backward",
+        &mut uncached_pipeline,
+    )?;
+    // The output gradient is the gradient of the single loss value, every chunk needs all of it
+    for chunk in elementwise_chunks(gpu_handles, &node.name, input)? {
+        let uniform: LossUniform = LossUniform::from_chunk(
+            gpu_handles,
+            "Loss Backward Uniform",
+            &chunk,
+            input.len(),
+            kind,
+        );
+        let to_be_bound: Vec<(u32, BindingResource)> = vec![
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (1, chunk.binding(input, 1)),
+            (2, chunk.binding(target, 1)),
+            (4, output_gradient.storage_buffer.as_entire_binding()),
+            (6, chunk.binding(input_gradient, 1)),
+        ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "Loss Backward",
+            chunk_launch_blocks(&chunk),
+        )?;
+    }
+
+    Ok(())
+}
+
+// Writes the gradient with respect to the input of the softmax, see graph::autograd
+pub fn softmax_cross_entropy_backward(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &BackwardNode,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+) -> Result<(), GraphError> {
+    buffer_count_check(
+        "nodes::softmax_cross_entropy_backward",
+        &node.name,
+        &node.buffer_indices,
+        4,
+    )?;
+
+    let softmax_output: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    let target: &Tensor2DGPU = &data_buffers[node.buffer_indices[1]];
+    let output_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[2]];
+    let softmax_input_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[3]];
+
+    let uniform: LossUniform = LossUniform::new(
+        gpu_handles,
+        "Softmax Cross Entropy Backward Uniform",
+        softmax_output.len(),
+        LossKind::CrossEntropy,
+    );
+    let global_target_sum: Tensor2DGPU =
+        Tensor2DGPU::new(gpu_handles, "Cross Entropy Global Target Sum", 0.0, 1, 1);
+
+    {
+        let mut uncached_pipeline: Option<ComputePipeline> = None;
+        let compute_pipeline: &ComputePipeline = get_pipeline(
+            gpu_handles,
+            use_cache,
+            pipeline_cache,
+            "Loss",
+            include_str!("../shared/shaders/loss.wgsl"),
+            "single_pass_target_sum",
+            &mut uncached_pipeline,
+        )?;
+        let to_be_bound: Vec<(u32, BindingResource)> = vec![
+            (0, uniform.storage_buffer.as_entire_binding()),
+            (2, target.storage_buffer.as_entire_binding()),
+            (5, global_target_sum.storage_buffer.as_entire_binding()),
+        ];
+        dispatch(
+            gpu_handles,
+            encoder,
+            compute_pipeline,
+            to_be_bound,
+            "Softmax Cross Entropy Backward - Target Sum",
+            (1, 1, 1),
+        )?;
+    }
+
+    {
+        let mut uncached_pipeline: Option<ComputePipeline> = None;
+        let compute_pipeline: &ComputePipeline = get_pipeline(
+            gpu_handles,
+            use_cache,
+            pipeline_cache,
+            "Loss",
+            include_str!("../shared/shaders/loss.wgsl"),
+            "softmax_backward",
+            &mut uncached_pipeline,
+        )?;
+        for chunk in elementwise_chunks(gpu_handles, &node.name, softmax_output)? {
+            let map_uniform: LossUniform = LossUniform::from_chunk(
+                gpu_handles,
+                "Softmax Cross Entropy Backward Map Uniform",
+                &chunk,
+                softmax_output.len(),
+                LossKind::CrossEntropy,
+            );
+            let to_be_bound: Vec<(u32, BindingResource)> = vec![
+                (0, map_uniform.storage_buffer.as_entire_binding()),
+                (1, chunk.binding(softmax_output, 1)),
+                (2, chunk.binding(target, 1)),
+                (4, output_gradient.storage_buffer.as_entire_binding()),
+                (5, global_target_sum.storage_buffer.as_entire_binding()),
+                (6, chunk.binding(softmax_input_gradient, 1)),
+            ];
+            dispatch(
+                gpu_handles,
+                encoder,
+                compute_pipeline,
+                to_be_bound,
+                "Softmax Cross Entropy Backward - Map",
+                chunk_launch_blocks(&chunk),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+// Both inputs get the whole output gradient
+pub fn add_backward(
+    gpu_handles: &GPUHandles,
+    use_cache: bool,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &BackwardNode,
+    data_buffers: &[Tensor2DGPU],
+    encoder: &mut CommandEncoder,
+) -> Result<(), GraphError> {
+    buffer_count_check("nodes::add_backward", &node.name, &node.buffer_indices, 3)?;
+
+    let output_gradient: &Tensor2DGPU = &data_buffers[node.buffer_indices[0]];
+    for gradient_index in &node.buffer_indices[1..] {
+        accumulate(
+            gpu_handles,
+            use_cache,
+            pipeline_cache,
+            output_gradient,
+            &data_buffers[*gradient_index],
+            encoder,
+        )?;
+    }
+
+    Ok(())
+}
+
+// Bound nodes
+// The uniforms, scratch buffers and bind groups of a node only depend on the
+// buffers it was given, so they can all be made once when the node is created.
+// Submitting the node after that is only setting pipelines and bind groups and
+// recording the dispatches. The pipelines have to be in the pipeline cache.
+fn bound_pass(
+    gpu_handles: &GPUHandles,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    pipeline_key: &str,
+    to_be_bound: Vec<(u32, BindingResource)>,
+    label: &'static str,
+    launch_blocks: (u32, u32, u32),
+) -> Result<BoundPass, GraphError> {
+    limits_check(gpu_handles, label, &to_be_bound, launch_blocks)?;
+
+    let compute_pipeline: &ComputePipeline = pipeline_cache.get(pipeline_key).ok_or_else(|| {
+        GraphError::UnsupportedOperator(format!(
+            "Tried to bind a node to the cached {} pipeline, but failed to find it in the pipeline cache!",
+            pipeline_key
+        ))
+    })?;
+    let bind_group_layout: BindGroupLayout = compute_pipeline.get_bind_group_layout(0);
+    let bind_group: BindGroup = create_bind_group(gpu_handles, &bind_group_layout, to_be_bound);
+
+    Ok(BoundPass {
+        label,
+        pipeline_key: pipeline_key.to_string(),
+        bind_group,
+        launch_blocks,
+    })
+}
+
+// Returns None for the nodes which don't dispatch anything, like the transfers and Concat
+pub fn bind_node(
+    gpu_handles: &GPUHandles,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &NodeGPU,
+    data_buffers: &[Tensor2DGPU],
+    kernel: LinearLayerKernel,
+) -> Result<Option<BoundNode>, GraphError> {
+    let buffers: Vec<&Tensor2DGPU> = node
+        .buffer_indices
+        .iter()
+        .map(|buffer_index| &data_buffers[*buffer_index])
+        .collect();
+    let mut bound: BoundNode = BoundNode::default();
+
+    match node.operator {
+        NodeOperatorGPU::HostToDevice
+        | NodeOperatorGPU::DeviceToHost
+        | NodeOperatorGPU::Concat { axis: _ } => return Ok(None),
+        NodeOperatorGPU::LinearLayer | NodeOperatorGPU::LinearReLU => {
+            buffer_count_check("nodes::bind_node", &node.name, &node.buffer_indices, 4)?;
+            let (input, weights, bias, output) = (buffers[0], buffers[1], buffers[2], buffers[3]);
+            let pipeline_key: &str = if node.operator == NodeOperatorGPU::LinearReLU {
+                "LinearReLU"
             } else {
-                panic!("Tried to get a cached {} pipeline in graph::nodes::softmax(), but failed to find it in the pipeline cache!", key);
+                "LinearLayer"
+            };
+            for chunk in linear_layer_chunks(gpu_handles, &node.name, kernel, input, output)? {
+                let uniform: LinearLayerUniform = LinearLayerUniform::from_chunk(
+                    gpu_handles,
+                    "Linear Layer Uniform",
+                    input,
+                    weights,
+                    bias,
+                    output,
+                    &chunk,
+                );
+                bound.passes.push(bound_pass(
+                    gpu_handles,
+                    pipeline_cache,
+                    pipeline_key,
+                    linear_layer_chunk_bindings(&uniform, input, weights, bias, output, &chunk),
+                    "linear_layer_graph",
+                    kernel.launch_blocks(chunk.row_count, output.column_count),
+                )?);
+                bound.buffers.push(uniform.storage_buffer);
             }
-        } else {
-            map_pipeline
-                .as_ref()
-                .expect("Failed to get a reference to compute pipeline in graph::nodes::softmax")
-        };
+        }
+        NodeOperatorGPU::ReLU => {
+            buffer_count_check("nodes::bind_node", &node.name, &node.buffer_indices, 2)?;
+            let (input, output) = (buffers[0], buffers[1]);
+            // The same chunks as relu
+            for chunk in elementwise_chunks(gpu_handles, &node.name, input)? {
+                let uniform: ReluUniform =
+                    ReluUniform::from_dimensions(gpu_handles, "Relu Uniform", chunk.row_count, 1);
+                bound.passes.push(bound_pass(
+                    gpu_handles,
+                    pipeline_cache,
+                    "ReLU",
+                    vec![
+                        (0, uniform.storage_buffer.as_entire_binding()),
+                        (1, chunk.binding(input, 1)),
+                        (2, chunk.binding(output, 1)),
+                    ],
+                    "Relu Graph",
+                    chunk_launch_blocks(&chunk),
+                )?);
+                bound.buffers.push(uniform.storage_buffer);
+            }
+        }
+        NodeOperatorGPU::Softmax => {
+            buffer_count_check("nodes::bind_node", &node.name, &node.buffer_indices, 2)?;
+            bind_softmax_passes(
+                gpu_handles,
+                pipeline_cache,
+                &node.name,
+                buffers[0],
+                buffers[1],
+                &mut bound,
+            )?;
+        }
+        NodeOperatorGPU::SoftmaxAxis { axis } => {
+            buffer_count_check("nodes::bind_node", &node.name, &node.buffer_indices, 2)?;
+            let (input, output) = (buffers[0], buffers[1]);
+            // The same chunks as softmax_axis
+            for chunk in softmax_axis_chunks(gpu_handles, &node.name, input, axis)? {
+                let uniform: SoftmaxAxisUniform = SoftmaxAxisUniform::from_chunk(
+                    gpu_handles,
+                    "Softmax Axis Uniform",
+                    input.row_count,
+                    input.column_count,
+                    axis,
+                    &chunk,
+                );
+                bound.passes.push(bound_pass(
+                    gpu_handles,
+                    pipeline_cache,
+                    "SoftmaxAxis_forward",
+                    vec![
+                        (0, uniform.storage_buffer.as_entire_binding()),
+                        (1, softmax_axis_binding(input, &chunk, axis)),
+                        (2, softmax_axis_binding(output, &chunk, axis)),
+                    ],
+                    "Softmax Axis",
+                    (uniform.lane_count(), 1, 1),
+                )?);
+                bound.buffers.push(uniform.storage_buffer);
+            }
+        }
+        NodeOperatorGPU::LinearReLUSoftmax => {
+            buffer_count_check("nodes::bind_node", &node.name, &node.buffer_indices, 4)?;
+            let (input, weights, bias, output) = (buffers[0], buffers[1], buffers[2], buffers[3]);
+            let intermediate: Tensor2DGPU = Tensor2DGPU::new(
+                gpu_handles,
+                "intermediate",
+                0.0,
+                bias.row_count,
+                bias.column_count,
+            );
+            for chunk in linear_layer_chunks(gpu_handles, &node.name, kernel, input, &intermediate)?
+            {
+                let uniform: LinearLayerUniform = LinearLayerUniform::from_chunk(
+                    gpu_handles,
+                    "Linear Layer Uniform",
+                    input,
+                    weights,
+                    bias,
+                    &intermediate,
+                    &chunk,
+                );
+                bound.passes.push(bound_pass(
+                    gpu_handles,
+                    pipeline_cache,
+                    "LinearReLU",
+                    linear_layer_chunk_bindings(
+                        &uniform,
+                        input,
+                        weights,
+                        bias,
+                        &intermediate,
+                        &chunk,
+                    ),
+                    "linear_layer_immediate",
+                    kernel.launch_blocks(chunk.row_count, intermediate.column_count),
+                )?);
+                bound.buffers.push(uniform.storage_buffer);
+            }
+            bind_softmax_passes(
+                gpu_handles,
+                pipeline_cache,
+                &node.name,
+                &intermediate,
+                output,
+                &mut bound,
+            )?;
+            bound.buffers.push(intermediate.storage_buffer);
+        }
+        NodeOperatorGPU::Add | NodeOperatorGPU::AddReLU => {
+            buffer_count_check("nodes::bind_node", &node.name, &node.buffer_indices, 3)?;
+            let (tensor_a, tensor_b, output) = (buffers[0], buffers[1], buffers[2]);
+            let (key, _) = add_pipeline(node.operator == NodeOperatorGPU::AddReLU);
+            for chunk in elementwise_chunks(gpu_handles, &node.name, output)? {
+                let uniform: ReluUniform =
+                    ReluUniform::from_dimensions(gpu_handles, "Add Uniform", chunk.row_count, 1);
+                bound.passes.push(bound_pass(
+                    gpu_handles,
+                    pipeline_cache,
+                    key,
+                    vec![
+                        (0, uniform.storage_buffer.as_entire_binding()),
+                        (1, chunk.binding(tensor_a, 1)),
+                        (2, chunk.binding(tensor_b, 1)),
+                        (3, chunk.binding(output, 1)),
+                    ],
+                    "add_graph",
+                    chunk_launch_blocks(&chunk),
+                )?);
+                bound.buffers.push(uniform.storage_buffer);
+            }
+        }
+        NodeOperatorGPU::LogSoftmax => {
+            buffer_count_check("nodes::bind_node", &node.name, &node.buffer_indices, 2)?;
+            let (input, output) = (buffers[0], buffers[1]);
+            let uniform: SoftmaxUniform =
+                SoftmaxUniform::new(gpu_handles, "Log Softmax Uniform", input.len());
+            bound.passes.push(bound_pass(
+                gpu_handles,
+                pipeline_cache,
+                "LogSoftmax_forward",
+                vec![
+                    (0, uniform.storage_buffer.as_entire_binding()),
+                    (1, input.storage_buffer.as_entire_binding()),
+                    (2, output.storage_buffer.as_entire_binding()),
+                ],
+                "Log Softmax",
+                (1, 1, 1),
+            )?);
+            bound.buffers.push(uniform.storage_buffer);
+        }
+        NodeOperatorGPU::Loss { kind } => {
+            buffer_count_check("nodes::bind_node", &node.name, &node.buffer_indices, 3)?;
+            let (input, target, output) = (buffers[0], buffers[1], buffers[2]);
+            let uniform: LossUniform =
+                LossUniform::new(gpu_handles, "Loss Uniform", input.len(), kind);
+            bound.passes.push(bound_pass(
+                gpu_handles,
+                pipeline_cache,
+                "Loss_forward",
+                vec![
+                    (0, uniform.storage_buffer.as_entire_binding()),
+                    (1, input.storage_buffer.as_entire_binding()),
+                    (2, target.storage_buffer.as_entire_binding()),
+                    (3, output.storage_buffer.as_entire_binding()),
+                ],
+                "Loss",
+                (1, 1, 1),
+            )?);
+            bound.buffers.push(uniform.storage_buffer);
+        }
+    }
+
+    Ok(Some(bound))
+}
 
-        let map_bind_group_layout: BindGroupLayout = map_compute_pipeline.get_bind_group_layout(0);
-        let map_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &map_bind_group_layout, to_be_bound);
+// The softmax passes, with the uniform and scratch buffers kept in the bound node
+fn bind_softmax_passes(
+    gpu_handles: &GPUHandles,
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    name: &str,
+    input: &Tensor2DGPU,
+    output: &Tensor2DGPU,
+    bound: &mut BoundNode,
+) -> Result<(), GraphError> {
+    let buffers: SoftmaxBuffers = SoftmaxBuffers::new(gpu_handles, name, input)?;
+    for (entry_point, label, to_be_bound, launch_blocks) in buffers.passes(input, output) {
+        bound.passes.push(bound_pass(
+            gpu_handles,
+            pipeline_cache,
+            &format!("Softmax_{}", entry_point),
+            to_be_bound,
+            label,
+            launch_blocks,
+        )?);
+    }
+
+    bound.buffers.push(buffers.uniform.storage_buffer);
+    bound.buffers.push(buffers.global_max.storage_buffer);
+    bound.buffers.push(buffers.global_offset.storage_buffer);
+    bound.buffers.push(buffers.partials.storage_buffer);
+    for (_, uniform) in buffers.map_chunks {
+        bound.buffers.push(uniform.storage_buffer);
+    }
+
+    Ok(())
+}
+
+// The whole per-submit cost of a bound node
+pub fn record_bound_node(
+    pipeline_cache: &HashMap<String, ComputePipeline>,
+    node: &NodeGPU,
+    bound: &BoundNode,
+    encoder: &mut CommandEncoder,
+) -> Result<(), GraphError> {
+    for pass in &bound.passes {
+        let compute_pipeline: &ComputePipeline =
+            pipeline_cache.get(&pass.pipeline_key).ok_or_else(|| {
+                GraphError::UnsupportedOperator(format!(
+                    "Node {} was bound to the cached {} pipeline, but it is no longer in the pipeline cache!",
+                    node.name, pass.pipeline_key
+                ))
+            })?;
 
         let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Map"),
+            label: Some(pass.label),
         });
-        cpass.set_pipeline(map_compute_pipeline);
-        cpass.set_bind_group(0, &map_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Map");
+        cpass.set_pipeline(compute_pipeline);
+        cpass.set_bind_group(0, &pass.bind_group, &[]);
+        cpass.insert_debug_marker(pass.label);
         cpass.dispatch_workgroups(
-            ((intermediate.len() + block_size - 1) / block_size) as u32,
-            1,
-            1,
-        ); // Number of cells to run, the (x,y,z) size of item being processed
+            pass.launch_blocks.0,
+            pass.launch_blocks.1,
+            pass.launch_blocks.2,
+        );
     }
+
+    Ok(())
 }
@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+
+use wgpu::{
+    util::DeviceExt, BindingResource, Buffer, CommandEncoder, ComputePipeline, ShaderModule,
+};
+
+use super::autograd::Parameter;
+use super::graph_error::GraphError;
+use super::nodes_gpu;
+use crate::shared::{
+    gpu_utilities::{create_compute_pipeline, create_shader_module, GPUHandles},
+    tensor2d::Tensor2D,
+    tensor2d_gpu::Tensor2DGPU,
+};
+
+// Optimizers update the parameters of a graph, the weights and biases, using the
+// gradients from the last backward pass. The runners hand them their parameters,
+// see GraphRunner::step and GraphRunnerGPU::step, so the parameters are updated
+// right where they live. On the GPU they never leave the device.
+//
+// Any state, like the momentum of every parameter, belongs to the optimizer
+// and is created the first time a parameter is seen. It is keyed by the name
+// of the parameter, so an optimizer should only be used with one graph.
+pub trait Optimizer {
+    fn step(
+        &mut self,
+        parameters: &[Parameter],
+        data_buffers: &mut [Tensor2D],
+    ) -> Result<(), GraphError>;
+
+    fn step_gpu(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        parameters: &[Parameter],
+        data_buffers: &[Tensor2DGPU],
+        encoder: &mut CommandEncoder,
+    ) -> Result<(), GraphError>;
+}
+
+// Stochastic gradient descent, with momentum if momentum isn't 0.
+// With momentum every parameter gets a velocity,
+// velocity = momentum * velocity + gradient
+// parameter -= learning_rate * velocity
+pub struct Sgd {
+    pub learning_rate: f32,
+    pub momentum: f32,
+    velocities: HashMap<String, Tensor2D>,
+    velocities_gpu: HashMap<String, Tensor2DGPU>,
+    // Compiled the first time they are needed, by entry point
+    pipelines: HashMap<String, ComputePipeline>,
+}
+
+impl Sgd {
+    pub fn new(learning_rate: f32) -> Self {
+        Self::with_momentum(learning_rate, 0.0)
+    }
+
+    pub fn with_momentum(learning_rate: f32, momentum: f32) -> Self {
+        Sgd {
+            learning_rate,
+            momentum,
+            velocities: HashMap::<String, Tensor2D>::new(),
+            velocities_gpu: HashMap::<String, Tensor2DGPU>::new(),
+            pipelines: HashMap::<String, ComputePipeline>::new(),
+        }
+    }
+}
+
+impl Optimizer for Sgd {
+    fn step(
+        &mut self,
+        parameters: &[Parameter],
+        data_buffers: &mut [Tensor2D],
+    ) -> Result<(), GraphError> {
+        for parameter in parameters {
+            let (value, gradient) = parameter_and_gradient(parameter, data_buffers)?;
+
+            if self.momentum == 0.0 {
+                for (value, gradient) in value.data.iter_mut().zip(gradient.data.iter()) {
+                    *value -= self.learning_rate * gradient;
+                }
+                continue;
+            }
+
+            let velocity: &mut Tensor2D = state_for(&mut self.velocities, parameter, gradient);
+            for ((value, velocity), gradient) in value
+                .data
+                .iter_mut()
+                .zip(velocity.data.iter_mut())
+                .zip(gradient.data.iter())
+            {
+                *velocity = self.momentum * *velocity + gradient;
+                *value -= self.learning_rate * *velocity;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn step_gpu(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        parameters: &[Parameter],
+        data_buffers: &[Tensor2DGPU],
+        encoder: &mut CommandEncoder,
+    ) -> Result<(), GraphError> {
+        let entry_point: &str = if self.momentum == 0.0 {
+            "sgd"
+        } else {
+            "sgd_momentum"
+        };
+        let pipeline: &ComputePipeline = self
+            .pipelines
+            .entry(entry_point.to_string())
+            .or_insert_with(|| optimizer_pipeline(gpu_handles, entry_point));
+
+        for parameter in parameters {
+            let (value, gradient) = parameter_and_gradient_gpu(parameter, data_buffers)?;
+            let velocity: Option<&Tensor2DGPU> = if self.momentum != 0.0 {
+                Some(state_for_gpu(
+                    &mut self.velocities_gpu,
+                    gpu_handles,
+                    parameter,
+                    value,
+                ))
+            } else {
+                None
+            };
+
+            // Large parameters are stepped a chunk at a time, like the elementwise nodes
+            for chunk in nodes_gpu::elementwise_chunks(gpu_handles, &parameter.name(), value)? {
+                let settings: OptimizerSettings = OptimizerSettings {
+                    element_count: chunk.row_count as u32,
+                    learning_rate: self.learning_rate,
+                    first_decay: self.momentum,
+                    second_decay: 0.0,
+                    epsilon: 0.0,
+                    first_correction: 1.0,
+                    second_correction: 1.0,
+                    padding: 0,
+                };
+                let uniform: Buffer = settings.create_uniform(gpu_handles);
+
+                let mut to_be_bound: Vec<(u32, BindingResource)> = vec![
+                    (0, uniform.as_entire_binding()),
+                    (1, chunk.binding(gradient, 1)),
+                    (2, chunk.binding(value, 1)),
+                ];
+                if let Some(velocity) = velocity {
+                    to_be_bound.push((3, chunk.binding(velocity, 1)));
+                }
+
+                nodes_gpu::dispatch(
+                    gpu_handles,
+                    encoder,
+                    pipeline,
+                    to_be_bound,
+                    "SGD",
+                    nodes_gpu::chunk_launch_blocks(&chunk),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Adam keeps a running mean of the gradients and of the squared gradients
+// of every parameter, and scales every step by the two. As both start out at 0,
+// they are divided by 1 - beta^t to correct for the bias towards 0 in the first steps.
+pub struct Adam {
+    pub learning_rate: f32,
+    pub beta_1: f32,
+    pub beta_2: f32,
+    pub epsilon: f32,
+    step_count: i32,
+    moments: HashMap<String, (Tensor2D, Tensor2D)>,
+    moments_gpu: HashMap<String, (Tensor2DGPU, Tensor2DGPU)>,
+    pipeline: Option<ComputePipeline>,
+}
+
+impl Adam {
+    // The defaults from the paper, beta_1 = 0.9, beta_2 = 0.999 and epsilon = 1e-8
+    pub fn new(learning_rate: f32) -> Self {
+        Self::with_settings(learning_rate, 0.9, 0.999, 1e-8)
+    }
+
+    pub fn with_settings(learning_rate: f32, beta_1: f32, beta_2: f32, epsilon: f32) -> Self {
+        Adam {
+            learning_rate,
+            beta_1,
+            beta_2,
+            epsilon,
+            step_count: 0,
+            moments: HashMap::<String, (Tensor2D, Tensor2D)>::new(),
+            moments_gpu: HashMap::<String, (Tensor2DGPU, Tensor2DGPU)>::new(),
+            pipeline: None,
+        }
+    }
+
+    // 1 - beta_1^t and 1 - beta_2^t for the next step
+    fn next_corrections(&mut self) -> (f32, f32) {
+        self.step_count += 1;
+        (
+            1.0 - self.beta_1.powi(self.step_count),
+            1.0 - self.beta_2.powi(self.step_count),
+        )
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(
+        &mut self,
+        parameters: &[Parameter],
+        data_buffers: &mut [Tensor2D],
+    ) -> Result<(), GraphError> {
+        let (first_correction, second_correction) = self.next_corrections();
+
+        for parameter in parameters {
+            let (value, gradient) = parameter_and_gradient(parameter, data_buffers)?;
+            let (first_moment, second_moment) =
+                self.moments.entry(parameter.name()).or_insert_with(|| {
+                    (
+                        Tensor2D::new(0.0, gradient.row_count, gradient.column_count),
+                        Tensor2D::new(0.0, gradient.row_count, gradient.column_count),
+                    )
+                });
+
+            for index in 0..value.len() {
+                let gradient: f32 = gradient.data[index];
+                let first: f32 =
+                    self.beta_1 * first_moment.data[index] + (1.0 - self.beta_1) * gradient;
+                let second: f32 = self.beta_2 * second_moment.data[index]
+                    + (1.0 - self.beta_2) * gradient * gradient;
+                first_moment.data[index] = first;
+                second_moment.data[index] = second;
+
+                let first_corrected: f32 = first / first_correction;
+                let second_corrected: f32 = second / second_correction;
+                value.data[index] -=
+                    self.learning_rate * first_corrected / (second_corrected.sqrt() + self.epsilon);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn step_gpu(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        parameters: &[Parameter],
+        data_buffers: &[Tensor2DGPU],
+        encoder: &mut CommandEncoder,
+    ) -> Result<(), GraphError> {
+        let (first_correction, second_correction) = self.next_corrections();
+        let pipeline: &ComputePipeline = self
+            .pipeline
+            .get_or_insert_with(|| optimizer_pipeline(gpu_handles, "adam"));
+
+        for parameter in parameters {
+            let (value, gradient) = parameter_and_gradient_gpu(parameter, data_buffers)?;
+            let (first_moment, second_moment) =
+                self.moments_gpu.entry(parameter.name()).or_insert_with(|| {
+                    (
+                        zeroes_like(gpu_handles, &parameter.name(), "first_moment", value),
+                        zeroes_like(gpu_handles, &parameter.name(), "second_moment", value),
+                    )
+                });
+
+            for chunk in nodes_gpu::elementwise_chunks(gpu_handles, &parameter.name(), value)? {
+                let settings: OptimizerSettings = OptimizerSettings {
+                    element_count: chunk.row_count as u32,
+                    learning_rate: self.learning_rate,
+                    first_decay: self.beta_1,
+                    second_decay: self.beta_2,
+                    epsilon: self.epsilon,
+                    first_correction,
+                    second_correction,
+                    padding: 0,
+                };
+                let uniform: Buffer = settings.create_uniform(gpu_handles);
+
+                let to_be_bound: Vec<(u32, BindingResource)> = vec![
+                    (0, uniform.as_entire_binding()),
+                    (1, chunk.binding(gradient, 1)),
+                    (2, chunk.binding(value, 1)),
+                    (3, chunk.binding(first_moment, 1)),
+                    (4, chunk.binding(second_moment, 1)),
+                ];
+                nodes_gpu::dispatch(
+                    gpu_handles,
+                    encoder,
+                    pipeline,
+                    to_be_bound,
+                    "Adam",
+                    nodes_gpu::chunk_launch_blocks(&chunk),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Has to match OptimizerSettings in optimizer.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct OptimizerSettings {
+    element_count: u32,
+    learning_rate: f32,
+    first_decay: f32,
+    second_decay: f32,
+    epsilon: f32,
+    first_correction: f32,
+    second_correction: f32,
+    padding: u32,
+}
+
+impl OptimizerSettings {
+    fn create_uniform(&self, gpu_handles: &GPUHandles) -> Buffer {
+        gpu_handles
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Optimizer Uniform"),
+                contents: bytemuck::bytes_of(self),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            })
+    }
+}
+
+fn optimizer_pipeline(gpu_handles: &GPUHandles, entry_point: &str) -> ComputePipeline {
+    let cs_module: ShaderModule = create_shader_module(
+        gpu_handles,
+        include_str!("../shared/shaders/optimizer.wgsl"),
+    );
+    create_compute_pipeline(gpu_handles, &cs_module, entry_point)
+}
+
+fn index_check(parameter: &Parameter, buffer_count: usize) -> Result<(), GraphError> {
+    if parameter.value_index == parameter.gradient_index
+        || buffer_count <= parameter.value_index.max(parameter.gradient_index)
+    {
+        return Err(GraphError::MalformedNode(format!(
+            "The parameter {} has the invalid buffers {} and {}, there are {} buffers",
+            parameter.name(),
+            parameter.value_index,
+            parameter.gradient_index,
+            buffer_count
+        )));
+    }
+
+    Ok(())
+}
+
+fn shape_check(
+    parameter: &Parameter,
+    value_shape: (usize, usize),
+    gradient_shape: (usize, usize),
+) -> Result<(), GraphError> {
+    if value_shape != gradient_shape {
+        return Err(GraphError::DimensionMismatch(format!(
+            "The parameter {} has the shape {:?}, but its gradient has the shape {:?}",
+            parameter.name(),
+            value_shape,
+            gradient_shape
+        )));
+    }
+
+    Ok(())
+}
+
+// The parameter and its gradient are two different buffers, so both can be borrowed at once
+fn parameter_and_gradient<'a>(
+    parameter: &Parameter,
+    data_buffers: &'a mut [Tensor2D],
+) -> Result<(&'a mut Tensor2D, &'a Tensor2D), GraphError> {
+    index_check(parameter, data_buffers.len())?;
+
+    let value: &'a mut Tensor2D;
+    let gradient: &'a Tensor2D;
+    if parameter.value_index < parameter.gradient_index {
+        let (left, right) = data_buffers.split_at_mut(parameter.gradient_index);
+        value = &mut left[parameter.value_index];
+        gradient = &right[0];
+    } else {
+        let (left, right) = data_buffers.split_at_mut(parameter.value_index);
+        value = &mut right[0];
+        gradient = &left[parameter.gradient_index];
+    }
+    shape_check(
+        parameter,
+        (value.row_count, value.column_count),
+        (gradient.row_count, gradient.column_count),
+    )?;
+
+    Ok((value, gradient))
+}
+
+fn parameter_and_gradient_gpu<'a>(
+    parameter: &Parameter,
+    data_buffers: &'a [Tensor2DGPU],
+) -> Result<(&'a Tensor2DGPU, &'a Tensor2DGPU), GraphError> {
+    index_check(parameter, data_buffers.len())?;
+
+    let value: &Tensor2DGPU = &data_buffers[parameter.value_index];
+    let gradient: &Tensor2DGPU = &data_buffers[parameter.gradient_index];
+    shape_check(
+        parameter,
+        (value.row_count, value.column_count),
+        (gradient.row_count, gradient.column_count),
+    )?;
+
+    Ok((value, gradient))
+}
+
+fn state_for<'a>(
+    states: &'a mut HashMap<String, Tensor2D>,
+    parameter: &Parameter,
+    gradient: &Tensor2D,
+) -> &'a mut Tensor2D {
+    states
+        .entry(parameter.name())
+        .or_insert_with(|| Tensor2D::new(0.0, gradient.row_count, gradient.column_count))
+}
+
+fn state_for_gpu<'a>(
+    states: &'a mut HashMap<String, Tensor2DGPU>,
+    gpu_handles: &GPUHandles,
+    parameter: &Parameter,
+    value: &Tensor2DGPU,
+) -> &'a Tensor2DGPU {
+    states
+        .entry(parameter.name())
+        .or_insert_with(|| zeroes_like(gpu_handles, &parameter.name(), "velocity", value))
+}
+
+fn zeroes_like(
+    gpu_handles: &GPUHandles,
+    parameter_name: &str,
+    state_name: &str,
+    value: &Tensor2DGPU,
+) -> Tensor2DGPU {
+    Tensor2DGPU::new(
+        gpu_handles,
+        &format!("{}_{}", parameter_name, state_name),
+        0.0,
+        value.row_count,
+        value.column_count,
+    )
+}
@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        graph::{
+            autograd::{Parameter, ParameterKind},
+            graph_error::GraphError,
+            graph_runner::GraphRunner,
+            optimizer::{Adam, Optimizer, Sgd},
+        },
+        shared::{
+            graph_operators::{GraphOperator, LossKind},
+            tensor2d::Tensor2D,
+        },
+    };
+
+    const ERROR_TOLERANCE: f32 = 0.00001;
+
+    // A single parameter in buffer 0 with its gradient in buffer 1
+    fn single_parameter(value: &[f32], gradient: &[f32]) -> (Vec<Parameter>, Vec<Tensor2D>) {
+        let mut value_tensor: Tensor2D = Tensor2D::new(0.0, 1, value.len());
+        value_tensor.data = value.to_vec();
+        let mut gradient_tensor: Tensor2D = Tensor2D::new(0.0, 1, gradient.len());
+        gradient_tensor.data = gradient.to_vec();
+
+        let parameters: Vec<Parameter> = vec![Parameter {
+            node_name: "linear".to_string(),
+            kind: ParameterKind::Weights,
+            value_index: 0,
+            gradient_index: 1,
+        }];
+        (parameters, vec![value_tensor, gradient_tensor])
+    }
+
+    fn assert_values(expected: &[f32], actual: &Tensor2D) {
+        for (expected, actual) in expected.iter().zip(actual.data.iter()) {
+            assert!(
+                (expected - actual).abs() < ERROR_TOLERANCE,
+                "expected {} but got {}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn sgd() {
+        let (parameters, mut data_buffers) = single_parameter(&[1.0, -2.0, 3.0], &[0.5, 1.0, -2.0]);
+        let mut optimizer: Sgd = Sgd::new(0.1);
+        optimizer.step(&parameters, &mut data_buffers).unwrap();
+        assert_values(&[0.95, -2.1, 3.2], &data_buffers[0]);
+        // The gradient is only read
+        assert_values(&[0.5, 1.0, -2.0], &data_buffers[1]);
+    }
+
+    #[test]
+    fn sgd_momentum() {
+        let (parameters, mut data_buffers) = single_parameter(&[1.0, -2.0], &[0.5, -1.0]);
+        let mut optimizer: Sgd = Sgd::with_momentum(0.1, 0.9);
+
+        // The first step has no velocity to build on, the second one
+        // moves (1 + momentum) times as far with the same gradient
+        optimizer.step(&parameters, &mut data_buffers).unwrap();
+        assert_values(&[0.95, -1.9], &data_buffers[0]);
+        optimizer.step(&parameters, &mut data_buffers).unwrap();
+        assert_values(&[0.855, -1.71], &data_buffers[0]);
+    }
+
+    #[test]
+    fn adam() {
+        let (parameters, mut data_buffers) = single_parameter(&[1.0, -2.0, 0.0], &[0.5, -4.0, 0.0]);
+        let mut optimizer: Adam = Adam::new(0.1);
+
+        // With the bias correction the first step is learning_rate * sign(gradient)
+        optimizer.step(&parameters, &mut data_buffers).unwrap();
+        assert_values(&[0.9, -1.9, 0.0], &data_buffers[0]);
+
+        // As long as the gradient stays the same, so does the step
+        optimizer.step(&parameters, &mut data_buffers).unwrap();
+        assert_values(&[0.8, -1.8, 0.0], &data_buffers[0]);
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let (mut parameters, mut data_buffers) = single_parameter(&[1.0, 2.0], &[1.0]);
+        let mut optimizer: Sgd = Sgd::new(0.1);
+        assert!(matches!(
+            optimizer.step(&parameters, &mut data_buffers),
+            Err(GraphError::DimensionMismatch(_))
+        ));
+
+        parameters[0].gradient_index = 0;
+        assert!(matches!(
+            optimizer.step(&parameters, &mut data_buffers),
+            Err(GraphError::MalformedNode(_))
+        ));
+
+        parameters[0].gradient_index = 2;
+        assert!(matches!(
+            optimizer.step(&parameters, &mut data_buffers),
+            Err(GraphError::MalformedNode(_))
+        ));
+    }
+
+    fn training_graph() -> Vec<GraphOperator> {
+        let mut input: Tensor2D = Tensor2D::new(0.0, 4, 3);
+        let mut weights: Tensor2D = Tensor2D::new(0.0, 3, 5);
+        for (index, value) in input.data.iter_mut().enumerate() {
+            *value = (index as f32 * 1.7).sin();
+        }
+        for (index, value) in weights.data.iter_mut().enumerate() {
+            *value = (index as f32 * 0.3 + 1.0).cos() * 0.5;
+        }
+        let mut target: Tensor2D = Tensor2D::new(0.0, 4, 2);
+        target.data[0] = 0.5;
+        target.data[7] = 0.5;
+
+        vec![
+            GraphOperator::HostToDevice { input },
+            GraphOperator::LinearLayer {
+                weights,
+                bias: Tensor2D::new(0.0, 4, 5),
+            },
+            GraphOperator::ReLU,
+            GraphOperator::LinearLayer {
+                weights: Tensor2D::new(0.1, 5, 2),
+                bias: Tensor2D::new(0.0, 4, 2),
+            },
+            GraphOperator::Softmax { axis: None },
+            GraphOperator::Loss {
+                kind: LossKind::CrossEntropy,
+                target,
+            },
+            GraphOperator::DeviceToHost,
+        ]
+    }
+
+    #[test]
+    fn training() {
+        let optimizers: Vec<Box<dyn Optimizer>> = vec![
+            Box::new(Sgd::new(0.5)),
+            Box::new(Sgd::with_momentum(0.2, 0.9)),
+            Box::new(Adam::new(0.05)),
+        ];
+
+        for mut optimizer in optimizers {
+            let mut graph_runner: GraphRunner =
+                GraphRunner::for_training(&training_graph()).unwrap();
+            let initial_loss: f32 = graph_runner.run().unwrap().data[0];
+
+            let mut loss: f32 = initial_loss;
+            for _ in 0..50 {
+                graph_runner.backward().unwrap();
+                graph_runner.step(optimizer.as_mut()).unwrap();
+                loss = graph_runner.run().unwrap().data[0];
+            }
+            assert!(
+                loss < 0.5 * initial_loss,
+                "the loss went from {} to {}",
+                initial_loss,
+                loss
+            );
+        }
+
+        // Without for_training there are no gradients to step with
+        let mut graph_runner: GraphRunner = GraphRunner::new(&training_graph(), false).unwrap();
+        graph_runner.run().unwrap();
+        assert!(matches!(
+            graph_runner.step(&mut Sgd::new(0.1)),
+            Err(GraphError::UnsupportedOperator(_))
+        ));
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures_intrusive::channel::shared::OneshotReceiver;
+use wgpu::{Buffer, BufferAsyncError, BufferSlice, BufferView, CommandEncoder};
+
+use super::graph_error::GraphError;
+use crate::shared::{gpu_utilities::GPUHandles, tensor2d::Tensor2D, tensor2d_gpu::Tensor2DGPU};
+
+// Returned by ReadbackRing::read_back, redeemed with ReadbackRing::receive.
+// The generation makes sure a handle can only be received once,
+// even after its slot has been reused by a later read back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadbackHandle {
+    slot: usize,
+    generation: u64,
+}
+
+// A copy in to a staging buffer which has been submitted, but not received yet
+struct InFlight {
+    // Set by the map callback, so is_ready and receive can check on the map without waiting
+    mapped: Arc<AtomicBool>,
+    receiver: OneshotReceiver<Result<(), BufferAsyncError>>,
+}
+
+struct ReadbackSlot {
+    staging_buffer: Buffer,
+    in_flight: Option<InFlight>,
+    generation: u64,
+}
+
+// A ring of staging buffers for reading back a tensor of the same size again and again,
+// without stalling the device on every read back like the Maintain::Wait before
+// Tensor2DGPU::retrieve_results does.
+// Every read back copies in to the next free staging buffer in its own submission and starts
+// mapping it, so the next iteration can be submitted while this one is being mapped.
+// Receiving only waits for that copy, not for the whole device, and never blocks the executor.
+// With 2 slots it is double buffered, more lets more iterations be in flight at once.
+pub struct ReadbackRing {
+    slots: Vec<ReadbackSlot>,
+    next_slot: usize,
+    row_count: usize,
+    column_count: usize,
+}
+
+impl ReadbackRing {
+    pub fn new(
+        gpu_handles: &GPUHandles,
+        label: &str,
+        row_count: usize,
+        column_count: usize,
+        slot_count: usize,
+    ) -> Self {
+        let size: u64 = (row_count * column_count * std::mem::size_of::<f32>()) as u64;
+        let slots: Vec<ReadbackSlot> = (0..slot_count.max(1))
+            .map(|slot| ReadbackSlot {
+                staging_buffer: gpu_handles.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("{} Readback {}", label, slot)),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                in_flight: None,
+                generation: 0,
+            })
+            .collect();
+
+        Self {
+            slots,
+            next_slot: 0,
+            row_count,
+            column_count,
+        }
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    // How many read backs have been started but not received yet
+    pub fn in_flight_count(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| slot.in_flight.is_some())
+            .count()
+    }
+
+    // Copies the storage buffer of source to a free staging buffer and starts mapping it.
+    // Doesn't wait for anything. Fails if every slot is still waiting to be received.
+    pub fn read_back(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        source: &Tensor2DGPU,
+    ) -> Result<ReadbackHandle, GraphError> {
+        if source.row_count != self.row_count || source.column_count != self.column_count {
+            return Err(GraphError::DimensionMismatch(format!(
+                "Tried to read back a tensor with {} rows and {} columns through a readback ring for {} rows and {} columns",
+                source.row_count, source.column_count, self.row_count, self.column_count
+            )));
+        }
+
+        // The oldest slot is the most likely to be free, so start looking from next_slot
+        let slot_index: usize = (0..self.slots.len())
+            .map(|offset| (self.next_slot + offset) % self.slots.len())
+            .find(|slot_index| self.slots[*slot_index].in_flight.is_none())
+            .ok_or_else(|| {
+                GraphError::UnsupportedOperator(format!(
+                    "All {} staging buffers of the readback ring are in flight, receive one of them first",
+                    self.slots.len()
+                ))
+            })?;
+        self.next_slot = (slot_index + 1) % self.slots.len();
+
+        let slot: &mut ReadbackSlot = &mut self.slots[slot_index];
+        let mut encoder: CommandEncoder = gpu_handles
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(
+            &source.storage_buffer,
+            0,
+            &slot.staging_buffer,
+            0,
+            source.size(),
+        );
+        gpu_handles.queue.submit(Some(encoder.finish()));
+
+        // Mapping has to be asked for after the copy was submitted
+        let mapped: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+        let callback_mapped: Arc<AtomicBool> = mapped.clone();
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        slot.staging_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |v| {
+                callback_mapped.store(true, Ordering::Release);
+                sender.send(v).unwrap()
+            });
+
+        slot.in_flight = Some(InFlight { mapped, receiver });
+
+        Ok(ReadbackHandle {
+            slot: slot_index,
+            generation: slot.generation,
+        })
+    }
+
+    fn in_flight(&self, handle: &ReadbackHandle) -> Result<&InFlight, GraphError> {
+        match self.slots.get(handle.slot) {
+            Some(ReadbackSlot {
+                in_flight: Some(in_flight),
+                generation,
+                ..
+            }) if *generation == handle.generation => Ok(in_flight),
+            _ => Err(GraphError::UnsupportedOperator(
+                "The readback handle was already received, or is from another readback ring"
+                    .to_string(),
+            )),
+        }
+    }
+
+    // Whether receive would return without waiting. Never blocks.
+    pub fn is_ready(
+        &self,
+        gpu_handles: &GPUHandles,
+        handle: &ReadbackHandle,
+    ) -> Result<bool, GraphError> {
+        let in_flight: &InFlight = self.in_flight(handle)?;
+        if !in_flight.mapped.load(Ordering::Acquire) {
+            // The map callbacks are only called from poll and submit
+            gpu_handles.device.poll(wgpu::Maintain::Poll);
+        }
+
+        Ok(in_flight.mapped.load(Ordering::Acquire))
+    }
+
+    // Waits for the copy of handle to finish, if it hasn't already,
+    // and returns what was in the tensor when it was read back.
+    // Anything submitted after the read back is left running.
+    // Until the copy is done it polls the device without blocking and yields back to the
+    // executor in between, so other tasks can run. Under a blocking executor like
+    // pollster::block_on that is a busy wait, use is_ready to check in on it instead.
+    pub async fn receive(
+        &mut self,
+        gpu_handles: &GPUHandles,
+        handle: &ReadbackHandle,
+    ) -> Result<Tensor2D, GraphError> {
+        let in_flight: &InFlight = self.in_flight(handle)?;
+        loop {
+            // The map callbacks are only called from poll and submit
+            gpu_handles.device.poll(wgpu::Maintain::Poll);
+            if in_flight.mapped.load(Ordering::Acquire) {
+                break;
+            }
+            YieldNow { yielded: false }.await;
+        }
+
+        // The receiver is only borrowed, so if this future is dropped before the map finishes,
+        // the slot stays in flight and the handle can still be received later.
+        // Freeing the slot any earlier would let read_back map a buffer which is pending or mapped.
+        let map_result: Result<(), BufferAsyncError> = in_flight
+            .receiver
+            .receive()
+            .await
+            .unwrap_or(Err(BufferAsyncError));
+
+        let slot: &mut ReadbackSlot = &mut self.slots[handle.slot];
+        if let Err(error) = map_result {
+            // A failed map leaves the buffer unmapped, so the slot can be reused without unmapping
+            slot.in_flight = None;
+            slot.generation += 1;
+            return Err(GraphError::BufferMapFailure(format!(
+                "Failed to map a staging buffer of the readback ring: {}",
+                error
+            )));
+        }
+
+        let buffer_slice: BufferSlice = slot.staging_buffer.slice(..);
+        let data: BufferView = buffer_slice.get_mapped_range();
+        let mut output: Tensor2D = Tensor2D::new(0.0, self.row_count, self.column_count);
+        output.data.copy_from_slice(bytemuck::cast_slice(&data));
+        drop(data);
+        slot.staging_buffer.unmap();
+        slot.in_flight = None;
+        slot.generation += 1;
+
+        Ok(output)
+    }
+}
+
+// Returns Pending once, after asking to be polled again right away.
+// Lets receive give the executor a turn without waiting on anything.
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        context.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use crate::{
+        graph::{
+            graph_error::GraphError,
+            readback_ring::{ReadbackHandle, ReadbackRing},
+        },
+        shared::{
+            gpu_utilities::{initialize_fallback_gpu, GPUHandles},
+            tensor2d::Tensor2D,
+            tensor2d_gpu::Tensor2DGPU,
+        },
+    };
+
+    #[test]
+    fn in_flight() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in readback_ring_test::in_flight() test");
+
+        let first: Tensor2D = Tensor2D::new(0.25, 5, 7);
+        let second: Tensor2D = Tensor2D::new(-1.5, 5, 7);
+        let tensor: Tensor2DGPU = Tensor2DGPU::from_tensor2d(&gpu_handles, "tensor", &first);
+        let mut readback_ring: ReadbackRing = ReadbackRing::new(&gpu_handles, "test", 5, 7, 2);
+
+        let first_handle: ReadbackHandle = readback_ring.read_back(&gpu_handles, &tensor).unwrap();
+        // Written after the first read back was submitted, so only the second one sees it
+        gpu_handles.queue.write_buffer(
+            &tensor.storage_buffer,
+            0,
+            bytemuck::cast_slice(&second.data),
+        );
+        let second_handle: ReadbackHandle = readback_ring.read_back(&gpu_handles, &tensor).unwrap();
+        assert_eq!(readback_ring.in_flight_count(), 2);
+        assert!(matches!(
+            readback_ring.read_back(&gpu_handles, &tensor),
+            Err(GraphError::UnsupportedOperator(_))
+        ));
+
+        // The handles don't have to be received in order
+        let output: Tensor2D =
+            pollster::block_on(readback_ring.receive(&gpu_handles, &second_handle)).unwrap();
+        assert_eq!(output.data, second.data);
+        assert!(readback_ring.is_ready(&gpu_handles, &first_handle).unwrap());
+        let output: Tensor2D =
+            pollster::block_on(readback_ring.receive(&gpu_handles, &first_handle)).unwrap();
+        assert_eq!(output.data, first.data);
+        assert_eq!(readback_ring.in_flight_count(), 0);
+
+        // A handle can only be received once, even when its slot is in flight again
+        let third_handle: ReadbackHandle = readback_ring.read_back(&gpu_handles, &tensor).unwrap();
+        assert!(readback_ring.is_ready(&gpu_handles, &first_handle).is_err());
+        assert!(pollster::block_on(readback_ring.receive(&gpu_handles, &first_handle)).is_err());
+        let output: Tensor2D =
+            pollster::block_on(readback_ring.receive(&gpu_handles, &third_handle)).unwrap();
+        assert_eq!(output.data, second.data);
+    }
+
+    #[test]
+    fn dropped_receive() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in readback_ring_test::dropped_receive() test");
+
+        let input: Tensor2D = Tensor2D::new(0.75, 5, 7);
+        let tensor: Tensor2DGPU = Tensor2DGPU::from_tensor2d(&gpu_handles, "tensor", &input);
+        let mut readback_ring: ReadbackRing = ReadbackRing::new(&gpu_handles, "test", 5, 7, 1);
+
+        // Dropping a receive before it finishes keeps the slot in flight
+        let handle: ReadbackHandle = readback_ring.read_back(&gpu_handles, &tensor).unwrap();
+        drop(readback_ring.receive(&gpu_handles, &handle));
+        assert_eq!(readback_ring.in_flight_count(), 1);
+        assert!(readback_ring.read_back(&gpu_handles, &tensor).is_err());
+
+        let output: Tensor2D =
+            pollster::block_on(readback_ring.receive(&gpu_handles, &handle)).unwrap();
+        assert_eq!(output.data, input.data);
+        assert_eq!(readback_ring.in_flight_count(), 0);
+
+        // The slot was unmapped, so it can be mapped again
+        let handle: ReadbackHandle = readback_ring.read_back(&gpu_handles, &tensor).unwrap();
+        let output: Tensor2D =
+            pollster::block_on(readback_ring.receive(&gpu_handles, &handle)).unwrap();
+        assert_eq!(output.data, input.data);
+    }
+
+    #[test]
+    fn polled_receive() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in readback_ring_test::polled_receive() test");
+
+        let input: Tensor2D = Tensor2D::new(0.5, 64, 64);
+        let tensor: Tensor2DGPU = Tensor2DGPU::from_tensor2d(&gpu_handles, "tensor", &input);
+        let mut readback_ring: ReadbackRing = ReadbackRing::new(&gpu_handles, "test", 64, 64, 1);
+
+        // Polled by hand, like an executor which runs other tasks in between.
+        // Every poll returns right away, whether the copy is done or not.
+        let handle: ReadbackHandle = readback_ring.read_back(&gpu_handles, &tensor).unwrap();
+        let mut context: Context = Context::from_waker(Waker::noop());
+        let output: Tensor2D = {
+            let mut receive = pin!(readback_ring.receive(&gpu_handles, &handle));
+            loop {
+                if let Poll::Ready(output) = receive.as_mut().poll(&mut context) {
+                    break output.unwrap();
+                }
+            }
+        };
+        assert_eq!(output.data, input.data);
+        assert_eq!(readback_ring.in_flight_count(), 0);
+    }
+
+    #[test]
+    fn dimension_mismatch() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in readback_ring_test::dimension_mismatch() test");
+
+        let tensor: Tensor2DGPU = Tensor2DGPU::new(&gpu_handles, "tensor", 0.5, 7, 5);
+        let mut readback_ring: ReadbackRing = ReadbackRing::new(&gpu_handles, "test", 5, 7, 2);
+        assert!(matches!(
+            readback_ring.read_back(&gpu_handles, &tensor),
+            Err(GraphError::DimensionMismatch(_))
+        ));
+        assert_eq!(readback_ring.in_flight_count(), 0);
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use crate::shared::graph_operators::GraphOperator::*;
 use crate::{
     graph::graph_runner::GraphRunner,
@@ -11,10 +13,15 @@ use crate::{
             benchmark_function_vector_gpu_graph, GraphFunction, PerformanceMeasurements,
         },
         tensor2d::Tensor2D,
+        tensor2d_gpu::LinearLayerKernel,
     },
 };
 
-use super::{graph_runner_gpu::GraphRunnerGPU, graph_validation};
+use super::{
+    graph_runner_gpu::{GraphRunnerGPU, GraphRunnerGPUOptions},
+    graph_validation,
+    readback_ring::ReadbackHandle,
+};
 
 fn cpu_benchmark(
     _gpu_handles: &GPUHandles,
@@ -23,8 +30,11 @@ fn cpu_benchmark(
     output: &mut Tensor2D,
 ) {
     let mut intermediate_output: Tensor2D = Tensor2D::default();
-    if !graph_validation::validate_graph_operators(graph) {
-        panic!("graph::graph::cpu_benchmark() was given an invalid graph!");
+    if let Err(error) = graph_validation::validate_graph_operators(graph) {
+        panic!(
+            "graph::graph::cpu_benchmark() was given an invalid graph! {}",
+            error
+        );
     }
 
     for operator in graph {
@@ -48,9 +58,15 @@ fn cpu_benchmark(
             ReLU => {
                 Tensor2D::relu_inplace_inline(&mut intermediate_output);
             }
-            Softmax => {
+            Softmax { axis: None } => {
                 Tensor2D::softmax_inplace_inline(&mut intermediate_output);
             }
+            Softmax { axis: Some(axis) } => {
+                intermediate_output = Tensor2D::softmax_axis(&intermediate_output, *axis);
+            }
+            LogSoftmax => {
+                intermediate_output = Tensor2D::log_softmax(&intermediate_output);
+            }
             LinearReLUFused { weights, bias } => {
                 let mut temp_output: Tensor2D =
                     Tensor2D::new(0.0, bias.row_count, bias.column_count);
@@ -73,6 +89,14 @@ fn cpu_benchmark(
                 );
                 intermediate_output = temp_output;
             }
+            Add | AddReLUFused | Concat { axis: _ } => {
+                // Multi input operators are rejected by validate_graph_operators
+            }
+            Loss { kind, target } => {
+                let loss: f32 = Tensor2D::loss(*kind, &intermediate_output, target);
+                intermediate_output = Tensor2D::new(0.0, 1, 1);
+                intermediate_output.data[0] = loss;
+            }
         }
     }
 
@@ -86,8 +110,9 @@ fn cpu_graph_benchmark(
     output: &mut Tensor2D,
 ) {
     let fuse_operators: bool = true;
-    let mut graph_runner: GraphRunner = GraphRunner::new(graph, fuse_operators);
-    *output = graph_runner.run();
+    let mut graph_runner: GraphRunner =
+        GraphRunner::new(graph, fuse_operators).expect("Failed to build the CPU graph");
+    *output = graph_runner.run().expect("Failed to run the CPU graph");
 }
 
 fn immediate_benchmark(
@@ -97,8 +122,11 @@ fn immediate_benchmark(
     output: &mut Tensor2D,
 ) {
     let mut intermediate_output: Tensor2D = Tensor2D::default();
-    if !graph_validation::validate_graph_operators(graph) {
-        panic!("graph::graph::immediate_benchmark() was given an invalid graph!");
+    if let Err(error) = graph_validation::validate_graph_operators(graph) {
+        panic!(
+            "graph::graph::immediate_benchmark() was given an invalid graph! {}",
+            error
+        );
     }
 
     for operator in graph {
@@ -126,7 +154,7 @@ fn immediate_benchmark(
                     &mut intermediate_output,
                 ));
             }
-            Softmax => {
+            Softmax { axis: None } => {
                 let mut temp_output: Tensor2D = Tensor2D::new(
                     0.0,
                     intermediate_output.row_count,
@@ -163,6 +191,24 @@ fn immediate_benchmark(
                 ));
                 intermediate_output = temp_output;
             }
+            Softmax { axis: Some(axis) } => {
+                // Softmax along an axis only exists in the graph runner
+                intermediate_output = Tensor2D::softmax_axis(&intermediate_output, *axis);
+            }
+            LogSoftmax => {
+                // Like the losses, LogSoftmax has no immediate GPU version
+                intermediate_output = Tensor2D::log_softmax(&intermediate_output);
+            }
+            Add | AddReLUFused | Concat { axis: _ } => {
+                // Multi input operators are rejected by validate_graph_operators
+            }
+            Loss { kind, target } => {
+                // The benchmark graphs don't contain losses, so there is no
+                // immediate GPU version, it is just computed on the host.
+                let loss: f32 = Tensor2D::loss(*kind, &intermediate_output, target);
+                intermediate_output = Tensor2D::new(0.0, 1, 1);
+                intermediate_output.data[0] = loss;
+            }
         }
     }
 
@@ -175,11 +221,15 @@ fn graph_benchmark(
     _iteration_count: usize,
     output: &mut Tensor2D,
 ) {
-    let fuse_operators: bool = false;
-    let cache_elements: bool = false;
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: false,
+        use_cache: false,
+        ..Default::default()
+    };
     let mut graph_runner: GraphRunnerGPU =
-        GraphRunnerGPU::new(gpu_handles, graph, fuse_operators, cache_elements);
-    *output = pollster::block_on(graph_runner.run(gpu_handles, 1));
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    *output =
+        pollster::block_on(graph_runner.run(gpu_handles, 1)).expect("Failed to run the GPU graph");
 }
 
 fn graph_fused_benchmark(
@@ -188,11 +238,15 @@ fn graph_fused_benchmark(
     _iteration_count: usize,
     output: &mut Tensor2D,
 ) {
-    let fuse_operators: bool = true;
-    let cache_elements: bool = false;
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: true,
+        use_cache: false,
+        ..Default::default()
+    };
     let mut graph_runner: GraphRunnerGPU =
-        GraphRunnerGPU::new(gpu_handles, graph, fuse_operators, cache_elements);
-    *output = pollster::block_on(graph_runner.run(gpu_handles, 1));
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    *output =
+        pollster::block_on(graph_runner.run(gpu_handles, 1)).expect("Failed to run the GPU graph");
 }
 
 fn graph_cached_benchmark(
@@ -201,11 +255,15 @@ fn graph_cached_benchmark(
     _iteration_count: usize,
     output: &mut Tensor2D,
 ) {
-    let fuse_operators: bool = false;
-    let cache_elements: bool = true;
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: false,
+        use_cache: true,
+        ..Default::default()
+    };
     let mut graph_runner: GraphRunnerGPU =
-        GraphRunnerGPU::new(gpu_handles, graph, fuse_operators, cache_elements);
-    *output = pollster::block_on(graph_runner.run(gpu_handles, 1));
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    *output =
+        pollster::block_on(graph_runner.run(gpu_handles, 1)).expect("Failed to run the GPU graph");
 }
 
 fn graph_cached_fused_benchmark(
@@ -214,11 +272,15 @@ fn graph_cached_fused_benchmark(
     _iteration_count: usize,
     output: &mut Tensor2D,
 ) {
-    let fuse_operators: bool = true;
-    let cache_elements: bool = true;
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: true,
+        use_cache: true,
+        ..Default::default()
+    };
     let mut graph_runner: GraphRunnerGPU =
-        GraphRunnerGPU::new(gpu_handles, graph, fuse_operators, cache_elements);
-    *output = pollster::block_on(graph_runner.run(gpu_handles, 1));
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    *output =
+        pollster::block_on(graph_runner.run(gpu_handles, 1)).expect("Failed to run the GPU graph");
 }
 
 fn graph_loop_benchmark(
@@ -227,11 +289,15 @@ fn graph_loop_benchmark(
     iteration_count: usize,
     output: &mut Tensor2D,
 ) {
-    let fuse_operators: bool = false;
-    let cache_elements: bool = false;
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: false,
+        use_cache: false,
+        ..Default::default()
+    };
     let mut graph_runner: GraphRunnerGPU =
-        GraphRunnerGPU::new(gpu_handles, graph, fuse_operators, cache_elements);
-    *output = pollster::block_on(graph_runner.run(gpu_handles, iteration_count));
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    *output = pollster::block_on(graph_runner.run(gpu_handles, iteration_count))
+        .expect("Failed to run the GPU graph");
 }
 
 fn graph_loop_fused_benchmark(
@@ -240,11 +306,15 @@ fn graph_loop_fused_benchmark(
     iteration_count: usize,
     output: &mut Tensor2D,
 ) {
-    let fuse_operators: bool = true;
-    let cache_elements: bool = false;
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: true,
+        use_cache: false,
+        ..Default::default()
+    };
     let mut graph_runner: GraphRunnerGPU =
-        GraphRunnerGPU::new(gpu_handles, graph, fuse_operators, cache_elements);
-    *output = pollster::block_on(graph_runner.run(gpu_handles, iteration_count));
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    *output = pollster::block_on(graph_runner.run(gpu_handles, iteration_count))
+        .expect("Failed to run the GPU graph");
 }
 
 fn graph_loop_cached_benchmark(
@@ -253,11 +323,15 @@ fn graph_loop_cached_benchmark(
     iteration_count: usize,
     output: &mut Tensor2D,
 ) {
-    let fuse_operators: bool = false;
-    let cache_elements: bool = true;
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: false,
+        use_cache: true,
+        ..Default::default()
+    };
     let mut graph_runner: GraphRunnerGPU =
-        GraphRunnerGPU::new(gpu_handles, graph, fuse_operators, cache_elements);
-    *output = pollster::block_on(graph_runner.run(gpu_handles, iteration_count));
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    *output = pollster::block_on(graph_runner.run(gpu_handles, iteration_count))
+        .expect("Failed to run the GPU graph");
 }
 
 fn graph_loop_cached_fused_benchmark(
@@ -266,11 +340,141 @@ fn graph_loop_cached_fused_benchmark(
     iteration_count: usize,
     output: &mut Tensor2D,
 ) {
-    let fuse_operators: bool = true;
-    let cache_elements: bool = true;
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: true,
+        use_cache: true,
+        ..Default::default()
+    };
+    let mut graph_runner: GraphRunnerGPU =
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    *output = pollster::block_on(graph_runner.run(gpu_handles, iteration_count))
+        .expect("Failed to run the GPU graph");
+}
+
+// Same as graph_loop_cached_benchmark, but the linear layers use the tiled kernel
+fn graph_loop_cached_tiled_benchmark(
+    gpu_handles: &GPUHandles,
+    graph: &Vec<GraphOperator>,
+    iteration_count: usize,
+    output: &mut Tensor2D,
+) {
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: false,
+        use_cache: true,
+        linear_layer_kernel: LinearLayerKernel::Tiled,
+        ..Default::default()
+    };
+    let mut graph_runner: GraphRunnerGPU =
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    *output = pollster::block_on(graph_runner.run(gpu_handles, iteration_count))
+        .expect("Failed to run the GPU graph");
+}
+
+// Same as graph_cached_benchmark, but the bind groups are made once per node
+fn graph_bound_benchmark(
+    gpu_handles: &GPUHandles,
+    graph: &Vec<GraphOperator>,
+    _iteration_count: usize,
+    output: &mut Tensor2D,
+) {
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: false,
+        use_cache: true,
+        bind_once: true,
+        ..Default::default()
+    };
+    let mut graph_runner: GraphRunnerGPU =
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    *output =
+        pollster::block_on(graph_runner.run(gpu_handles, 1)).expect("Failed to run the GPU graph");
+}
+
+// Same as graph_loop_cached_benchmark, every iteration only records the dispatches
+fn graph_loop_bound_benchmark(
+    gpu_handles: &GPUHandles,
+    graph: &Vec<GraphOperator>,
+    iteration_count: usize,
+    output: &mut Tensor2D,
+) {
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: false,
+        use_cache: true,
+        bind_once: true,
+        ..Default::default()
+    };
     let mut graph_runner: GraphRunnerGPU =
-        GraphRunnerGPU::new(gpu_handles, graph, fuse_operators, cache_elements);
-    *output = pollster::block_on(graph_runner.run(gpu_handles, iteration_count));
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    *output = pollster::block_on(graph_runner.run(gpu_handles, iteration_count))
+        .expect("Failed to run the GPU graph");
+}
+
+// The input of the HostToDevice node the benchmark graphs start with
+fn benchmark_input(graph: &[GraphOperator]) -> Tensor2D {
+    match &graph[0] {
+        HostToDevice { input } => input.clone(),
+        _ => panic!("The benchmark graphs start with a HostToDevice node"),
+    }
+}
+
+// Every iteration writes a new input and reads its output back, waiting for each one
+fn graph_loop_readback_benchmark(
+    gpu_handles: &GPUHandles,
+    graph: &Vec<GraphOperator>,
+    iteration_count: usize,
+    output: &mut Tensor2D,
+) {
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: false,
+        use_cache: true,
+        ..Default::default()
+    };
+    let mut graph_runner: GraphRunnerGPU =
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    let input: Tensor2D = benchmark_input(graph);
+    for _ in 0..iteration_count {
+        *output = pollster::block_on(graph_runner.run_with_input(gpu_handles, &input))
+            .expect("Failed to run the GPU graph");
+    }
+}
+
+// Same as graph_loop_readback_benchmark, but the outputs are streamed through the readback ring.
+// The next iteration is submitted before waiting for the output of the last one.
+fn graph_loop_streaming_benchmark(
+    gpu_handles: &GPUHandles,
+    graph: &Vec<GraphOperator>,
+    iteration_count: usize,
+    output: &mut Tensor2D,
+) {
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
+        fuse_operators: false,
+        use_cache: true,
+        ..Default::default()
+    };
+    let slot_count: usize = 2;
+    let mut graph_runner: GraphRunnerGPU =
+        GraphRunnerGPU::new(gpu_handles, graph, &options).expect("Failed to build the GPU graph");
+    graph_runner
+        .set_readback_slot_count(slot_count)
+        .expect("Failed to set the readback slot count");
+    let input: Tensor2D = benchmark_input(graph);
+
+    let mut in_flight: VecDeque<ReadbackHandle> = VecDeque::<ReadbackHandle>::new();
+    for _ in 0..iteration_count {
+        if in_flight.len() == slot_count {
+            let handle: ReadbackHandle = in_flight.pop_front().unwrap();
+            *output = pollster::block_on(graph_runner.receive_output(gpu_handles, &handle))
+                .expect("Failed to receive the output of the GPU graph");
+        }
+        in_flight.push_back(
+            graph_runner
+                .submit_with_input(gpu_handles, &input)
+                .expect("Failed to submit the GPU graph"),
+        );
+    }
+    while let Some(handle) = in_flight.pop_front() {
+        *output = pollster::block_on(graph_runner.receive_output(gpu_handles, &handle))
+            .expect("Failed to receive the output of the GPU graph");
+    }
 }
 
 fn graph_benchmarks(config: &Configuration, gpu_handles: &GPUHandles) {
@@ -282,10 +486,15 @@ fn graph_benchmarks(config: &Configuration, gpu_handles: &GPUHandles) {
         "graph_fused".to_string(),
         "graph_cached".to_string(),
         "graph_cached_fused".to_string(),
+        "graph_bound".to_string(),
         "graph_loop".to_string(),
         "graph_loop_fused".to_string(),
         "graph_loop_cached".to_string(),
         "graph_loop_cached_fused".to_string(),
+        "graph_loop_cached_tiled".to_string(),
+        "graph_loop_bound".to_string(),
+        "graph_loop_readback".to_string(),
+        "graph_loop_streaming".to_string(),
     ];
 
     let functions: Vec<(
@@ -299,10 +508,15 @@ fn graph_benchmarks(config: &Configuration, gpu_handles: &GPUHandles) {
         (GraphFunction::Graph, graph_fused_benchmark),
         (GraphFunction::Graph, graph_cached_benchmark),
         (GraphFunction::Graph, graph_cached_fused_benchmark),
+        (GraphFunction::Graph, graph_bound_benchmark),
         (GraphFunction::GraphLoop, graph_loop_benchmark),
         (GraphFunction::GraphLoop, graph_loop_fused_benchmark),
         (GraphFunction::GraphLoop, graph_loop_cached_benchmark),
         (GraphFunction::GraphLoop, graph_loop_cached_fused_benchmark),
+        (GraphFunction::GraphLoop, graph_loop_cached_tiled_benchmark),
+        (GraphFunction::GraphLoop, graph_loop_bound_benchmark),
+        (GraphFunction::GraphLoop, graph_loop_readback_benchmark),
+        (GraphFunction::GraphLoop, graph_loop_streaming_benchmark),
     ];
 
     let mut all_measurements: Vec<PerformanceMeasurements> =
@@ -398,7 +612,7 @@ pub async fn execute(gpu_handles: &GPUHandles, config: &Configuration) {
             bias: bias_c,
         },
         ReLU,
-        Softmax,
+        Softmax { axis: None },
         DeviceToHost,
     ];
 
@@ -406,9 +620,13 @@ pub async fn execute(gpu_handles: &GPUHandles, config: &Configuration) {
     let cache_elements: bool = true;
     // let mut graph_runner: GraphRunner = GraphRunner::new(&gpu_handles, graph_operators, fuse_operators, cache_elements);
     // let output: Tensor2D = graph_runner.run(&gpu_handles).await;
-    let mut graph_runner: GraphRunner = GraphRunner::new(&graph_operators, fuse_operators);
-    let output: Tensor2D = graph_runner.run();
+    let mut graph_runner: GraphRunner =
+        GraphRunner::new(&graph_operators, fuse_operators).expect("Failed to build the CPU graph");
+    graph_runner.set_thread_count(config.thread_count);
+    let output: Tensor2D = graph_runner.run().expect("Failed to run the CPU graph");
     println!("cpu output: {:?}", output);
+    println!("cpu memory: {}", graph_runner.memory_report());
+    println!("cpu {}", graph_runner.fusion_report());
 
     let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
     println!("cpu difference: {:?}", difference);
@@ -417,14 +635,27 @@ pub async fn execute(gpu_handles: &GPUHandles, config: &Configuration) {
         difference.data.iter().map(|x| x.abs()).sum::<f32>()
     );
 
-    let mut graph_runner: GraphRunnerGPU = GraphRunnerGPU::new(
-        gpu_handles,
-        &graph_operators,
+    let options: GraphRunnerGPUOptions = GraphRunnerGPUOptions {
         fuse_operators,
-        cache_elements,
-    );
-    let output: Tensor2D = graph_runner.run(gpu_handles, 1).await;
+        use_cache: cache_elements,
+        ..Default::default()
+    };
+    let mut graph_runner: GraphRunnerGPU =
+        GraphRunnerGPU::new(gpu_handles, &graph_operators, &options)
+            .expect("Failed to build the GPU graph");
+    let output: Tensor2D = graph_runner
+        .run(gpu_handles, 1)
+        .await
+        .expect("Failed to run the GPU graph");
     println!("gpu output: {:?}", output);
+    println!("gpu memory: {}", graph_runner.memory_report());
+    println!(
+        "gpu {}",
+        graph_runner
+            .time_nodes(gpu_handles)
+            .await
+            .expect("Failed to time the GPU graph")
+    );
 
     let difference: Tensor2D = Tensor2D::subtraction(&output_cpu, &output);
     println!("gpu difference: {:?}", difference);
@@ -6,20 +6,23 @@ use wgpu::{
 use crate::shared::{
     gpu_utilities::{create_bind_group, create_compute_pipeline, create_shader_module, GPUHandles},
     tensor2d::Tensor2D,
-    tensor2d_gpu::{LinearLayerUniform, ReluUniform, SoftmaxUniform, SumUniform, Tensor2DGPU},
+    tensor2d_gpu::{
+        reduction_block_count, LinearLayerKernel, LinearLayerUniform, ReluUniform, SoftmaxUniform,
+        SumUniform, Tensor2DGPU,
+    },
 };
 
 pub async fn linear_layer(
     gpu_handles: &GPUHandles,
-    entry_point: &str,
+    kernel: LinearLayerKernel,
+    with_relu: bool,
     input: &Tensor2DGPU,
     weights: &Tensor2DGPU,
     bias: &Tensor2DGPU,
     output: &mut Tensor2DGPU,
 ) {
-    let block_size: usize = 8;
-    let launch_blocks_x: u32 = ((output.row_count + block_size - 1) / block_size) as u32;
-    let launch_blocks_y: u32 = ((output.column_count + block_size - 1) / block_size) as u32;
+    let (launch_blocks_x, launch_blocks_y, _): (u32, u32, u32) =
+        kernel.launch_blocks(output.row_count, output.column_count);
 
     let uniform_device: LinearLayerUniform = LinearLayerUniform::from_tensor_2d_gpu(
         gpu_handles,
@@ -35,7 +38,7 @@ pub async fn linear_layer(
         include_str!("../shared/shaders/linear_layer.wgsl"),
     );
     let compute_pipeline: ComputePipeline =
-        create_compute_pipeline(gpu_handles, &cs_module, entry_point);
+        create_compute_pipeline(gpu_handles, &cs_module, kernel.entry_point(with_relu));
 
     // Instantiates the bind group, once again specifying the binding of buffers.
     let bind_group_layout: BindGroupLayout = compute_pipeline.get_bind_group_layout(0);
@@ -90,7 +93,8 @@ pub async fn linear_layer_from_tensor_2d(
 
     linear_layer(
         gpu_handles,
-        "main",
+        LinearLayerKernel::Naive,
+        false,
         &input_device,
         &weights_device,
         &bias_device,
@@ -135,7 +139,41 @@ pub async fn linear_layer_with_relu_from_tensor_2d(
 
     linear_layer(
         gpu_handles,
-        "main_with_relu",
+        LinearLayerKernel::Naive,
+        true,
+        &input_device,
+        &weights_device,
+        &bias_device,
+        &mut output_device,
+    )
+    .await;
+    if output_device.live_data_on_device {
+        output_device.retrieve_results().await;
+    }
+    *output = output_device.data.clone();
+}
+
+// The same as linear_layer_from_tensor_2d and linear_layer_with_relu_from_tensor_2d,
+// but with the tiled kernel, which stages the input and weights in workgroup memory.
+pub async fn linear_layer_tiled_from_tensor_2d(
+    gpu_handles: &GPUHandles,
+    with_relu: bool,
+    input: &Tensor2D,
+    weights: &Tensor2D,
+    bias: &Tensor2D,
+    output: &mut Tensor2D,
+) {
+    Tensor2DGPU::linear_layer_assert(input, weights, bias, output);
+
+    let input_device: Tensor2DGPU = Tensor2DGPU::from_tensor2d(gpu_handles, "input", input);
+    let weights_device: Tensor2DGPU = Tensor2DGPU::from_tensor2d(gpu_handles, "weights", weights);
+    let bias_device: Tensor2DGPU = Tensor2DGPU::from_tensor2d(gpu_handles, "bias", bias);
+    let mut output_device: Tensor2DGPU = Tensor2DGPU::from_tensor2d(gpu_handles, "output", output);
+
+    linear_layer(
+        gpu_handles,
+        LinearLayerKernel::Tiled,
+        with_relu,
         &input_device,
         &weights_device,
         &bias_device,
@@ -194,8 +232,8 @@ pub async fn relu(
         cpass.set_bind_group(0, &bind_group, &[]);
         cpass.insert_debug_marker("Relu Immediate");
         cpass.dispatch_workgroups(
-            (input_device.row_count + 31 / 32) as u32,
-            (input_device.column_count + 31 / 32) as u32,
+            input_device.row_count.div_ceil(32) as u32,
+            input_device.column_count as u32,
             1,
         ); // Number of cells to run, the (x,y,z) size of item being processed
     }
@@ -251,8 +289,8 @@ pub async fn relu_inplace(gpu_handles: &GPUHandles, data_device: &mut Tensor2DGP
         cpass.set_bind_group(0, &bind_group, &[]);
         cpass.insert_debug_marker("Relu Inplace Immediate");
         cpass.dispatch_workgroups(
-            (data_device.row_count + 31 / 32) as u32,
-            (data_device.column_count + 31 / 32) as u32,
+            data_device.row_count.div_ceil(32) as u32,
+            data_device.column_count as u32,
             1,
         ); // Number of cells to run, the (x,y,z) size of item being processed
     }
@@ -270,40 +308,78 @@ pub async fn relu_inplace(gpu_handles: &GPUHandles, data_device: &mut Tensor2DGP
     data_device.retrieve_results().await;
 }
 
-// Do an alternative version that does tree reduction with a loop, but with a single shader.
+// Records a single compute pass of an already created shader module.
+// The reductions take a handful of passes each, which would otherwise all spell this out.
+fn record_pass(
+    gpu_handles: &GPUHandles,
+    encoder: &mut CommandEncoder,
+    cs_module: &ShaderModule,
+    entry_point: &str,
+    to_be_bound: Vec<(u32, BindingResource)>,
+    label: &str,
+    launch_blocks: u32,
+) {
+    let compute_pipeline: ComputePipeline =
+        create_compute_pipeline(gpu_handles, cs_module, entry_point);
+    let bind_group_layout: BindGroupLayout = compute_pipeline.get_bind_group_layout(0);
+    let bind_group: BindGroup = create_bind_group(gpu_handles, &bind_group_layout, to_be_bound);
+
+    let mut cpass: ComputePass =
+        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some(label) });
+    cpass.set_pipeline(&compute_pipeline);
+    cpass.set_bind_group(0, &bind_group, &[]);
+    cpass.insert_debug_marker(label);
+    cpass.dispatch_workgroups(launch_blocks, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
+}
+
+// The sum is reduced in two passes. The first pass launches up to REDUCTION_BLOCK_SIZE
+// workgroups which each write a partial sum, the second reduces those with a single workgroup.
 pub async fn sum(
     gpu_handles: &GPUHandles,
     input_device: &Tensor2DGPU,
     output_device: &mut Tensor2DGPU,
 ) -> f32 {
+    let block_count: usize = reduction_block_count(input_device.len());
     let uniform_device: SumUniform =
-        SumUniform::new(gpu_handles, "Sum Uniform", input_device.len(), 1);
+        SumUniform::new(gpu_handles, "Sum Uniform", input_device.len(), block_count);
+    let partial_sums_device: Tensor2DGPU =
+        Tensor2DGPU::new(gpu_handles, "Sum Partials", 0.0, block_count, 1);
 
     let cs_module: ShaderModule =
         create_shader_module(gpu_handles, include_str!("../shared/shaders/sum.wgsl"));
-    let compute_pipeline: ComputePipeline =
-        create_compute_pipeline(gpu_handles, &cs_module, "single_pass_sum");
 
-    // Instantiates the bind group, once again specifying the binding of buffers.
-    let bind_group_layout: BindGroupLayout = compute_pipeline.get_bind_group_layout(0);
+    let mut encoder: CommandEncoder = gpu_handles
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
     let to_be_bound: Vec<(u32, BindingResource)> = vec![
         (0, uniform_device.storage_buffer.as_entire_binding()),
         (1, input_device.storage_buffer.as_entire_binding()),
-        (2, output_device.storage_buffer.as_entire_binding()),
+        (3, partial_sums_device.storage_buffer.as_entire_binding()),
     ];
-    let bind_group: BindGroup = create_bind_group(gpu_handles, &bind_group_layout, to_be_bound);
+    record_pass(
+        gpu_handles,
+        &mut encoder,
+        &cs_module,
+        "partial_sum",
+        to_be_bound,
+        "Sum Immediate - Partial",
+        block_count as u32,
+    );
 
-    let mut encoder: CommandEncoder = gpu_handles
-        .device
-        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-    {
-        let mut cpass: ComputePass =
-            encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
-        cpass.set_pipeline(&compute_pipeline);
-        cpass.set_bind_group(0, &bind_group, &[]);
-        cpass.insert_debug_marker("Sum Immediate");
-        cpass.dispatch_workgroups(1, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
-    }
+    let to_be_bound: Vec<(u32, BindingResource)> = vec![
+        (0, uniform_device.storage_buffer.as_entire_binding()),
+        (2, output_device.storage_buffer.as_entire_binding()),
+        (3, partial_sums_device.storage_buffer.as_entire_binding()),
+    ];
+    record_pass(
+        gpu_handles,
+        &mut encoder,
+        &cs_module,
+        "final_sum",
+        to_be_bound,
+        "Sum Immediate - Final",
+        1,
+    );
     output_device.copy_from_gpu_mut(&mut encoder);
 
     gpu_handles.queue.submit(Some(encoder.finish()));
@@ -342,10 +418,13 @@ pub async fn softmax_from_tensor_2d(
     *output = output_device.data.clone();
 }
 
-pub async fn softmax(
+// The max and the sum are both reduced in two passes like in sum,
+// with the partial results of both going through the same partials buffer.
+fn record_softmax_passes(
     gpu_handles: &GPUHandles,
+    encoder: &mut CommandEncoder,
     input_device: &Tensor2DGPU,
-    output_device: &mut Tensor2DGPU,
+    output_device: &Tensor2DGPU,
 ) {
     let uniform_device: SoftmaxUniform =
         SoftmaxUniform::new(gpu_handles, "Softmax Uniform", input_device.len());
@@ -353,84 +432,87 @@ pub async fn softmax(
         Tensor2DGPU::new(gpu_handles, "Softmax Global Max", 0.0, 1, 1);
     let global_offset_device: Tensor2DGPU =
         Tensor2DGPU::new(gpu_handles, "Softmax Global Offset", 0.0, 1, 1);
+    let partials_device: Tensor2DGPU = Tensor2DGPU::new(
+        gpu_handles,
+        "Softmax Partials",
+        0.0,
+        uniform_device.block_count() as usize,
+        1,
+    );
 
     let cs_module: ShaderModule =
         create_shader_module(gpu_handles, include_str!("../shared/shaders/softmax.wgsl"));
 
-    let to_be_bound: Vec<(u32, BindingResource)> = vec![
-        (0, uniform_device.storage_buffer.as_entire_binding()),
-        (1, input_device.storage_buffer.as_entire_binding()),
-        (2, global_max_device.storage_buffer.as_entire_binding()),
+    // Entry point, bindings, label and the number of workgroups to launch
+    let block_size: usize = 32;
+    let passes: [(&str, Vec<u32>, &str, u32); 5] = [
+        (
+            "partial_max",
+            vec![0, 1, 5],
+            "Softmax Immediate - Partial Max",
+            uniform_device.block_count(),
+        ),
+        (
+            "final_max",
+            vec![0, 2, 5],
+            "Softmax Immediate - Final Max",
+            1,
+        ),
+        (
+            "partial_sum",
+            vec![0, 1, 2, 5],
+            "Softmax Immediate - Partial Sum",
+            uniform_device.block_count(),
+        ),
+        (
+            "final_sum",
+            vec![0, 2, 3, 5],
+            "Softmax Immediate - Final Sum",
+            1,
+        ),
+        (
+            "map",
+            vec![0, 1, 3, 4],
+            "Softmax Immediate - Map",
+            input_device.len().div_ceil(block_size) as u32,
+        ),
     ];
+    for (entry_point, bindings, label, launch_blocks) in passes {
+        let to_be_bound: Vec<(u32, BindingResource)> = bindings
+            .into_iter()
+            .map(|binding| {
+                let resource: BindingResource = match binding {
+                    0 => uniform_device.storage_buffer.as_entire_binding(),
+                    1 => input_device.storage_buffer.as_entire_binding(),
+                    2 => global_max_device.storage_buffer.as_entire_binding(),
+                    3 => global_offset_device.storage_buffer.as_entire_binding(),
+                    4 => output_device.storage_buffer.as_entire_binding(),
+                    _ => partials_device.storage_buffer.as_entire_binding(),
+                };
+                (binding, resource)
+            })
+            .collect();
+        record_pass(
+            gpu_handles,
+            encoder,
+            &cs_module,
+            entry_point,
+            to_be_bound,
+            label,
+            launch_blocks,
+        );
+    }
+}
 
-    // Instantiates the bind group, once again specifying the binding of buffers.
+pub async fn softmax(
+    gpu_handles: &GPUHandles,
+    input_device: &Tensor2DGPU,
+    output_device: &mut Tensor2DGPU,
+) {
     let mut encoder: CommandEncoder = gpu_handles
         .device
         .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-    {
-        let max_compute_pipeline: ComputePipeline =
-            create_compute_pipeline(gpu_handles, &cs_module, "single_pass_max");
-        let max_bind_group_layout: BindGroupLayout = max_compute_pipeline.get_bind_group_layout(0);
-        let max_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &max_bind_group_layout, to_be_bound);
-
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Max"),
-        });
-        cpass.set_pipeline(&max_compute_pipeline);
-        cpass.set_bind_group(0, &max_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Max");
-        cpass.dispatch_workgroups(1, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
-    }
-
-    let to_be_bound: Vec<(u32, BindingResource)> = vec![
-        (0, uniform_device.storage_buffer.as_entire_binding()),
-        (1, input_device.storage_buffer.as_entire_binding()),
-        (2, global_max_device.storage_buffer.as_entire_binding()),
-        (3, global_offset_device.storage_buffer.as_entire_binding()),
-    ];
-    {
-        let sum_compute_pipeline: ComputePipeline =
-            create_compute_pipeline(gpu_handles, &cs_module, "single_pass_sum");
-        let sum_bind_group_layout: BindGroupLayout = sum_compute_pipeline.get_bind_group_layout(0);
-        let sum_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &sum_bind_group_layout, to_be_bound);
-
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Sum"),
-        });
-        cpass.set_pipeline(&sum_compute_pipeline);
-        cpass.set_bind_group(0, &sum_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Sum");
-        cpass.dispatch_workgroups(1, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
-    }
-
-    let to_be_bound: Vec<(u32, BindingResource)> = vec![
-        (0, uniform_device.storage_buffer.as_entire_binding()),
-        (1, input_device.storage_buffer.as_entire_binding()),
-        (3, global_offset_device.storage_buffer.as_entire_binding()),
-        (4, output_device.storage_buffer.as_entire_binding()),
-    ];
-    let block_size: usize = 32;
-    {
-        let map_compute_pipeline: ComputePipeline =
-            create_compute_pipeline(gpu_handles, &cs_module, "map");
-        let map_bind_group_layout: BindGroupLayout = map_compute_pipeline.get_bind_group_layout(0);
-        let map_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &map_bind_group_layout, to_be_bound);
-
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Map"),
-        });
-        cpass.set_pipeline(&map_compute_pipeline);
-        cpass.set_bind_group(0, &map_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Map");
-        cpass.dispatch_workgroups(
-            ((input_device.len() + block_size - 1) / block_size) as u32,
-            1,
-            1,
-        ); // Number of cells to run, the (x,y,z) size of item being processed
-    }
+    record_softmax_passes(gpu_handles, &mut encoder, input_device, output_device);
 
     output_device.copy_from_gpu_mut(&mut encoder);
 
@@ -475,7 +557,8 @@ pub async fn linear_relu_softmax_from_tensor_2d(
 
     linear_layer(
         gpu_handles,
-        "main",
+        LinearLayerKernel::Naive,
+        false,
         &input_device,
         &weights_device,
         &bias_device,
@@ -536,7 +619,8 @@ pub async fn linearrelu_softmax_from_tensor_2d(
 
     linear_layer(
         gpu_handles,
-        "main_with_relu",
+        LinearLayerKernel::Naive,
+        true,
         &input_device,
         &weights_device,
         &bias_device,
@@ -584,10 +668,8 @@ pub async fn linear_relu_softmax_fused(
     let linear_entry_point: &str = "main_with_relu";
 
     let linear_block_size: usize = 8;
-    let linear_launch_blocks_x: u32 =
-        ((intermediate.row_count + linear_block_size - 1) / linear_block_size) as u32;
-    let linear_launch_blocks_y: u32 =
-        ((intermediate.column_count + linear_block_size - 1) / linear_block_size) as u32;
+    let linear_launch_blocks_x: u32 = intermediate.row_count.div_ceil(linear_block_size) as u32;
+    let linear_launch_blocks_y: u32 = intermediate.column_count.div_ceil(linear_block_size) as u32;
 
     let linear_uniform: LinearLayerUniform = LinearLayerUniform::from_tensor_2d_gpu(
         gpu_handles,
@@ -597,20 +679,10 @@ pub async fn linear_relu_softmax_fused(
         bias,
         &intermediate,
     );
-    let softmax_uniform: SoftmaxUniform =
-        SoftmaxUniform::new(gpu_handles, "Softmax Uniform", intermediate.len());
-    let softmax_global_max: Tensor2DGPU =
-        Tensor2DGPU::new(gpu_handles, "Softmax Global Max", 0.0, 1, 1);
-    let softmax_global_offset: Tensor2DGPU =
-        Tensor2DGPU::new(gpu_handles, "Softmax Global Offset", 0.0, 1, 1);
-
     let linear_cs_module: ShaderModule = create_shader_module(
         gpu_handles,
         include_str!("../shared/shaders/linear_layer.wgsl"),
     );
-    let softmax_cs_module: ShaderModule =
-        create_shader_module(gpu_handles, include_str!("../shared/shaders/softmax.wgsl"));
-
     let mut encoder: CommandEncoder = gpu_handles
         .device
         .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
@@ -640,78 +712,7 @@ pub async fn linear_relu_softmax_fused(
         // Number of cells to run, the (x,y,z) size of item being processed
     }
 
-    {
-        let to_be_bound: Vec<(u32, BindingResource)> = vec![
-            (0, softmax_uniform.storage_buffer.as_entire_binding()),
-            (1, intermediate.storage_buffer.as_entire_binding()),
-            (2, softmax_global_max.storage_buffer.as_entire_binding()),
-        ];
-
-        let max_compute_pipeline: ComputePipeline =
-            create_compute_pipeline(gpu_handles, &softmax_cs_module, "single_pass_max");
-        let max_bind_group_layout: BindGroupLayout = max_compute_pipeline.get_bind_group_layout(0);
-        let max_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &max_bind_group_layout, to_be_bound);
-
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Max"),
-        });
-        cpass.set_pipeline(&max_compute_pipeline);
-        cpass.set_bind_group(0, &max_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Max");
-        cpass.dispatch_workgroups(1, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
-    }
-
-    {
-        let to_be_bound: Vec<(u32, BindingResource)> = vec![
-            (0, softmax_uniform.storage_buffer.as_entire_binding()),
-            (1, intermediate.storage_buffer.as_entire_binding()),
-            (2, softmax_global_max.storage_buffer.as_entire_binding()),
-            (3, softmax_global_offset.storage_buffer.as_entire_binding()),
-        ];
-
-        let sum_compute_pipeline: ComputePipeline =
-            create_compute_pipeline(gpu_handles, &softmax_cs_module, "single_pass_sum");
-        let sum_bind_group_layout: BindGroupLayout = sum_compute_pipeline.get_bind_group_layout(0);
-        let sum_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &sum_bind_group_layout, to_be_bound);
-
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Sum"),
-        });
-        cpass.set_pipeline(&sum_compute_pipeline);
-        cpass.set_bind_group(0, &sum_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Sum");
-        cpass.dispatch_workgroups(1, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
-    }
-
-    let block_size: usize = 32;
-    {
-        let to_be_bound: Vec<(u32, BindingResource)> = vec![
-            (0, softmax_uniform.storage_buffer.as_entire_binding()),
-            (1, intermediate.storage_buffer.as_entire_binding()),
-            (3, softmax_global_offset.storage_buffer.as_entire_binding()),
-            (4, output.storage_buffer.as_entire_binding()),
-        ];
-
-        let map_compute_pipeline: ComputePipeline =
-            create_compute_pipeline(gpu_handles, &softmax_cs_module, "map");
-        let map_bind_group_layout: BindGroupLayout = map_compute_pipeline.get_bind_group_layout(0);
-        let map_bind_group: BindGroup =
-            create_bind_group(gpu_handles, &map_bind_group_layout, to_be_bound);
-
-        let mut cpass: ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Softmax - Map"),
-        });
-        cpass.set_pipeline(&map_compute_pipeline);
-        cpass.set_bind_group(0, &map_bind_group, &[]);
-        cpass.insert_debug_marker("Softmax Immediate - Map");
-        cpass.dispatch_workgroups(
-            ((intermediate.len() + block_size - 1) / block_size) as u32,
-            1,
-            1,
-        ); // Number of cells to run, the (x,y,z) size of item being processed
-    }
+    record_softmax_passes(gpu_handles, &mut encoder, &intermediate, output);
 
     output.copy_from_gpu_mut(&mut encoder);
 
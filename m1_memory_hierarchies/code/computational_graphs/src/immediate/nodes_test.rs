@@ -1,12 +1,16 @@
+// The tests which use initialize_gpu want a hardware adapter, the rest run on the
+// software adapter. Without a GPU, like in CI, set WGPU_FORCE_FALLBACK_ADAPTER=1
+// to run every test on the software adapter.
 #[cfg(test)]
 mod tests {
     use crate::immediate::nodes::{
-        linear_layer_from_tensor_2d_blocking, linear_relu_softmax_from_tensor_2d_blocking,
+        linear_layer_from_tensor_2d_blocking, linear_layer_tiled_from_tensor_2d,
+        linear_relu_softmax_from_tensor_2d_blocking,
         linear_relu_softmax_fused_from_tensor_2d_blocking,
         linearrelu_softmax_from_tensor_2d_blocking, relu_from_tensor_2d, softmax_from_tensor_2d,
         sum_from_tensor_2d,
     };
-    use crate::shared::gpu_utilities::{initialize_gpu, GPUHandles};
+    use crate::shared::gpu_utilities::{initialize_fallback_gpu, initialize_gpu, GPUHandles};
     use crate::shared::tensor2d::Tensor2D;
     use crate::shared::tensor2d_gpu::Tensor2DGPU;
 
@@ -91,7 +95,7 @@ mod tests {
                     let expected_result: f32 = output.sum();
                     println!("expected result: {:?}", expected_result);
 
-                    test(&gpu_handles, &input, &weights, &bias, &mut output);
+                    test(gpu_handles, &input, &weights, &bias, &mut output);
 
                     let result: f32 = output.sum();
                     println!("result: {:?}", result);
@@ -158,6 +162,55 @@ mod tests {
         }
     }
 
+    // Sizes around the workgroup size, and past the point where every workgroup loops
+    #[test]
+    fn sum_multi_workgroup() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in immediate::sum_multi_workgroup() test");
+
+        for element_count in [1, 31, 255, 256, 257, 65_537, 100_000, 1_000_003] {
+            let mut input: Tensor2D = Tensor2D::new(0.0, element_count, 1);
+            for (index, value) in input.data.iter_mut().enumerate() {
+                *value = (index % 7) as f32 * 0.25 - 0.5;
+            }
+            let expected_result: f32 = input.sum();
+
+            let result: f32 = pollster::block_on(sum_from_tensor_2d(&gpu_handles, &input));
+            // The order of the additions differs from the CPU, so the error grows with the size
+            let tolerance: f32 = ERROR_TOLERANCE * (element_count as f32).max(1.0);
+
+            assert!(
+                (expected_result - result).abs() < tolerance,
+                "{} elements: expected {} but got {}",
+                element_count,
+                expected_result,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn softmax_multi_workgroup() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in immediate::softmax_multi_workgroup() test");
+
+        for element_count in [257, 70_001] {
+            let mut input: Tensor2D = Tensor2D::new(0.0, element_count, 1);
+            for (index, value) in input.data.iter_mut().enumerate() {
+                *value = (index as f32 * 1.7).sin() * 4.0;
+            }
+            let expected: Tensor2D = Tensor2D::softmax(&input);
+
+            let mut output: Tensor2D = Tensor2D::new(0.0, element_count, 1);
+            pollster::block_on(softmax_from_tensor_2d(&gpu_handles, &input, &mut output));
+
+            assert!((1.0 - output.sum()).abs() < 0.001);
+            for (expected, result) in expected.data.iter().zip(output.data.iter()) {
+                assert!((expected - result).abs() < ERROR_TOLERANCE);
+            }
+        }
+    }
+
     #[test]
     fn softmax() {
         let gpu_handles: GPUHandles = pollster::block_on(initialize_gpu(true))
@@ -196,6 +249,61 @@ mod tests {
         );
     }
 
+    // Sizes which don't divide into the 32x32 output tiles or the tile depth of 16
+    #[test]
+    fn linear_layer_tiled() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_fallback_gpu())
+            .expect("Failed to get GPU handles in immediate::linear_layer_tiled() test");
+
+        for (row_count, inner_count, column_count) in [
+            (1, 1, 1),
+            (3, 17, 5),
+            (32, 16, 32),
+            (33, 40, 70),
+            (100, 65, 31),
+        ] {
+            let mut input: Tensor2D = Tensor2D::new(0.0, row_count, inner_count);
+            for (index, value) in input.data.iter_mut().enumerate() {
+                *value = (index as f32 * 0.7).sin();
+            }
+            let mut weights: Tensor2D = Tensor2D::new(0.0, inner_count, column_count);
+            for (index, value) in weights.data.iter_mut().enumerate() {
+                *value = (index as f32 * 1.3).cos() * 0.5;
+            }
+            let bias: Tensor2D = Tensor2D::new(0.1, row_count, column_count);
+
+            for with_relu in [false, true] {
+                let mut expected: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+                Tensor2D::linear_layer_preallocated(&input, &weights, &bias, &mut expected);
+                if with_relu {
+                    expected = Tensor2D::relu(&expected);
+                }
+
+                let mut output: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+                pollster::block_on(linear_layer_tiled_from_tensor_2d(
+                    &gpu_handles,
+                    with_relu,
+                    &input,
+                    &weights,
+                    &bias,
+                    &mut output,
+                ));
+
+                for (expected, result) in expected.data.iter().zip(output.data.iter()) {
+                    assert!(
+                        (expected - result).abs() < ERROR_TOLERANCE * 10.0,
+                        "{}x{}x{}: expected {} but got {}",
+                        row_count,
+                        inner_count,
+                        column_count,
+                        expected,
+                        result
+                    );
+                }
+            }
+        }
+    }
+
     #[test]
     fn relu() {
         let gpu_handles: GPUHandles = pollster::block_on(initialize_gpu(true))
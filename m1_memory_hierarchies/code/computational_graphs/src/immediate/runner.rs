@@ -9,10 +9,10 @@ use crate::shared::{
 };
 
 use super::nodes::{
-    linear_layer_from_tensor_2d, linear_layer_with_relu_from_tensor_2d,
-    linear_relu_softmax_from_tensor_2d, linear_relu_softmax_fused_from_tensor_2d,
-    linearrelu_softmax_from_tensor_2d, relu_from_tensor_2d, relu_inplace_from_tensor_2d,
-    softmax_from_tensor_2d, sum_from_tensor_2d,
+    linear_layer_from_tensor_2d, linear_layer_tiled_from_tensor_2d,
+    linear_layer_with_relu_from_tensor_2d, linear_relu_softmax_from_tensor_2d,
+    linear_relu_softmax_fused_from_tensor_2d, linearrelu_softmax_from_tensor_2d,
+    relu_from_tensor_2d, relu_inplace_from_tensor_2d, softmax_from_tensor_2d, sum_from_tensor_2d,
 };
 
 fn immediate_linear_layer_benchmark(
@@ -47,12 +47,53 @@ fn immediate_linear_layer_with_relu_benchmark(
     ));
 }
 
+fn immediate_linear_layer_tiled_benchmark(
+    gpu_handles: &GPUHandles,
+    input: &mut Tensor2D,
+    weights: &Tensor2D,
+    bias: &Tensor2D,
+    output: &mut Tensor2D,
+) {
+    pollster::block_on(linear_layer_tiled_from_tensor_2d(
+        gpu_handles,
+        false,
+        input,
+        weights,
+        bias,
+        output,
+    ));
+}
+
+fn immediate_linear_layer_tiled_with_relu_benchmark(
+    gpu_handles: &GPUHandles,
+    input: &mut Tensor2D,
+    weights: &Tensor2D,
+    bias: &Tensor2D,
+    output: &mut Tensor2D,
+) {
+    pollster::block_on(linear_layer_tiled_from_tensor_2d(
+        gpu_handles,
+        true,
+        input,
+        weights,
+        bias,
+        output,
+    ));
+}
+
 fn linear_layer_benchmark(config: &Configuration, gpu_handles: &GPUHandles) {
-    let names: Vec<String> = vec!["immediate".to_string(), "with_relu".to_string()];
+    let names: Vec<String> = vec![
+        "immediate".to_string(),
+        "with_relu".to_string(),
+        "tiled".to_string(),
+        "tiled_with_relu".to_string(),
+    ];
 
     let functions: Vec<fn(&GPUHandles, &mut Tensor2D, &Tensor2D, &Tensor2D, &mut Tensor2D)> = vec![
         immediate_linear_layer_benchmark,
         immediate_linear_layer_with_relu_benchmark,
+        immediate_linear_layer_tiled_benchmark,
+        immediate_linear_layer_tiled_with_relu_benchmark,
     ];
 
     let mut all_measurements: Vec<PerformanceMeasurements> =
@@ -13,7 +13,7 @@ mod stack;
 
 use shared::{
     configuration::Configuration,
-    gpu_utilities::{self, initialize_gpu, GPUHandles},
+    gpu_utilities::{self, initialize_gpu_with_selection, AdapterSelection, GPUHandles},
 };
 
 pub async fn run() {
@@ -25,11 +25,15 @@ pub async fn run() {
     let loop_count: usize = 10;
     let loop_range: Vec<usize> = (2u32..8u32).map(|x| 2usize.pow(x)).collect();
     let log_scale: bool = false;
-    let compatible_gpu_found: bool = pollster::block_on(gpu_utilities::self_test());
+    // Set WGPU_FORCE_FALLBACK_ADAPTER=1 or WGPU_ADAPTER_NAME=llvmpipe to run on a software adapter
+    let adapter_selection: AdapterSelection = AdapterSelection::from_env();
+    let compatible_gpu_found: bool =
+        pollster::block_on(gpu_utilities::self_test(&adapter_selection));
     let warmup_gpu: bool = true;
     let default_graph_layer_count: usize = 64; // Only used for benchmarking graph functions
     let default_graph_operator_size: usize = 256; // Only used for benchmarking graph functions
     let graph_depth_range: Vec<usize> = (2u32..8u32).map(|x| 2usize.pow(x)).collect();
+    let thread_count: usize = 0; // 0 uses every core available
 
     let configuration: Configuration = Configuration::build_gpu(
         debug_level,
@@ -42,16 +46,26 @@ pub async fn run() {
         default_graph_layer_count,
         default_graph_operator_size,
         graph_depth_range,
+        thread_count,
     );
     // stack::runner::execute(&configuration);8
 
-    if configuration.compatible_gpu_found {
-        let gpu_handles: GPUHandles = initialize_gpu(configuration.warmup_gpu)
-            .await
-            .expect("Failed to acquire GPU Handles");
+    // The adapter can still refuse to give us a device after passing the self test
+    let gpu_handles: Option<GPUHandles> = if configuration.compatible_gpu_found {
+        initialize_gpu_with_selection(&adapter_selection, configuration.warmup_gpu).await
+    } else {
+        None
+    };
 
-        // pollster::block_on(immediate::runner::execute(&gpu_handles, &configuration));
-        pollster::block_on(graph::runner::execute(&gpu_handles, &configuration));
-        // op_code_compiler::runner::compile_linear_shader(&gpu_handles, true);
+    match gpu_handles {
+        Some(gpu_handles) => {
+            // pollster::block_on(immediate::runner::execute(&gpu_handles, &configuration));
+            pollster::block_on(graph::runner::execute(&gpu_handles, &configuration));
+            // op_code_compiler::runner::compile_linear_shader(&gpu_handles, true);
+        }
+        None => {
+            println!("Failed to acquire GPU handles, only running the CPU code.");
+            stack::runner::execute(&configuration);
+        }
     }
 }
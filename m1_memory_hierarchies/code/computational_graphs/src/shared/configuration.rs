@@ -10,6 +10,8 @@ pub struct Configuration {
     pub default_graph_layer_count: usize,
     pub default_graph_operator_size: usize,
     pub graph_depth_range: Vec<usize>,
+    // Threads used by the parallel CPU kernels, 0 uses every core available
+    pub thread_count: usize,
 }
 
 impl Configuration {
@@ -31,6 +33,7 @@ impl Configuration {
             default_graph_layer_count: 0,
             default_graph_operator_size: 0,
             graph_depth_range: Vec::<usize>::new(),
+            thread_count: 0,
         }
     }
 
@@ -45,6 +48,7 @@ impl Configuration {
         default_graph_layer_count: usize,
         default_graph_operator_size: usize,
         graph_depth_range: Vec<usize>,
+        thread_count: usize,
     ) -> Self {
         assert_eq!(loop_range.len(), graph_depth_range.len());
 
@@ -59,6 +63,7 @@ impl Configuration {
             default_graph_layer_count,
             default_graph_operator_size,
             graph_depth_range,
+            thread_count,
         }
     }
 }
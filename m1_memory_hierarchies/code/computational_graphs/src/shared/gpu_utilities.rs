@@ -1,102 +1,216 @@
 use std::borrow::Cow;
 
 use wgpu::{
-    Adapter, AdapterInfo, BindGroup, BindGroupEntry, BindGroupLayout, BindingResource,
-    ComputePipeline, Device, Instance, Queue, RequestAdapterOptions, ShaderModule,
+    Adapter, AdapterInfo, Backends, BindGroup, BindGroupEntry, BindGroupLayout, BindingResource,
+    ComputePipeline, Device, DeviceType, Features, Instance, Limits, PowerPreference, Queue,
+    RequestAdapterOptions, ShaderModule,
 };
 
 use crate::immediate::nodes::sum_from_tensor_2d;
 
 use super::tensor2d::Tensor2D;
 
+// Features which are used if the adapter has them, but never required.
+// TIMESTAMP_QUERY lets the compute passes be timed on the GPU itself.
+const OPTIONAL_FEATURES: Features = Features::TIMESTAMP_QUERY;
+
+// The vendor id of Mesa, whose software adapter is LavaPipe/llvmpipe
+const MESA_SOFTWARE_VENDOR: usize = 0x10005;
+
+// Which adapter to run on. The default is the fastest GPU on any backend.
+// A software adapter, like LavaPipe/llvmpipe, lets the GPU code run on machines without a GPU,
+// such as CI boxes, just a lot slower.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdapterSelection {
+    pub backends: Backends,
+    pub power_preference: PowerPreference,
+    // Only accept the fallback adapter, which is a software adapter on the platforms having one
+    pub force_fallback_adapter: bool,
+    // Pick the first adapter whose name contains this, ignoring case, like "llvmpipe" or "nvidia"
+    pub adapter_name: Option<String>,
+}
+
+impl Default for AdapterSelection {
+    fn default() -> Self {
+        Self {
+            backends: Backends::all(),
+            power_preference: PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            adapter_name: None,
+        }
+    }
+}
+
+impl AdapterSelection {
+    pub fn software() -> Self {
+        Self {
+            power_preference: PowerPreference::LowPower,
+            force_fallback_adapter: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn by_name(adapter_name: &str) -> Self {
+        Self {
+            adapter_name: Some(adapter_name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    // The default, overridden by the same environment variables wgpu's own examples read,
+    // WGPU_BACKEND (like "vulkan,gl"), WGPU_POWER_PREF ("low" or "high") and WGPU_ADAPTER_NAME,
+    // along with WGPU_FORCE_FALLBACK_ADAPTER=1 to ask for the software adapter.
+    pub fn from_env() -> Self {
+        let default: AdapterSelection = AdapterSelection::default();
+        let force_fallback_adapter: bool = std::env::var("WGPU_FORCE_FALLBACK_ADAPTER")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+
+        Self {
+            backends: wgpu::util::backend_bits_from_env().unwrap_or(default.backends),
+            power_preference: wgpu::util::power_preference_from_env()
+                .unwrap_or(default.power_preference),
+            force_fallback_adapter,
+            adapter_name: std::env::var("WGPU_ADAPTER_NAME").ok(),
+        }
+    }
+
+    // Software adapters are only used when asked for, either as the fallback or by name
+    fn allows_software(&self) -> bool {
+        self.force_fallback_adapter || self.adapter_name.is_some()
+    }
+}
+
 pub struct GPUHandles {
     pub queue: Queue,
     pub device: Device,
     pub adapter: Adapter,
     pub adapter_info: AdapterInfo,
+    // How the adapter was chosen, adapter_info says which one it was
+    pub adapter_selection: AdapterSelection,
+    // What the device was created with. The limits are the adapter's own, not the defaults,
+    // and the nodes check tensors and dispatches against these instead of letting wgpu fail.
+    pub limits: Limits,
+    pub features: Features,
 }
 
-pub async fn self_test() -> bool {
-    println!("Performing self test to check system for compatibility.");
+async fn select_adapter(adapter_selection: &AdapterSelection) -> Option<Adapter> {
     // Instantiates instance of wgpu
     let instance: Instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::all(),
+        backends: adapter_selection.backends,
         dx12_shader_compiler: Default::default(),
     });
 
+    if let Some(adapter_name) = &adapter_selection.adapter_name {
+        let adapter_name: String = adapter_name.to_lowercase();
+        return instance
+            .enumerate_adapters(adapter_selection.backends)
+            .find(|adapter| {
+                let info: AdapterInfo = adapter.get_info();
+                info.name.to_lowercase().contains(&adapter_name)
+                    && (!adapter_selection.force_fallback_adapter
+                        || info.device_type == DeviceType::Cpu)
+            });
+    }
+
     let adapter_request: RequestAdapterOptions = RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance,
-        compatible_surface: None,
-        force_fallback_adapter: false,
+        power_preference: adapter_selection.power_preference,
+        compatible_surface: None, // We aren't doing any graphics
+        force_fallback_adapter: adapter_selection.force_fallback_adapter,
     };
 
     // `request_adapter` instantiates the general connection to the GPU
-    let adapter_option: Option<Adapter> = instance.request_adapter(&adapter_request).await;
+    let adapter: Adapter = instance.request_adapter(&adapter_request).await?;
+
+    // skip LavaPipe temporarily, unless it was asked for.
+    // Checked here, so self_test and initialize_gpu_with_selection always agree.
+    if adapter.get_info().vendor == MESA_SOFTWARE_VENDOR && !adapter_selection.allows_software() {
+        return None;
+    }
 
-    match adapter_option {
+    Some(adapter)
+}
+
+pub async fn self_test(adapter_selection: &AdapterSelection) -> bool {
+    println!("Performing self test to check system for compatibility.");
+    match select_adapter(adapter_selection).await {
         Some(adapter) => {
             let info: AdapterInfo = adapter.get_info();
             println!("Found GPU: {:?}", info);
             true
         }
         None => {
-            println!("Failed to find a usable GPU. This framework will only run CPU code.");
+            println!(
+                "Failed to find a usable GPU with {:?}. This framework will only run CPU code.",
+                adapter_selection
+            );
             false
         }
     }
 }
 
+// Uses AdapterSelection::from_env, which is the default unless the environment says otherwise.
 pub async fn initialize_gpu(warmup_gpu: bool) -> Option<GPUHandles> {
-    // Instantiates instance of wgpu
-    let instance: Instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::all(),
-        dx12_shader_compiler: Default::default(),
-    });
+    initialize_gpu_with_selection(&AdapterSelection::from_env(), warmup_gpu).await
+}
 
-    // `request_adapter` instantiates the general connection to the GPU
-    let adapter: Adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: None, // We aren't doing any graphics
-            force_fallback_adapter: false,
-        })
-        .await
-        .expect("Failed to find a usable GPU!");
+// Returns None if no adapter matches the selection or it won't give us a device.
+// Finds an adapter exactly when self_test does.
+pub async fn initialize_gpu_with_selection(
+    adapter_selection: &AdapterSelection,
+    warmup_gpu: bool,
+) -> Option<GPUHandles> {
+    let adapter: Adapter = select_adapter(adapter_selection).await?;
+    let gpu_handles: GPUHandles = request_gpu_handles(adapter, adapter_selection).await?;
+
+    if warmup_gpu {
+        let input: Tensor2D = Tensor2D::new(-0.5, 4, 3);
+        let output: f32 = sum_from_tensor_2d(&gpu_handles, &input).await;
+        let _dummy_value: f32 = output * 3.0 + 6.2;
+    }
 
+    Some(gpu_handles)
+}
+
+// The software adapter, when the platform has one. Slow, but it runs anywhere,
+// which makes it the adapter to check the shaders on when there is no GPU.
+pub async fn initialize_fallback_gpu() -> Option<GPUHandles> {
+    initialize_gpu_with_selection(&AdapterSelection::software(), false).await
+}
+
+async fn request_gpu_handles(
+    adapter: Adapter,
+    adapter_selection: &AdapterSelection,
+) -> Option<GPUHandles> {
     // `request_device` instantiates the feature specific connection to the GPU, defining some parameters,
     //  `features` being the available features.
+    // Asking for the adapter's own limits gets us everything it can do,
+    // like storage buffers larger than the default 128 MB.
     let (device, queue): (Device, Queue) = adapter
         .request_device(
             &wgpu::DeviceDescriptor {
                 label: None,
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
+                features: adapter.features() & OPTIONAL_FEATURES,
+                limits: adapter.limits(),
             },
             None,
         )
         .await
-        .unwrap();
+        .ok()?;
 
     let adapter_info: AdapterInfo = adapter.get_info();
-    // skip this on LavaPipe temporarily
-    if adapter_info.vendor == 0x10005 {
-        return None;
-    }
+    let limits: Limits = device.limits();
+    let features: Features = device.features();
 
-    let gpu_handles: GPUHandles = GPUHandles {
+    Some(GPUHandles {
         queue,
         device,
         adapter,
         adapter_info,
-    };
-
-    if warmup_gpu {
-        let input: Tensor2D = Tensor2D::new(-0.5, 4, 3);
-        let output: f32 = sum_from_tensor_2d(&gpu_handles, &input).await;
-        let _dummy_value: f32 = output * 3.0 + 6.2;
-    }
-
-    Some(gpu_handles)
+        adapter_selection: adapter_selection.clone(),
+        limits,
+        features,
+    })
 }
 
 pub fn create_shader_module(gpu_handles: &GPUHandles, shader: &str) -> ShaderModule {
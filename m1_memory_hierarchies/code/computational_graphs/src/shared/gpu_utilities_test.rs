@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use wgpu::DeviceType;
+
+    use crate::shared::gpu_utilities::{
+        initialize_gpu_with_selection, self_test, AdapterSelection, GPUHandles,
+    };
+
+    #[test]
+    fn software_adapter() {
+        let gpu_handles: GPUHandles = pollster::block_on(initialize_gpu_with_selection(
+            &AdapterSelection::software(),
+            true,
+        ))
+        .expect("Failed to get GPU handles in gpu_utilities::software_adapter() test");
+
+        assert_eq!(gpu_handles.adapter_selection, AdapterSelection::software());
+        assert_eq!(gpu_handles.adapter_info.device_type, DeviceType::Cpu);
+    }
+
+    #[test]
+    fn adapter_by_name() {
+        // Any part of the name will do, in any case
+        let name: String = pollster::block_on(initialize_gpu_with_selection(
+            &AdapterSelection::software(),
+            false,
+        ))
+        .expect("Failed to get GPU handles in gpu_utilities::adapter_by_name() test")
+        .adapter_info
+        .name
+        .to_uppercase();
+        let name: &str = &name[..name.len().min(6)];
+
+        let selection: AdapterSelection = AdapterSelection::by_name(name);
+        let gpu_handles: GPUHandles =
+            pollster::block_on(initialize_gpu_with_selection(&selection, false))
+                .expect("Failed to find the software adapter by name");
+        assert_eq!(gpu_handles.adapter_selection, selection);
+        assert!(gpu_handles
+            .adapter_info
+            .name
+            .to_uppercase()
+            .starts_with(name));
+    }
+
+    #[test]
+    fn missing_adapter() {
+        // Not finding an adapter is not an error worth panicking over
+        let selection: AdapterSelection = AdapterSelection::by_name("no such adapter");
+        assert!(pollster::block_on(initialize_gpu_with_selection(&selection, false)).is_none());
+    }
+
+    #[test]
+    fn self_test_agrees() {
+        // Whatever adapters the machine has, the self test may only pass
+        // when the GPU can actually be initialized with the same selection
+        let selections: [AdapterSelection; 4] = [
+            AdapterSelection::default(),
+            AdapterSelection::software(),
+            AdapterSelection::by_name("llvmpipe"),
+            AdapterSelection::by_name("no such adapter"),
+        ];
+        for selection in &selections {
+            let compatible_gpu_found: bool = pollster::block_on(self_test(selection));
+            let gpu_handles: Option<GPUHandles> =
+                pollster::block_on(initialize_gpu_with_selection(selection, false));
+            assert_eq!(
+                compatible_gpu_found,
+                gpu_handles.is_some(),
+                "{:?}",
+                selection
+            );
+        }
+    }
+}
@@ -7,7 +7,58 @@ pub enum GraphOperator {
     DeviceToHost,
     LinearLayer { weights: Tensor2D, bias: Tensor2D },
     ReLU,
-    Softmax,
+    // Without an axis, softmax normalizes over every element of the tensor.
+    // Axis 0 normalizes every column and axis 1 every row, so a batch
+    // of samples with one sample per row wants axis 1.
+    Softmax { axis: Option<usize> },
+    // ln(softmax(input)), computed without ever taking the log of a tiny probability
+    LogSoftmax,
     LinearReLUFused { weights: Tensor2D, bias: Tensor2D },
     LinearReLUSoftmaxFused { weights: Tensor2D, bias: Tensor2D },
+    // The operators below take more than one input, so they
+    // can only be used in a GraphDAG, not in a sequential graph.
+    // Elementwise addition of two tensors with the same shape
+    Add,
+    AddReLUFused,
+    // Concatenation of two or more tensors. Axis 0 stacks the rows,
+    // axis 1 places the columns next to each other.
+    Concat { axis: usize },
+    // Compares its input to the target, which must have the same shape,
+    // and outputs the loss as a 1x1 tensor.
+    // Only needed to train the graph, see GraphRunner::for_training.
+    Loss { kind: LossKind, target: Tensor2D },
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LossKind {
+    // -sum(target * ln(input)), the input is a probability distribution like the output of Softmax
+    CrossEntropy,
+    // -sum(target * input), the input holds log probabilities like the output of LogSoftmax.
+    // LogSoftmax followed by NegativeLogLikelihood is cross entropy which can't underflow.
+    NegativeLogLikelihood,
+    // mean((input - target)^2)
+    MeanSquaredError,
+    // mean(|input - target|), also known as L1
+    MeanAbsoluteError,
+}
+
+impl LossKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LossKind::CrossEntropy => "CrossEntropy",
+            LossKind::NegativeLogLikelihood => "NegativeLogLikelihood",
+            LossKind::MeanSquaredError => "MeanSquaredError",
+            LossKind::MeanAbsoluteError => "MeanAbsoluteError",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "CrossEntropy" => Some(LossKind::CrossEntropy),
+            "NegativeLogLikelihood" => Some(LossKind::NegativeLogLikelihood),
+            "MeanSquaredError" => Some(LossKind::MeanSquaredError),
+            "MeanAbsoluteError" => Some(LossKind::MeanAbsoluteError),
+            _ => None,
+        }
+    }
 }
@@ -1,8 +1,29 @@
 pub mod benchmark_plot;
 pub mod configuration;
 pub mod gpu_utilities;
+pub mod gpu_utilities_test;
 pub mod graph_operators;
 pub mod performance_measurement;
 pub mod tensor2d;
+pub mod tensor2d_autograd;
+pub mod tensor2d_autograd_test;
+pub mod tensor2d_blocked;
+pub mod tensor2d_blocked_test;
 pub mod tensor2d_gpu;
+pub mod tensor2d_gpu_test;
+pub mod tensor2d_loss;
+pub mod tensor2d_loss_test;
+pub mod tensor2d_parallel;
+pub mod tensor2d_parallel_test;
+pub mod tensor2d_simd;
+pub mod tensor2d_simd_test;
+pub mod tensor2d_softmax_axis;
+pub mod tensor2d_softmax_axis_test;
+// The tests sweep values starting at -3.14, which isn't meant to be pi
+#[allow(clippy::approx_constant)]
 pub mod tensor2d_test;
+pub mod tensor_element;
+pub mod tensor_io;
+pub mod tensor_io_test;
+pub mod tensor_nd;
+pub mod tensor_nd_test;
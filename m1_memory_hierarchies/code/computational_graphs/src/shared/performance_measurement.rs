@@ -8,6 +8,10 @@ use super::{
     tensor2d::Tensor2D,
 };
 
+// A boxed closure rather than a function pointer,
+// so a benchmark can capture settings like the thread count
+pub type BenchmarkFunction = Box<dyn Fn(&mut Tensor2D, &Tensor2D, &Tensor2D, &mut Tensor2D)>;
+
 #[derive(Debug, Default, Clone)]
 pub struct PerformanceMeasurements {
     pub name: String,
@@ -56,8 +60,8 @@ impl PerformanceMeasurements {
 pub fn benchmark_function_vector(
     config: &Configuration,
     names: Vec<String>,
-    functions: Vec<fn(&mut Tensor2D, &Tensor2D, &Tensor2D, &mut Tensor2D)>,
-    all_measurements: &mut Vec<PerformanceMeasurements>,
+    functions: Vec<BenchmarkFunction>,
+    all_measurements: &mut [PerformanceMeasurements],
 ) {
     assert!(functions.len() == all_measurements.len());
     assert!(names.len() == all_measurements.len());
@@ -68,7 +72,7 @@ pub fn benchmark_function_vector(
     for test_index in 0..test_count {
         let mut performance_measurements: Vec<(u128, usize)> = vec![(0, 0); range_count];
         let mut total_elements_per_measurement: Vec<usize> = vec![0; range_count];
-        let function = functions[test_index];
+        let function: &BenchmarkFunction = &functions[test_index];
         for (size_index, size) in config.loop_range.iter().enumerate() {
             let size: usize = *size;
             let mut input: Tensor2D = Tensor2D::new(0.5, size, size);
@@ -99,7 +103,7 @@ pub fn benchmark_function_vector_gpu(
     names: Vec<String>,
     gpu_handles: &GPUHandles,
     functions: Vec<fn(&GPUHandles, &mut Tensor2D, &Tensor2D, &Tensor2D, &mut Tensor2D)>,
-    all_measurements: &mut Vec<PerformanceMeasurements>,
+    all_measurements: &mut [PerformanceMeasurements],
 ) {
     assert!(functions.len() == all_measurements.len());
     assert!(names.len() == all_measurements.len());
@@ -175,7 +179,7 @@ fn benchmark_function_vector_gpu_graph_inner_loop(
         _ => graph.push(GraphOperator::ReLU),
     };
 
-    graph.push(GraphOperator::Softmax);
+    graph.push(GraphOperator::Softmax { axis: None });
     graph.push(GraphOperator::DeviceToHost);
 
     let mut out: Tensor2D = Tensor2D::new(0.0, size, size);
@@ -208,11 +212,11 @@ pub fn benchmark_function_vector_gpu_graph(
     config: &Configuration,
     names: Vec<String>,
     gpu_handles: &GPUHandles,
-    functions: &Vec<(
+    functions: &[(
         GraphFunction,
         fn(&GPUHandles, &Vec<GraphOperator>, usize, &mut Tensor2D),
-    )>,
-    all_measurements: &mut Vec<PerformanceMeasurements>,
+    )],
+    all_measurements: &mut [PerformanceMeasurements],
     measure_depth: bool,
 ) {
     assert!(functions.len() == all_measurements.len());
@@ -64,4 +64,106 @@ fn main_with_relu(
 
         output[output_index] = max(0.0, result + bias[output_index]);
     }
-}
\ No newline at end of file
+}
+
+// The tiled version stages a TILE_SIZE x TILE_DEPTH tile of the input and a
+// TILE_DEPTH x TILE_SIZE tile of the weights in workgroup memory at a time,
+// so every element loaded from global memory is reused by TILE_SIZE outputs.
+// On top of that every thread computes a THREAD_TILE x THREAD_TILE block of outputs,
+// keeping its results in registers and reusing every value it reads from the tiles.
+// A workgroup of THREAD_COUNT x THREAD_COUNT threads covers TILE_SIZE x TILE_SIZE outputs,
+// see LinearLayerKernel in tensor2d_gpu.rs for the launch.
+const TILE_SIZE: u32 = 32u;
+const TILE_DEPTH: u32 = 16u;
+const THREAD_COUNT: u32 = 16u;
+const THREAD_TILE: u32 = 2u;
+// TILE_SIZE * TILE_DEPTH elements, loaded by THREAD_COUNT * THREAD_COUNT threads
+const LOADS_PER_THREAD: u32 = 2u;
+
+var<workgroup> input_tile: array<f32, 512>;
+var<workgroup> weights_tile: array<f32, 512>;
+
+fn tiled_linear_layer(group_id: vec3<u32>, local_id: vec3<u32>, local_index: u32, with_relu: bool) {
+    let tile_row: u32 = group_id.x * TILE_SIZE;
+    let tile_column: u32 = group_id.y * TILE_SIZE;
+    let inner_count: u32 = dimensions.input_column_count;
+    let tile_count: u32 = (inner_count + TILE_DEPTH - 1u) / TILE_DEPTH;
+
+    var results: array<f32, 4>;
+    var input_values: array<f32, 2>;
+    var weights_values: array<f32, 2>;
+
+    for (var tile_index: u32 = 0u; tile_index < tile_count; tile_index += 1u) {
+        let inner_offset: u32 = tile_index * TILE_DEPTH;
+
+        // Neighbouring threads load neighbouring elements of both tiles.
+        // Anything outside of the tensors is loaded as 0 and doesn't change the results.
+        for (var load: u32 = 0u; load < LOADS_PER_THREAD; load += 1u) {
+            let element: u32 = load * THREAD_COUNT * THREAD_COUNT + local_index;
+
+            let input_row: u32 = tile_row + element / TILE_DEPTH;
+            let input_column: u32 = inner_offset + element % TILE_DEPTH;
+            var input_value: f32 = 0.0;
+            if (input_row < dimensions.input_row_count && input_column < inner_count) {
+                input_value = input[input_row * inner_count + input_column];
+            }
+            input_tile[element] = input_value;
+
+            let weights_row: u32 = inner_offset + element / TILE_SIZE;
+            let weights_column: u32 = tile_column + element % TILE_SIZE;
+            var weights_value: f32 = 0.0;
+            if (weights_row < dimensions.weights_row_count && weights_column < dimensions.weights_column_count) {
+                weights_value = weights[weights_row * dimensions.weights_column_count + weights_column];
+            }
+            weights_tile[element] = weights_value;
+        }
+        workgroupBarrier();
+
+        for (var inner: u32 = 0u; inner < TILE_DEPTH; inner += 1u) {
+            for (var index: u32 = 0u; index < THREAD_TILE; index += 1u) {
+                input_values[index] = input_tile[(local_id.x + index * THREAD_COUNT) * TILE_DEPTH + inner];
+                weights_values[index] = weights_tile[inner * TILE_SIZE + local_id.y + index * THREAD_COUNT];
+            }
+            for (var row: u32 = 0u; row < THREAD_TILE; row += 1u) {
+                for (var column: u32 = 0u; column < THREAD_TILE; column += 1u) {
+                    results[row * THREAD_TILE + column] += input_values[row] * weights_values[column];
+                }
+            }
+        }
+        // Nobody can start loading the next tiles before everyone is done with these
+        workgroupBarrier();
+    }
+
+    for (var row: u32 = 0u; row < THREAD_TILE; row += 1u) {
+        for (var column: u32 = 0u; column < THREAD_TILE; column += 1u) {
+            let output_row_index: u32 = tile_row + local_id.x + row * THREAD_COUNT;
+            let output_column_index: u32 = tile_column + local_id.y + column * THREAD_COUNT;
+            if (output_row_index < dimensions.output_row_count && output_column_index < dimensions.output_column_count) {
+                let output_index: u32 = output_row_index * dimensions.output_column_count + output_column_index;
+                var result: f32 = results[row * THREAD_TILE + column] + bias[output_index];
+                if (with_relu) {
+                    result = max(0.0, result);
+                }
+                output[output_index] = result;
+            }
+        }
+    }
+}
+
+@compute @workgroup_size(16, 16, 1)
+fn tiled(
+    @builtin(workgroup_id) group_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    ) {
+    tiled_linear_layer(group_id, local_id, local_index, false);
+}
+
+@compute @workgroup_size(16, 16, 1)
+fn tiled_with_relu(
+    @builtin(workgroup_id) group_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    ) {
+    tiled_linear_layer(group_id, local_id, local_index, true);
+}
@@ -0,0 +1,66 @@
+struct TensorDimensions {
+    input_row_count: u32,
+    input_column_count: u32,
+    weights_row_count: u32,
+    weights_column_count: u32,
+    output_gradient_row_count: u32,
+    output_gradient_column_count: u32,
+    output_row_count: u32,
+    output_column_count: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> dimensions: TensorDimensions;
+
+@group(0) @binding(1)
+var<storage, read> input: array<f32>;
+
+@group(0) @binding(2)
+var<storage, read> weights: array<f32>;
+
+@group(0) @binding(3)
+var<storage, read> output_gradient: array<f32>;
+
+@group(0) @binding(4)
+var<storage, read_write> input_gradient: array<f32>;
+
+@group(0) @binding(5)
+var<storage, read_write> weights_gradient: array<f32>;
+
+// input_gradient += output_gradient * weights^T
+// One thread per element of the input gradient
+@compute @workgroup_size(8, 8, 1) 
+fn backward_input(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    ) {
+    let input_row_index: u32 = global_id.x;
+    let input_column_index: u32 = global_id.y;
+
+    if (input_row_index < dimensions.input_row_count && input_column_index < dimensions.input_column_count) {
+        var result: f32 = 0.0;
+        for (var output_column_index: u32 = 0u; output_column_index < dimensions.output_column_count; output_column_index += 1u) {
+            result += output_gradient[input_row_index * dimensions.output_column_count + output_column_index] * weights[input_column_index * dimensions.weights_column_count + output_column_index];
+        }
+
+        input_gradient[input_row_index * dimensions.input_column_count + input_column_index] += result;
+    }
+}
+
+// weights_gradient += input^T * output_gradient
+// One thread per element of the weights gradient
+@compute @workgroup_size(8, 8, 1) 
+fn backward_weights(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    ) {
+    let weights_row_index: u32 = global_id.x;
+    let weights_column_index: u32 = global_id.y;
+
+    if (weights_row_index < dimensions.weights_row_count && weights_column_index < dimensions.weights_column_count) {
+        var result: f32 = 0.0;
+        for (var input_row_index: u32 = 0u; input_row_index < dimensions.input_row_count; input_row_index += 1u) {
+            result += input[input_row_index * dimensions.input_column_count + weights_row_index] * output_gradient[input_row_index * dimensions.output_column_count + weights_column_index];
+        }
+
+        weights_gradient[weights_row_index * dimensions.weights_column_count + weights_column_index] += result;
+    }
+}
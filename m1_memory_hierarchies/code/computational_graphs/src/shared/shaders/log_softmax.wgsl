@@ -0,0 +1,118 @@
+const BLOCK_SIZE: u32 = 32u;
+
+struct SoftmaxUniform {
+    element_count: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> softmax_uniform: SoftmaxUniform;
+
+@group(0) @binding(1)
+var<storage, read> input: array<f32>;
+
+@group(0) @binding(2)
+var<storage, read_write> output: array<f32>;
+
+@group(0) @binding(3)
+var<storage, read> output_gradient: array<f32>;
+
+@group(0) @binding(4)
+var<storage, read_write> global_sum: array<f32>;
+
+@group(0) @binding(5)
+var<storage, read_write> input_gradient: array<f32>;
+
+var<workgroup> shared_data: array<f32, BLOCK_SIZE>;
+var<workgroup> offset: f32;
+
+// output = input - (max + ln(sum(exp(input - max)))), with a single workgroup
+@compute @workgroup_size(32, 1, 1) 
+fn forward(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    ) {
+    let tid: u32 = local_id.x;
+    let element_count: u32 = softmax_uniform.element_count;
+
+    var max_value: f32 = -3.00282346638528859812e+37f;
+    var index: u32 = tid;
+    while (index < element_count) {
+        max_value = max(max_value, input[index]);
+        index += BLOCK_SIZE;
+    }
+    shared_data[tid] = max_value;
+    workgroupBarrier();
+
+    if (tid == 0u) {
+        var max_value: f32 = shared_data[0];
+        for (var index: u32 = 1u; index < BLOCK_SIZE; index++) {
+            max_value = max(max_value, shared_data[index]);
+        }
+        offset = max_value;
+    }
+    workgroupBarrier();
+
+    let global_max: f32 = offset;
+    var sum_value: f32 = 0.0;
+    index = tid;
+    while (index < element_count) {
+        sum_value += exp(input[index] - global_max);
+        index += BLOCK_SIZE;
+    }
+    workgroupBarrier();
+    shared_data[tid] = sum_value;
+    workgroupBarrier();
+
+    if (tid == 0u) {
+        var sum_value: f32 = 0.0;
+        for (var index: u32 = 0u; index < BLOCK_SIZE; index++) {
+            sum_value += shared_data[index];
+        }
+        offset = global_max + log(sum_value);
+    }
+    workgroupBarrier();
+
+    let final_offset: f32 = offset;
+    index = tid;
+    while (index < element_count) {
+        output[index] = input[index] - final_offset;
+        index += BLOCK_SIZE;
+    }
+}
+
+// sum(output_gradient), with a single workgroup
+@compute @workgroup_size(32, 1, 1) 
+fn single_pass_sum(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    ) {
+    let tid: u32 = local_id.x;
+    var sum_value: f32 = 0.0;
+    var index: u32 = tid;
+    while (index < softmax_uniform.element_count) {
+        sum_value += output_gradient[index];
+        index += BLOCK_SIZE;
+    }
+
+    shared_data[tid] = sum_value;
+    workgroupBarrier();
+
+    if (tid == 0u) {
+        var sum_value: f32 = 0.0;
+        for (var index: u32 = 0u; index < BLOCK_SIZE; index++) {
+            sum_value += shared_data[index];
+        }
+        global_sum[0] = sum_value;
+    }
+}
+
+// input_gradient += output_gradient - exp(output) * sum(output_gradient)
+@compute @workgroup_size(32, 1, 1) 
+fn backward(
+    @builtin(workgroup_id) group_id: vec3<u32>, 
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let index: u32 = group_id.x * BLOCK_SIZE + local_id.x;
+
+    if (index < softmax_uniform.element_count) {
+        input_gradient[index] += output_gradient[index] - exp(output[index]) * global_sum[0];
+    }
+}
@@ -0,0 +1,163 @@
+const BLOCK_SIZE: u32 = 32u;
+// Keeps the log finite when a probability underflows to 0
+const EPSILON: f32 = 1e-12;
+
+// Has to match the order of LossKind
+const CROSS_ENTROPY: u32 = 0u;
+const NEGATIVE_LOG_LIKELIHOOD: u32 = 1u;
+const MEAN_SQUARED_ERROR: u32 = 2u;
+const MEAN_ABSOLUTE_ERROR: u32 = 3u;
+
+// The means are taken over all total_element_count elements,
+// even when a dispatch only covers element_count of them
+struct LossUniform {
+    element_count: u32,
+    kind: u32,
+    total_element_count: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> loss_uniform: LossUniform;
+
+@group(0) @binding(1)
+var<storage, read> input: array<f32>;
+
+@group(0) @binding(2)
+var<storage, read> target_data: array<f32>;
+
+@group(0) @binding(3)
+var<storage, read_write> output: array<f32>;
+
+// The gradient of the loss with respect to the output, a single element
+@group(0) @binding(4)
+var<storage, read> output_gradient: array<f32>;
+
+@group(0) @binding(5)
+var<storage, read_write> global_target_sum: array<f32>;
+
+@group(0) @binding(6)
+var<storage, read_write> input_gradient: array<f32>;
+
+var<workgroup> shared_data: array<f32, BLOCK_SIZE>;
+
+// The contribution of a single element to the sum in the loss
+fn loss_term(index: u32) -> f32 {
+    let kind: u32 = loss_uniform.kind;
+    if (kind == CROSS_ENTROPY) {
+        return -target_data[index] * log(max(input[index], EPSILON));
+    } else if (kind == NEGATIVE_LOG_LIKELIHOOD) {
+        return -target_data[index] * input[index];
+    } else if (kind == MEAN_SQUARED_ERROR) {
+        let difference: f32 = input[index] - target_data[index];
+        return difference * difference;
+    }
+    return abs(input[index] - target_data[index]);
+}
+
+// The derivative of the loss with respect to a single element of the input
+fn loss_term_gradient(index: u32) -> f32 {
+    let kind: u32 = loss_uniform.kind;
+    let element_count: f32 = f32(loss_uniform.total_element_count);
+    if (kind == CROSS_ENTROPY) {
+        return -target_data[index] / max(input[index], EPSILON);
+    } else if (kind == NEGATIVE_LOG_LIKELIHOOD) {
+        return -target_data[index];
+    } else if (kind == MEAN_SQUARED_ERROR) {
+        return 2.0 * (input[index] - target_data[index]) / element_count;
+    }
+    // The gradient of |x| at 0 is taken to be 0, which sign does as well
+    return sign(input[index] - target_data[index]) / element_count;
+}
+
+// output[0] = the loss, with a single workgroup
+@compute @workgroup_size(32, 1, 1) 
+fn forward(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    ) {
+    let tid: u32 = local_id.x;
+    var elements_left: u32 = loss_uniform.element_count;
+    var index: u32 = tid;
+    var sum_value: f32 = 0.0;
+    while (BLOCK_SIZE < elements_left) {
+        sum_value += loss_term(index);
+        elements_left -= BLOCK_SIZE;
+        index += BLOCK_SIZE;
+    }
+    if(tid < elements_left) {
+        sum_value += loss_term(index);
+    }
+
+    shared_data[tid] = sum_value;
+    workgroupBarrier();
+
+    if (tid == 0u) {
+        var sum_value: f32 = 0.0;
+        var index: u32 = 0u;
+        while (index < BLOCK_SIZE) {
+            sum_value += shared_data[index];
+            index++;
+        }
+        if (MEAN_SQUARED_ERROR <= loss_uniform.kind) {
+            sum_value /= f32(loss_uniform.element_count);
+        }
+        output[0] = sum_value;
+    }
+}
+
+@compute @workgroup_size(32, 1, 1) 
+fn backward(
+    @builtin(workgroup_id) group_id: vec3<u32>, 
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let index: u32 = group_id.x * BLOCK_SIZE + local_id.x;
+
+    if (index < loss_uniform.element_count) {
+        input_gradient[index] += output_gradient[0] * loss_term_gradient(index);
+    }
+}
+
+// When the input of a cross entropy comes straight from a softmax, the gradient with respect to the
+// input of the softmax is output_gradient * (input * sum(target) - target),
+// which needs the sum of the target first
+@compute @workgroup_size(32, 1, 1) 
+fn single_pass_target_sum(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    ) {
+    let tid: u32 = local_id.x;
+    var elements_left: u32 = loss_uniform.element_count;
+    var index: u32 = tid;
+    var sum_value: f32 = 0.0;
+    while (BLOCK_SIZE < elements_left) {
+        sum_value += target_data[index];
+        elements_left -= BLOCK_SIZE;
+        index += BLOCK_SIZE;
+    }
+    if(tid < elements_left) {
+        sum_value += target_data[index];
+    }
+
+    shared_data[tid] = sum_value;
+    workgroupBarrier();
+
+    if (tid == 0u) {
+        var sum_value: f32 = 0.0;
+        var index: u32 = 0u;
+        while (index < BLOCK_SIZE) {
+            sum_value += shared_data[index];
+            index++;
+        }
+        global_target_sum[0] = sum_value;
+    }
+}
+
+@compute @workgroup_size(32, 1, 1) 
+fn softmax_backward(
+    @builtin(workgroup_id) group_id: vec3<u32>, 
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let index: u32 = group_id.x * BLOCK_SIZE + local_id.x;
+
+    if (index < loss_uniform.element_count) {
+        input_gradient[index] += output_gradient[0] * (input[index] * global_target_sum[0] - target_data[index]);
+    }
+}
@@ -0,0 +1,61 @@
+struct OptimizerSettings {
+    element_count: u32,
+    learning_rate: f32,
+    // The momentum for sgd_momentum, beta_1 for adam
+    first_decay: f32,
+    second_decay: f32,
+    epsilon: f32,
+    // 1 - beta_1^t and 1 - beta_2^t, computed on the CPU
+    first_correction: f32,
+    second_correction: f32,
+    padding: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> settings: OptimizerSettings;
+
+@group(0) @binding(1)
+var<storage, read> gradient: array<f32>;
+
+@group(0) @binding(2)
+var<storage, read_write> parameter: array<f32>;
+
+@group(0) @binding(3)
+var<storage, read_write> first_moment: array<f32>;
+
+@group(0) @binding(4)
+var<storage, read_write> second_moment: array<f32>;
+
+@compute @workgroup_size(32, 1, 1)
+fn sgd(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index: u32 = global_id.x;
+    if (index < settings.element_count) {
+        parameter[index] -= settings.learning_rate * gradient[index];
+    }
+}
+
+@compute @workgroup_size(32, 1, 1)
+fn sgd_momentum(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index: u32 = global_id.x;
+    if (index < settings.element_count) {
+        let velocity: f32 = settings.first_decay * first_moment[index] + gradient[index];
+        first_moment[index] = velocity;
+        parameter[index] -= settings.learning_rate * velocity;
+    }
+}
+
+@compute @workgroup_size(32, 1, 1)
+fn adam(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index: u32 = global_id.x;
+    if (index < settings.element_count) {
+        let current_gradient: f32 = gradient[index];
+        let first: f32 = settings.first_decay * first_moment[index] + (1.0 - settings.first_decay) * current_gradient;
+        let second: f32 = settings.second_decay * second_moment[index] + (1.0 - settings.second_decay) * current_gradient * current_gradient;
+        first_moment[index] = first;
+        second_moment[index] = second;
+
+        let first_corrected: f32 = first / settings.first_correction;
+        let second_corrected: f32 = second / settings.second_correction;
+        parameter[index] -= settings.learning_rate * first_corrected / (sqrt(second_corrected) + settings.epsilon);
+    }
+}
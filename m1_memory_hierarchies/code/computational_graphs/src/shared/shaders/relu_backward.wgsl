@@ -0,0 +1,30 @@
+struct TensorDimensions {
+    data_row_count: u32,
+    data_column_count: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> dimensions: TensorDimensions;
+
+@group(0) @binding(1)
+var<storage, read> input: array<f32>;
+
+@group(0) @binding(2)
+var<storage, read> output_gradient: array<f32>;
+
+@group(0) @binding(3)
+var<storage, read_write> input_gradient: array<f32>;
+
+// The gradient only flows through the elements which were positive
+@compute @workgroup_size(32, 1, 1) 
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let data_row_index: u32 = global_id.x;
+    let data_column_index: u32 = global_id.y;
+    
+    if (data_row_index < dimensions.data_row_count && data_column_index < dimensions.data_column_count) {
+        let index: u32 = data_row_index * dimensions.data_column_count + data_column_index;
+        if (0.0 < input[index]) {
+            input_gradient[index] += output_gradient[index];
+        }
+    }
+}
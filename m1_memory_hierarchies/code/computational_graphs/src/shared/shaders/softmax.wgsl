@@ -1,7 +1,15 @@
-const BLOCK_SIZE: u32 = 32u;
+// The max and the sum are both reduced in two passes, like in sum.wgsl.
+// Up to BLOCK_SIZE workgroups write their partial results to partials,
+// then a single workgroup reduces those to the global max or offset.
+// Has to match REDUCTION_BLOCK_SIZE in tensor2d_gpu.rs.
+const BLOCK_SIZE: u32 = 256u;
+// The map is elementwise and doesn't need workgroups as large
+const MAP_BLOCK_SIZE: u32 = 32u;
 
 struct SoftmaxUniform {
     element_count: u32,
+    // The number of workgroups launched for the partial passes, at most BLOCK_SIZE
+    block_count: u32,
 };
 
 @group(0) @binding(0)
@@ -19,88 +27,128 @@ var<storage, read_write> global_offset: array<f32>;
 @group(0) @binding(4)
 var<storage, read_write> output: array<f32>;
 
+@group(0) @binding(5)
+var<storage, read_write> partials: array<f32>;
+
 var<workgroup> shared_data: array<f32, BLOCK_SIZE>;
 
-@compute @workgroup_size(32, 1, 1) 
-fn single_pass_max(
-    @builtin(workgroup_id) group_id: vec3<u32>, 
+fn reduce_max(tid: u32) {
+    var stride: u32 = BLOCK_SIZE / 2u;
+    while (0u < stride) {
+        if (tid < stride) {
+            shared_data[tid] = max(shared_data[tid], shared_data[tid + stride]);
+        }
+        workgroupBarrier();
+        stride = stride / 2u;
+    }
+}
+
+fn reduce_sum(tid: u32) {
+    var stride: u32 = BLOCK_SIZE / 2u;
+    while (0u < stride) {
+        if (tid < stride) {
+            shared_data[tid] += shared_data[tid + stride];
+        }
+        workgroupBarrier();
+        stride = stride / 2u;
+    }
+}
+
+@compute @workgroup_size(256, 1, 1)
+fn partial_max(
+    @builtin(workgroup_id) group_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
     ) {
     let tid: u32 = local_id.x;
-    // In this first section we can use all 32 threads
-    var elements_left: u32 = softmax_uniform.element_count;
-    var i: u32 = tid;
+    let stride: u32 = softmax_uniform.block_count * BLOCK_SIZE;
+    var index: u32 = group_id.x * BLOCK_SIZE + tid;
     var max_value: f32 = -3.00282346638528859812e+37f;
-    // How do we handle the odd case?
-    while (BLOCK_SIZE < elements_left) {
-        max_value = max(max_value, input[i]);
-        elements_left -= BLOCK_SIZE;
-        i += BLOCK_SIZE;
-    }
-    if(tid < elements_left) {
-        max_value = max(max_value, input[i]);
+    while (index < softmax_uniform.element_count) {
+        max_value = max(max_value, input[index]);
+        index += stride;
     }
 
     shared_data[tid] = max_value;
     workgroupBarrier();
+    reduce_max(tid);
 
+    if (tid == 0u) {
+        partials[group_id.x] = shared_data[0];
+    }
+}
+
+// This function should only ever be launched for a single workgroup
+@compute @workgroup_size(256, 1, 1)
+fn final_max(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    ) {
+    let tid: u32 = local_id.x;
+    var max_value: f32 = -3.00282346638528859812e+37f;
+    if (tid < softmax_uniform.block_count) {
+        max_value = partials[tid];
+    }
+
+    shared_data[tid] = max_value;
+    workgroupBarrier();
+    reduce_max(tid);
 
     if (tid == 0u) {
-        var max_value: f32 = shared_data[0];
-        var index: u32 = 1u;
-        while (index < BLOCK_SIZE) {
-            max_value = max(max_value, shared_data[index]);
-            index++;
-        }
-        global_max[0] = max_value;
+        global_max[0] = shared_data[0];
     }
 }
 
-@compute @workgroup_size(32, 1, 1) 
-fn single_pass_sum(
-    @builtin(workgroup_id) group_id: vec3<u32>, 
+@compute @workgroup_size(256, 1, 1)
+fn partial_sum(
+    @builtin(workgroup_id) group_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
     ) {
     let tid: u32 = local_id.x;
-    // In this first section we can use all 32 threads
-    var elements_left: u32 = softmax_uniform.element_count;
-    var index: u32 = tid;
-    var sum_value: f32 = 0.0;
+    let stride: u32 = softmax_uniform.block_count * BLOCK_SIZE;
     let max_value: f32 = global_max[0];
-    // How do we handle the odd case?
-    while (BLOCK_SIZE < elements_left) {
-        sum_value += exp(input[index] - max_value);
-        elements_left -= BLOCK_SIZE;
-        index += BLOCK_SIZE;
-    }
-    if(tid < elements_left) {
+    var index: u32 = group_id.x * BLOCK_SIZE + tid;
+    var sum_value: f32 = 0.0;
+    while (index < softmax_uniform.element_count) {
         sum_value += exp(input[index] - max_value);
+        index += stride;
     }
 
     shared_data[tid] = sum_value;
     workgroupBarrier();
+    reduce_sum(tid);
 
+    if (tid == 0u) {
+        partials[group_id.x] = shared_data[0];
+    }
+}
+
+// This function should only ever be launched for a single workgroup
+@compute @workgroup_size(256, 1, 1)
+fn final_sum(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    ) {
+    let tid: u32 = local_id.x;
+    var sum_value: f32 = 0.0;
+    if (tid < softmax_uniform.block_count) {
+        sum_value = partials[tid];
+    }
+
+    shared_data[tid] = sum_value;
+    workgroupBarrier();
+    reduce_sum(tid);
 
     if (tid == 0u) {
-        var sum_value: f32 = 0.0;
-        var index: u32 = 0u;
-        while (index < BLOCK_SIZE) {
-            sum_value += shared_data[index];
-            index++;
-        }
-        global_offset[0] = max_value + log(sum_value);
+        global_offset[0] = global_max[0] + log(shared_data[0]);
     }
 }
 
-@compute @workgroup_size(32, 1, 1) 
+@compute @workgroup_size(32, 1, 1)
 fn map(
-    @builtin(workgroup_id) group_id: vec3<u32>, 
+    @builtin(workgroup_id) group_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
 ) {
-    let tid: u32 = local_id.x;
-    let index: u32 = group_id.x * BLOCK_SIZE + local_id.x;
+    let index: u32 = group_id.x * MAP_BLOCK_SIZE + local_id.x;
 
     if (index < softmax_uniform.element_count) {
         output[index] = exp(input[index] - global_offset[0]);
     }
-}
\ No newline at end of file
+}
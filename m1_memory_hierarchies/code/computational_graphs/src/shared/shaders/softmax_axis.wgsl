@@ -0,0 +1,131 @@
+const BLOCK_SIZE: u32 = 32u;
+
+// Every row (axis 1) or every column (axis 0) is a lane, and every lane gets
+// a workgroup of its own. Lane l starts at l * lane_stride and its elements
+// are element_stride apart, see tensor2d_softmax_axis::softmax_lanes.
+struct SoftmaxAxisUniform {
+    lane_count: u32,
+    lane_length: u32,
+    lane_stride: u32,
+    element_stride: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> softmax_uniform: SoftmaxAxisUniform;
+
+@group(0) @binding(1)
+var<storage, read> input: array<f32>;
+
+@group(0) @binding(2)
+var<storage, read_write> output: array<f32>;
+
+@group(0) @binding(3)
+var<storage, read> output_gradient: array<f32>;
+
+@group(0) @binding(4)
+var<storage, read_write> input_gradient: array<f32>;
+
+var<workgroup> shared_data: array<f32, BLOCK_SIZE>;
+
+// Halves the number of active threads every step,
+// so the result of the whole workgroup ends up in shared_data[0]
+fn reduce_max(tid: u32) {
+    var stride: u32 = BLOCK_SIZE / 2u;
+    while (0u < stride) {
+        if (tid < stride) {
+            shared_data[tid] = max(shared_data[tid], shared_data[tid + stride]);
+        }
+        workgroupBarrier();
+        stride = stride / 2u;
+    }
+}
+
+fn reduce_sum(tid: u32) {
+    var stride: u32 = BLOCK_SIZE / 2u;
+    while (0u < stride) {
+        if (tid < stride) {
+            shared_data[tid] += shared_data[tid + stride];
+        }
+        workgroupBarrier();
+        stride = stride / 2u;
+    }
+}
+
+fn lane_index(lane: u32, element: u32) -> u32 {
+    return lane * softmax_uniform.lane_stride + element * softmax_uniform.element_stride;
+}
+
+// output = exp(input - max) / sum(exp(input - max)), with the max and sum taken per lane
+@compute @workgroup_size(32, 1, 1)
+fn forward(
+    @builtin(workgroup_id) group_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let lane: u32 = group_id.x;
+    let tid: u32 = local_id.x;
+
+    var max_value: f32 = -3.00282346638528859812e+37f;
+    var element: u32 = tid;
+    while (element < softmax_uniform.lane_length) {
+        max_value = max(max_value, input[lane_index(lane, element)]);
+        element += BLOCK_SIZE;
+    }
+    shared_data[tid] = max_value;
+    workgroupBarrier();
+    reduce_max(tid);
+    let lane_max: f32 = shared_data[0];
+    // Everyone has to have read the max before shared_data is used for the sum
+    workgroupBarrier();
+
+    var sum_value: f32 = 0.0;
+    element = tid;
+    while (element < softmax_uniform.lane_length) {
+        let index: u32 = lane_index(lane, element);
+        let exponent: f32 = exp(input[index] - lane_max);
+        output[index] = exponent;
+        sum_value += exponent;
+        element += BLOCK_SIZE;
+    }
+    shared_data[tid] = sum_value;
+    workgroupBarrier();
+    reduce_sum(tid);
+    let lane_sum: f32 = shared_data[0];
+
+    // Every thread only divides the elements it wrote itself
+    element = tid;
+    while (element < softmax_uniform.lane_length) {
+        let index: u32 = lane_index(lane, element);
+        output[index] = output[index] / lane_sum;
+        element += BLOCK_SIZE;
+    }
+}
+
+// input_gradient += output * (output_gradient - sum(output * output_gradient)),
+// with the sum taken per lane
+@compute @workgroup_size(32, 1, 1)
+fn backward(
+    @builtin(workgroup_id) group_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let lane: u32 = group_id.x;
+    let tid: u32 = local_id.x;
+
+    var dot_value: f32 = 0.0;
+    var element: u32 = tid;
+    while (element < softmax_uniform.lane_length) {
+        let index: u32 = lane_index(lane, element);
+        dot_value += output[index] * output_gradient[index];
+        element += BLOCK_SIZE;
+    }
+    shared_data[tid] = dot_value;
+    workgroupBarrier();
+    reduce_sum(tid);
+    let lane_dot: f32 = shared_data[0];
+
+    element = tid;
+    while (element < softmax_uniform.lane_length) {
+        let index: u32 = lane_index(lane, element);
+        input_gradient[index] += output[index] * (output_gradient[index] - lane_dot);
+        element += BLOCK_SIZE;
+    }
+}
@@ -0,0 +1,67 @@
+const BLOCK_SIZE: u32 = 32u;
+
+struct SoftmaxUniform {
+    element_count: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> softmax_uniform: SoftmaxUniform;
+
+@group(0) @binding(1)
+var<storage, read> output: array<f32>;
+
+@group(0) @binding(2)
+var<storage, read> output_gradient: array<f32>;
+
+@group(0) @binding(3)
+var<storage, read_write> global_dot: array<f32>;
+
+@group(0) @binding(4)
+var<storage, read_write> input_gradient: array<f32>;
+
+var<workgroup> shared_data: array<f32, BLOCK_SIZE>;
+
+// sum(output * output_gradient), with a single workgroup just like the forward softmax
+@compute @workgroup_size(32, 1, 1) 
+fn single_pass_dot(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    ) {
+    let tid: u32 = local_id.x;
+    var elements_left: u32 = softmax_uniform.element_count;
+    var index: u32 = tid;
+    var dot_value: f32 = 0.0;
+    while (BLOCK_SIZE < elements_left) {
+        dot_value += output[index] * output_gradient[index];
+        elements_left -= BLOCK_SIZE;
+        index += BLOCK_SIZE;
+    }
+    if(tid < elements_left) {
+        dot_value += output[index] * output_gradient[index];
+    }
+
+    shared_data[tid] = dot_value;
+    workgroupBarrier();
+
+    if (tid == 0u) {
+        var dot_value: f32 = 0.0;
+        var index: u32 = 0u;
+        while (index < BLOCK_SIZE) {
+            dot_value += shared_data[index];
+            index++;
+        }
+        global_dot[0] = dot_value;
+    }
+}
+
+// input_gradient += output * (output_gradient - sum(output * output_gradient))
+@compute @workgroup_size(32, 1, 1) 
+fn map(
+    @builtin(workgroup_id) group_id: vec3<u32>, 
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let index: u32 = group_id.x * BLOCK_SIZE + local_id.x;
+
+    if (index < softmax_uniform.element_count) {
+        input_gradient[index] += output[index] * (output_gradient[index] - global_dot[0]);
+    }
+}
@@ -1,22 +1,27 @@
+use super::tensor_element::{FloatElement, TensorElement};
+
 // We won't enforce it in this tutorial
 // But it is assumed that all the active
 // data in the tensor is located in
 // indices 0 to row_count*column_count
+//
+// The element type defaults to f32 as that is what
+// the GPU side of things works with.
 #[derive(Clone, Debug, Default)]
-pub struct Tensor2D {
-    pub data: Vec<f32>,
+pub struct Tensor2D<T: TensorElement = f32> {
+    pub data: Vec<T>,
     pub row_count: usize,
     pub column_count: usize,
 }
 
-impl Tensor2D {
-    pub fn new(scale: f32, row_count: usize, column_count: usize) -> Self {
-        let mut data: Vec<f32> = Vec::<f32>::new();
+impl<T: TensorElement> Tensor2D<T> {
+    pub fn new(scale: T, row_count: usize, column_count: usize) -> Self {
+        let mut data: Vec<T> = Vec::<T>::new();
         // This might not be the best thing performance-wise,
         // but the efficiency of this function does not matter for
         // this tutorial.
         for index in 0..row_count * column_count {
-            data.push(index as f32 * scale);
+            data.push(T::from_f64(index as f64) * scale);
         }
 
         Tensor2D {
@@ -26,9 +31,14 @@ impl Tensor2D {
         }
     }
 
-    pub fn linear_layer(input: &Tensor2D, weights: &Tensor2D, bias: &Tensor2D) -> Tensor2D {
+    pub fn linear_layer(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+    ) -> Tensor2D<T> {
         // Create a matrix and set all initial values to 0.0
-        let mut output: Tensor2D = Tensor2D::new(0.0, input.row_count, weights.column_count);
+        let mut output: Tensor2D<T> =
+            Tensor2D::new(T::zero(), input.row_count, weights.column_count);
 
         Tensor2D::linear_layer_preallocated(input, weights, bias, &mut output);
         output
@@ -39,11 +49,11 @@ impl Tensor2D {
     }
 
     #[inline(always)]
-    fn linear_layer_assert(
-        input: &Tensor2D,
-        weights: &Tensor2D,
-        bias: &Tensor2D,
-        output: &mut Tensor2D,
+    pub(crate) fn linear_layer_assert(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
     ) {
         debug_assert!(
             0 < input.row_count,
@@ -98,11 +108,11 @@ impl Tensor2D {
     }
 
     #[inline(always)]
-    fn linear_relu_softmax_assert(
-        input: &Tensor2D,
-        weights: &Tensor2D,
-        bias: &Tensor2D,
-        output: &mut Tensor2D,
+    pub(crate) fn linear_relu_softmax_assert(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
     ) {
         debug_assert!(
             0 < input.row_count,
@@ -152,10 +162,10 @@ impl Tensor2D {
     }
 
     pub fn linear_layer_preallocated(
-        input: &Tensor2D,
-        weights: &Tensor2D,
-        bias: &Tensor2D,
-        output: &mut Tensor2D,
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
     ) {
         Self::linear_layer_assert(input, weights, bias, output);
 
@@ -182,10 +192,10 @@ impl Tensor2D {
 
     #[inline(always)]
     pub fn linear_layer_preallocated_inline(
-        input: &Tensor2D,
-        weights: &Tensor2D,
-        bias: &Tensor2D,
-        output: &mut Tensor2D,
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
     ) {
         Self::linear_layer_assert(input, weights, bias, output);
 
@@ -211,16 +221,16 @@ impl Tensor2D {
     }
 
     pub fn linear_layer_local_accumulation(
-        input: &Tensor2D,
-        weights: &Tensor2D,
-        bias: &Tensor2D,
-        output: &mut Tensor2D,
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
     ) {
         Self::linear_layer_assert(input, weights, bias, output);
 
         for row_output in 0..output.row_count {
             for column_output in 0..output.column_count {
-                let mut result: f32 = 0.0;
+                let mut result: T = T::zero();
                 for inner_dimension in 0..input.column_count {
                     result += input.data[row_output * input.column_count + inner_dimension]
                         * weights.data[inner_dimension * weights.column_count + column_output];
@@ -238,21 +248,21 @@ impl Tensor2D {
     }
 
     pub fn linear_layer_optimized(
-        input: &Tensor2D,
-        weights: &Tensor2D,
-        bias: &Tensor2D,
-        output: &mut Tensor2D,
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
     ) {
         Self::linear_layer_assert(input, weights, bias, output);
 
         for row_output in 0..output.row_count {
             for column_output in 0..output.column_count {
-                let mut result: f32 = 0.0;
-                let mut index_input: usize = row_output * input.column_count;
+                let mut result: T = T::zero();
                 let mut index_weights: usize = column_output;
-                for _ in 0..input.column_count {
+                for index_input in
+                    (row_output * input.column_count)..((row_output + 1) * input.column_count)
+                {
                     result += input.data[index_input] * weights.data[index_weights];
-                    index_input += 1;
                     index_weights += weights.column_count;
                 }
 
@@ -263,76 +273,218 @@ impl Tensor2D {
         }
     }
 
-    pub fn relu(x: &Tensor2D) -> Tensor2D {
+    pub fn relu(x: &Tensor2D<T>) -> Tensor2D<T> {
         // Create a matrix and set all initial values to 0.0
-        let mut out: Tensor2D = Tensor2D::new(0.0, x.row_count, x.column_count);
+        let mut out: Tensor2D<T> = Tensor2D::new(T::zero(), x.row_count, x.column_count);
 
         Self::relu_preallocated(x, &mut out);
 
         out
     }
 
-    pub fn relu_preallocated(input: &Tensor2D, output: &mut Tensor2D) {
+    pub fn relu_preallocated(input: &Tensor2D<T>, output: &mut Tensor2D<T>) {
         for index in 0..(output.column_count * output.row_count) {
-            output.data[index] = input.data[index].max(0.0);
+            output.data[index] = input.data[index].max(T::zero());
         }
     }
 
-    pub fn relu_inplace(data: &mut Tensor2D) {
+    pub fn relu_inplace(data: &mut Tensor2D<T>) {
         for index in 0..(data.column_count * data.row_count) {
-            data.data[index] = data.data[index].max(0.0);
+            data.data[index] = data.data[index].max(T::zero());
         }
     }
 
     #[inline(always)]
-    pub fn relu_inplace_inline(data: &mut Tensor2D) {
+    pub fn relu_inplace_inline(data: &mut Tensor2D<T>) {
         for index in 0..(data.column_count * data.row_count) {
-            data.data[index] = data.data[index].max(0.0);
+            data.data[index] = data.data[index].max(T::zero());
+        }
+    }
+
+    #[inline]
+    pub fn linear_layer_local_accumulation_relu(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
+    ) {
+        Self::linear_layer_assert(input, weights, bias, output);
+
+        for row_output in 0..output.row_count {
+            for column_output in 0..output.column_count {
+                let mut result: T = T::zero();
+                let mut index_weights: usize = column_output;
+                for index_input in
+                    (row_output * input.column_count)..((row_output + 1) * input.column_count)
+                {
+                    result += input.data[index_input] * weights.data[index_weights];
+                    index_weights += weights.column_count;
+                }
+                output.data[row_output * output.column_count + column_output] = result;
+            }
+        }
+
+        for index in 0..(bias.row_count * bias.column_count) {
+            output.data[index] = (output.data[index] + bias.data[index]).max(T::zero());
+        }
+    }
+
+    #[inline]
+    pub fn linear_layer_optimized_relu(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
+    ) {
+        Self::linear_layer_assert(input, weights, bias, output);
+
+        for row_output in 0..output.row_count {
+            for column_output in 0..output.column_count {
+                let mut result: T = T::zero();
+                let mut index_weights: usize = column_output;
+                for index_input in
+                    (row_output * input.column_count)..((row_output + 1) * input.column_count)
+                {
+                    result += input.data[index_input] * weights.data[index_weights];
+                    index_weights += weights.column_count;
+                }
+
+                let index: usize = row_output * output.column_count + column_output;
+                output.data[index] = (result + bias.data[index]).max(T::zero());
+            }
+        }
+    }
+
+    pub fn add_preallocated(left: &Tensor2D<T>, right: &Tensor2D<T>, output: &mut Tensor2D<T>) {
+        debug_assert_eq!(left.len(), right.len());
+        debug_assert_eq!(left.len(), output.len());
+
+        for index in 0..(output.column_count * output.row_count) {
+            output.data[index] = left.data[index] + right.data[index];
+        }
+    }
+
+    pub fn add_relu_preallocated(
+        left: &Tensor2D<T>,
+        right: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
+    ) {
+        debug_assert_eq!(left.len(), right.len());
+        debug_assert_eq!(left.len(), output.len());
+
+        for index in 0..(output.column_count * output.row_count) {
+            output.data[index] = (left.data[index] + right.data[index]).max(T::zero());
+        }
+    }
+
+    // Axis 0 stacks the inputs on top of each other, which in row-major
+    // is just copying them one after the other. Axis 1 places them side by side,
+    // so every row of the output is made from one row of each input.
+    pub fn concat_preallocated(inputs: &[&Tensor2D<T>], axis: usize, output: &mut Tensor2D<T>) {
+        if axis == 0 {
+            let mut output_index: usize = 0;
+            for input in inputs {
+                debug_assert_eq!(input.column_count, output.column_count);
+                let element_count: usize = input.row_count * input.column_count;
+                output.data[output_index..(output_index + element_count)]
+                    .copy_from_slice(&input.data[0..element_count]);
+                output_index += element_count;
+            }
+        } else {
+            for row_index in 0..output.row_count {
+                let mut output_index: usize = row_index * output.column_count;
+                for input in inputs {
+                    debug_assert_eq!(input.row_count, output.row_count);
+                    let input_index: usize = row_index * input.column_count;
+                    output.data[output_index..(output_index + input.column_count)]
+                        .copy_from_slice(
+                            &input.data[input_index..(input_index + input.column_count)],
+                        );
+                    output_index += input.column_count;
+                }
+            }
+        }
+    }
+
+    // Just for testing
+    #[inline(always)]
+    pub fn subtraction(left: &Tensor2D<T>, right: &Tensor2D<T>) -> Tensor2D<T> {
+        debug_assert_eq!(left.len(), right.len());
+
+        let mut output: Tensor2D<T> = Tensor2D::new(T::zero(), left.row_count, left.column_count);
+        for index in 0..(left.column_count * left.row_count) {
+            output.data[index] = left.data[index] - right.data[index];
+        }
+
+        output
+    }
+
+    // Just for testing.
+    // Get the sum of all active elements
+    // Mostly for verifying correctness
+    pub fn sum(&self) -> T {
+        let mut sum: T = T::zero();
+        for index in 0..self.row_count * self.column_count {
+            sum += self.data[index];
+        }
+
+        sum
+    }
+
+    // Mostly for verifying correctness, such as comparing
+    // results computed in f32 against an f64 reference.
+    pub fn convert<U: TensorElement>(&self) -> Tensor2D<U> {
+        Tensor2D {
+            data: self.data.iter().map(|x| U::from_f64(x.to_f64())).collect(),
+            row_count: self.row_count,
+            column_count: self.column_count,
         }
     }
+}
 
-    pub fn softmax(input: &Tensor2D) -> Tensor2D {
-        let mut output: Tensor2D = Tensor2D::new(0.0, input.row_count, input.column_count);
+impl<T: FloatElement> Tensor2D<T> {
+    pub fn softmax(input: &Tensor2D<T>) -> Tensor2D<T> {
+        let mut output: Tensor2D<T> = Tensor2D::new(T::zero(), input.row_count, input.column_count);
 
         Self::softmax_preallocated(input, &mut output);
 
         output
     }
 
-    pub fn softmax_preallocated(input: &Tensor2D, output: &mut Tensor2D) {
-        let mut max: f32 = f32::NEG_INFINITY;
+    pub fn softmax_preallocated(input: &Tensor2D<T>, output: &mut Tensor2D<T>) {
+        let mut max: T = T::neg_infinity();
         for index in 0..(output.column_count * output.row_count) {
             if max < input.data[index] {
                 max = input.data[index];
             }
         }
 
-        let mut sum: f32 = 0.0;
+        let mut sum: T = T::zero();
         for index in 0..(output.column_count * output.row_count) {
             sum += (input.data[index] - max).exp();
         }
 
-        let offset: f32 = max + sum.ln();
+        let offset: T = max + sum.ln();
 
         for index in 0..(output.column_count * output.row_count) {
             output.data[index] = (input.data[index] - offset).exp();
         }
     }
 
-    pub fn softmax_inplace(out: &mut Tensor2D) {
-        let mut max: f32 = f32::NEG_INFINITY;
+    pub fn softmax_inplace(out: &mut Tensor2D<T>) {
+        let mut max: T = T::neg_infinity();
         for index in 0..(out.column_count * out.row_count) {
             if max < out.data[index] {
                 max = out.data[index];
             }
         }
 
-        let mut sum: f32 = 0.0;
+        let mut sum: T = T::zero();
         for index in 0..(out.column_count * out.row_count) {
             sum += (out.data[index] - max).exp();
         }
 
-        let offset: f32 = max + sum.ln();
+        let offset: T = max + sum.ln();
 
         for index in 0..(out.column_count * out.row_count) {
             out.data[index] = (out.data[index] - offset).exp();
@@ -340,121 +492,91 @@ impl Tensor2D {
     }
 
     #[inline(always)]
-    pub fn softmax_inplace_inline(out: &mut Tensor2D) {
-        let mut max: f32 = f32::NEG_INFINITY;
+    pub fn softmax_inplace_inline(out: &mut Tensor2D<T>) {
+        let mut max: T = T::neg_infinity();
         for index in 0..(out.column_count * out.row_count) {
             if max < out.data[index] {
                 max = out.data[index];
             }
         }
 
-        let mut sum: f32 = 0.0;
+        let mut sum: T = T::zero();
         for index in 0..(out.column_count * out.row_count) {
             sum += (out.data[index] - max).exp();
         }
 
-        let offset: f32 = max + sum.ln();
+        let offset: T = max + sum.ln();
 
         for index in 0..(out.column_count * out.row_count) {
             out.data[index] = (out.data[index] - offset).exp();
         }
     }
 
-    #[inline]
-    pub fn linear_layer_local_accumulation_relu(
-        input: &Tensor2D,
-        weights: &Tensor2D,
-        bias: &Tensor2D,
-        output: &mut Tensor2D,
-    ) {
-        Self::linear_layer_assert(input, weights, bias, output);
+    // Only one exp() per element instead of two. The exponentials are
+    // stored directly in the output and scaled with the reciprocal of
+    // the sum afterwards.
+    pub fn softmax_optimized(out: &mut Tensor2D<T>) {
+        let element_count: usize = out.column_count * out.row_count;
 
-        for row_output in 0..output.row_count {
-            for column_output in 0..output.column_count {
-                let mut result: f32 = 0.0;
-                let mut index_input: usize = row_output * input.column_count;
-                let mut index_weights: usize = column_output;
-                for _ in 0..input.column_count {
-                    result += input.data[index_input] * weights.data[index_weights];
-                    index_input += 1;
-                    index_weights += weights.column_count;
-                }
-                output.data[row_output * output.column_count + column_output] = result;
-            }
+        let mut max: T = T::neg_infinity();
+        for index in 0..element_count {
+            max = max.max(out.data[index]);
         }
 
-        for index in 0..(bias.row_count * bias.column_count) {
-            output.data[index] = (output.data[index] + bias.data[index]).max(0.0);
+        let mut sum: T = T::zero();
+        for index in 0..element_count {
+            let exponential: T = (out.data[index] - max).exp();
+            out.data[index] = exponential;
+            sum += exponential;
         }
-    }
-
-    #[inline]
-    pub fn linear_layer_optimized_relu(
-        input: &Tensor2D,
-        weights: &Tensor2D,
-        bias: &Tensor2D,
-        output: &mut Tensor2D,
-    ) {
-        Self::linear_layer_assert(input, weights, bias, output);
-
-        for row_output in 0..output.row_count {
-            for column_output in 0..output.column_count {
-                let mut result: f32 = 0.0;
-                let mut index_input: usize = row_output * input.column_count;
-                let mut index_weights: usize = column_output;
-                for _ in 0..input.column_count {
-                    result += input.data[index_input] * weights.data[index_weights];
-                    index_input += 1;
-                    index_weights += weights.column_count;
-                }
 
-                let index: usize = row_output * output.column_count + column_output;
-                output.data[index] = (result + bias.data[index]).max(0.0);
-            }
+        let reciprocal: T = T::from_f64(1.0) / sum;
+        for index in 0..element_count {
+            out.data[index] = out.data[index] * reciprocal;
         }
     }
 
     // Maybe just inline
     #[inline]
     pub fn linear_relu_softmax_fused_fission(
-        input: &Tensor2D,
-        weights: &Tensor2D,
-        bias: &Tensor2D,
-        output: &mut Tensor2D,
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
     ) {
         Self::linear_relu_softmax_assert(input, weights, bias, output);
 
         for row_output in 0..output.row_count {
             for column_output in 0..output.column_count {
-                let mut result: f32 = 0.0;
-                let mut index_input: usize = row_output * input.column_count;
+                let mut result: T = T::zero();
                 let mut index_weights: usize = column_output;
-                for _ in 0..input.column_count {
+                for index_input in
+                    (row_output * input.column_count)..((row_output + 1) * input.column_count)
+                {
                     result += input.data[index_input] * weights.data[index_weights];
-                    index_input += 1;
                     index_weights += weights.column_count;
                 }
 
                 // TODO: Try this with bias fissioned
                 let index: usize = row_output * output.column_count + column_output;
-         
+
                 output.data[index] = result;
             }
         }
 
-        let mut max: f32 = f32::NEG_INFINITY;
+        let mut max: T = T::neg_infinity();
         for index in 0..(bias.row_count * bias.column_count) {
-            let result: f32 = (output.data[index] + bias.data[index]).max(0.0);
+            let result: T = (output.data[index] + bias.data[index]).max(T::zero());
             max = max.max(result);
             output.data[index] = result;
         }
 
-        let mut sum: f32 = 0.0;
+        let mut sum: T = T::zero();
         for index in 0..(output.column_count * output.row_count) {
             sum += (output.data[index] - max).exp();
         }
 
-        let offset: f32 = max + sum.ln();
+        let offset: T = max + sum.ln();
 
         for index in 0..(output.column_count * output.row_count) {
             output.data[index] = (output.data[index] - offset).exp();
@@ -464,67 +586,42 @@ impl Tensor2D {
     // Maybe just inline
     #[inline]
     pub fn linear_relu_softmax_fused(
-        input: &Tensor2D,
-        weights: &Tensor2D,
-        bias: &Tensor2D,
-        output: &mut Tensor2D,
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
     ) {
         Self::linear_relu_softmax_assert(input, weights, bias, output);
 
-        let mut max: f32 = f32::NEG_INFINITY;
+        let mut max: T = T::neg_infinity();
         for row_output in 0..output.row_count {
             for column_output in 0..output.column_count {
-                let mut result: f32 = 0.0;
-                let mut index_input: usize = row_output * input.column_count;
+                let mut result: T = T::zero();
                 let mut index_weights: usize = column_output;
-                for _ in 0..input.column_count {
+                for index_input in
+                    (row_output * input.column_count)..((row_output + 1) * input.column_count)
+                {
                     result += input.data[index_input] * weights.data[index_weights];
-                    index_input += 1;
                     index_weights += weights.column_count;
                 }
 
                 let index: usize = row_output * output.column_count + column_output;
-                result = (result + bias.data[index]).max(0.0);
+                result = (result + bias.data[index]).max(T::zero());
                 max = max.max(result);
 
                 output.data[index] = result;
             }
         }
 
-        let mut sum: f32 = 0.0;
+        let mut sum: T = T::zero();
         for index in 0..(output.column_count * output.row_count) {
             sum += (output.data[index] - max).exp();
         }
 
-        let offset: f32 = max + sum.ln();
+        let offset: T = max + sum.ln();
 
         for index in 0..(output.column_count * output.row_count) {
             output.data[index] = (output.data[index] - offset).exp();
         }
     }
-
-    // Just for testing
-    #[inline(always)]
-    pub fn subtraction(left: &Tensor2D, right: &Tensor2D) -> Tensor2D {
-        debug_assert_eq!(left.len(), right.len());
-
-        let mut output: Tensor2D = Tensor2D::new(0.0, left.row_count, left.column_count);
-        for index in 0..(left.column_count * left.row_count) {
-            output.data[index] = left.data[index] - right.data[index];
-        }
-
-        output
-    }
-
-    // Just for testing.
-    // Get the sum of all active elements
-    // Mostly for verifying correctness
-    pub fn sum(&self) -> f32 {
-        let mut sum: f32 = 0.0;
-        for index in 0..self.row_count * self.column_count {
-            sum += self.data[index];
-        }
-
-        sum
-    }
 }
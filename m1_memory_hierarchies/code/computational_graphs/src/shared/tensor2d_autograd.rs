@@ -0,0 +1,179 @@
+use super::tensor2d::Tensor2D;
+use super::tensor_element::{FloatElement, TensorElement};
+
+// The backward versions of the operators, used to train a graph.
+// Each function gets the gradient of the loss with respect to the output of the
+// operator, output_gradient, and computes the gradient with respect to its inputs.
+//
+// The gradients are added to, not overwritten. A tensor read by several nodes,
+// like the input of a residual block, gets a contribution from each of them,
+// and adding them all up is exactly what the chain rule asks for.
+// So the gradients have to be zeroed before every backward pass.
+//
+// Softmax without an axis normalizes over every element of the tensor,
+// not per row, so the backward versions do the same. Softmax along
+// an axis is in tensor2d_softmax_axis.
+
+// Keeps the log of cross entropy finite when a probability underflows to 0
+const CROSS_ENTROPY_EPSILON: f64 = 1e-12;
+
+impl<T: TensorElement> Tensor2D<T> {
+    pub fn accumulate(gradient: &Tensor2D<T>, accumulated: &mut Tensor2D<T>) {
+        debug_assert_eq!(
+            (gradient.row_count, gradient.column_count),
+            (accumulated.row_count, accumulated.column_count),
+            "\nThe gradient and the accumulated gradient need the same shape."
+        );
+
+        for (accumulated, gradient) in accumulated.data[0..gradient.len()]
+            .iter_mut()
+            .zip(gradient.data[0..gradient.len()].iter())
+        {
+            *accumulated += *gradient;
+        }
+    }
+
+    // output = input * weights + bias, which means
+    // input_gradient = output_gradient * weights^T
+    // weights_gradient = input^T * output_gradient
+    // bias_gradient = output_gradient, the bias has the shape of the output
+    pub fn linear_layer_backward(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        output_gradient: &Tensor2D<T>,
+        input_gradient: &mut Tensor2D<T>,
+        weights_gradient: &mut Tensor2D<T>,
+        bias_gradient: &mut Tensor2D<T>,
+    ) {
+        // The bias gradient has the shape of the output, just like the bias
+        Self::linear_layer_assert(input, weights, output_gradient, bias_gradient);
+        debug_assert_eq!(
+            (input.row_count, input.column_count),
+            (input_gradient.row_count, input_gradient.column_count)
+        );
+        debug_assert_eq!(
+            (weights.row_count, weights.column_count),
+            (weights_gradient.row_count, weights_gradient.column_count)
+        );
+
+        let row_count: usize = output_gradient.row_count;
+        let column_count: usize = output_gradient.column_count;
+        let inner_dimension: usize = input.column_count;
+
+        // Both rows are contiguous, so this is a dot product per element
+        for row in 0..row_count {
+            let gradient_row: &[T] =
+                &output_gradient.data[(row * column_count)..((row + 1) * column_count)];
+            for inner in 0..inner_dimension {
+                let weights_row: &[T] =
+                    &weights.data[(inner * column_count)..((inner + 1) * column_count)];
+                let mut result: T = T::zero();
+                for (gradient, weight) in gradient_row.iter().zip(weights_row.iter()) {
+                    result += *gradient * *weight;
+                }
+                input_gradient.data[row * inner_dimension + inner] += result;
+            }
+        }
+
+        // Every row of the input adds its outer product with the matching
+        // row of the output gradient, which keeps every access contiguous.
+        for row in 0..row_count {
+            let gradient_row: &[T] =
+                &output_gradient.data[(row * column_count)..((row + 1) * column_count)];
+            for inner in 0..inner_dimension {
+                let input_value: T = input.data[row * inner_dimension + inner];
+                let weights_gradient_row: &mut [T] = &mut weights_gradient.data
+                    [(inner * column_count)..((inner + 1) * column_count)];
+                for (weight_gradient, gradient) in
+                    weights_gradient_row.iter_mut().zip(gradient_row.iter())
+                {
+                    *weight_gradient += input_value * *gradient;
+                }
+            }
+        }
+
+        Self::accumulate(output_gradient, bias_gradient);
+    }
+
+    // The gradient only flows through the elements which were positive
+    pub fn relu_backward(
+        input: &Tensor2D<T>,
+        output_gradient: &Tensor2D<T>,
+        input_gradient: &mut Tensor2D<T>,
+    ) {
+        for index in 0..output_gradient.len() {
+            if T::zero() < input.data[index] {
+                input_gradient.data[index] += output_gradient.data[index];
+            }
+        }
+    }
+}
+
+impl<T: FloatElement> Tensor2D<T> {
+    // The Jacobian of softmax is diag(output) - output * output^T,
+    // multiplied with the output gradient that becomes
+    // input_gradient = output * (output_gradient - sum(output_gradient * output))
+    pub fn softmax_backward(
+        output: &Tensor2D<T>,
+        output_gradient: &Tensor2D<T>,
+        input_gradient: &mut Tensor2D<T>,
+    ) {
+        let mut dot: T = T::zero();
+        for index in 0..output.len() {
+            dot += output.data[index] * output_gradient.data[index];
+        }
+
+        for index in 0..output.len() {
+            input_gradient.data[index] += output.data[index] * (output_gradient.data[index] - dot);
+        }
+    }
+
+    // -sum(target * ln(input)), where the input is a probability distribution,
+    // usually the output of a softmax.
+    pub fn cross_entropy(input: &Tensor2D<T>, target: &Tensor2D<T>) -> T {
+        let epsilon: T = T::from_f64(CROSS_ENTROPY_EPSILON);
+        let mut loss: T = T::zero();
+        for index in 0..input.len() {
+            loss += target.data[index] * input.data[index].max(epsilon).ln();
+        }
+
+        T::zero() - loss
+    }
+
+    // loss_gradient is the gradient of the final loss with respect to the cross entropy,
+    // which is 1 unless something is done to the loss afterwards.
+    pub fn cross_entropy_backward(
+        input: &Tensor2D<T>,
+        target: &Tensor2D<T>,
+        loss_gradient: T,
+        input_gradient: &mut Tensor2D<T>,
+    ) {
+        let epsilon: T = T::from_f64(CROSS_ENTROPY_EPSILON);
+        for index in 0..input.len() {
+            input_gradient.data[index] +=
+                T::zero() - loss_gradient * target.data[index] / input.data[index].max(epsilon);
+        }
+    }
+
+    // Going through cross_entropy_backward and softmax_backward one after the other
+    // divides by the probabilities, just to multiply by them again, which blows up
+    // for tiny probabilities. Done in one step it is just
+    // softmax_input_gradient = softmax_output * sum(target) - target,
+    // which is softmax_output - target when the target sums to 1.
+    pub fn softmax_cross_entropy_backward(
+        softmax_output: &Tensor2D<T>,
+        target: &Tensor2D<T>,
+        loss_gradient: T,
+        softmax_input_gradient: &mut Tensor2D<T>,
+    ) {
+        let mut target_sum: T = T::zero();
+        for index in 0..target.len() {
+            target_sum += target.data[index];
+        }
+
+        for index in 0..softmax_output.len() {
+            softmax_input_gradient.data[index] +=
+                loss_gradient * (softmax_output.data[index] * target_sum - target.data[index]);
+        }
+    }
+}
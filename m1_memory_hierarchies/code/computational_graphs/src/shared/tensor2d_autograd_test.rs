@@ -0,0 +1,197 @@
+#[cfg(test)]
+mod tests {
+    use crate::shared::tensor2d::Tensor2D;
+
+    // The gradients are checked against central differences,
+    // which are done in f64 so the check itself is accurate.
+    const STEP: f64 = 0.000001;
+    const ERROR_TOLERANCE: f64 = 0.00001;
+
+    // Values with both signs and no two alike
+    fn test_tensor(offset: f64, row_count: usize, column_count: usize) -> Tensor2D<f64> {
+        let mut tensor: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, row_count, column_count);
+        for (index, value) in tensor.data.iter_mut().enumerate() {
+            *value = ((index as f64 + offset) * 1.7).sin();
+        }
+        tensor
+    }
+
+    // The gradient of loss at tensor, one element at a time
+    fn numerical_gradient(
+        tensor: &Tensor2D<f64>,
+        loss: impl Fn(&Tensor2D<f64>) -> f64,
+    ) -> Tensor2D<f64> {
+        let mut gradient: Tensor2D<f64> =
+            Tensor2D::<f64>::new(0.0, tensor.row_count, tensor.column_count);
+        let mut shifted: Tensor2D<f64> = tensor.clone();
+        for index in 0..tensor.len() {
+            shifted.data[index] = tensor.data[index] + STEP;
+            let above: f64 = loss(&shifted);
+            shifted.data[index] = tensor.data[index] - STEP;
+            let below: f64 = loss(&shifted);
+            shifted.data[index] = tensor.data[index];
+            gradient.data[index] = (above - below) / (2.0 * STEP);
+        }
+        gradient
+    }
+
+    fn assert_close(expected: &Tensor2D<f64>, actual: &Tensor2D<f64>) {
+        assert_eq!(
+            (expected.row_count, expected.column_count),
+            (actual.row_count, actual.column_count)
+        );
+        for (expected, actual) in expected.data.iter().zip(actual.data.iter()) {
+            assert!(
+                (expected - actual).abs() < ERROR_TOLERANCE,
+                "expected {} but got {}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    // Weights every element of the output differently, so
+    // a gradient in the wrong place doesn't go unnoticed
+    fn weighted_sum(tensor: &Tensor2D<f64>, weights: &Tensor2D<f64>) -> f64 {
+        tensor
+            .data
+            .iter()
+            .zip(weights.data.iter())
+            .map(|(value, weight)| value * weight)
+            .sum()
+    }
+
+    #[test]
+    fn linear_layer_backward() {
+        for (row_count, inner_dimension, column_count) in [(1, 1, 1), (3, 4, 5), (6, 2, 3)] {
+            let input: Tensor2D<f64> = test_tensor(0.0, row_count, inner_dimension);
+            let weights: Tensor2D<f64> = test_tensor(1.0, inner_dimension, column_count);
+            let bias: Tensor2D<f64> = test_tensor(2.0, row_count, column_count);
+            let output_gradient: Tensor2D<f64> = test_tensor(3.0, row_count, column_count);
+
+            let mut input_gradient: Tensor2D<f64> =
+                Tensor2D::<f64>::new(0.0, row_count, inner_dimension);
+            let mut weights_gradient: Tensor2D<f64> =
+                Tensor2D::<f64>::new(0.0, inner_dimension, column_count);
+            let mut bias_gradient: Tensor2D<f64> =
+                Tensor2D::<f64>::new(0.0, row_count, column_count);
+            Tensor2D::<f64>::linear_layer_backward(
+                &input,
+                &weights,
+                &output_gradient,
+                &mut input_gradient,
+                &mut weights_gradient,
+                &mut bias_gradient,
+            );
+
+            let loss = |input: &Tensor2D<f64>, weights: &Tensor2D<f64>, bias: &Tensor2D<f64>| {
+                weighted_sum(
+                    &Tensor2D::<f64>::linear_layer(input, weights, bias),
+                    &output_gradient,
+                )
+            };
+            assert_close(
+                &numerical_gradient(&input, |input| loss(input, &weights, &bias)),
+                &input_gradient,
+            );
+            assert_close(
+                &numerical_gradient(&weights, |weights| loss(&input, weights, &bias)),
+                &weights_gradient,
+            );
+            assert_close(
+                &numerical_gradient(&bias, |bias| loss(&input, &weights, bias)),
+                &bias_gradient,
+            );
+        }
+    }
+
+    #[test]
+    fn relu_backward() {
+        let input: Tensor2D<f64> = test_tensor(0.5, 3, 7);
+        let output_gradient: Tensor2D<f64> = test_tensor(5.0, 3, 7);
+
+        let mut input_gradient: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 3, 7);
+        Tensor2D::<f64>::relu_backward(&input, &output_gradient, &mut input_gradient);
+
+        let expected: Tensor2D<f64> = numerical_gradient(&input, |input| {
+            weighted_sum(&Tensor2D::<f64>::relu(input), &output_gradient)
+        });
+        assert_close(&expected, &input_gradient);
+    }
+
+    #[test]
+    fn softmax_backward() {
+        let input: Tensor2D<f64> = test_tensor(0.0, 4, 3);
+        let output: Tensor2D<f64> = Tensor2D::<f64>::softmax(&input);
+        let output_gradient: Tensor2D<f64> = test_tensor(5.0, 4, 3);
+
+        let mut input_gradient: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 4, 3);
+        Tensor2D::<f64>::softmax_backward(&output, &output_gradient, &mut input_gradient);
+
+        let expected: Tensor2D<f64> = numerical_gradient(&input, |input| {
+            weighted_sum(&Tensor2D::<f64>::softmax(input), &output_gradient)
+        });
+        assert_close(&expected, &input_gradient);
+    }
+
+    #[test]
+    fn cross_entropy_backward() {
+        let input: Tensor2D<f64> = test_tensor(0.0, 2, 5);
+        let probabilities: Tensor2D<f64> = Tensor2D::<f64>::softmax(&input);
+        // A target which doesn't sum to 1 checks the general case
+        let mut target: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 2, 5);
+        target.data[3] = 1.0;
+        target.data[8] = 0.5;
+
+        let loss_gradient: f64 = 2.0;
+        let mut probabilities_gradient: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 2, 5);
+        Tensor2D::<f64>::cross_entropy_backward(
+            &probabilities,
+            &target,
+            loss_gradient,
+            &mut probabilities_gradient,
+        );
+        let expected: Tensor2D<f64> = numerical_gradient(&probabilities, |probabilities| {
+            loss_gradient * Tensor2D::<f64>::cross_entropy(probabilities, &target)
+        });
+        assert_close(&expected, &probabilities_gradient);
+
+        // Softmax and cross entropy in one step gives the same
+        // as cross entropy followed by softmax
+        let mut merged_gradient: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 2, 5);
+        Tensor2D::<f64>::softmax_cross_entropy_backward(
+            &probabilities,
+            &target,
+            loss_gradient,
+            &mut merged_gradient,
+        );
+        let mut separate_gradient: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 2, 5);
+        Tensor2D::<f64>::softmax_backward(
+            &probabilities,
+            &probabilities_gradient,
+            &mut separate_gradient,
+        );
+        assert_close(&separate_gradient, &merged_gradient);
+
+        let expected: Tensor2D<f64> = numerical_gradient(&input, |input| {
+            loss_gradient
+                * Tensor2D::<f64>::cross_entropy(&Tensor2D::<f64>::softmax(input), &target)
+        });
+        assert_close(&expected, &merged_gradient);
+    }
+
+    #[test]
+    fn gradients_accumulate() {
+        let input: Tensor2D<f64> = test_tensor(0.0, 3, 3);
+        let output_gradient: Tensor2D<f64> = test_tensor(1.0, 3, 3);
+
+        let mut once: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 3, 3);
+        Tensor2D::<f64>::relu_backward(&input, &output_gradient, &mut once);
+        let mut twice: Tensor2D<f64> = once.clone();
+        Tensor2D::<f64>::relu_backward(&input, &output_gradient, &mut twice);
+
+        for (once, twice) in once.data.iter().zip(twice.data.iter()) {
+            assert_eq!(2.0 * once, *twice);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::time::{Duration, Instant};
+
+use super::tensor2d::Tensor2D;
+use super::tensor_element::TensorElement;
+
+// A cache-blocked linear layer.
+// In linear_layer_optimized every output element walks down a whole column
+// of the weights, jumping weights.column_count elements for every read.
+// Once the weights no longer fit in the cache, nearly every one of those
+// reads is a cache miss, just like in the strided_access_and_transposition example.
+//
+// Instead we work on tiles. A panel of inner_tile rows and column_tile columns
+// of the weights is copied, packed, in to a small buffer which should fit in the L2 cache.
+// The packed panel is laid out as strips of STRIP_WIDTH columns, where all of
+// the elements a strip needs are next to each other in memory, so the innermost loop
+// reads the panel front to back. Then row_tile rows of the input, which should
+// fit in the L1 cache, are multiplied with the panel before moving on to the next rows.
+//
+// Which tile sizes are best depends on the cache sizes of the CPU,
+// which is what tune_tile_sizes is for. The tile sizes are always
+// passed in by the caller, there is no process wide setting.
+
+// The number of columns computed together in the innermost loop.
+// 8 f32's is one AVX2 register, which gives the compiler a good shot at vectorizing it.
+const STRIP_WIDTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileSizes {
+    // Rows of the input processed per packed panel, sized for L1
+    pub row_tile: usize,
+    // Rows of the weights in a packed panel
+    pub inner_tile: usize,
+    // Columns of the weights in a packed panel, inner_tile * column_tile is sized for L2
+    pub column_tile: usize,
+}
+
+impl TileSizes {
+    // 64 * 256 f32's is 64 KB for the weight panel and 32 KB for the input rows
+    pub const DEFAULT: TileSizes = TileSizes {
+        row_tile: 32,
+        inner_tile: 256,
+        column_tile: 64,
+    };
+}
+
+impl Default for TileSizes {
+    fn default() -> Self {
+        TileSizes::DEFAULT
+    }
+}
+
+impl<T: TensorElement> Tensor2D<T> {
+    // Copies weights[first_row..first_row + row_count, first_column..first_column + column_count]
+    // in to strips of STRIP_WIDTH columns. Columns past the edge of the weights are zero,
+    // so the innermost loop never has to check whether it is at the edge.
+    fn pack_weight_panel(
+        weights: &Tensor2D<T>,
+        first_row: usize,
+        row_count: usize,
+        first_column: usize,
+        column_count: usize,
+        packed: &mut Vec<T>,
+    ) {
+        let strip_count: usize = column_count.div_ceil(STRIP_WIDTH);
+        packed.clear();
+        packed.resize(strip_count * row_count * STRIP_WIDTH, T::zero());
+
+        for strip in 0..strip_count {
+            let strip_column: usize = strip * STRIP_WIDTH;
+            let strip_width: usize = STRIP_WIDTH.min(column_count - strip_column);
+            for row in 0..row_count {
+                let source: usize =
+                    (first_row + row) * weights.column_count + first_column + strip_column;
+                let destination: usize = (strip * row_count + row) * STRIP_WIDTH;
+                packed[destination..(destination + strip_width)]
+                    .copy_from_slice(&weights.data[source..(source + strip_width)]);
+            }
+        }
+    }
+
+    pub fn linear_layer_blocked(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
+        tile_sizes: &TileSizes,
+    ) {
+        Self::linear_layer_assert(input, weights, bias, output);
+        // step_by(0) panics without saying why, so check up front in release builds as well
+        assert!(
+            0 < tile_sizes.row_tile && 0 < tile_sizes.inner_tile && 0 < tile_sizes.column_tile,
+            "\nTile sizes must be larger than 0. Current value: {:?}.",
+            tile_sizes
+        );
+
+        let row_count: usize = output.row_count;
+        let column_count: usize = output.column_count;
+        let inner_dimension: usize = input.column_count;
+
+        // Every tile adds its part of the result to the output, so we start with the bias
+        output.data[0..(row_count * column_count)]
+            .copy_from_slice(&bias.data[0..(row_count * column_count)]);
+
+        let mut packed: Vec<T> = Vec::<T>::new();
+        for first_column in (0..column_count).step_by(tile_sizes.column_tile) {
+            let panel_column_count: usize = tile_sizes.column_tile.min(column_count - first_column);
+            let strip_count: usize = panel_column_count.div_ceil(STRIP_WIDTH);
+
+            for first_inner in (0..inner_dimension).step_by(tile_sizes.inner_tile) {
+                let panel_row_count: usize =
+                    tile_sizes.inner_tile.min(inner_dimension - first_inner);
+                Self::pack_weight_panel(
+                    weights,
+                    first_inner,
+                    panel_row_count,
+                    first_column,
+                    panel_column_count,
+                    &mut packed,
+                );
+
+                for first_row in (0..row_count).step_by(tile_sizes.row_tile) {
+                    let last_row: usize = (first_row + tile_sizes.row_tile).min(row_count);
+                    for row in first_row..last_row {
+                        let input_row: &[T] = &input.data[(row * inner_dimension + first_inner)
+                            ..(row * inner_dimension + first_inner + panel_row_count)];
+
+                        for strip in 0..strip_count {
+                            let strip_data: &[T] = &packed[(strip * panel_row_count * STRIP_WIDTH)
+                                ..((strip + 1) * panel_row_count * STRIP_WIDTH)];
+
+                            let mut accumulators: [T; STRIP_WIDTH] = [T::zero(); STRIP_WIDTH];
+                            for (input_value, weight_row) in
+                                input_row.iter().zip(strip_data.chunks_exact(STRIP_WIDTH))
+                            {
+                                for (accumulator, weight) in
+                                    accumulators.iter_mut().zip(weight_row.iter())
+                                {
+                                    *accumulator += *input_value * *weight;
+                                }
+                            }
+
+                            let strip_column: usize = first_column + strip * STRIP_WIDTH;
+                            let strip_width: usize = STRIP_WIDTH.min(column_count - strip_column);
+                            let output_index: usize = row * column_count + strip_column;
+                            for (output_element, accumulator) in output.data
+                                [output_index..(output_index + strip_width)]
+                                .iter_mut()
+                                .zip(accumulators.iter())
+                            {
+                                *output_element += *accumulator;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The tile sizes tried by the tuner. Most combinations fit in the caches of
+// any recent desktop CPU, the tuner finds out which of them fits best.
+const ROW_TILE_CANDIDATES: [usize; 3] = [16, 32, 64];
+const INNER_TILE_CANDIDATES: [usize; 4] = [64, 128, 256, 512];
+const COLUMN_TILE_CANDIDATES: [usize; 4] = [32, 64, 128, 256];
+
+// A tile at least as large as the dimension is just one tile covering all of it,
+// so every such candidate runs the exact same loops and only differs by noise.
+// If no candidate is smaller, that single tile is the only choice.
+fn candidates_below(candidates: &[usize], dimension: usize) -> Vec<usize> {
+    let smaller: Vec<usize> = candidates
+        .iter()
+        .copied()
+        .filter(|candidate| *candidate < dimension)
+        .collect();
+    if smaller.is_empty() {
+        vec![dimension.max(1)]
+    } else {
+        smaller
+    }
+}
+
+// Runs linear_layer_blocked for an input of row_count x inner_dimension and weights
+// of inner_dimension x column_count with every candidate tile size smaller than
+// the problem, and returns the tile sizes with the lowest time.
+// Each candidate keeps its fastest of iteration_count runs, which is much less
+// sensitive to the odd interruption than the total.
+pub fn tune_tile_sizes(
+    row_count: usize,
+    inner_dimension: usize,
+    column_count: usize,
+    iteration_count: usize,
+) -> TileSizes {
+    let input: Tensor2D = Tensor2D::new(0.5, row_count, inner_dimension);
+    let weights: Tensor2D = Tensor2D::new(1.0, inner_dimension, column_count);
+    let bias: Tensor2D = Tensor2D::new(0.1, row_count, column_count);
+    let mut output: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+
+    let mut best_tile_sizes: TileSizes = TileSizes {
+        row_tile: row_count.max(1),
+        inner_tile: inner_dimension.max(1),
+        column_tile: column_count.max(1),
+    };
+    let mut best_time: Duration = Duration::MAX;
+    for row_tile in candidates_below(&ROW_TILE_CANDIDATES, row_count) {
+        for inner_tile in candidates_below(&INNER_TILE_CANDIDATES, inner_dimension) {
+            for column_tile in candidates_below(&COLUMN_TILE_CANDIDATES, column_count) {
+                let tile_sizes: TileSizes = TileSizes {
+                    row_tile,
+                    inner_tile,
+                    column_tile,
+                };
+
+                for _ in 0..iteration_count.max(1) {
+                    let now: Instant = Instant::now();
+                    Tensor2D::linear_layer_blocked(
+                        &input,
+                        &weights,
+                        &bias,
+                        &mut output,
+                        &tile_sizes,
+                    );
+                    let elapsed_time: Duration = now.elapsed();
+                    if elapsed_time < best_time {
+                        best_time = elapsed_time;
+                        best_tile_sizes = tile_sizes;
+                    }
+                }
+            }
+        }
+    }
+
+    best_tile_sizes
+}
+
+// The benchmarks run square tensors of every size in Configuration::loop_range.
+// Which tiles are best depends on how much of the problem fits in the caches,
+// so every size gets its own tuning instead of using the tiles of the largest size.
+// Returns the tile sizes for each size, in the same order.
+pub fn tune_tile_sizes_per_size(
+    sizes: &[usize],
+    iteration_count: usize,
+) -> Vec<(usize, TileSizes)> {
+    sizes
+        .iter()
+        .map(|size| (*size, tune_tile_sizes(*size, *size, *size, iteration_count)))
+        .collect()
+}
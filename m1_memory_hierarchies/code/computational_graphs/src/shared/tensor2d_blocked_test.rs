@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod tests {
+    use crate::shared::tensor2d::Tensor2D;
+    use crate::shared::tensor2d_blocked::{tune_tile_sizes, tune_tile_sizes_per_size, TileSizes};
+
+    const ERROR_TOLERANCE: f32 = 0.00001;
+
+    fn relative_difference(expected: &Tensor2D, actual: &Tensor2D) -> f32 {
+        expected
+            .data
+            .iter()
+            .zip(actual.data.iter())
+            .map(|(expected, actual)| (expected - actual).abs() / expected.abs().max(1.0))
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn linear_layer_blocked() {
+        // Small tiles, so every edge case is hit with small matrices.
+        // A column tile of 12 gives a strip which is only partially filled.
+        let all_tile_sizes: [TileSizes; 3] = [
+            TileSizes {
+                row_tile: 1,
+                inner_tile: 1,
+                column_tile: 1,
+            },
+            TileSizes {
+                row_tile: 3,
+                inner_tile: 5,
+                column_tile: 12,
+            },
+            TileSizes::default(),
+        ];
+
+        for row_count in [1, 4, 13] {
+            for column_count in [1, 8, 17] {
+                for inner_dimension in [1, 6, 19] {
+                    let input: Tensor2D = Tensor2D::new(0.01, row_count, inner_dimension);
+                    let weights: Tensor2D = Tensor2D::new(-0.02, inner_dimension, column_count);
+                    let bias: Tensor2D = Tensor2D::new(0.03, row_count, column_count);
+
+                    let mut expected: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+                    Tensor2D::linear_layer_optimized(&input, &weights, &bias, &mut expected);
+
+                    for tile_sizes in &all_tile_sizes {
+                        // The output is overwritten, not added to
+                        let mut output: Tensor2D = Tensor2D::new(1.0, row_count, column_count);
+                        Tensor2D::linear_layer_blocked(
+                            &input,
+                            &weights,
+                            &bias,
+                            &mut output,
+                            tile_sizes,
+                        );
+                        assert!(relative_difference(&expected, &output) < ERROR_TOLERANCE);
+                    }
+                }
+            }
+        }
+
+        let input: Tensor2D<i32> = Tensor2D::<i32>::new(1, 5, 9);
+        let weights: Tensor2D<i32> = Tensor2D::<i32>::new(2, 9, 11);
+        let bias: Tensor2D<i32> = Tensor2D::<i32>::new(-1, 5, 11);
+        let mut expected: Tensor2D<i32> = Tensor2D::<i32>::new(0, 5, 11);
+        Tensor2D::<i32>::linear_layer_optimized(&input, &weights, &bias, &mut expected);
+        let mut output: Tensor2D<i32> = Tensor2D::<i32>::new(0, 5, 11);
+        Tensor2D::<i32>::linear_layer_blocked(
+            &input,
+            &weights,
+            &bias,
+            &mut output,
+            &all_tile_sizes[1],
+        );
+        assert_eq!(output.data, expected.data);
+    }
+
+    #[test]
+    fn tune_tile_sizes_below_the_problem() {
+        // Only candidates smaller than every dimension are tried
+        let tile_sizes: TileSizes = tune_tile_sizes(100, 100, 100, 1);
+        assert!([16, 32, 64].contains(&tile_sizes.row_tile));
+        assert_eq!(tile_sizes.inner_tile, 64);
+        assert!([32, 64].contains(&tile_sizes.column_tile));
+
+        // Too small to be tiled at all
+        let tile_sizes: TileSizes = tune_tile_sizes(8, 16, 4, 1);
+        assert_eq!(
+            tile_sizes,
+            TileSizes {
+                row_tile: 8,
+                inner_tile: 16,
+                column_tile: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn tune_tile_sizes_for_every_size() {
+        let tuned_tile_sizes: Vec<(usize, TileSizes)> = tune_tile_sizes_per_size(&[4, 100], 1);
+        assert_eq!(tuned_tile_sizes.len(), 2);
+        assert_eq!(
+            tuned_tile_sizes[0],
+            (
+                4,
+                TileSizes {
+                    row_tile: 4,
+                    inner_tile: 4,
+                    column_tile: 4,
+                }
+            )
+        );
+        assert_eq!(tuned_tile_sizes[1].0, 100);
+        assert_eq!(tuned_tile_sizes[1].1.inner_tile, 64);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_tile_sizes() {
+        let input: Tensor2D = Tensor2D::new(0.5, 4, 4);
+        let weights: Tensor2D = Tensor2D::new(1.0, 4, 4);
+        let bias: Tensor2D = Tensor2D::new(0.1, 4, 4);
+        let mut output: Tensor2D = Tensor2D::new(0.0, 4, 4);
+        let tile_sizes: TileSizes = TileSizes {
+            row_tile: 0,
+            ..TileSizes::DEFAULT
+        };
+        Tensor2D::linear_layer_blocked(&input, &weights, &bias, &mut output, &tile_sizes);
+    }
+}
@@ -1,7 +1,96 @@
 use futures_intrusive::channel::shared::{OneshotReceiver, OneshotSender};
-use wgpu::{util::DeviceExt, Buffer, BufferAsyncError, BufferSlice, BufferView, CommandEncoder};
+use wgpu::{
+    util::DeviceExt, BindingResource, Buffer, BufferAsyncError, BufferBinding, BufferSize,
+    BufferSlice, BufferView, CommandEncoder, Limits,
+};
+
+use super::{
+    gpu_utilities::GPUHandles, graph_operators::LossKind, tensor2d::Tensor2D,
+    tensor2d_softmax_axis::softmax_lanes,
+};
+
+// The workgroup size of the reductions in sum.wgsl and softmax.wgsl.
+// A multiple of both 32 and 64, the common subgroup sizes.
+pub const REDUCTION_BLOCK_SIZE: usize = 256;
+
+// How many workgroups the first pass of a reduction is launched with.
+// Every workgroup writes a single partial result and the final pass
+// is a single workgroup, so there can be at most REDUCTION_BLOCK_SIZE of them.
+// Past that every thread just loops over more of the elements.
+pub fn reduction_block_count(element_count: usize) -> usize {
+    element_count
+        .div_ceil(REDUCTION_BLOCK_SIZE)
+        .clamp(1, REDUCTION_BLOCK_SIZE)
+}
 
-use super::{gpu_utilities::GPUHandles, tensor2d::Tensor2D};
+// A range of rows which is bound and dispatched on its own.
+// Tensors which don't fit in a single binding, or need more workgroups than
+// a single dispatch can launch, are split in to several of these, see dispatch_chunks.
+// The elementwise operators treat their tensors as rows of a single element.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DispatchChunk {
+    pub row_offset: usize,
+    pub row_count: usize,
+}
+
+impl DispatchChunk {
+    // Binds just the rows of the chunk, for a tensor with row_length elements per row
+    pub fn binding<'a>(&self, tensor: &'a Tensor2DGPU, row_length: usize) -> BindingResource<'a> {
+        let row_size: u64 = (row_length * tensor.element_size) as u64;
+        BindingResource::Buffer(BufferBinding {
+            buffer: &tensor.storage_buffer,
+            offset: self.row_offset as u64 * row_size,
+            size: BufferSize::new(self.row_count as u64 * row_size),
+        })
+    }
+}
+
+// Splits row_count rows in to as few chunks as the limits allow.
+// The chunks bind rows of every one of row_lengths, and every chunk
+//   fits in max_storage_buffer_binding_size for all of them,
+//   starts at a multiple of min_storage_buffer_offset_alignment for all of them,
+//   needs at most max_compute_workgroups_per_dimension workgroups of rows_per_workgroup rows.
+// Returns None if not even the smallest chunk which keeps the next one aligned fits.
+pub fn dispatch_chunks(
+    limits: &Limits,
+    row_count: usize,
+    row_lengths: &[usize],
+    rows_per_workgroup: usize,
+) -> Option<Vec<DispatchChunk>> {
+    let element_size: usize = std::mem::size_of::<f32>();
+    // The alignment is always a power of two, which makes the smallest number of rows
+    // keeping the next chunk aligned a power of two as well
+    let alignment: usize = limits.min_storage_buffer_offset_alignment as usize;
+    let mut aligned_rows: usize = 1;
+    let mut largest_row_size: usize = element_size;
+    for row_length in row_lengths {
+        let row_size: usize = row_length * element_size;
+        let row_alignment: usize = 1 << row_size.trailing_zeros().min(alignment.trailing_zeros());
+        aligned_rows = aligned_rows.max(alignment / row_alignment);
+        largest_row_size = largest_row_size.max(row_size);
+    }
+
+    let max_rows: usize = (limits.max_storage_buffer_binding_size as usize / largest_row_size)
+        .min(limits.max_compute_workgroups_per_dimension as usize * rows_per_workgroup);
+    let chunk_rows: usize = if row_count <= max_rows {
+        row_count
+    } else {
+        max_rows / aligned_rows * aligned_rows
+    };
+    if chunk_rows == 0 {
+        return None;
+    }
+
+    Some(
+        (0..row_count)
+            .step_by(chunk_rows)
+            .map(|row_offset| DispatchChunk {
+                row_offset,
+                row_count: chunk_rows.min(row_count - row_offset),
+            })
+            .collect(),
+    )
+}
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -90,12 +179,95 @@ impl LinearLayerUniform {
         }
     }
 
+    // Same as from_tensor_2d_gpu, but for only the rows of chunk of the input, bias and output.
+    // Bound to the same rows of those, see DispatchChunk::binding.
+    pub fn from_chunk(
+        handles: &GPUHandles,
+        label: &str,
+        input: &Tensor2DGPU,
+        weights: &Tensor2DGPU,
+        bias: &Tensor2DGPU,
+        output: &Tensor2DGPU,
+        chunk: &DispatchChunk,
+    ) -> Self {
+        let dimensions: LinearLayerDimensions = LinearLayerDimensions {
+            data: [
+                chunk.row_count as u32,
+                input.column_count as u32,
+                weights.row_count as u32,
+                weights.column_count as u32,
+                chunk.row_count as u32,
+                bias.column_count as u32,
+                chunk.row_count as u32,
+                output.column_count as u32,
+            ],
+        };
+
+        let storage_buffer: Buffer =
+            handles
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents: bytemuck::cast_slice(&dimensions.data),
+                    usage: wgpu::BufferUsages::UNIFORM
+                        | wgpu::BufferUsages::COPY_DST,
+                });
+
+        Self {
+            dimensions,
+            storage_buffer,
+        }
+    }
+
     #[inline(always)]
     pub fn size(&self) -> u64 {
         std::mem::size_of::<LinearLayerDimensions>() as u64
     }
 }
 
+// Which of the kernels in linear_layer.wgsl to run.
+// Naive has every thread compute a single output straight from global memory.
+// Tiled stages tiles of the input and weights in workgroup memory
+// and has every thread compute a 2x2 block of outputs.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LinearLayerKernel {
+    #[default]
+    Naive,
+    Tiled,
+}
+
+impl LinearLayerKernel {
+    pub fn entry_point(&self, with_relu: bool) -> &'static str {
+        match (self, with_relu) {
+            (LinearLayerKernel::Naive, false) => "main",
+            (LinearLayerKernel::Naive, true) => "main_with_relu",
+            (LinearLayerKernel::Tiled, false) => "tiled",
+            (LinearLayerKernel::Tiled, true) => "tiled_with_relu",
+        }
+    }
+
+    // Every workgroup covers block_size x block_size outputs
+    pub fn block_size(&self) -> usize {
+        match self {
+            LinearLayerKernel::Naive => 8,
+            LinearLayerKernel::Tiled => 32,
+        }
+    }
+
+    pub fn launch_blocks(
+        &self,
+        output_row_count: usize,
+        output_column_count: usize,
+    ) -> (u32, u32, u32) {
+        let block_size: usize = self.block_size();
+        (
+            output_row_count.div_ceil(block_size) as u32,
+            output_column_count.div_ceil(block_size) as u32,
+            1,
+        )
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ReluDimensions {
@@ -109,8 +281,17 @@ pub struct ReluUniform {
 
 impl ReluUniform {
     pub fn new(handles: &GPUHandles, label: &str, input: &Tensor2D) -> Self {
+        Self::from_dimensions(handles, label, input.row_count, input.column_count)
+    }
+
+    pub fn from_dimensions(
+        handles: &GPUHandles,
+        label: &str,
+        row_count: usize,
+        column_count: usize,
+    ) -> Self {
         let dimensions: ReluDimensions = ReluDimensions {
-            data: [input.row_count as u32, input.column_count as u32],
+            data: [row_count as u32, column_count as u32],
         };
 
         let storage_buffer: Buffer =
@@ -184,7 +365,7 @@ impl SumUniform {
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct SoftmaxDimensions {
-    pub data: [u32; 1],
+    pub data: [u32; 2],
 }
 
 pub struct SoftmaxUniform {
@@ -194,8 +375,9 @@ pub struct SoftmaxUniform {
 
 impl SoftmaxUniform {
     pub fn new(handles: &GPUHandles, label: &str, element_count: usize) -> Self {
+        // The softmax reductions are launched with block_count workgroups
         let dimensions: SoftmaxDimensions = SoftmaxDimensions {
-            data: [element_count as u32],
+            data: [element_count as u32, reduction_block_count(element_count) as u32],
         };
 
         let storage_buffer: Buffer =
@@ -214,6 +396,11 @@ impl SoftmaxUniform {
         }
     }
 
+    #[inline(always)]
+    pub fn block_count(&self) -> u32 {
+        self.dimensions.data[1]
+    }
+
     #[inline(always)]
     pub fn size(&self) -> u64 {
         std::mem::size_of::<SoftmaxDimensions>() as u64
@@ -221,6 +408,171 @@ impl SoftmaxUniform {
 
 }
 
+// lane_count, lane_length, lane_stride and element_stride, see softmax_lanes
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SoftmaxAxisDimensions {
+    pub data: [u32; 4],
+}
+
+pub struct SoftmaxAxisUniform {
+    pub dimensions: SoftmaxAxisDimensions,
+    pub storage_buffer: Buffer,
+}
+
+impl SoftmaxAxisUniform {
+    pub fn new(
+        handles: &GPUHandles,
+        label: &str,
+        row_count: usize,
+        column_count: usize,
+        axis: usize,
+    ) -> Self {
+        let (lane_count, lane_length, lane_stride, element_stride) =
+            softmax_lanes(row_count, column_count, axis);
+        let dimensions: SoftmaxAxisDimensions = SoftmaxAxisDimensions {
+            data: [
+                lane_count as u32,
+                lane_length as u32,
+                lane_stride as u32,
+                element_stride as u32,
+            ],
+        };
+
+        let storage_buffer: Buffer =
+            handles
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents: bytemuck::cast_slice(&dimensions.data),
+                    usage: wgpu::BufferUsages::UNIFORM
+                        | wgpu::BufferUsages::COPY_DST,
+                });
+
+        Self {
+            dimensions,
+            storage_buffer,
+        }
+    }
+
+    // Same as new, but for only the lanes of chunk. The lanes are rows along axis 1
+    // and columns along axis 0, see nodes_gpu::softmax_axis_binding.
+    pub fn from_chunk(
+        handles: &GPUHandles,
+        label: &str,
+        row_count: usize,
+        column_count: usize,
+        axis: usize,
+        chunk: &DispatchChunk,
+    ) -> Self {
+        let (_, lane_length, lane_stride, element_stride) =
+            softmax_lanes(row_count, column_count, axis);
+        let dimensions: SoftmaxAxisDimensions = SoftmaxAxisDimensions {
+            data: [
+                chunk.row_count as u32,
+                lane_length as u32,
+                lane_stride as u32,
+                element_stride as u32,
+            ],
+        };
+
+        let storage_buffer: Buffer =
+            handles
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents: bytemuck::cast_slice(&dimensions.data),
+                    usage: wgpu::BufferUsages::UNIFORM
+                        | wgpu::BufferUsages::COPY_DST,
+                });
+
+        Self {
+            dimensions,
+            storage_buffer,
+        }
+    }
+
+    #[inline(always)]
+    pub fn lane_count(&self) -> u32 {
+        self.dimensions.data[0]
+    }
+
+    #[inline(always)]
+    pub fn size(&self) -> u64 {
+        std::mem::size_of::<SoftmaxAxisDimensions>() as u64
+    }
+
+}
+
+// element_count, kind and total_element_count, see LossUniform in loss.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LossElements {
+    pub data: [u32; 3],
+}
+
+pub struct LossUniform {
+    pub elements: LossElements,
+    pub storage_buffer: Buffer,
+}
+
+impl LossUniform {
+    pub fn new(handles: &GPUHandles, label: &str, element_count: usize, kind: LossKind) -> Self {
+        let elements: LossElements = LossElements {
+            data: [element_count as u32, kind as u32, element_count as u32],
+        };
+
+        let storage_buffer: Buffer =
+            handles
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents: bytemuck::cast_slice(&elements.data),
+                    usage: wgpu::BufferUsages::UNIFORM
+                        | wgpu::BufferUsages::COPY_DST,
+                });
+
+        Self {
+            elements,
+            storage_buffer,
+        }
+    }
+
+    // Same as new, but for only the elements of chunk, bound with DispatchChunk::binding
+    pub fn from_chunk(
+        handles: &GPUHandles,
+        label: &str,
+        chunk: &DispatchChunk,
+        element_count: usize,
+        kind: LossKind,
+    ) -> Self {
+        let elements: LossElements = LossElements {
+            data: [chunk.row_count as u32, kind as u32, element_count as u32],
+        };
+
+        let storage_buffer: Buffer =
+            handles
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents: bytemuck::cast_slice(&elements.data),
+                    usage: wgpu::BufferUsages::UNIFORM
+                        | wgpu::BufferUsages::COPY_DST,
+                });
+
+        Self {
+            elements,
+            storage_buffer,
+        }
+    }
+
+    #[inline(always)]
+    pub fn size(&self) -> u64 {
+        std::mem::size_of::<LossElements>() as u64
+    }
+
+}
+
 #[derive(Debug)]
 pub struct Tensor2DGPU {
     pub staging_buffer: Buffer,
@@ -351,36 +703,44 @@ impl Tensor2DGPU {
         self.live_data_on_device = true;
     }
 
+    // The callers poll with Maintain::Wait before this, which stalls the whole device.
+    // That stays on purpose, every tensor has its own staging buffer which is read back once,
+    // at the end of an immediate mode function or a graph run, when there is nothing left
+    // to overlap with. Reading back the same tensor every iteration should use a ReadbackRing.
     pub async fn retrieve_results(&mut self) {
+        if self.try_retrieve_results().await.is_err() {
+            panic!("Failed to retrieve results from the gpu!")
+        }
+    }
+
+    // Same as retrieve_results, but lets the caller handle a failed mapping
+    pub async fn try_retrieve_results(&mut self) -> Result<(), BufferAsyncError> {
         if !self.live_data_on_device {
             println!("Already retrieved results from GPU, no reason to do it again.");
-            return;
+            return Ok(());
         }
         if self.receiver.is_none() {
             println!("Tried to get_results for a Tensor2DGPU, without having a receiver in place. You are probably calling the functions in the wrong order.");
-            return;
+            return Ok(());
         }
 
         let buffer_slice: BufferSlice = self.staging_buffer.slice(..);
 
-        let result: Vec<f32> =
-            if let Some(Ok(())) =
-                self.receiver
-                    .take() // Take ownership of the option and leave None in it's place. This is to enforce the fact that this is a oneshot receiver.
-                    .expect("Took the receiver from the tensor in Tensor2DGPU::get_results, but the option did not contain a receiver.")
-                    .receive().await {
-            let data: BufferView = buffer_slice.get_mapped_range();
-            let result: Vec<f32> = bytemuck::cast_slice(&data).to_vec();
-
-            drop(data);
-            self.staging_buffer.unmap();
-            self.live_data_on_device = false;
-            result
-        } else {
-            panic!("Failed to retrieve results from the gpu!")
-        };
+        // Take ownership of the option and leave None in it's place. This is to enforce the fact that this is a oneshot receiver.
+        // If the sender was dropped without sending, the mapping never happened.
+        let receiver: OneshotReceiver<Result<(), BufferAsyncError>> = self.receiver.take().expect("Took the receiver from the tensor in Tensor2DGPU::get_results, but the option did not contain a receiver.");
+        receiver.receive().await.unwrap_or(Err(BufferAsyncError))?;
+
+        let data: BufferView = buffer_slice.get_mapped_range();
+        let result: Vec<f32> = bytemuck::cast_slice(&data).to_vec();
+
+        drop(data);
+        self.staging_buffer.unmap();
+        self.live_data_on_device = false;
 
         self.data.data = result;
+
+        Ok(())
     }
 
     #[inline(always)]
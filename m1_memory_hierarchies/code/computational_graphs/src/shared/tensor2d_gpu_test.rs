@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use wgpu::Limits;
+
+    use crate::shared::tensor2d_gpu::{dispatch_chunks, DispatchChunk};
+
+    fn limits(binding_size: u32, workgroups: u32, alignment: u32) -> Limits {
+        Limits {
+            max_storage_buffer_binding_size: binding_size,
+            max_compute_workgroups_per_dimension: workgroups,
+            min_storage_buffer_offset_alignment: alignment,
+            ..Default::default()
+        }
+    }
+
+    // The chunks have to cover every row exactly once, in order
+    fn assert_covers(chunks: &[DispatchChunk], row_count: usize) {
+        let mut next_row: usize = 0;
+        for chunk in chunks {
+            assert_eq!(chunk.row_offset, next_row);
+            assert!(0 < chunk.row_count);
+            next_row += chunk.row_count;
+        }
+        assert_eq!(next_row, row_count);
+    }
+
+    #[test]
+    fn single_chunk() {
+        let chunks: Vec<DispatchChunk> =
+            dispatch_chunks(&Limits::default(), 1000, &[37, 5], 8).unwrap();
+        assert_eq!(
+            chunks,
+            vec![DispatchChunk {
+                row_offset: 0,
+                row_count: 1000
+            }]
+        );
+    }
+
+    #[test]
+    fn limited_by_workgroups() {
+        // 4 workgroups of 32 single element rows is 128 elements
+        let chunks: Vec<DispatchChunk> =
+            dispatch_chunks(&limits(1 << 20, 4, 256), 1000, &[1], 32).unwrap();
+        assert_covers(&chunks, 1000);
+        assert!(chunks[..chunks.len() - 1]
+            .iter()
+            .all(|chunk| chunk.row_count == 128));
+    }
+
+    #[test]
+    fn limited_by_binding_size() {
+        for (row_lengths, alignment) in [(vec![20], 32), (vec![12, 20], 256), (vec![13], 64)] {
+            let chunks: Vec<DispatchChunk> =
+                dispatch_chunks(&limits(4096, 65535, alignment), 100, &row_lengths, 8).unwrap();
+            assert!(1 < chunks.len());
+            assert_covers(&chunks, 100);
+
+            for chunk in &chunks {
+                for row_length in &row_lengths {
+                    let row_size: usize = row_length * std::mem::size_of::<f32>();
+                    assert!(chunk.row_count * row_size <= 4096);
+                    assert_eq!((chunk.row_offset * row_size) % alignment as usize, 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn row_too_large() {
+        // A single row of 2048 elements is twice the size of a binding
+        assert!(dispatch_chunks(&limits(4096, 65535, 256), 10, &[2048], 8).is_none());
+        // Rows of 20 elements only line up with an alignment of 256 every 16 rows,
+        // which is more than fits in a binding of 1024 bytes
+        assert!(dispatch_chunks(&limits(1024, 65535, 256), 100, &[20], 8).is_none());
+    }
+}
@@ -0,0 +1,140 @@
+use super::graph_operators::LossKind;
+use super::tensor2d::Tensor2D;
+use super::tensor_element::FloatElement;
+
+// The loss functions of GraphOperator::Loss and LogSoftmax, along with their backward versions.
+// Cross entropy on probabilities lives in tensor2d_autograd with the rest of the backward pass.
+// Like softmax, log softmax normalizes over every element of the tensor, not per row.
+
+impl<T: FloatElement> Tensor2D<T> {
+    pub fn log_softmax(input: &Tensor2D<T>) -> Tensor2D<T> {
+        let mut output: Tensor2D<T> = Tensor2D::new(T::zero(), input.row_count, input.column_count);
+
+        Self::log_softmax_preallocated(input, &mut output);
+
+        output
+    }
+
+    // ln(exp(x) / sum(exp(x))) = x - ln(sum(exp(x))), and subtracting the max before
+    // taking the exponential keeps the sum from overflowing. Unlike ln(softmax(x)),
+    // no tiny probability is ever rounded to 0 before the log is taken.
+    pub fn log_softmax_preallocated(input: &Tensor2D<T>, output: &mut Tensor2D<T>) {
+        let mut max: T = T::neg_infinity();
+        for index in 0..input.len() {
+            if max < input.data[index] {
+                max = input.data[index];
+            }
+        }
+
+        let mut sum: T = T::zero();
+        for index in 0..input.len() {
+            sum += (input.data[index] - max).exp();
+        }
+
+        let offset: T = max + sum.ln();
+
+        for index in 0..input.len() {
+            output.data[index] = input.data[index] - offset;
+        }
+    }
+
+    // The Jacobian of log softmax is I - softmax(x) * 1^T, so
+    // input_gradient = output_gradient - exp(output) * sum(output_gradient)
+    pub fn log_softmax_backward(
+        output: &Tensor2D<T>,
+        output_gradient: &Tensor2D<T>,
+        input_gradient: &mut Tensor2D<T>,
+    ) {
+        let mut sum: T = T::zero();
+        for index in 0..output_gradient.len() {
+            sum += output_gradient.data[index];
+        }
+
+        for index in 0..output.len() {
+            input_gradient.data[index] +=
+                output_gradient.data[index] - output.data[index].exp() * sum;
+        }
+    }
+
+    pub fn loss(kind: LossKind, input: &Tensor2D<T>, target: &Tensor2D<T>) -> T {
+        debug_assert_eq!(
+            (input.row_count, input.column_count),
+            (target.row_count, target.column_count),
+            "\nThe input and the target of a loss need the same shape."
+        );
+
+        match kind {
+            LossKind::CrossEntropy => Self::cross_entropy(input, target),
+            LossKind::NegativeLogLikelihood => Self::negative_log_likelihood(input, target),
+            LossKind::MeanSquaredError => Self::mean_squared_error(input, target),
+            LossKind::MeanAbsoluteError => Self::mean_absolute_error(input, target),
+        }
+    }
+
+    // loss_gradient is the gradient of the final loss with respect to this loss,
+    // see cross_entropy_backward
+    pub fn loss_backward(
+        kind: LossKind,
+        input: &Tensor2D<T>,
+        target: &Tensor2D<T>,
+        loss_gradient: T,
+        input_gradient: &mut Tensor2D<T>,
+    ) {
+        match kind {
+            LossKind::CrossEntropy => {
+                Self::cross_entropy_backward(input, target, loss_gradient, input_gradient)
+            }
+            LossKind::NegativeLogLikelihood => {
+                for index in 0..input.len() {
+                    input_gradient.data[index] += T::zero() - loss_gradient * target.data[index];
+                }
+            }
+            LossKind::MeanSquaredError => {
+                let scale: T = loss_gradient * T::from_f64(2.0 / input.len() as f64);
+                for index in 0..input.len() {
+                    input_gradient.data[index] += scale * (input.data[index] - target.data[index]);
+                }
+            }
+            LossKind::MeanAbsoluteError => {
+                // The gradient of |x| at 0 is taken to be 0
+                let scale: T = loss_gradient * T::from_f64(1.0 / input.len() as f64);
+                for index in 0..input.len() {
+                    if target.data[index] < input.data[index] {
+                        input_gradient.data[index] += scale;
+                    } else if input.data[index] < target.data[index] {
+                        input_gradient.data[index] += T::zero() - scale;
+                    }
+                }
+            }
+        }
+    }
+
+    // -sum(target * input), where the input holds log probabilities
+    pub fn negative_log_likelihood(input: &Tensor2D<T>, target: &Tensor2D<T>) -> T {
+        let mut loss: T = T::zero();
+        for index in 0..input.len() {
+            loss += target.data[index] * input.data[index];
+        }
+
+        T::zero() - loss
+    }
+
+    pub fn mean_squared_error(input: &Tensor2D<T>, target: &Tensor2D<T>) -> T {
+        let mut loss: T = T::zero();
+        for index in 0..input.len() {
+            let difference: T = input.data[index] - target.data[index];
+            loss += difference * difference;
+        }
+
+        loss / T::from_f64(input.len() as f64)
+    }
+
+    pub fn mean_absolute_error(input: &Tensor2D<T>, target: &Tensor2D<T>) -> T {
+        let mut loss: T = T::zero();
+        for index in 0..input.len() {
+            loss += (input.data[index] - target.data[index]).abs();
+        }
+
+        loss / T::from_f64(input.len() as f64)
+    }
+}
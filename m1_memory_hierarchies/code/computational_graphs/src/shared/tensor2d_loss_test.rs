@@ -0,0 +1,152 @@
+#[cfg(test)]
+mod tests {
+    use crate::shared::{graph_operators::LossKind, tensor2d::Tensor2D};
+
+    const STEP: f64 = 0.000001;
+    const ERROR_TOLERANCE: f64 = 0.00001;
+
+    fn test_tensor(offset: f64, row_count: usize, column_count: usize) -> Tensor2D<f64> {
+        let mut tensor: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, row_count, column_count);
+        for (index, value) in tensor.data.iter_mut().enumerate() {
+            *value = ((index as f64 + offset) * 1.7).sin();
+        }
+        tensor
+    }
+
+    fn numerical_gradient(
+        tensor: &Tensor2D<f64>,
+        loss: impl Fn(&Tensor2D<f64>) -> f64,
+    ) -> Tensor2D<f64> {
+        let mut gradient: Tensor2D<f64> =
+            Tensor2D::<f64>::new(0.0, tensor.row_count, tensor.column_count);
+        let mut shifted: Tensor2D<f64> = tensor.clone();
+        for index in 0..tensor.len() {
+            shifted.data[index] = tensor.data[index] + STEP;
+            let above: f64 = loss(&shifted);
+            shifted.data[index] = tensor.data[index] - STEP;
+            let below: f64 = loss(&shifted);
+            shifted.data[index] = tensor.data[index];
+            gradient.data[index] = (above - below) / (2.0 * STEP);
+        }
+        gradient
+    }
+
+    fn assert_close(expected: &Tensor2D<f64>, actual: &Tensor2D<f64>) {
+        for (expected, actual) in expected.data.iter().zip(actual.data.iter()) {
+            assert!(
+                (expected - actual).abs() < ERROR_TOLERANCE,
+                "expected {} but got {}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn log_softmax() {
+        let input: Tensor2D<f64> = test_tensor(0.0, 3, 4);
+        let output: Tensor2D<f64> = Tensor2D::<f64>::log_softmax(&input);
+        let softmax: Tensor2D<f64> = Tensor2D::<f64>::softmax(&input);
+        for (log_probability, probability) in output.data.iter().zip(softmax.data.iter()) {
+            assert!((log_probability - probability.ln()).abs() < ERROR_TOLERANCE);
+        }
+
+        // Where softmax underflows to 0, log softmax is still exact
+        let mut input: Tensor2D<f32> = Tensor2D::<f32>::new(0.0, 1, 2);
+        input.data = vec![0.0, 200.0];
+        let output: Tensor2D<f32> = Tensor2D::<f32>::log_softmax(&input);
+        assert_eq!(Tensor2D::<f32>::softmax(&input).data[0], 0.0);
+        assert!((output.data[0] + 200.0).abs() < 0.001);
+        assert!(output.data[1].abs() < 0.001);
+    }
+
+    #[test]
+    fn log_softmax_backward() {
+        let input: Tensor2D<f64> = test_tensor(0.0, 3, 4);
+        let output: Tensor2D<f64> = Tensor2D::<f64>::log_softmax(&input);
+        let output_gradient: Tensor2D<f64> = test_tensor(5.0, 3, 4);
+
+        let mut input_gradient: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 3, 4);
+        Tensor2D::<f64>::log_softmax_backward(&output, &output_gradient, &mut input_gradient);
+
+        let expected: Tensor2D<f64> = numerical_gradient(&input, |input| {
+            Tensor2D::<f64>::log_softmax(input)
+                .data
+                .iter()
+                .zip(output_gradient.data.iter())
+                .map(|(value, weight)| value * weight)
+                .sum()
+        });
+        assert_close(&expected, &input_gradient);
+    }
+
+    #[test]
+    fn losses() {
+        let mut input: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 1, 4);
+        input.data = vec![1.0, -2.0, 0.5, 3.0];
+        let mut target: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 1, 4);
+        target.data = vec![0.0, -1.0, 0.5, 1.0];
+
+        assert_eq!(
+            Tensor2D::<f64>::loss(LossKind::MeanSquaredError, &input, &target),
+            (1.0 + 1.0 + 0.0 + 4.0) / 4.0
+        );
+        assert_eq!(
+            Tensor2D::<f64>::loss(LossKind::MeanAbsoluteError, &input, &target),
+            (1.0 + 1.0 + 0.0 + 2.0) / 4.0
+        );
+        assert_eq!(
+            Tensor2D::<f64>::loss(LossKind::NegativeLogLikelihood, &input, &target),
+            -(2.0 + 0.25 + 3.0)
+        );
+
+        // LogSoftmax and NegativeLogLikelihood is cross entropy on the softmax
+        let logits: Tensor2D<f64> = test_tensor(0.0, 2, 3);
+        let mut target: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 2, 3);
+        target.data[4] = 1.0;
+        let expected: f64 = Tensor2D::<f64>::loss(
+            LossKind::CrossEntropy,
+            &Tensor2D::<f64>::softmax(&logits),
+            &target,
+        );
+        let actual: f64 = Tensor2D::<f64>::loss(
+            LossKind::NegativeLogLikelihood,
+            &Tensor2D::<f64>::log_softmax(&logits),
+            &target,
+        );
+        assert!((expected - actual).abs() < ERROR_TOLERANCE);
+    }
+
+    #[test]
+    fn loss_backward() {
+        let input: Tensor2D<f64> = test_tensor(0.0, 3, 5);
+        let target: Tensor2D<f64> = test_tensor(0.5, 3, 5);
+        let mut probabilities_target: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 3, 5);
+        probabilities_target.data[7] = 1.0;
+        let probabilities: Tensor2D<f64> = Tensor2D::<f64>::softmax(&input);
+
+        let loss_gradient: f64 = 1.5;
+        for (kind, input, target) in [
+            (
+                LossKind::CrossEntropy,
+                &probabilities,
+                &probabilities_target,
+            ),
+            (
+                LossKind::NegativeLogLikelihood,
+                &input,
+                &probabilities_target,
+            ),
+            (LossKind::MeanSquaredError, &input, &target),
+            (LossKind::MeanAbsoluteError, &input, &target),
+        ] {
+            let mut input_gradient: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 3, 5);
+            Tensor2D::<f64>::loss_backward(kind, input, target, loss_gradient, &mut input_gradient);
+
+            let expected: Tensor2D<f64> = numerical_gradient(input, |input| {
+                loss_gradient * Tensor2D::<f64>::loss(kind, input, target)
+            });
+            assert_close(&expected, &input_gradient);
+        }
+    }
+}
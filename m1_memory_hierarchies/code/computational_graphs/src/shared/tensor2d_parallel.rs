@@ -0,0 +1,358 @@
+use std::thread::{self, ScopedJoinHandle};
+
+use super::tensor2d::Tensor2D;
+use super::tensor_element::{FloatElement, TensorElement};
+
+// The multi-threaded versions of the CPU kernels.
+// Every kernel splits the rows of its output in to one contiguous block
+// per thread. Each thread only ever writes to its own block, so
+// no locks are needed, and std::thread::scope lets the threads borrow
+// the input tensors without having to copy them or wrap them in an Arc.
+//
+// Reductions, like the sum, or the max in softmax, are done in two steps.
+// Every thread reduces its own block, and the partial results
+// are combined by the calling thread once every thread is done.
+// Note that this changes the order the elements are added in,
+// so the results can differ from the single-threaded kernels in the last few bits.
+//
+// Spawning threads isn't free. Every kernel works out how much work it has,
+// and only spawns as many threads as there is work for, see effective_thread_count.
+// Below MINIMUM_WORK_PER_THREAD it runs the single-threaded kernel instead.
+//
+// The thread count is always passed to the kernels by the caller,
+// e.g. the GraphRunner keeps its own, so there is no process wide setting.
+
+// Roughly how many multiply-adds, or elements of an elementwise pass,
+// are needed to make up for the cost of spawning and joining a thread
+pub const MINIMUM_WORK_PER_THREAD: usize = 32 * 1024;
+
+pub fn available_thread_count() -> usize {
+    thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+}
+
+// A thread count of 0 uses every core available
+pub fn resolve_thread_count(thread_count: usize) -> usize {
+    if thread_count == 0 {
+        available_thread_count()
+    } else {
+        thread_count
+    }
+}
+
+// How many of thread_count threads are worth spawning for work_count units of work.
+// 1 means the single-threaded kernel should be used.
+pub fn effective_thread_count(thread_count: usize, work_count: usize) -> usize {
+    thread_count
+        .min(work_count / MINIMUM_WORK_PER_THREAD)
+        .max(1)
+}
+
+// Rounded up, so the last thread might get fewer rows than the others
+#[inline(always)]
+fn rows_per_thread(row_count: usize, thread_count: usize) -> usize {
+    row_count.div_ceil(thread_count.max(1)).max(1)
+}
+
+// Reduces every block of data on its own thread, and then combines the partial results
+fn parallel_reduce<T: TensorElement>(
+    data: &[T],
+    thread_count: usize,
+    reduce: impl Fn(&[T]) -> T + Sync,
+    combine: impl Fn(T, T) -> T,
+) -> T {
+    let block_size: usize = data.len().div_ceil(thread_count.max(1)).max(1);
+    let partial_results: Vec<T> = thread::scope(|scope| {
+        let handles: Vec<ScopedJoinHandle<T>> = data
+            .chunks(block_size)
+            .map(|block| scope.spawn(|| reduce(block)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("A reduction thread panicked"))
+            .collect()
+    });
+
+    let mut result: T = partial_results[0];
+    for partial_result in &partial_results[1..] {
+        result = combine(result, *partial_result);
+    }
+
+    result
+}
+
+impl<T: TensorElement> Tensor2D<T> {
+    // Computes the rows of the output starting at first_row.
+    // The output only contains the rows belonging to this thread,
+    // but input and bias are indexed with the row in the whole tensor.
+    // Returns the largest value written, which the fused softmax needs.
+    #[inline(always)]
+    fn linear_layer_rows(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output_rows: &mut [T],
+        first_row: usize,
+        column_count: usize,
+        apply_relu: bool,
+    ) -> T {
+        let mut max: Option<T> = None;
+        for (local_row, output_row) in output_rows.chunks_mut(column_count).enumerate() {
+            let row_output: usize = first_row + local_row;
+            for (column_output, output_element) in output_row.iter_mut().enumerate() {
+                let mut result: T = T::zero();
+                let mut index_weights: usize = column_output;
+                for index_input in
+                    (row_output * input.column_count)..((row_output + 1) * input.column_count)
+                {
+                    result += input.data[index_input] * weights.data[index_weights];
+                    index_weights += weights.column_count;
+                }
+
+                result += bias.data[row_output * column_count + column_output];
+                if apply_relu {
+                    result = result.max(T::zero());
+                }
+                max = Some(match max {
+                    Some(max) => max.max(result),
+                    None => result,
+                });
+                *output_element = result;
+            }
+        }
+
+        max.expect("linear_layer_rows was given an empty block of rows")
+    }
+
+    // Returns the max of every thread's block
+    fn linear_layer_parallel_inner(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
+        thread_count: usize,
+        apply_relu: bool,
+    ) -> Vec<T> {
+        let column_count: usize = output.column_count;
+        let row_block: usize = rows_per_thread(output.row_count, thread_count);
+        let element_count: usize = output.len();
+
+        thread::scope(|scope| {
+            let handles: Vec<ScopedJoinHandle<T>> = output.data[0..element_count]
+                .chunks_mut(row_block * column_count)
+                .enumerate()
+                .map(|(block_index, output_rows)| {
+                    scope.spawn(move || {
+                        Self::linear_layer_rows(
+                            input,
+                            weights,
+                            bias,
+                            output_rows,
+                            block_index * row_block,
+                            column_count,
+                            apply_relu,
+                        )
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("A linear layer thread panicked"))
+                .collect()
+        })
+    }
+
+    pub fn linear_layer_parallel(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
+        thread_count: usize,
+    ) {
+        let thread_count: usize =
+            effective_thread_count(thread_count, output.len() * input.column_count);
+        if thread_count < 2 || output.row_count < 2 {
+            Self::linear_layer_optimized(input, weights, bias, output);
+            return;
+        }
+
+        Self::linear_layer_parallel_inner(input, weights, bias, output, thread_count, false);
+    }
+
+    pub fn linear_layer_parallel_relu(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
+        thread_count: usize,
+    ) {
+        let thread_count: usize =
+            effective_thread_count(thread_count, output.len() * input.column_count);
+        if thread_count < 2 || output.row_count < 2 {
+            Self::linear_layer_optimized_relu(input, weights, bias, output);
+            return;
+        }
+
+        Self::linear_layer_parallel_inner(input, weights, bias, output, thread_count, true);
+    }
+
+    pub fn sum_parallel(&self, thread_count: usize) -> T {
+        let thread_count: usize = effective_thread_count(thread_count, self.len());
+        if thread_count < 2 {
+            return self.sum();
+        }
+
+        parallel_reduce(
+            &self.data[0..self.len()],
+            thread_count,
+            |block| {
+                let mut sum: T = T::zero();
+                for element in block {
+                    sum += *element;
+                }
+                sum
+            },
+            |left, right| left + right,
+        )
+    }
+}
+
+impl<T: FloatElement> Tensor2D<T> {
+    // Writes exp(input - offset) to the output, one block of rows per thread
+    fn softmax_normalize_parallel(input: &[T], output: &mut [T], offset: T, thread_count: usize) {
+        let block_size: usize = output.len().div_ceil(thread_count.max(1)).max(1);
+        thread::scope(|scope| {
+            for (input_block, output_block) in
+                input.chunks(block_size).zip(output.chunks_mut(block_size))
+            {
+                scope.spawn(move || {
+                    for (input_element, output_element) in
+                        input_block.iter().zip(output_block.iter_mut())
+                    {
+                        *output_element = (*input_element - offset).exp();
+                    }
+                });
+            }
+        });
+    }
+
+    // Every thread reads and writes the same block, so no copy of the input is needed
+    fn softmax_normalize_inplace_parallel(data: &mut [T], offset: T, thread_count: usize) {
+        let block_size: usize = data.len().div_ceil(thread_count.max(1)).max(1);
+        thread::scope(|scope| {
+            for block in data.chunks_mut(block_size) {
+                scope.spawn(move || {
+                    for element in block.iter_mut() {
+                        *element = (*element - offset).exp();
+                    }
+                });
+            }
+        });
+    }
+
+    // The log of the softmax denominator, max + ln(sum(exp(x - max)))
+    fn softmax_offset_parallel(data: &[T], max: T, thread_count: usize) -> T {
+        let sum: T = parallel_reduce(
+            data,
+            thread_count,
+            |block| {
+                let mut sum: T = T::zero();
+                for element in block {
+                    sum += (*element - max).exp();
+                }
+                sum
+            },
+            |left, right| left + right,
+        );
+
+        max + sum.ln()
+    }
+
+    fn max_parallel(data: &[T], thread_count: usize) -> T {
+        parallel_reduce(
+            data,
+            thread_count,
+            |block| {
+                let mut max: T = T::neg_infinity();
+                for element in block {
+                    max = max.max(*element);
+                }
+                max
+            },
+            |left, right| left.max(right),
+        )
+    }
+
+    pub fn softmax_parallel(input: &Tensor2D<T>, output: &mut Tensor2D<T>, thread_count: usize) {
+        let thread_count: usize = effective_thread_count(thread_count, output.len());
+        if thread_count < 2 {
+            Self::softmax_preallocated(input, output);
+            return;
+        }
+
+        let element_count: usize = output.len();
+        let input_data: &[T] = &input.data[0..element_count];
+        let max: T = Self::max_parallel(input_data, thread_count);
+        let offset: T = Self::softmax_offset_parallel(input_data, max, thread_count);
+        Self::softmax_normalize_parallel(
+            input_data,
+            &mut output.data[0..element_count],
+            offset,
+            thread_count,
+        );
+    }
+
+    pub fn softmax_inplace_parallel(out: &mut Tensor2D<T>, thread_count: usize) {
+        let thread_count: usize = effective_thread_count(thread_count, out.len());
+        if thread_count < 2 {
+            Self::softmax_inplace(out);
+            return;
+        }
+
+        let element_count: usize = out.len();
+        let max: T = Self::max_parallel(&out.data[0..element_count], thread_count);
+        let offset: T =
+            Self::softmax_offset_parallel(&out.data[0..element_count], max, thread_count);
+
+        Self::softmax_normalize_inplace_parallel(
+            &mut out.data[0..element_count],
+            offset,
+            thread_count,
+        );
+    }
+
+    // The linear layer and ReLU are computed per block of rows, and every
+    // thread hands back the largest value in its block, which saves the softmax
+    // a pass over the data to find the max.
+    pub fn linear_relu_softmax_fused_parallel(
+        input: &Tensor2D<T>,
+        weights: &Tensor2D<T>,
+        bias: &Tensor2D<T>,
+        output: &mut Tensor2D<T>,
+        thread_count: usize,
+    ) {
+        let thread_count: usize =
+            effective_thread_count(thread_count, output.len() * input.column_count);
+        if thread_count < 2 || output.row_count < 2 {
+            Self::linear_relu_softmax_fused(input, weights, bias, output);
+            return;
+        }
+
+        let block_maxes: Vec<T> =
+            Self::linear_layer_parallel_inner(input, weights, bias, output, thread_count, true);
+        let mut max: T = T::neg_infinity();
+        for block_max in block_maxes {
+            max = max.max(block_max);
+        }
+
+        let element_count: usize = output.len();
+        let offset: T =
+            Self::softmax_offset_parallel(&output.data[0..element_count], max, thread_count);
+        Self::softmax_normalize_inplace_parallel(
+            &mut output.data[0..element_count],
+            offset,
+            thread_count,
+        );
+    }
+}
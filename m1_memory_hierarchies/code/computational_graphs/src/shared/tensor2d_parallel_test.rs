@@ -0,0 +1,189 @@
+#[cfg(test)]
+mod tests {
+    use crate::shared::tensor2d::Tensor2D;
+    use crate::shared::tensor2d_parallel::{
+        available_thread_count, effective_thread_count, resolve_thread_count,
+        MINIMUM_WORK_PER_THREAD,
+    };
+
+    const ERROR_TOLERANCE: f32 = 0.00001;
+
+    fn max_difference(left: &Tensor2D, right: &Tensor2D) -> f32 {
+        assert_eq!(
+            (left.row_count, left.column_count),
+            (right.row_count, right.column_count)
+        );
+        left.data
+            .iter()
+            .zip(right.data.iter())
+            .map(|(left, right)| (left - right).abs())
+            .fold(0.0, f32::max)
+    }
+
+    // More threads than rows, and row counts which don't divide evenly
+    const THREAD_COUNTS: [usize; 4] = [1, 2, 3, 16];
+
+    #[test]
+    fn linear_layer_parallel() {
+        for row_count in 1..10 {
+            for column_count in [1, 5, 8] {
+                let input: Tensor2D = Tensor2D::new(0.01, row_count, 7);
+                let weights: Tensor2D = Tensor2D::new(0.02, 7, column_count);
+                let bias: Tensor2D = Tensor2D::new(-0.03, row_count, column_count);
+
+                let mut expected: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+                Tensor2D::linear_layer_optimized(&input, &weights, &bias, &mut expected);
+                let mut expected_relu: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+                Tensor2D::linear_layer_optimized_relu(&input, &weights, &bias, &mut expected_relu);
+
+                for thread_count in THREAD_COUNTS {
+                    // The rows are computed exactly like the single-threaded kernel
+                    let mut output: Tensor2D = Tensor2D::new(1.0, row_count, column_count);
+                    Tensor2D::linear_layer_parallel(
+                        &input,
+                        &weights,
+                        &bias,
+                        &mut output,
+                        thread_count,
+                    );
+                    assert_eq!(output.data, expected.data);
+
+                    let mut output: Tensor2D = Tensor2D::new(1.0, row_count, column_count);
+                    Tensor2D::linear_layer_parallel_relu(
+                        &input,
+                        &weights,
+                        &bias,
+                        &mut output,
+                        thread_count,
+                    );
+                    assert_eq!(output.data, expected_relu.data);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn softmax_parallel() {
+        for row_count in 1..10 {
+            let input: Tensor2D = Tensor2D::new(0.1, row_count, 6);
+            let expected: Tensor2D = Tensor2D::softmax(&input);
+
+            for thread_count in THREAD_COUNTS {
+                let mut output: Tensor2D = Tensor2D::new(0.0, row_count, 6);
+                Tensor2D::softmax_parallel(&input, &mut output, thread_count);
+                assert!(max_difference(&expected, &output) < ERROR_TOLERANCE);
+
+                let mut output: Tensor2D = input.clone();
+                Tensor2D::softmax_inplace_parallel(&mut output, thread_count);
+                assert!(max_difference(&expected, &output) < ERROR_TOLERANCE);
+            }
+        }
+    }
+
+    #[test]
+    fn linear_relu_softmax_fused_parallel() {
+        for row_count in 1..10 {
+            let input: Tensor2D = Tensor2D::new(0.01, row_count, 5);
+            let weights: Tensor2D = Tensor2D::new(0.02, 5, 4);
+            let bias: Tensor2D = Tensor2D::new(-0.01, row_count, 4);
+
+            let mut expected: Tensor2D = Tensor2D::new(0.0, row_count, 4);
+            Tensor2D::linear_relu_softmax_fused(&input, &weights, &bias, &mut expected);
+
+            for thread_count in THREAD_COUNTS {
+                let mut output: Tensor2D = Tensor2D::new(0.0, row_count, 4);
+                Tensor2D::linear_relu_softmax_fused_parallel(
+                    &input,
+                    &weights,
+                    &bias,
+                    &mut output,
+                    thread_count,
+                );
+                assert!(max_difference(&expected, &output) < ERROR_TOLERANCE);
+            }
+        }
+    }
+
+    #[test]
+    fn sum_parallel() {
+        let tensor: Tensor2D<i32> = Tensor2D::<i32>::new(3, 17, 5);
+        for thread_count in THREAD_COUNTS {
+            assert_eq!(tensor.sum_parallel(thread_count), tensor.sum());
+        }
+
+        let tensor: Tensor2D = Tensor2D::new(0.001, 9, 9);
+        for thread_count in THREAD_COUNTS {
+            assert!((tensor.sum_parallel(thread_count) - tensor.sum()).abs() < ERROR_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn thread_count() {
+        assert_eq!(resolve_thread_count(0), available_thread_count());
+        assert_eq!(resolve_thread_count(3), 3);
+
+        // Small tensors stay on the calling thread
+        assert_eq!(effective_thread_count(16, 0), 1);
+        assert_eq!(
+            effective_thread_count(16, MINIMUM_WORK_PER_THREAD * 2 - 1),
+            1
+        );
+        assert_eq!(effective_thread_count(16, MINIMUM_WORK_PER_THREAD * 2), 2);
+        assert_eq!(
+            effective_thread_count(16, MINIMUM_WORK_PER_THREAD * 100),
+            16
+        );
+        assert_eq!(effective_thread_count(0, MINIMUM_WORK_PER_THREAD * 100), 1);
+    }
+
+    // The tests above are all below the threshold, these are large enough to be split
+    #[test]
+    fn large_tensors() {
+        let input: Tensor2D = Tensor2D::new(0.01, 67, 256);
+        let weights: Tensor2D = Tensor2D::new(0.02, 256, 61);
+        let bias: Tensor2D = Tensor2D::new(-0.03, 67, 61);
+        assert_eq!(
+            effective_thread_count(16, input.len() * weights.column_count),
+            16
+        );
+
+        let mut expected: Tensor2D = Tensor2D::new(0.0, 67, 61);
+        Tensor2D::linear_layer_optimized(&input, &weights, &bias, &mut expected);
+        let mut expected_fused: Tensor2D = Tensor2D::new(0.0, 67, 61);
+        Tensor2D::linear_relu_softmax_fused(&input, &weights, &bias, &mut expected_fused);
+        for thread_count in THREAD_COUNTS {
+            let mut output: Tensor2D = Tensor2D::new(1.0, 67, 61);
+            Tensor2D::linear_layer_parallel(&input, &weights, &bias, &mut output, thread_count);
+            assert_eq!(output.data, expected.data);
+
+            let mut output: Tensor2D = Tensor2D::new(0.0, 67, 61);
+            Tensor2D::linear_relu_softmax_fused_parallel(
+                &input,
+                &weights,
+                &bias,
+                &mut output,
+                thread_count,
+            );
+            assert!(max_difference(&expected_fused, &output) < ERROR_TOLERANCE);
+        }
+
+        let input: Tensor2D = Tensor2D::new(0.1, 1031, 509);
+        assert_eq!(effective_thread_count(16, input.len()), 16);
+        let expected: Tensor2D = Tensor2D::softmax(&input);
+        // Too large for the sum of an i32 tensor, f64 keeps the rounding differences tiny
+        let tensor: Tensor2D<f64> = Tensor2D::<f64>::new(0.001, 1031, 509);
+        for thread_count in THREAD_COUNTS {
+            let mut output: Tensor2D = Tensor2D::new(0.0, 1031, 509);
+            Tensor2D::softmax_parallel(&input, &mut output, thread_count);
+            assert!(max_difference(&expected, &output) < ERROR_TOLERANCE);
+
+            let mut output: Tensor2D = input.clone();
+            Tensor2D::softmax_inplace_parallel(&mut output, thread_count);
+            assert!(max_difference(&expected, &output) < ERROR_TOLERANCE);
+
+            assert!(
+                ((tensor.sum_parallel(thread_count) - tensor.sum()) / tensor.sum()).abs() < 1e-12
+            );
+        }
+    }
+}
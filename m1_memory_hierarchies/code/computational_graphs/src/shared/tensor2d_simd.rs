@@ -0,0 +1,467 @@
+use super::tensor2d::Tensor2D;
+
+// Explicitly vectorized versions of the f32 CPU kernels.
+// The compiler might autovectorize linear_layer_optimized and relu_inplace_inline,
+// but whether it does depends on the compiler version, the optimization level
+// and how the loops happen to be written. Here we write the SIMD instructions ourselves.
+//
+// Which instructions a CPU supports isn't known until the program runs,
+// so every kernel checks for AVX2 and FMA at runtime and falls back to
+// the existing scalar kernel if they aren't there. AVX2 registers hold
+// 8 f32's, so the kernels work on 8 columns at a time, and handle the
+// columns left over at the end of a row with regular scalar code.
+// Only x86_64 is supported for now, every other architecture gets the scalar kernels.
+
+pub fn simd_available() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    const LANE_COUNT: usize = 8;
+    // How many rows of the output the matrix multiplication micro-kernel computes at once.
+    // Every row of weights loaded is used ROW_BLOCK times before it is thrown away.
+    const ROW_BLOCK: usize = 4;
+
+    #[inline]
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn horizontal_sum(values: __m256) -> f32 {
+        let low: __m128 = _mm256_castps256_ps128(values);
+        let high: __m128 = _mm256_extractf128_ps(values, 1);
+        let sum: __m128 = _mm_add_ps(low, high);
+        let sum: __m128 = _mm_add_ps(sum, _mm_movehl_ps(sum, sum));
+        let sum: __m128 = _mm_add_ss(sum, _mm_shuffle_ps(sum, sum, 0b01));
+        _mm_cvtss_f32(sum)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn horizontal_max(values: __m256) -> f32 {
+        let low: __m128 = _mm256_castps256_ps128(values);
+        let high: __m128 = _mm256_extractf128_ps(values, 1);
+        let max: __m128 = _mm_max_ps(low, high);
+        let max: __m128 = _mm_max_ps(max, _mm_movehl_ps(max, max));
+        let max: __m128 = _mm_max_ss(max, _mm_shuffle_ps(max, max, 0b01));
+        _mm_cvtss_f32(max)
+    }
+
+    // There is no exp instruction, so we use the same polynomial
+    // approximation as the Cephes library. x is split in to n * ln(2) + r,
+    // exp(r) is approximated with a polynomial and 2^n is built directly
+    // in the exponent bits of the float. Accurate to about 1 ulp.
+    #[inline]
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn exp(x: __m256) -> __m256 {
+        let x: __m256 = _mm256_min_ps(x, _mm256_set1_ps(88.376_26));
+        let x: __m256 = _mm256_max_ps(x, _mm256_set1_ps(-88.376_26));
+
+        let n: __m256 = _mm256_floor_ps(_mm256_fmadd_ps(
+            x,
+            _mm256_set1_ps(std::f32::consts::LOG2_E),
+            _mm256_set1_ps(0.5),
+        ));
+        // ln(2) is split in two, so the subtraction doesn't lose precision
+        let r: __m256 = _mm256_fnmadd_ps(n, _mm256_set1_ps(0.693_359_4), x);
+        let r: __m256 = _mm256_fnmadd_ps(n, _mm256_set1_ps(-2.121_944_4e-4), r);
+
+        let mut y: __m256 = _mm256_set1_ps(1.987_569_1e-4);
+        y = _mm256_fmadd_ps(y, r, _mm256_set1_ps(1.398_2e-3));
+        y = _mm256_fmadd_ps(y, r, _mm256_set1_ps(8.333_452e-3));
+        y = _mm256_fmadd_ps(y, r, _mm256_set1_ps(4.166_579_6e-2));
+        y = _mm256_fmadd_ps(y, r, _mm256_set1_ps(1.666_666_5e-1));
+        y = _mm256_fmadd_ps(y, r, _mm256_set1_ps(0.5));
+        y = _mm256_fmadd_ps(y, _mm256_mul_ps(r, r), r);
+        y = _mm256_add_ps(y, _mm256_set1_ps(1.0));
+
+        let exponent: __m256i = _mm256_slli_epi32(
+            _mm256_add_epi32(_mm256_cvtps_epi32(n), _mm256_set1_epi32(127)),
+            23,
+        );
+        _mm256_mul_ps(y, _mm256_castsi256_ps(exponent))
+    }
+
+    // Computes ROW_COUNT rows, starting at first_row, for 8 columns starting at column.
+    // Each row gets its own accumulator, and every weight loaded is
+    // multiplied with a value from each of the rows.
+    #[inline]
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn linear_layer_micro_kernel<const ROW_COUNT: usize>(
+        input: &[f32],
+        weights: &[f32],
+        bias: &[f32],
+        output: &mut [f32],
+        inner_dimension: usize,
+        column_count: usize,
+        first_row: usize,
+        column: usize,
+        apply_relu: bool,
+    ) {
+        let mut accumulators: [__m256; ROW_COUNT] = [_mm256_setzero_ps(); ROW_COUNT];
+        for inner_index in 0..inner_dimension {
+            let weight_row: __m256 =
+                _mm256_loadu_ps(weights.as_ptr().add(inner_index * column_count + column));
+            for (row_offset, accumulator) in accumulators.iter_mut().enumerate() {
+                let input_value: __m256 = _mm256_set1_ps(
+                    *input.get_unchecked((first_row + row_offset) * inner_dimension + inner_index),
+                );
+                *accumulator = _mm256_fmadd_ps(input_value, weight_row, *accumulator);
+            }
+        }
+
+        for (row_offset, accumulator) in accumulators.iter().enumerate() {
+            let index: usize = (first_row + row_offset) * column_count + column;
+            let mut result: __m256 =
+                _mm256_add_ps(*accumulator, _mm256_loadu_ps(bias.as_ptr().add(index)));
+            if apply_relu {
+                result = _mm256_max_ps(result, _mm256_setzero_ps());
+            }
+            _mm256_storeu_ps(output.as_mut_ptr().add(index), result);
+        }
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn linear_layer(
+        input: &[f32],
+        weights: &[f32],
+        bias: &[f32],
+        output: &mut [f32],
+        row_count: usize,
+        inner_dimension: usize,
+        column_count: usize,
+        apply_relu: bool,
+    ) {
+        let vector_column_count: usize = column_count - column_count % LANE_COUNT;
+        let block_row_count: usize = row_count - row_count % ROW_BLOCK;
+
+        for column in (0..vector_column_count).step_by(LANE_COUNT) {
+            for first_row in (0..block_row_count).step_by(ROW_BLOCK) {
+                linear_layer_micro_kernel::<ROW_BLOCK>(
+                    input,
+                    weights,
+                    bias,
+                    output,
+                    inner_dimension,
+                    column_count,
+                    first_row,
+                    column,
+                    apply_relu,
+                );
+            }
+            for row in block_row_count..row_count {
+                linear_layer_micro_kernel::<1>(
+                    input,
+                    weights,
+                    bias,
+                    output,
+                    inner_dimension,
+                    column_count,
+                    row,
+                    column,
+                    apply_relu,
+                );
+            }
+        }
+
+        // The columns which don't fill a whole register
+        for row in 0..row_count {
+            for column in vector_column_count..column_count {
+                let mut result: f32 = 0.0;
+                for inner_index in 0..inner_dimension {
+                    result += input[row * inner_dimension + inner_index]
+                        * weights[inner_index * column_count + column];
+                }
+                let index: usize = row * column_count + column;
+                result += bias[index];
+                output[index] = if apply_relu { result.max(0.0) } else { result };
+            }
+        }
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn relu(input: &[f32], output: &mut [f32]) {
+        let vector_length: usize = output.len() - output.len() % LANE_COUNT;
+        let zero: __m256 = _mm256_setzero_ps();
+        for index in (0..vector_length).step_by(LANE_COUNT) {
+            let values: __m256 = _mm256_loadu_ps(input.as_ptr().add(index));
+            _mm256_storeu_ps(output.as_mut_ptr().add(index), _mm256_max_ps(values, zero));
+        }
+        for index in vector_length..output.len() {
+            output[index] = input[index].max(0.0);
+        }
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn relu_inplace(data: &mut [f32]) {
+        let vector_length: usize = data.len() - data.len() % LANE_COUNT;
+        let zero: __m256 = _mm256_setzero_ps();
+        for index in (0..vector_length).step_by(LANE_COUNT) {
+            let pointer: *mut f32 = data.as_mut_ptr().add(index);
+            _mm256_storeu_ps(pointer, _mm256_max_ps(_mm256_loadu_ps(pointer), zero));
+        }
+        for element in &mut data[vector_length..] {
+            *element = element.max(0.0);
+        }
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn max(data: &[f32]) -> f32 {
+        let vector_length: usize = data.len() - data.len() % LANE_COUNT;
+        let mut maxes: __m256 = _mm256_set1_ps(f32::NEG_INFINITY);
+        for index in (0..vector_length).step_by(LANE_COUNT) {
+            maxes = _mm256_max_ps(maxes, _mm256_loadu_ps(data.as_ptr().add(index)));
+        }
+
+        let mut max: f32 = horizontal_max(maxes);
+        for element in &data[vector_length..] {
+            max = max.max(*element);
+        }
+        max
+    }
+
+    // Writes exp(input - max) to the output and returns the sum of the results.
+    // Takes raw pointers, so input and output can be the same buffer for the
+    // in-place softmax. Each element is read before it is written.
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn exp_and_sum(
+        input: *const f32,
+        output: *mut f32,
+        element_count: usize,
+        max: f32,
+    ) -> f32 {
+        let vector_length: usize = element_count - element_count % LANE_COUNT;
+        let max_vector: __m256 = _mm256_set1_ps(max);
+        let mut sums: __m256 = _mm256_setzero_ps();
+        for index in (0..vector_length).step_by(LANE_COUNT) {
+            let values: __m256 = _mm256_loadu_ps(input.add(index));
+            let exponentials: __m256 = exp(_mm256_sub_ps(values, max_vector));
+            _mm256_storeu_ps(output.add(index), exponentials);
+            sums = _mm256_add_ps(sums, exponentials);
+        }
+
+        let mut sum: f32 = horizontal_sum(sums);
+        for index in vector_length..element_count {
+            let exponential: f32 = (*input.add(index) - max).exp();
+            *output.add(index) = exponential;
+            sum += exponential;
+        }
+        sum
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn scale(data: &mut [f32], factor: f32) {
+        let vector_length: usize = data.len() - data.len() % LANE_COUNT;
+        let factor_vector: __m256 = _mm256_set1_ps(factor);
+        for index in (0..vector_length).step_by(LANE_COUNT) {
+            let pointer: *mut f32 = data.as_mut_ptr().add(index);
+            _mm256_storeu_ps(
+                pointer,
+                _mm256_mul_ps(_mm256_loadu_ps(pointer), factor_vector),
+            );
+        }
+        for element in &mut data[vector_length..] {
+            *element *= factor;
+        }
+    }
+}
+
+// The AVX2 kernels index with raw pointers, so unlike linear_layer_assert
+// these checks are left in for release builds. Every buffer has to hold
+// row_count * column_count elements, the fields are public after all.
+fn assert_buffer_lengths(function: &str, tensors: &[&Tensor2D<f32>]) {
+    for tensor in tensors {
+        assert!(
+            tensor.len() <= tensor.data.len(),
+            "{} was given a tensor of {} rows and {} columns, but only {} elements of data",
+            function,
+            tensor.row_count,
+            tensor.column_count,
+            tensor.data.len()
+        );
+    }
+}
+
+fn assert_linear_layer_shapes(
+    function: &str,
+    input: &Tensor2D<f32>,
+    weights: &Tensor2D<f32>,
+    bias: &Tensor2D<f32>,
+    output: &Tensor2D<f32>,
+) {
+    assert!(
+        input.column_count == weights.row_count
+            && input.row_count == output.row_count
+            && weights.column_count == output.column_count
+            && bias.row_count == output.row_count
+            && bias.column_count == output.column_count,
+        "{} was given mismatched shapes. input: {}x{}, weights: {}x{}, bias: {}x{}, output: {}x{}",
+        function,
+        input.row_count,
+        input.column_count,
+        weights.row_count,
+        weights.column_count,
+        bias.row_count,
+        bias.column_count,
+        output.row_count,
+        output.column_count
+    );
+    assert_buffer_lengths(function, &[input, weights, bias, output]);
+}
+
+impl Tensor2D<f32> {
+    pub fn linear_layer_simd(
+        input: &Tensor2D<f32>,
+        weights: &Tensor2D<f32>,
+        bias: &Tensor2D<f32>,
+        output: &mut Tensor2D<f32>,
+    ) {
+        Self::linear_layer_assert(input, weights, bias, output);
+
+        #[cfg(target_arch = "x86_64")]
+        if simd_available() {
+            assert_linear_layer_shapes("linear_layer_simd", input, weights, bias, output);
+            // Safe as we just checked the CPU supports the instructions,
+            // and assert_linear_layer_shapes checks, in release builds as well, that
+            // the shapes agree and every buffer holds all of its elements.
+            unsafe {
+                avx2::linear_layer(
+                    &input.data,
+                    &weights.data,
+                    &bias.data,
+                    &mut output.data,
+                    output.row_count,
+                    input.column_count,
+                    output.column_count,
+                    false,
+                );
+            }
+            return;
+        }
+
+        Self::linear_layer_optimized(input, weights, bias, output);
+    }
+
+    pub fn linear_layer_simd_relu(
+        input: &Tensor2D<f32>,
+        weights: &Tensor2D<f32>,
+        bias: &Tensor2D<f32>,
+        output: &mut Tensor2D<f32>,
+    ) {
+        Self::linear_layer_assert(input, weights, bias, output);
+
+        #[cfg(target_arch = "x86_64")]
+        if simd_available() {
+            assert_linear_layer_shapes("linear_layer_simd_relu", input, weights, bias, output);
+            // Safe for the same reasons as in linear_layer_simd
+            unsafe {
+                avx2::linear_layer(
+                    &input.data,
+                    &weights.data,
+                    &bias.data,
+                    &mut output.data,
+                    output.row_count,
+                    input.column_count,
+                    output.column_count,
+                    true,
+                );
+            }
+            return;
+        }
+
+        Self::linear_layer_optimized_relu(input, weights, bias, output);
+    }
+
+    pub fn relu_simd(input: &Tensor2D<f32>, output: &mut Tensor2D<f32>) {
+        assert_eq!(input.len(), output.len());
+        assert_buffer_lengths("relu_simd", &[input, output]);
+        let element_count: usize = output.len();
+
+        #[cfg(target_arch = "x86_64")]
+        if simd_available() {
+            // Safe as the CPU supports the instructions and the asserts above check
+            // that both slices hold element_count elements, which is all the kernel touches
+            unsafe {
+                avx2::relu(
+                    &input.data[0..element_count],
+                    &mut output.data[0..element_count],
+                );
+            }
+            return;
+        }
+
+        Self::relu_preallocated(input, output);
+    }
+
+    pub fn relu_inplace_simd(data: &mut Tensor2D<f32>) {
+        assert_buffer_lengths("relu_inplace_simd", &[data]);
+        let element_count: usize = data.len();
+
+        #[cfg(target_arch = "x86_64")]
+        if simd_available() {
+            // Safe as the CPU supports the instructions and the kernel stays within the slice
+            unsafe {
+                avx2::relu_inplace(&mut data.data[0..element_count]);
+            }
+            return;
+        }
+
+        Self::relu_inplace_inline(data);
+    }
+
+    // Like softmax_optimized, exp() is only computed once per element,
+    // and the division by the sum is a multiplication with its reciprocal.
+    pub fn softmax_simd(input: &Tensor2D<f32>, output: &mut Tensor2D<f32>) {
+        assert_eq!(input.len(), output.len());
+        assert_buffer_lengths("softmax_simd", &[input, output]);
+        let element_count: usize = output.len();
+
+        #[cfg(target_arch = "x86_64")]
+        if simd_available() {
+            // Safe as the CPU supports the instructions and the asserts above check that
+            // both slices, and so both pointers given to exp_and_sum, hold element_count elements
+            unsafe {
+                let input_data: &[f32] = &input.data[0..element_count];
+                let output_data: &mut [f32] = &mut output.data[0..element_count];
+                let max: f32 = avx2::max(input_data);
+                let sum: f32 = avx2::exp_and_sum(
+                    input_data.as_ptr(),
+                    output_data.as_mut_ptr(),
+                    element_count,
+                    max,
+                );
+                avx2::scale(output_data, 1.0 / sum);
+            }
+            return;
+        }
+
+        Self::softmax_preallocated(input, output);
+    }
+
+    pub fn softmax_inplace_simd(data: &mut Tensor2D<f32>) {
+        assert_buffer_lengths("softmax_inplace_simd", &[data]);
+        let element_count: usize = data.len();
+
+        #[cfg(target_arch = "x86_64")]
+        if simd_available() {
+            // Safe as the CPU supports the instructions and the kernel stays within the slice
+            unsafe {
+                let values: &mut [f32] = &mut data.data[0..element_count];
+                let max: f32 = avx2::max(values);
+                let pointer: *mut f32 = values.as_mut_ptr();
+                let sum: f32 = avx2::exp_and_sum(pointer, pointer, element_count, max);
+                avx2::scale(values, 1.0 / sum);
+            }
+            return;
+        }
+
+        Self::softmax_optimized(data);
+    }
+}
@@ -0,0 +1,121 @@
+#[cfg(test)]
+mod tests {
+    use crate::shared::tensor2d::Tensor2D;
+
+    // FMA rounds once instead of twice, and exp is an approximation,
+    // so the results are close to the scalar kernels, but not identical.
+    const ERROR_TOLERANCE: f32 = 0.00001;
+
+    fn relative_difference(expected: &Tensor2D, actual: &Tensor2D) -> f32 {
+        assert_eq!(
+            (expected.row_count, expected.column_count),
+            (actual.row_count, actual.column_count)
+        );
+        expected
+            .data
+            .iter()
+            .zip(actual.data.iter())
+            .map(|(expected, actual)| (expected - actual).abs() / expected.abs().max(1.0))
+            .fold(0.0, f32::max)
+    }
+
+    // Covers the 4 row micro-kernel, the single row kernel and the scalar columns
+    const DIMENSIONS: [usize; 7] = [1, 3, 4, 7, 8, 9, 21];
+
+    #[test]
+    fn linear_layer_simd() {
+        for row_count in DIMENSIONS {
+            for column_count in DIMENSIONS {
+                for inner_dimension in [1, 5, 16] {
+                    let input: Tensor2D = Tensor2D::new(0.01, row_count, inner_dimension);
+                    let weights: Tensor2D = Tensor2D::new(-0.02, inner_dimension, column_count);
+                    let bias: Tensor2D = Tensor2D::new(0.03, row_count, column_count);
+
+                    let mut expected: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+                    Tensor2D::linear_layer_optimized(&input, &weights, &bias, &mut expected);
+                    let mut output: Tensor2D = Tensor2D::new(0.0, row_count, column_count);
+                    Tensor2D::linear_layer_simd(&input, &weights, &bias, &mut output);
+                    assert!(relative_difference(&expected, &output) < ERROR_TOLERANCE);
+
+                    Tensor2D::linear_layer_optimized_relu(&input, &weights, &bias, &mut expected);
+                    Tensor2D::linear_layer_simd_relu(&input, &weights, &bias, &mut output);
+                    assert!(relative_difference(&expected, &output) < ERROR_TOLERANCE);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn relu_simd() {
+        for element_count in DIMENSIONS {
+            let input: Tensor2D = Tensor2D::new(-0.5, 3, element_count);
+            let mut shifted: Tensor2D = input.clone();
+            for element in shifted.data.iter_mut() {
+                *element += 4.0;
+            }
+
+            for input in [input, shifted] {
+                let expected: Tensor2D = Tensor2D::relu(&input);
+
+                let mut output: Tensor2D = Tensor2D::new(1.0, 3, element_count);
+                Tensor2D::relu_simd(&input, &mut output);
+                assert_eq!(output.data, expected.data);
+
+                let mut output: Tensor2D = input.clone();
+                Tensor2D::relu_inplace_simd(&mut output);
+                assert_eq!(output.data, expected.data);
+            }
+        }
+    }
+
+    #[test]
+    fn softmax_simd() {
+        for element_count in DIMENSIONS {
+            for scale in [-1.5, 0.1, 2.0] {
+                let input: Tensor2D = Tensor2D::new(scale, 2, element_count);
+                let expected: Tensor2D = Tensor2D::softmax(&input);
+
+                let mut output: Tensor2D = Tensor2D::new(0.0, 2, element_count);
+                Tensor2D::softmax_simd(&input, &mut output);
+                assert!(relative_difference(&expected, &output) < ERROR_TOLERANCE);
+                assert!((output.sum() - 1.0).abs() < ERROR_TOLERANCE);
+
+                let mut output: Tensor2D = input.clone();
+                Tensor2D::softmax_inplace_simd(&mut output);
+                assert!(relative_difference(&expected, &output) < ERROR_TOLERANCE);
+            }
+        }
+    }
+
+    // The fields are public, so a tensor can claim more elements than its data holds.
+    // The kernels have to refuse it, in release builds too, instead of reading past the end.
+    #[test]
+    #[should_panic]
+    fn linear_layer_simd_short_data() {
+        let input: Tensor2D = Tensor2D::new(0.01, 9, 16);
+        let mut weights: Tensor2D = Tensor2D::new(-0.02, 16, 21);
+        weights.data.truncate(16 * 8);
+        let bias: Tensor2D = Tensor2D::new(0.03, 9, 21);
+        let mut output: Tensor2D = Tensor2D::new(0.0, 9, 21);
+        Tensor2D::linear_layer_simd(&input, &weights, &bias, &mut output);
+    }
+
+    #[test]
+    #[should_panic]
+    fn linear_layer_simd_mismatched_shapes() {
+        let input: Tensor2D = Tensor2D::new(0.01, 9, 16);
+        let weights: Tensor2D = Tensor2D::new(-0.02, 16, 21);
+        let bias: Tensor2D = Tensor2D::new(0.03, 4, 21);
+        let mut output: Tensor2D = Tensor2D::new(0.0, 4, 21);
+        Tensor2D::linear_layer_simd(&input, &weights, &bias, &mut output);
+    }
+
+    #[test]
+    #[should_panic]
+    fn softmax_simd_short_data() {
+        let mut input: Tensor2D = Tensor2D::new(0.5, 4, 21);
+        input.data.truncate(40);
+        let mut output: Tensor2D = Tensor2D::new(0.0, 4, 21);
+        Tensor2D::softmax_simd(&input, &mut output);
+    }
+}
@@ -0,0 +1,87 @@
+use super::tensor2d::Tensor2D;
+use super::tensor_element::FloatElement;
+
+// Softmax along one axis of the tensor. Every row (axis 1) or every column (axis 0)
+// is its own lane, which is normalized by itself like softmax normalizes the whole tensor.
+
+// The layout of the lanes in the row major data, as
+// (lane_count, lane_length, lane_stride, element_stride).
+// Lane l starts at l * lane_stride, and its elements are element_stride apart.
+// The GPU version of softmax along an axis uses the exact same layout.
+pub fn softmax_lanes(
+    row_count: usize,
+    column_count: usize,
+    axis: usize,
+) -> (usize, usize, usize, usize) {
+    if axis == 0 {
+        (column_count, row_count, 1, column_count)
+    } else {
+        (row_count, column_count, column_count, 1)
+    }
+}
+
+impl<T: FloatElement> Tensor2D<T> {
+    pub fn softmax_axis(input: &Tensor2D<T>, axis: usize) -> Tensor2D<T> {
+        let mut output: Tensor2D<T> = Tensor2D::new(T::zero(), input.row_count, input.column_count);
+
+        Self::softmax_axis_preallocated(input, axis, &mut output);
+
+        output
+    }
+
+    pub fn softmax_axis_preallocated(input: &Tensor2D<T>, axis: usize, output: &mut Tensor2D<T>) {
+        let (lane_count, lane_length, lane_stride, element_stride) =
+            softmax_lanes(input.row_count, input.column_count, axis);
+
+        for lane_index in 0..lane_count {
+            let start: usize = lane_index * lane_stride;
+
+            let mut max: T = T::neg_infinity();
+            for element_index in 0..lane_length {
+                let value: T = input.data[start + element_index * element_stride];
+                if max < value {
+                    max = value;
+                }
+            }
+
+            let mut sum: T = T::zero();
+            for element_index in 0..lane_length {
+                let index: usize = start + element_index * element_stride;
+                output.data[index] = (input.data[index] - max).exp();
+                sum += output.data[index];
+            }
+
+            for element_index in 0..lane_length {
+                let index: usize = start + element_index * element_stride;
+                output.data[index] = output.data[index] / sum;
+            }
+        }
+    }
+
+    // Same as softmax_backward, with the dot product taken per lane
+    pub fn softmax_axis_backward(
+        output: &Tensor2D<T>,
+        output_gradient: &Tensor2D<T>,
+        axis: usize,
+        input_gradient: &mut Tensor2D<T>,
+    ) {
+        let (lane_count, lane_length, lane_stride, element_stride) =
+            softmax_lanes(output.row_count, output.column_count, axis);
+
+        for lane_index in 0..lane_count {
+            let start: usize = lane_index * lane_stride;
+
+            let mut dot: T = T::zero();
+            for element_index in 0..lane_length {
+                let index: usize = start + element_index * element_stride;
+                dot += output.data[index] * output_gradient.data[index];
+            }
+
+            for element_index in 0..lane_length {
+                let index: usize = start + element_index * element_stride;
+                input_gradient.data[index] +=
+                    output.data[index] * (output_gradient.data[index] - dot);
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod tests {
+    use crate::shared::tensor2d::Tensor2D;
+
+    const STEP: f64 = 0.000001;
+    const ERROR_TOLERANCE: f64 = 0.00001;
+
+    fn test_tensor(offset: f64, row_count: usize, column_count: usize) -> Tensor2D<f64> {
+        let mut tensor: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, row_count, column_count);
+        for (index, value) in tensor.data.iter_mut().enumerate() {
+            *value = ((index as f64 + offset) * 1.7).sin() * 4.0;
+        }
+        tensor
+    }
+
+    fn transpose(tensor: &Tensor2D<f64>) -> Tensor2D<f64> {
+        let mut transposed: Tensor2D<f64> =
+            Tensor2D::<f64>::new(0.0, tensor.column_count, tensor.row_count);
+        for row_index in 0..tensor.row_count {
+            for column_index in 0..tensor.column_count {
+                transposed.data[column_index * tensor.row_count + row_index] =
+                    tensor.data[row_index * tensor.column_count + column_index];
+            }
+        }
+        transposed
+    }
+
+    fn assert_close(expected: &Tensor2D<f64>, actual: &Tensor2D<f64>) {
+        assert_eq!(
+            (expected.row_count, expected.column_count),
+            (actual.row_count, actual.column_count)
+        );
+        for (expected, actual) in expected.data.iter().zip(actual.data.iter()) {
+            assert!(
+                (expected - actual).abs() < ERROR_TOLERANCE,
+                "expected {} but got {}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn rows_and_columns() {
+        for (row_count, column_count) in [(1, 1), (1, 7), (4, 3), (5, 40)] {
+            let input: Tensor2D<f64> = test_tensor(0.0, row_count, column_count);
+
+            // Every row is the softmax over the whole of a single row tensor
+            let rows: Tensor2D<f64> = Tensor2D::<f64>::softmax_axis(&input, 1);
+            for row_index in 0..row_count {
+                let range: std::ops::Range<usize> =
+                    row_index * column_count..(row_index + 1) * column_count;
+                let mut row: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, 1, column_count);
+                row.data.copy_from_slice(&input.data[range.clone()]);
+                let expected: Tensor2D<f64> = Tensor2D::<f64>::softmax(&row);
+                for (expected, actual) in expected.data.iter().zip(rows.data[range].iter()) {
+                    assert!((expected - actual).abs() < ERROR_TOLERANCE);
+                }
+            }
+
+            // The columns are the rows of the transpose
+            let columns: Tensor2D<f64> = Tensor2D::<f64>::softmax_axis(&input, 0);
+            let expected: Tensor2D<f64> =
+                transpose(&Tensor2D::<f64>::softmax_axis(&transpose(&input), 1));
+            assert_close(&expected, &columns);
+        }
+    }
+
+    #[test]
+    fn backward() {
+        for axis in [0, 1] {
+            let (row_count, column_count): (usize, usize) = (3, 4);
+            let input: Tensor2D<f64> = test_tensor(0.0, row_count, column_count);
+            let weights: Tensor2D<f64> = test_tensor(1.0, row_count, column_count);
+            let loss = |input: &Tensor2D<f64>| -> f64 {
+                Tensor2D::<f64>::softmax_axis(input, axis)
+                    .data
+                    .iter()
+                    .zip(weights.data.iter())
+                    .map(|(value, weight)| value * weight)
+                    .sum()
+            };
+
+            let mut expected: Tensor2D<f64> = Tensor2D::<f64>::new(0.0, row_count, column_count);
+            let mut shifted: Tensor2D<f64> = input.clone();
+            for index in 0..input.len() {
+                shifted.data[index] = input.data[index] + STEP;
+                let above: f64 = loss(&shifted);
+                shifted.data[index] = input.data[index] - STEP;
+                let below: f64 = loss(&shifted);
+                shifted.data[index] = input.data[index];
+                expected.data[index] = (above - below) / (2.0 * STEP);
+            }
+
+            let output: Tensor2D<f64> = Tensor2D::<f64>::softmax_axis(&input, axis);
+            let mut input_gradient: Tensor2D<f64> =
+                Tensor2D::<f64>::new(0.0, row_count, column_count);
+            Tensor2D::<f64>::softmax_axis_backward(&output, &weights, axis, &mut input_gradient);
+            assert_close(&expected, &input_gradient);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod tests {
     use crate::shared::tensor2d::Tensor2D;
+    use crate::shared::tensor_element::F16;
 
     const ERROR_TOLERANCE: f32 = 0.00001;
 
@@ -34,7 +35,7 @@ mod tests {
         expected: fn(&Tensor2D, &Tensor2D, &Tensor2D) -> Tensor2D,
         test: fn(&Tensor2D, &Tensor2D, &Tensor2D, &mut Tensor2D),
     ) -> f32 {
-        let mut input: Tensor2D = Tensor2D::new(0.5, outer_dimension_input, inner_dimension);
+        let input: Tensor2D = Tensor2D::new(0.5, outer_dimension_input, inner_dimension);
         let weights: Tensor2D = Tensor2D::new(1.0, inner_dimension, outer_dimension_weights);
         let bias: Tensor2D = Tensor2D::new(0.1, outer_dimension_input, outer_dimension_weights);
 
@@ -42,7 +43,7 @@ mod tests {
 
         let mut output: Tensor2D =
             Tensor2D::new(0.0, outer_dimension_input, outer_dimension_weights);
-        test(&mut input, &weights, &bias, &mut output);
+        test(&input, &weights, &bias, &mut output);
 
         subtract_tensors(&expected_output, &output).sum().abs()
     }
@@ -479,4 +480,122 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn linear_layer_f64() {
+        let outer_dimension_input: usize = 3;
+        let outer_dimension_weights: usize = 4;
+        let inner_dimension: usize = 4;
+
+        let expected_result: f64 = 1116.6;
+
+        let input: Tensor2D<f64> = Tensor2D::new(0.5, outer_dimension_input, inner_dimension);
+        let weights: Tensor2D<f64> = Tensor2D::new(1.0, inner_dimension, outer_dimension_weights);
+        let bias: Tensor2D<f64> =
+            Tensor2D::new(0.1, outer_dimension_input, outer_dimension_weights);
+
+        let output: Tensor2D<f64> = Tensor2D::linear_layer(&input, &weights, &bias);
+
+        let abs_result_difference: f64 = (expected_result - output.sum()).abs();
+
+        assert!(abs_result_difference < ERROR_TOLERANCE as f64);
+    }
+
+    #[test]
+    fn linear_relu_softmax_f32_against_f64() {
+        let outer_dimension_range: usize = 10;
+        let inner_dimension_range: usize = 10;
+
+        for outer_dimension in 1..outer_dimension_range {
+            for inner_dimension in 1..inner_dimension_range {
+                let input: Tensor2D = Tensor2D::new(0.05, outer_dimension, inner_dimension);
+                let weights: Tensor2D = Tensor2D::new(-0.1, inner_dimension, outer_dimension);
+                let bias: Tensor2D = Tensor2D::new(0.1, outer_dimension, outer_dimension);
+
+                let mut output: Tensor2D = Tensor2D::new(0.0, outer_dimension, outer_dimension);
+                Tensor2D::linear_relu_softmax_fused(&input, &weights, &bias, &mut output);
+
+                let input_reference: Tensor2D<f64> = input.convert();
+                let weights_reference: Tensor2D<f64> = weights.convert();
+                let bias_reference: Tensor2D<f64> = bias.convert();
+                let mut output_reference: Tensor2D<f64> =
+                    Tensor2D::new(0.0, outer_dimension, outer_dimension);
+                Tensor2D::linear_relu_softmax_fused(
+                    &input_reference,
+                    &weights_reference,
+                    &bias_reference,
+                    &mut output_reference,
+                );
+
+                let abs_result_difference: f64 =
+                    Tensor2D::subtraction(&output.convert::<f64>(), &output_reference)
+                        .data
+                        .iter()
+                        .map(|x| x.abs())
+                        .sum();
+                assert!(abs_result_difference < ERROR_TOLERANCE as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn linear_layer_relu_i32() {
+        let input: Tensor2D<i32> = Tensor2D::new(1, 3, 4);
+        let weights: Tensor2D<i32> = Tensor2D::new(1, 4, 2);
+        let bias: Tensor2D<i32> = Tensor2D::new(-50, 3, 2);
+
+        let mut output: Tensor2D<i32> = Tensor2D::new(0, 3, 2);
+        Tensor2D::linear_layer_optimized_relu(&input, &weights, &bias, &mut output);
+
+        let mut expected_output: Tensor2D<i32> = Tensor2D::linear_layer(&input, &weights, &bias);
+        Tensor2D::relu_inplace(&mut expected_output);
+
+        assert_eq!(output.data, expected_output.data);
+        assert_eq!(output.data, vec![28, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn f16_conversion() {
+        let values: Vec<f32> = vec![
+            0.0,
+            -0.0,
+            1.0,
+            -2.5,
+            0.1,
+            65504.0,
+            6.1035156e-5,
+            5.9604645e-8,
+        ];
+        for value in values {
+            let converted: f32 = F16::from_f32(value).to_f32();
+            let abs_difference: f32 = (value - converted).abs();
+            assert!(abs_difference <= value.abs() / 1024.0);
+        }
+
+        assert_eq!(F16::from_f32(1.0).0, 0x3c00);
+        assert_eq!(F16::from_f32(-2.0).0, 0xc000);
+        assert_eq!(F16::from_f32(1.0e6).to_f32(), f32::INFINITY);
+        assert!(F16::from_f32(f32::NAN).to_f32().is_nan());
+    }
+
+    #[test]
+    fn softmax_f16() {
+        let row_count: usize = 4;
+        let column_count: usize = 3;
+
+        let input: Tensor2D = Tensor2D::new(0.5, row_count, column_count);
+        let input_half: Tensor2D<F16> = input.convert();
+
+        let output: Tensor2D = Tensor2D::softmax(&input);
+        let output_half: Tensor2D<F16> = Tensor2D::softmax(&input_half);
+
+        let abs_result_difference: f32 = subtract_tensors(&output, &output_half.convert())
+            .data
+            .iter()
+            .map(|x| x.abs())
+            .sum();
+
+        // Half precision has roughly 3 significant digits
+        assert!(abs_result_difference < 0.01);
+    }
 }
@@ -0,0 +1,309 @@
+use std::fmt::Debug;
+use std::ops::{Add, AddAssign, Div, Mul, Sub};
+
+// Everything a Tensor2D needs from its element type to do
+// linear layers, ReLU, sums and subtraction.
+// The conversions to and from f64 are mostly there to create
+// the synthetic ramp data in Tensor2D::new and to be able to
+// compare results computed in different precisions.
+// Send and Sync let the multi-threaded kernels share tensors between threads.
+pub trait TensorElement:
+    Copy
+    + Send
+    + Sync
+    + Debug
+    + Default
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + AddAssign
+{
+    fn zero() -> Self;
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn max(self, other: Self) -> Self;
+}
+
+// Softmax needs exp and ln, which only makes sense for floating point types
+pub trait FloatElement: TensorElement + Div<Output = Self> {
+    fn neg_infinity() -> Self;
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn abs(self) -> Self;
+}
+
+impl TensorElement for f32 {
+    #[inline(always)]
+    fn zero() -> Self {
+        0.0
+    }
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        f32::max(self, other)
+    }
+}
+
+impl FloatElement for f32 {
+    #[inline(always)]
+    fn neg_infinity() -> Self {
+        f32::NEG_INFINITY
+    }
+
+    #[inline(always)]
+    fn exp(self) -> Self {
+        f32::exp(self)
+    }
+
+    #[inline(always)]
+    fn ln(self) -> Self {
+        f32::ln(self)
+    }
+
+    #[inline(always)]
+    fn abs(self) -> Self {
+        f32::abs(self)
+    }
+}
+
+impl TensorElement for f64 {
+    #[inline(always)]
+    fn zero() -> Self {
+        0.0
+    }
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        f64::max(self, other)
+    }
+}
+
+impl FloatElement for f64 {
+    #[inline(always)]
+    fn neg_infinity() -> Self {
+        f64::NEG_INFINITY
+    }
+
+    #[inline(always)]
+    fn exp(self) -> Self {
+        f64::exp(self)
+    }
+
+    #[inline(always)]
+    fn ln(self) -> Self {
+        f64::ln(self)
+    }
+
+    #[inline(always)]
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+}
+
+impl TensorElement for i32 {
+    #[inline(always)]
+    fn zero() -> Self {
+        0
+    }
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        value as i32
+    }
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        Ord::max(self, other)
+    }
+}
+
+// Half precision float stored as its raw bits in a u16.
+// Rust has no native f16 arithmetic on stable, so every operation
+// converts to f32, computes and rounds back to half precision.
+// This is slow, but it gives us results with the same rounding
+// behavior as storing the values as f16 on the GPU.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct F16(pub u16);
+
+impl F16 {
+    pub fn from_f32(value: f32) -> Self {
+        let bits: u32 = value.to_bits();
+        let sign: u16 = ((bits >> 16) & 0x8000) as u16;
+        let exponent: i32 = ((bits >> 23) & 0xff) as i32;
+        let mantissa: u32 = bits & 0x007f_ffff;
+
+        // Infinity and NaN
+        if exponent == 0xff {
+            let nan_bit: u16 = if mantissa != 0 { 0x0200 } else { 0 };
+            return F16(sign | 0x7c00 | nan_bit);
+        }
+
+        let half_exponent: i32 = exponent - 127 + 15;
+
+        // Too large to be represented, round to infinity
+        if 0x1f <= half_exponent {
+            return F16(sign | 0x7c00);
+        }
+
+        // Subnormal half or too small to be represented at all
+        if half_exponent <= 0 {
+            if half_exponent < -10 {
+                return F16(sign);
+            }
+            let mantissa: u32 = mantissa | 0x0080_0000;
+            let shift: u32 = (14 - half_exponent) as u32;
+            let mut half_mantissa: u32 = mantissa >> shift;
+            let round_bit: u32 = 1 << (shift - 1);
+            let remainder: u32 = mantissa & ((1 << shift) - 1);
+            if round_bit < remainder || (remainder == round_bit && (half_mantissa & 1) == 1) {
+                half_mantissa += 1;
+            }
+            return F16(sign | half_mantissa as u16);
+        }
+
+        // Round to nearest, ties to even. A carry out of the mantissa
+        // correctly increments the exponent, possibly all the way to infinity.
+        let mut result: u32 = ((half_exponent as u32) << 10) | (mantissa >> 13);
+        let remainder: u32 = mantissa & 0x1fff;
+        if 0x1000 < remainder || (remainder == 0x1000 && (result & 1) == 1) {
+            result += 1;
+        }
+
+        F16(sign | result as u16)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        let sign: u32 = ((self.0 & 0x8000) as u32) << 16;
+        let exponent: u32 = ((self.0 >> 10) & 0x1f) as u32;
+        let mantissa: u32 = (self.0 & 0x03ff) as u32;
+
+        if exponent == 0 {
+            // Zero or subnormal - value is mantissa * 2^-24
+            let magnitude: f32 = mantissa as f32 * 2.0f32.powi(-24);
+            return if sign != 0 { -magnitude } else { magnitude };
+        }
+
+        let bits: u32 = if exponent == 0x1f {
+            sign | 0x7f80_0000 | (mantissa << 13)
+        } else {
+            sign | ((exponent + 112) << 23) | (mantissa << 13)
+        };
+
+        f32::from_bits(bits)
+    }
+}
+
+impl PartialOrd for F16 {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.to_f32().partial_cmp(&other.to_f32())
+    }
+}
+
+impl Add for F16 {
+    type Output = F16;
+
+    fn add(self, other: Self) -> Self {
+        F16::from_f32(self.to_f32() + other.to_f32())
+    }
+}
+
+impl Sub for F16 {
+    type Output = F16;
+
+    fn sub(self, other: Self) -> Self {
+        F16::from_f32(self.to_f32() - other.to_f32())
+    }
+}
+
+impl Mul for F16 {
+    type Output = F16;
+
+    fn mul(self, other: Self) -> Self {
+        F16::from_f32(self.to_f32() * other.to_f32())
+    }
+}
+
+impl Div for F16 {
+    type Output = F16;
+
+    fn div(self, other: Self) -> Self {
+        F16::from_f32(self.to_f32() / other.to_f32())
+    }
+}
+
+impl AddAssign for F16 {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl TensorElement for F16 {
+    #[inline(always)]
+    fn zero() -> Self {
+        F16(0)
+    }
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        F16::from_f32(value as f32)
+    }
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self.to_f32() as f64
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        F16::from_f32(self.to_f32().max(other.to_f32()))
+    }
+}
+
+impl FloatElement for F16 {
+    #[inline(always)]
+    fn neg_infinity() -> Self {
+        F16(0xfc00)
+    }
+
+    #[inline(always)]
+    fn exp(self) -> Self {
+        F16::from_f32(self.to_f32().exp())
+    }
+
+    #[inline(always)]
+    fn ln(self) -> Self {
+        F16::from_f32(self.to_f32().ln())
+    }
+
+    #[inline(always)]
+    fn abs(self) -> Self {
+        F16(self.0 & 0x7fff)
+    }
+}
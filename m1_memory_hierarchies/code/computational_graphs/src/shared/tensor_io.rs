@@ -0,0 +1,716 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::tensor2d::Tensor2D;
+use super::tensor_element::{TensorElement, F16};
+
+// Loading and saving tensors in formats Python can read directly.
+// .npy holds a single array and is what numpy.save and numpy.load use.
+// The safetensors format holds several named tensors in one file, which is
+// handy for the weights of a whole network, or for dumping every intermediate
+// buffer of a graph to compare against a reference implementation.
+//
+// Both formats are little endian. Only 1 and 2 dimensional arrays can be
+// loaded, a 1 dimensional array of length n becomes a 1xn Tensor2D.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TensorFileError {
+    // Reading or writing the file itself failed
+    Io(String),
+    // The file isn't a valid .npy or safetensors file, or uses features we don't support
+    InvalidFormat(String),
+    // The file holds a different element type than the one we asked for
+    DTypeMismatch(String),
+    // The shape in the file doesn't match the data, isn't 2D or isn't the expected shape
+    ShapeMismatch(String),
+}
+
+impl fmt::Display for TensorFileError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TensorFileError::Io(message) => write!(formatter, "IO error: {}", message),
+            TensorFileError::InvalidFormat(message) => {
+                write!(formatter, "Invalid format: {}", message)
+            }
+            TensorFileError::DTypeMismatch(message) => {
+                write!(formatter, "DType mismatch: {}", message)
+            }
+            TensorFileError::ShapeMismatch(message) => {
+                write!(formatter, "Shape mismatch: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TensorFileError {}
+
+fn io_error(path: &Path, error: std::io::Error) -> TensorFileError {
+    TensorFileError::Io(format!("{}: {}", path.display(), error))
+}
+
+// The element types which can be stored in a file, along with
+// the names the two formats use for them.
+pub trait TensorFileElement: TensorElement {
+    const NPY_DESCR: &'static str;
+    const SAFETENSORS_DTYPE: &'static str;
+    const BYTE_COUNT: usize;
+
+    fn write_le_bytes(self, bytes: &mut Vec<u8>);
+    // bytes is always exactly BYTE_COUNT long
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+}
+
+impl TensorFileElement for f32 {
+    const NPY_DESCR: &'static str = "<f4";
+    const SAFETENSORS_DTYPE: &'static str = "F32";
+    const BYTE_COUNT: usize = 4;
+
+    fn write_le_bytes(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+}
+
+impl TensorFileElement for f64 {
+    const NPY_DESCR: &'static str = "<f8";
+    const SAFETENSORS_DTYPE: &'static str = "F64";
+    const BYTE_COUNT: usize = 8;
+
+    fn write_le_bytes(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut array: [u8; 8] = [0; 8];
+        array.copy_from_slice(bytes);
+        f64::from_le_bytes(array)
+    }
+}
+
+impl TensorFileElement for i32 {
+    const NPY_DESCR: &'static str = "<i4";
+    const SAFETENSORS_DTYPE: &'static str = "I32";
+    const BYTE_COUNT: usize = 4;
+
+    fn write_le_bytes(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+}
+
+impl TensorFileElement for F16 {
+    const NPY_DESCR: &'static str = "<f2";
+    const SAFETENSORS_DTYPE: &'static str = "F16";
+    const BYTE_COUNT: usize = 2;
+
+    fn write_le_bytes(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.0.to_le_bytes());
+    }
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        F16(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+// A 1 dimensional array becomes a single row
+fn shape_to_2d(shape: &[usize]) -> Result<(usize, usize), TensorFileError> {
+    match shape {
+        [column_count] => Ok((1, *column_count)),
+        [row_count, column_count] => Ok((*row_count, *column_count)),
+        _ => Err(TensorFileError::ShapeMismatch(format!(
+            "Only 1 and 2 dimensional arrays can be loaded as a Tensor2D, got shape {:?}",
+            shape
+        ))),
+    }
+}
+
+fn tensor_from_le_bytes<T: TensorFileElement>(
+    bytes: &[u8],
+    row_count: usize,
+    column_count: usize,
+) -> Result<Tensor2D<T>, TensorFileError> {
+    // The shape comes straight from the file, so it can claim more bytes than fit in a usize
+    let byte_count: usize = row_count
+        .checked_mul(column_count)
+        .and_then(|element_count| element_count.checked_mul(T::BYTE_COUNT))
+        .ok_or_else(|| {
+            TensorFileError::ShapeMismatch(format!(
+                "A {}x{} tensor of {} is too large to be loaded",
+                row_count,
+                column_count,
+                T::SAFETENSORS_DTYPE
+            ))
+        })?;
+    if bytes.len() != byte_count {
+        return Err(TensorFileError::ShapeMismatch(format!(
+            "A {}x{} tensor of {} needs {} bytes, but {} bytes were found",
+            row_count,
+            column_count,
+            T::SAFETENSORS_DTYPE,
+            byte_count,
+            bytes.len()
+        )));
+    }
+
+    let data: Vec<T> = bytes
+        .chunks_exact(T::BYTE_COUNT)
+        .map(T::from_le_bytes)
+        .collect();
+
+    Ok(Tensor2D {
+        data,
+        row_count,
+        column_count,
+    })
+}
+
+fn tensor_to_le_bytes<T: TensorFileElement>(
+    tensor: &Tensor2D<T>,
+    bytes: &mut Vec<u8>,
+) -> Result<(), TensorFileError> {
+    if tensor.data.len() < tensor.len() {
+        return Err(TensorFileError::ShapeMismatch(format!(
+            "The tensor is {}x{}, but only holds {} elements",
+            tensor.row_count,
+            tensor.column_count,
+            tensor.data.len()
+        )));
+    }
+
+    for value in &tensor.data[..tensor.len()] {
+        value.write_le_bytes(bytes);
+    }
+
+    Ok(())
+}
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+// Finds the value following 'key': in the Python dict literal of a .npy header
+fn npy_header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, TensorFileError> {
+    let pattern: String = format!("'{}':", key);
+    match header.find(&pattern) {
+        Some(index) => Ok(header[index + pattern.len()..].trim_start()),
+        None => Err(TensorFileError::InvalidFormat(format!(
+            "The .npy header has no {}",
+            key
+        ))),
+    }
+}
+
+fn parse_npy_header(header: &str) -> Result<(String, bool, Vec<usize>), TensorFileError> {
+    let descr: &str = npy_header_value(header, "descr")?;
+    let descr: String = match descr
+        .strip_prefix('\'')
+        .and_then(|rest| rest.split_once('\''))
+    {
+        Some((descr, _)) => descr.to_string(),
+        None => {
+            return Err(TensorFileError::InvalidFormat(
+                "The descr in the .npy header is not a simple string".to_string(),
+            ));
+        }
+    };
+
+    let fortran_order: &str = npy_header_value(header, "fortran_order")?;
+    let fortran_order: bool = if fortran_order.starts_with("True") {
+        true
+    } else if fortran_order.starts_with("False") {
+        false
+    } else {
+        return Err(TensorFileError::InvalidFormat(
+            "fortran_order in the .npy header is neither True nor False".to_string(),
+        ));
+    };
+
+    let shape: &str = npy_header_value(header, "shape")?;
+    let shape: &str = match shape
+        .strip_prefix('(')
+        .and_then(|rest| rest.split_once(')'))
+    {
+        Some((shape, _)) => shape,
+        None => {
+            return Err(TensorFileError::InvalidFormat(
+                "The shape in the .npy header is not a tuple".to_string(),
+            ));
+        }
+    };
+    let shape: Vec<usize> = shape
+        .split(',')
+        .map(|dimension| dimension.trim())
+        .filter(|dimension| !dimension.is_empty())
+        .map(|dimension| {
+            dimension.parse::<usize>().map_err(|_| {
+                TensorFileError::InvalidFormat(format!(
+                    "{} in the .npy shape is not a dimension",
+                    dimension
+                ))
+            })
+        })
+        .collect::<Result<Vec<usize>, TensorFileError>>()?;
+
+    Ok((descr, fortran_order, shape))
+}
+
+// Safetensors uses a JSON header. We only need a small subset of JSON,
+// objects, arrays, strings and non-negative integers, so we parse it by hand.
+#[derive(Clone, Debug, PartialEq)]
+enum JsonValue {
+    Object(Vec<(String, JsonValue)>),
+    Array(Vec<JsonValue>),
+    String(String),
+    Number(u64),
+    Other,
+}
+
+// A safetensors header is never nested deeper than an object of objects holding arrays.
+// The parser recurses once per level, so without a limit a header of nothing but [
+// would overflow the stack instead of returning an error.
+const MAX_JSON_DEPTH: usize = 4;
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn error(&self, message: &str) -> TensorFileError {
+        TensorFileError::InvalidFormat(format!(
+            "safetensors header at byte {}: {}",
+            self.position, message
+        ))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.position < self.bytes.len() && self.bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), TensorFileError> {
+        self.skip_whitespace();
+        if self.bytes.get(self.position) == Some(&byte) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}", byte as char)))
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, TensorFileError> {
+        self.expect(b'"')?;
+        let mut value: Vec<u8> = Vec::<u8>::new();
+        loop {
+            match self.bytes.get(self.position) {
+                Some(b'"') => {
+                    self.position += 1;
+                    break;
+                }
+                Some(b'\\') => {
+                    let escaped: u8 = match self.bytes.get(self.position + 1) {
+                        Some(b'n') => b'\n',
+                        Some(b't') => b'\t',
+                        Some(b'r') => b'\r',
+                        Some(byte @ (b'"' | b'\\' | b'/')) => *byte,
+                        _ => return Err(self.error("unsupported escape sequence")),
+                    };
+                    value.push(escaped);
+                    self.position += 2;
+                }
+                Some(byte) => {
+                    value.push(*byte);
+                    self.position += 1;
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+
+        String::from_utf8(value).map_err(|_| self.error("string is not valid UTF-8"))
+    }
+
+    // depth is how many objects and arrays the value is nested in
+    fn parse_value(&mut self, depth: usize) -> Result<JsonValue, TensorFileError> {
+        self.skip_whitespace();
+        if MAX_JSON_DEPTH <= depth && matches!(self.bytes.get(self.position), Some(b'{' | b'[')) {
+            return Err(self.error(&format!(
+                "objects and arrays are nested deeper than {} levels",
+                MAX_JSON_DEPTH
+            )));
+        }
+        match self.bytes.get(self.position) {
+            Some(b'{') => {
+                self.position += 1;
+                let mut members: Vec<(String, JsonValue)> = Vec::<(String, JsonValue)>::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.position) == Some(&b'}') {
+                    self.position += 1;
+                    return Ok(JsonValue::Object(members));
+                }
+                loop {
+                    let key: String = self.parse_string()?;
+                    self.expect(b':')?;
+                    members.push((key, self.parse_value(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.bytes.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return Ok(JsonValue::Object(members));
+                        }
+                        _ => return Err(self.error("expected , or }")),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let mut elements: Vec<JsonValue> = Vec::<JsonValue>::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.position) == Some(&b']') {
+                    self.position += 1;
+                    return Ok(JsonValue::Array(elements));
+                }
+                loop {
+                    elements.push(self.parse_value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.bytes.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return Ok(JsonValue::Array(elements));
+                        }
+                        _ => return Err(self.error("expected , or ]")),
+                    }
+                }
+            }
+            Some(b'"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some(byte) if byte.is_ascii_digit() => {
+                let start: usize = self.position;
+                while self.position < self.bytes.len() && self.bytes[self.position].is_ascii_digit()
+                {
+                    self.position += 1;
+                }
+                let digits: &str = std::str::from_utf8(&self.bytes[start..self.position]).unwrap();
+                digits
+                    .parse::<u64>()
+                    .map(JsonValue::Number)
+                    .map_err(|_| self.error("number is too large"))
+            }
+            _ => {
+                for literal in ["true", "false", "null"] {
+                    if self.bytes[self.position..].starts_with(literal.as_bytes()) {
+                        self.position += literal.len();
+                        return Ok(JsonValue::Other);
+                    }
+                }
+                Err(self.error("unsupported value"))
+            }
+        }
+    }
+}
+
+fn json_member<'a>(members: &'a [(String, JsonValue)], key: &str) -> Option<&'a JsonValue> {
+    members
+        .iter()
+        .find(|(member_key, _)| member_key == key)
+        .map(|(_, value)| value)
+}
+
+fn json_numbers(value: Option<&JsonValue>) -> Option<Vec<usize>> {
+    match value {
+        Some(JsonValue::Array(elements)) => elements
+            .iter()
+            .map(|element| match element {
+                JsonValue::Number(number) => Some(*number as usize),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+fn escape_json_string(value: &str) -> String {
+    let mut escaped: String = String::with_capacity(value.len());
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            character => escaped.push(character),
+        }
+    }
+
+    escaped
+}
+
+impl<T: TensorFileElement> Tensor2D<T> {
+    pub fn to_npy_bytes(&self) -> Result<Vec<u8>, TensorFileError> {
+        let mut header: String = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+            T::NPY_DESCR,
+            self.row_count,
+            self.column_count
+        );
+        // The header is padded with spaces and ends with a newline,
+        // such that the data starts at a multiple of 64 bytes.
+        let preamble_length: usize = NPY_MAGIC.len() + 2 + 2;
+        let unpadded_length: usize = preamble_length + header.len() + 1;
+        let padding: usize = (64 - unpadded_length % 64) % 64;
+        header.push_str(&" ".repeat(padding));
+        header.push('\n');
+
+        let mut bytes: Vec<u8> =
+            Vec::<u8>::with_capacity(preamble_length + header.len() + self.len() * T::BYTE_COUNT);
+        bytes.extend_from_slice(NPY_MAGIC);
+        bytes.push(1);
+        bytes.push(0);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        tensor_to_le_bytes(self, &mut bytes)?;
+
+        Ok(bytes)
+    }
+
+    pub fn from_npy_bytes(bytes: &[u8]) -> Result<Self, TensorFileError> {
+        if bytes.len() < NPY_MAGIC.len() + 4 || !bytes.starts_with(NPY_MAGIC) {
+            return Err(TensorFileError::InvalidFormat(
+                "Not a .npy file, the magic string is missing".to_string(),
+            ));
+        }
+
+        // Version 1.0 has a 2 byte header length, 2.0 and 3.0 use 4 bytes
+        let major_version: u8 = bytes[NPY_MAGIC.len()];
+        let (header_start, header_length): (usize, usize) = match major_version {
+            1 => (
+                NPY_MAGIC.len() + 4,
+                u16::from_le_bytes([bytes[8], bytes[9]]) as usize,
+            ),
+            2 | 3 if 12 <= bytes.len() => (
+                NPY_MAGIC.len() + 6,
+                u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+            ),
+            _ => {
+                return Err(TensorFileError::InvalidFormat(format!(
+                    ".npy version {} is not supported",
+                    major_version
+                )));
+            }
+        };
+        let data_start: usize = header_start + header_length;
+        if bytes.len() < data_start {
+            return Err(TensorFileError::InvalidFormat(
+                "The .npy file ends inside its header".to_string(),
+            ));
+        }
+        let header: &str = std::str::from_utf8(&bytes[header_start..data_start]).map_err(|_| {
+            TensorFileError::InvalidFormat("The .npy header is not valid text".to_string())
+        })?;
+
+        let (descr, fortran_order, shape) = parse_npy_header(header)?;
+        if descr != T::NPY_DESCR {
+            return Err(TensorFileError::DTypeMismatch(format!(
+                "The .npy file holds {}, but {} was requested",
+                descr,
+                T::NPY_DESCR
+            )));
+        }
+        let (row_count, column_count) = shape_to_2d(&shape)?;
+
+        let tensor: Tensor2D<T> =
+            tensor_from_le_bytes(&bytes[data_start..], row_count, column_count)?;
+        if !fortran_order || row_count == 1 || column_count == 1 {
+            return Ok(tensor);
+        }
+
+        // Column major data, transpose it to our row major layout
+        let mut transposed: Tensor2D<T> = Tensor2D::new(T::zero(), row_count, column_count);
+        for row_index in 0..row_count {
+            for column_index in 0..column_count {
+                transposed.data[row_index * column_count + column_index] =
+                    tensor.data[column_index * row_count + row_index];
+            }
+        }
+
+        Ok(transposed)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), TensorFileError> {
+        fs::write(path, self.to_npy_bytes()?).map_err(|error| io_error(path, error))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, TensorFileError> {
+        let bytes: Vec<u8> = fs::read(path).map_err(|error| io_error(path, error))?;
+        Self::from_npy_bytes(&bytes)
+    }
+
+    // For loading weights which have to fit in a specific place in a graph
+    pub fn load_with_shape(
+        path: &Path,
+        row_count: usize,
+        column_count: usize,
+    ) -> Result<Self, TensorFileError> {
+        let tensor: Tensor2D<T> = Self::load(path)?;
+        if tensor.row_count != row_count || tensor.column_count != column_count {
+            return Err(TensorFileError::ShapeMismatch(format!(
+                "{} is {}x{}, but {}x{} was expected",
+                path.display(),
+                tensor.row_count,
+                tensor.column_count,
+                row_count,
+                column_count
+            )));
+        }
+
+        Ok(tensor)
+    }
+}
+
+// Tensors are written in the order they are given, names have to be unique.
+pub fn to_safetensors_bytes<T: TensorFileElement>(
+    tensors: &[(&str, &Tensor2D<T>)],
+) -> Result<Vec<u8>, TensorFileError> {
+    let mut data: Vec<u8> = Vec::<u8>::new();
+    let mut header: String = String::from("{");
+    for (index, (name, tensor)) in tensors.iter().enumerate() {
+        if tensors[..index]
+            .iter()
+            .any(|(previous_name, _)| previous_name == name)
+        {
+            return Err(TensorFileError::InvalidFormat(format!(
+                "The name {} is used by more than one tensor",
+                name
+            )));
+        }
+
+        let begin: usize = data.len();
+        tensor_to_le_bytes(tensor, &mut data)?;
+        if 0 < index {
+            header.push(',');
+        }
+        header.push_str(&format!(
+            "\"{}\":{{\"dtype\":\"{}\",\"shape\":[{},{}],\"data_offsets\":[{},{}]}}",
+            escape_json_string(name),
+            T::SAFETENSORS_DTYPE,
+            tensor.row_count,
+            tensor.column_count,
+            begin,
+            data.len()
+        ));
+    }
+    header.push('}');
+    // Like the reference implementation, pad the header with spaces to keep the data aligned
+    let padding: usize = (8 - header.len() % 8) % 8;
+    header.push_str(&" ".repeat(padding));
+
+    let mut bytes: Vec<u8> = Vec::<u8>::with_capacity(8 + header.len() + data.len());
+    bytes.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes.extend_from_slice(&data);
+
+    Ok(bytes)
+}
+
+// Every tensor in the file has to be of type T
+pub fn from_safetensors_bytes<T: TensorFileElement>(
+    bytes: &[u8],
+) -> Result<HashMap<String, Tensor2D<T>>, TensorFileError> {
+    if bytes.len() < 8 {
+        return Err(TensorFileError::InvalidFormat(
+            "A safetensors file starts with an 8 byte header length".to_string(),
+        ));
+    }
+    let mut header_length: [u8; 8] = [0; 8];
+    header_length.copy_from_slice(&bytes[..8]);
+    let header_length: usize = u64::from_le_bytes(header_length) as usize;
+    if bytes.len() - 8 < header_length {
+        return Err(TensorFileError::InvalidFormat(
+            "The safetensors file ends inside its header".to_string(),
+        ));
+    }
+    let data: &[u8] = &bytes[8 + header_length..];
+
+    let mut parser: JsonParser = JsonParser {
+        bytes: &bytes[8..8 + header_length],
+        position: 0,
+    };
+    let members: Vec<(String, JsonValue)> = match parser.parse_value(0)? {
+        JsonValue::Object(members) => members,
+        _ => {
+            return Err(TensorFileError::InvalidFormat(
+                "The safetensors header is not a JSON object".to_string(),
+            ));
+        }
+    };
+
+    let mut tensors: HashMap<String, Tensor2D<T>> = HashMap::<String, Tensor2D<T>>::new();
+    for (name, value) in members {
+        // Free form string metadata, which we don't use
+        if name == "__metadata__" {
+            continue;
+        }
+
+        let invalid_entry = || {
+            TensorFileError::InvalidFormat(format!(
+                "The safetensors entry {} needs a dtype, shape and data_offsets",
+                name
+            ))
+        };
+        let fields: Vec<(String, JsonValue)> = match value {
+            JsonValue::Object(fields) => fields,
+            _ => return Err(invalid_entry()),
+        };
+        let dtype: &str = match json_member(&fields, "dtype") {
+            Some(JsonValue::String(dtype)) => dtype,
+            _ => return Err(invalid_entry()),
+        };
+        let shape: Vec<usize> =
+            json_numbers(json_member(&fields, "shape")).ok_or_else(invalid_entry)?;
+        let offsets: Vec<usize> =
+            json_numbers(json_member(&fields, "data_offsets")).ok_or_else(invalid_entry)?;
+
+        if dtype != T::SAFETENSORS_DTYPE {
+            return Err(TensorFileError::DTypeMismatch(format!(
+                "The tensor {} holds {}, but {} was requested",
+                name,
+                dtype,
+                T::SAFETENSORS_DTYPE
+            )));
+        }
+        let (row_count, column_count) = shape_to_2d(&shape)?;
+        if offsets.len() != 2 || offsets[1] < offsets[0] || data.len() < offsets[1] {
+            return Err(TensorFileError::InvalidFormat(format!(
+                "The data offsets {:?} of {} are outside the file",
+                offsets, name
+            )));
+        }
+
+        let tensor: Tensor2D<T> =
+            tensor_from_le_bytes(&data[offsets[0]..offsets[1]], row_count, column_count)?;
+        tensors.insert(name, tensor);
+    }
+
+    Ok(tensors)
+}
+
+pub fn save_safetensors<T: TensorFileElement>(
+    path: &Path,
+    tensors: &[(&str, &Tensor2D<T>)],
+) -> Result<(), TensorFileError> {
+    fs::write(path, to_safetensors_bytes(tensors)?).map_err(|error| io_error(path, error))
+}
+
+pub fn load_safetensors<T: TensorFileElement>(
+    path: &Path,
+) -> Result<HashMap<String, Tensor2D<T>>, TensorFileError> {
+    let bytes: Vec<u8> = fs::read(path).map_err(|error| io_error(path, error))?;
+    from_safetensors_bytes(&bytes)
+}
@@ -0,0 +1,185 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use crate::shared::tensor2d::Tensor2D;
+    use crate::shared::tensor_element::F16;
+    use crate::shared::tensor_io::{
+        from_safetensors_bytes, load_safetensors, save_safetensors, to_safetensors_bytes,
+        TensorFileError,
+    };
+
+    // Builds a .npy file the same way numpy.save would
+    fn npy_bytes(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut header: String = header.to_string();
+        let padding: usize = (64 - (10 + header.len() + 1) % 64) % 64;
+        header.push_str(&" ".repeat(padding));
+        header.push('\n');
+
+        let mut bytes: Vec<u8> = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn npy_round_trip() {
+        let tensor: Tensor2D = Tensor2D::new(0.1, 3, 5);
+        let bytes: Vec<u8> = tensor.to_npy_bytes().unwrap();
+        assert_eq!(&bytes[..6], b"\x93NUMPY");
+        // The data starts at a multiple of 64 bytes
+        assert_eq!((bytes.len() - tensor.len() * 4) % 64, 0);
+
+        let loaded: Tensor2D = Tensor2D::from_npy_bytes(&bytes).unwrap();
+        assert_eq!((loaded.row_count, loaded.column_count), (3, 5));
+        assert_eq!(loaded.data, tensor.data);
+
+        let tensor: Tensor2D<f64> = Tensor2D::<f64>::new(0.25, 2, 2);
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("computational_graphs_{}.npy", std::process::id()));
+        tensor.save(&path).unwrap();
+        let loaded: Tensor2D<f64> = Tensor2D::<f64>::load_with_shape(&path, 2, 2).unwrap();
+        let wrong_shape: Result<Tensor2D<f64>, TensorFileError> =
+            Tensor2D::<f64>::load_with_shape(&path, 4, 1);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.data, tensor.data);
+        assert!(matches!(
+            wrong_shape,
+            Err(TensorFileError::ShapeMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn npy_layouts() {
+        // numpy.asfortranarray([[1, 2, 3], [4, 5, 6]], dtype=np.float32)
+        let data: Vec<u8> = [1.0f32, 4.0, 2.0, 5.0, 3.0, 6.0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let bytes: Vec<u8> = npy_bytes(
+            "{'descr': '<f4', 'fortran_order': True, 'shape': (2, 3), }",
+            &data,
+        );
+        let loaded: Tensor2D = Tensor2D::from_npy_bytes(&bytes).unwrap();
+        assert_eq!((loaded.row_count, loaded.column_count), (2, 3));
+        assert_eq!(loaded.data, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        // A bias vector saved from numpy is usually 1 dimensional
+        let data: Vec<u8> = [7i32, 8, 9]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let bytes: Vec<u8> = npy_bytes(
+            "{'descr': '<i4', 'fortran_order': False, 'shape': (3,), }",
+            &data,
+        );
+        let loaded: Tensor2D<i32> = Tensor2D::<i32>::from_npy_bytes(&bytes).unwrap();
+        assert_eq!((loaded.row_count, loaded.column_count), (1, 3));
+        assert_eq!(loaded.data, vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn npy_errors() {
+        let bytes: Vec<u8> = Tensor2D::<f64>::new(1.0, 2, 2).to_npy_bytes().unwrap();
+        let result: Result<Tensor2D, TensorFileError> = Tensor2D::from_npy_bytes(&bytes);
+        assert!(matches!(result, Err(TensorFileError::DTypeMismatch(_))));
+
+        let truncated: Result<Tensor2D<f64>, TensorFileError> =
+            Tensor2D::<f64>::from_npy_bytes(&bytes[..bytes.len() - 1]);
+        assert!(matches!(truncated, Err(TensorFileError::ShapeMismatch(_))));
+
+        let bytes: Vec<u8> = npy_bytes(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 1, 1), }",
+            &[0; 8],
+        );
+        let result: Result<Tensor2D, TensorFileError> = Tensor2D::from_npy_bytes(&bytes);
+        assert!(matches!(result, Err(TensorFileError::ShapeMismatch(_))));
+
+        let result: Result<Tensor2D, TensorFileError> = Tensor2D::from_npy_bytes(b"not a npy");
+        assert!(matches!(result, Err(TensorFileError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn safetensors_round_trip() {
+        let weights: Tensor2D<F16> = Tensor2D::<F16>::new(F16::from_f32(0.5), 4, 2);
+        let bias: Tensor2D<F16> = Tensor2D::<F16>::new(F16::from_f32(0.1), 3, 2);
+
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "computational_graphs_{}.safetensors",
+            std::process::id()
+        ));
+        save_safetensors(&path, &[("layer.weights", &weights), ("layer.bias", &bias)]).unwrap();
+        let loaded: HashMap<String, Tensor2D<F16>> = load_safetensors(&path).unwrap();
+        let wrong_dtype: Result<HashMap<String, Tensor2D>, TensorFileError> =
+            load_safetensors(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded["layer.weights"].data, weights.data);
+        assert_eq!(
+            (
+                loaded["layer.bias"].row_count,
+                loaded["layer.bias"].column_count
+            ),
+            (3, 2)
+        );
+        assert_eq!(loaded["layer.bias"].data, bias.data);
+        assert!(matches!(
+            wrong_dtype,
+            Err(TensorFileError::DTypeMismatch(_))
+        ));
+
+        let tensor: Tensor2D = Tensor2D::new(1.0, 2, 2);
+        let result: Result<Vec<u8>, TensorFileError> =
+            to_safetensors_bytes(&[("a", &tensor), ("a", &tensor)]);
+        assert!(matches!(result, Err(TensorFileError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn safetensors_from_python() {
+        // What safetensors.numpy.save_file writes, metadata and whitespace included
+        let header: &str = r#"{"__metadata__": {"format": "np"}, "bias": {"dtype": "F32", "shape": [2], "data_offsets": [16, 24]}, "weights": {"dtype": "F32", "shape": [2, 2], "data_offsets": [0, 16]}}"#;
+        let mut bytes: Vec<u8> = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(header.as_bytes());
+        for value in [1.0f32, 2.0, 3.0, 4.0, 0.5, -0.5] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let tensors: HashMap<String, Tensor2D> = from_safetensors_bytes(&bytes).unwrap();
+        assert_eq!(tensors.len(), 2);
+        assert_eq!(tensors["weights"].data, vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(
+            (tensors["bias"].row_count, tensors["bias"].column_count),
+            (1, 2)
+        );
+        assert_eq!(tensors["bias"].data, vec![0.5, -0.5]);
+
+        let result: Result<HashMap<String, Tensor2D>, TensorFileError> =
+            from_safetensors_bytes(&bytes[..bytes.len() - 4]);
+        assert!(matches!(result, Err(TensorFileError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn safetensors_malicious_headers() {
+        let safetensors_bytes = |header: &str| -> Vec<u8> {
+            let mut bytes: Vec<u8> = (header.len() as u64).to_le_bytes().to_vec();
+            bytes.extend_from_slice(header.as_bytes());
+            bytes
+        };
+
+        // Deep enough to overflow the stack if every level was recursed in to
+        let header: String = "[".repeat(1 << 20);
+        let result: Result<HashMap<String, Tensor2D>, TensorFileError> =
+            from_safetensors_bytes(&safetensors_bytes(&header));
+        assert!(matches!(result, Err(TensorFileError::InvalidFormat(_))));
+
+        // The number of bytes the shape needs doesn't fit in a usize
+        let header: &str = r#"{"weights": {"dtype": "F32", "shape": [4294967296, 4294967296], "data_offsets": [0, 0]}}"#;
+        let result: Result<HashMap<String, Tensor2D>, TensorFileError> =
+            from_safetensors_bytes(&safetensors_bytes(header));
+        assert!(matches!(result, Err(TensorFileError::ShapeMismatch(_))));
+    }
+}
@@ -0,0 +1,365 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::tensor2d::Tensor2D;
+use super::tensor_element::TensorElement;
+
+// An N-dimensional tensor described by a shape and a set of strides.
+// The data is shared between views, so transposing, slicing
+// and broadcasting only create new shapes and strides and never copy.
+// The element at index [i, j, k] is located at
+// offset + i * strides[0] + j * strides[1] + k * strides[2].
+// A stride of 0 means the dimension is broadcast.
+#[derive(Clone, Debug, Default)]
+pub struct TensorND<T: TensorElement = f32> {
+    pub data: Arc<Vec<T>>,
+    pub shape: Vec<usize>,
+    pub strides: Vec<usize>,
+    pub offset: usize,
+}
+
+impl<T: TensorElement> TensorND<T> {
+    // Same ramp as Tensor2D::new
+    pub fn new(scale: T, shape: &[usize]) -> Self {
+        let element_count: usize = shape.iter().product();
+        let mut data: Vec<T> = Vec::<T>::with_capacity(element_count);
+        for index in 0..element_count {
+            data.push(T::from_f64(index as f64) * scale);
+        }
+
+        Self::from_vec(data, shape)
+    }
+
+    pub fn from_vec(data: Vec<T>, shape: &[usize]) -> Self {
+        let element_count: usize = shape.iter().product();
+        assert_eq!(
+            data.len(),
+            element_count,
+            "\nTensorND::from_vec - data has {} elements, but shape {:?} needs {}.",
+            data.len(),
+            shape,
+            element_count
+        );
+
+        TensorND {
+            data: Arc::new(data),
+            shape: shape.to_vec(),
+            strides: Self::row_major_strides(shape),
+            offset: 0,
+        }
+    }
+
+    pub fn row_major_strides(shape: &[usize]) -> Vec<usize> {
+        let mut strides: Vec<usize> = vec![1; shape.len()];
+        for dimension in (0..shape.len().saturating_sub(1)).rev() {
+            strides[dimension] = strides[dimension + 1] * shape[dimension + 1];
+        }
+        strides
+    }
+
+    #[inline(always)]
+    pub fn rank(&self) -> usize {
+        self.shape.len()
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    // True if the view can be read as one row-major run of the underlying data
+    pub fn is_contiguous(&self) -> bool {
+        let expected_strides: Vec<usize> = Self::row_major_strides(&self.shape);
+        // Dimensions of size 1 can have any stride without changing the layout
+        self.shape
+            .iter()
+            .zip(self.strides.iter().zip(expected_strides.iter()))
+            .all(|(size, (stride, expected))| *size == 1 || stride == expected)
+    }
+
+    #[inline(always)]
+    fn data_index(&self, indices: &[usize]) -> usize {
+        debug_assert_eq!(indices.len(), self.rank());
+        let mut index: usize = self.offset;
+        for (dimension, element_index) in indices.iter().enumerate() {
+            debug_assert!(
+                *element_index < self.shape[dimension],
+                "\nIndex {} is out of bounds for dimension {} with size {}.",
+                element_index,
+                dimension,
+                self.shape[dimension]
+            );
+            index += element_index * self.strides[dimension];
+        }
+        index
+    }
+
+    pub fn get(&self, indices: &[usize]) -> T {
+        self.data[self.data_index(indices)]
+    }
+
+    // Visits every element in row-major order of the view,
+    // regardless of how it is laid out in memory.
+    pub fn for_each_index<F: FnMut(&[usize], usize)>(&self, mut function: F) {
+        if self.shape.contains(&0) {
+            return;
+        }
+
+        let rank: usize = self.rank();
+        let mut indices: Vec<usize> = vec![0; rank];
+        let mut data_index: usize = self.offset;
+        for _ in 0..self.len() {
+            function(&indices, data_index);
+
+            // Increment the last index and carry into the previous dimensions
+            for dimension in (0..rank).rev() {
+                indices[dimension] += 1;
+                data_index += self.strides[dimension];
+                if indices[dimension] < self.shape[dimension] {
+                    break;
+                }
+                data_index -= indices[dimension] * self.strides[dimension];
+                indices[dimension] = 0;
+            }
+        }
+    }
+
+    // Copies the elements into a fresh row-major buffer
+    pub fn to_vec(&self) -> Vec<T> {
+        let mut output: Vec<T> = Vec::<T>::with_capacity(self.len());
+        self.for_each_index(|_, data_index| output.push(self.data[data_index]));
+        output
+    }
+
+    pub fn to_contiguous(&self) -> Self {
+        if self.is_contiguous() && self.offset == 0 && self.data.len() == self.len() {
+            return self.clone();
+        }
+        Self::from_vec(self.to_vec(), &self.shape)
+    }
+
+    // Reshaping a contiguous tensor is free, otherwise the data is copied first
+    pub fn reshape(&self, shape: &[usize]) -> Self {
+        let element_count: usize = shape.iter().product();
+        assert_eq!(
+            self.len(),
+            element_count,
+            "\nTensorND::reshape - cannot reshape {:?} into {:?}.",
+            self.shape,
+            shape
+        );
+
+        let source: Self = if self.is_contiguous() {
+            self.clone()
+        } else {
+            self.to_contiguous()
+        };
+
+        TensorND {
+            data: source.data,
+            shape: shape.to_vec(),
+            strides: Self::row_major_strides(shape),
+            offset: source.offset,
+        }
+    }
+
+    // Reorders the dimensions as a view, no data is moved
+    pub fn permute(&self, axes: &[usize]) -> Self {
+        assert_eq!(
+            axes.len(),
+            self.rank(),
+            "\nTensorND::permute - expected {} axes, received {:?}.",
+            self.rank(),
+            axes
+        );
+        let mut seen: Vec<bool> = vec![false; self.rank()];
+        for axis in axes {
+            assert!(
+                *axis < self.rank() && !seen[*axis],
+                "\nTensorND::permute - {:?} is not a permutation of the axes of a rank {} tensor.",
+                axes,
+                self.rank()
+            );
+            seen[*axis] = true;
+        }
+
+        TensorND {
+            data: self.data.clone(),
+            shape: axes.iter().map(|axis| self.shape[*axis]).collect(),
+            strides: axes.iter().map(|axis| self.strides[*axis]).collect(),
+            offset: self.offset,
+        }
+    }
+
+    // Swaps the last two dimensions as a view
+    pub fn transpose(&self) -> Self {
+        assert!(
+            2 <= self.rank(),
+            "\nTensorND::transpose - needs at least 2 dimensions, found {}.",
+            self.rank()
+        );
+        let mut axes: Vec<usize> = (0..self.rank()).collect();
+        axes.swap(self.rank() - 2, self.rank() - 1);
+        self.permute(&axes)
+    }
+
+    // Takes a range in every dimension as a view
+    pub fn slice(&self, ranges: &[Range<usize>]) -> Self {
+        assert_eq!(
+            ranges.len(),
+            self.rank(),
+            "\nTensorND::slice - expected {} ranges, received {}.",
+            self.rank(),
+            ranges.len()
+        );
+
+        let mut offset: usize = self.offset;
+        let mut shape: Vec<usize> = Vec::<usize>::with_capacity(self.rank());
+        for (dimension, range) in ranges.iter().enumerate() {
+            assert!(
+                range.start <= range.end && range.end <= self.shape[dimension],
+                "\nTensorND::slice - range {:?} is out of bounds for dimension {} with size {}.",
+                range,
+                dimension,
+                self.shape[dimension]
+            );
+            offset += range.start * self.strides[dimension];
+            shape.push(range.end - range.start);
+        }
+
+        TensorND {
+            data: self.data.clone(),
+            shape,
+            strides: self.strides.clone(),
+            offset,
+        }
+    }
+
+    // Numpy style broadcasting. Shapes are aligned from the last dimension,
+    // and each pair of dimensions has to either match or contain a 1.
+    pub fn broadcast_shapes(left: &[usize], right: &[usize]) -> Option<Vec<usize>> {
+        let rank: usize = left.len().max(right.len());
+        let mut shape: Vec<usize> = vec![0; rank];
+        for dimension in 0..rank {
+            let left_size: usize = if dimension < rank - left.len() {
+                1
+            } else {
+                left[dimension - (rank - left.len())]
+            };
+            let right_size: usize = if dimension < rank - right.len() {
+                1
+            } else {
+                right[dimension - (rank - right.len())]
+            };
+
+            shape[dimension] = if left_size == right_size || right_size == 1 {
+                left_size
+            } else if left_size == 1 {
+                right_size
+            } else {
+                return None;
+            };
+        }
+        Some(shape)
+    }
+
+    // Expands the tensor to the given shape as a view by setting
+    // the stride of every broadcast dimension to 0.
+    pub fn broadcast_to(&self, shape: &[usize]) -> Self {
+        assert!(
+            self.rank() <= shape.len(),
+            "\nTensorND::broadcast_to - cannot broadcast {:?} to the lower rank {:?}.",
+            self.shape,
+            shape
+        );
+
+        let leading_dimensions: usize = shape.len() - self.rank();
+        let mut strides: Vec<usize> = vec![0; shape.len()];
+        for dimension in 0..self.rank() {
+            let size: usize = self.shape[dimension];
+            let target: usize = shape[leading_dimensions + dimension];
+            if size == target {
+                strides[leading_dimensions + dimension] = self.strides[dimension];
+            } else {
+                assert_eq!(
+                    size, 1,
+                    "\nTensorND::broadcast_to - cannot broadcast {:?} to {:?}.",
+                    self.shape, shape
+                );
+            }
+        }
+
+        TensorND {
+            data: self.data.clone(),
+            shape: shape.to_vec(),
+            strides,
+            offset: self.offset,
+        }
+    }
+
+    pub fn elementwise<F: Fn(T, T) -> T>(left: &Self, right: &Self, function: F) -> Self {
+        let shape: Vec<usize> =
+            Self::broadcast_shapes(&left.shape, &right.shape).unwrap_or_else(|| {
+                panic!(
+                    "\nTensorND::elementwise - shapes {:?} and {:?} cannot be broadcast together.",
+                    left.shape, right.shape
+                )
+            });
+        let left: Self = left.broadcast_to(&shape);
+        let right: Self = right.broadcast_to(&shape);
+
+        let mut output: Vec<T> = Vec::<T>::with_capacity(left.len());
+        left.for_each_index(|indices, data_index| {
+            output.push(function(left.data[data_index], right.get(indices)));
+        });
+
+        Self::from_vec(output, &shape)
+    }
+
+    pub fn add(left: &Self, right: &Self) -> Self {
+        Self::elementwise(left, right, |a, b| a + b)
+    }
+
+    pub fn subtraction(left: &Self, right: &Self) -> Self {
+        Self::elementwise(left, right, |a, b| a - b)
+    }
+
+    pub fn multiply(left: &Self, right: &Self) -> Self {
+        Self::elementwise(left, right, |a, b| a * b)
+    }
+
+    // Mostly for verifying correctness
+    pub fn sum(&self) -> T {
+        let mut sum: T = T::zero();
+        self.for_each_index(|_, data_index| sum += self.data[data_index]);
+        sum
+    }
+
+    pub fn from_tensor2d(tensor: &Tensor2D<T>) -> Self {
+        Self::from_vec(
+            tensor.data[0..tensor.len()].to_vec(),
+            &[tensor.row_count, tensor.column_count],
+        )
+    }
+
+    pub fn to_tensor2d(&self) -> Tensor2D<T> {
+        assert_eq!(
+            self.rank(),
+            2,
+            "\nTensorND::to_tensor2d - only rank 2 tensors can be converted, found shape {:?}.",
+            self.shape
+        );
+
+        Tensor2D {
+            data: self.to_vec(),
+            row_count: self.shape[0],
+            column_count: self.shape[1],
+        }
+    }
+}
+
+impl<T: TensorElement> From<&Tensor2D<T>> for TensorND<T> {
+    fn from(tensor: &Tensor2D<T>) -> Self {
+        TensorND::from_tensor2d(tensor)
+    }
+}
@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod tests {
+    use crate::shared::tensor2d::Tensor2D;
+    use crate::shared::tensor_nd::TensorND;
+
+    #[test]
+    fn strides() {
+        let tensor: TensorND = TensorND::new(1.0, &[2, 3, 4]);
+        assert_eq!(tensor.strides, vec![12, 4, 1]);
+        assert_eq!(tensor.len(), 24);
+        assert!(tensor.is_contiguous());
+        assert_eq!(tensor.get(&[1, 2, 3]), 23.0);
+    }
+
+    #[test]
+    fn tensor2d_round_trip() {
+        let tensor_2d: Tensor2D = Tensor2D::new(0.5, 3, 5);
+        let tensor_nd: TensorND = TensorND::from(&tensor_2d);
+        assert_eq!(tensor_nd.shape, vec![3, 5]);
+        for row in 0..3 {
+            for column in 0..5 {
+                assert_eq!(
+                    tensor_nd.get(&[row, column]),
+                    tensor_2d.data[row * 5 + column]
+                );
+            }
+        }
+
+        let round_trip: Tensor2D = tensor_nd.to_tensor2d();
+        assert_eq!(round_trip.row_count, tensor_2d.row_count);
+        assert_eq!(round_trip.column_count, tensor_2d.column_count);
+        assert_eq!(round_trip.data, tensor_2d.data);
+    }
+
+    #[test]
+    fn reshape() {
+        let tensor: TensorND = TensorND::new(1.0, &[2, 6]);
+        let reshaped: TensorND = tensor.reshape(&[2, 3, 2]);
+        assert_eq!(reshaped.strides, vec![6, 2, 1]);
+        assert_eq!(reshaped.get(&[1, 1, 0]), 8.0);
+        assert_eq!(reshaped.to_vec(), tensor.to_vec());
+
+        // Reshaping a transposed view has to copy to keep the row-major order
+        let transposed: TensorND = tensor.transpose().reshape(&[12]);
+        assert_eq!(
+            transposed.to_vec(),
+            vec![0.0, 6.0, 1.0, 7.0, 2.0, 8.0, 3.0, 9.0, 4.0, 10.0, 5.0, 11.0]
+        );
+    }
+
+    #[test]
+    fn transpose_is_a_view() {
+        let tensor: TensorND = TensorND::new(1.0, &[2, 3]);
+        let transposed: TensorND = tensor.transpose();
+        assert_eq!(transposed.shape, vec![3, 2]);
+        assert_eq!(transposed.strides, vec![1, 3]);
+        assert!(!transposed.is_contiguous());
+        assert!(std::sync::Arc::ptr_eq(&tensor.data, &transposed.data));
+        assert_eq!(transposed.to_vec(), vec![0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+        assert_eq!(transposed.transpose().to_vec(), tensor.to_vec());
+    }
+
+    #[test]
+    fn slice() {
+        let tensor: TensorND = TensorND::new(1.0, &[3, 4]);
+        let sliced: TensorND = tensor.slice(&[1..3, 1..3]);
+        assert_eq!(sliced.shape, vec![2, 2]);
+        assert_eq!(sliced.to_vec(), vec![5.0, 6.0, 9.0, 10.0]);
+        assert_eq!(sliced.sum(), 30.0);
+
+        let contiguous: TensorND = sliced.to_contiguous();
+        assert_eq!(contiguous.offset, 0);
+        assert_eq!(contiguous.data.len(), 4);
+    }
+
+    #[test]
+    fn broadcasting() {
+        assert_eq!(
+            TensorND::<f32>::broadcast_shapes(&[4, 1, 3], &[5, 1]),
+            Some(vec![4, 5, 3])
+        );
+        assert_eq!(TensorND::<f32>::broadcast_shapes(&[2, 3], &[4]), None);
+
+        // Adding a bias row to every row of a matrix
+        let matrix: TensorND = TensorND::new(1.0, &[2, 3]);
+        let bias: TensorND = TensorND::from_vec(vec![10.0, 20.0, 30.0], &[3]);
+        let sum: TensorND = TensorND::add(&matrix, &bias);
+        assert_eq!(sum.shape, vec![2, 3]);
+        assert_eq!(sum.to_vec(), vec![10.0, 21.0, 32.0, 13.0, 24.0, 35.0]);
+
+        // Column vector times row vector
+        let column: TensorND = TensorND::from_vec(vec![1.0, 2.0], &[2, 1]);
+        let row: TensorND = TensorND::from_vec(vec![3.0, 4.0, 5.0], &[1, 3]);
+        let product: TensorND = TensorND::multiply(&column, &row);
+        assert_eq!(product.to_vec(), vec![3.0, 4.0, 5.0, 6.0, 8.0, 10.0]);
+
+        let broadcast: TensorND = bias.broadcast_to(&[2, 2, 3]);
+        assert_eq!(broadcast.strides, vec![0, 0, 1]);
+        assert_eq!(broadcast.get(&[1, 1, 2]), 30.0);
+    }
+}
@@ -1,8 +1,12 @@
 use crate::shared::{
     benchmark_plot::draw_benchmark_plot,
     configuration::Configuration,
-    performance_measurement::{benchmark_function_vector, PerformanceMeasurements},
+    performance_measurement::{
+        benchmark_function_vector, BenchmarkFunction, PerformanceMeasurements,
+    },
     tensor2d::Tensor2D,
+    tensor2d_blocked::{tune_tile_sizes_per_size, TileSizes},
+    tensor2d_parallel::resolve_thread_count,
 };
 
 fn naive_linear_layer_benchmark(
@@ -50,21 +54,68 @@ fn optimized_linear_layer_benchmark(
     Tensor2D::linear_layer_optimized(input, weights, bias, output);
 }
 
+// The thread count comes from the Configuration
+fn parallel_linear_layer_benchmark(thread_count: usize) -> BenchmarkFunction {
+    Box::new(
+        move |input: &mut Tensor2D, weights: &Tensor2D, bias: &Tensor2D, output: &mut Tensor2D| {
+            Tensor2D::linear_layer_parallel(input, weights, bias, output, thread_count);
+        },
+    )
+}
+
+// Falls back to linear_layer_optimized if the CPU doesn't support AVX2 and FMA
+fn simd_linear_layer_benchmark(
+    input: &mut Tensor2D,
+    weights: &Tensor2D,
+    bias: &Tensor2D,
+    output: &mut Tensor2D,
+) {
+    Tensor2D::linear_layer_simd(input, weights, bias, output);
+}
+
+// Uses the tile sizes the tuner found for the size of the input in linear_layer_benchmark
+fn blocked_linear_layer_benchmark(tuned_tile_sizes: Vec<(usize, TileSizes)>) -> BenchmarkFunction {
+    Box::new(
+        move |input: &mut Tensor2D, weights: &Tensor2D, bias: &Tensor2D, output: &mut Tensor2D| {
+            let tile_sizes: TileSizes = tuned_tile_sizes
+                .iter()
+                .find(|(size, _)| *size == input.row_count)
+                .map_or(TileSizes::DEFAULT, |(_, tile_sizes)| *tile_sizes);
+            Tensor2D::linear_layer_blocked(input, weights, bias, output, &tile_sizes);
+        },
+    )
+}
+
 fn linear_layer_benchmark(config: &Configuration) {
+    let tuned_tile_sizes: Vec<(usize, TileSizes)> =
+        tune_tile_sizes_per_size(&config.loop_range, config.loop_count);
+    if 1 < config.debug_level {
+        for (size, tile_sizes) in &tuned_tile_sizes {
+            println!("Tuned tile sizes for {}x{}: {:?}", size, size, tile_sizes);
+        }
+    }
+
     let names: Vec<String> = vec![
         "naive".to_string(),
         "preallocated".to_string(),
         "inline".to_string(),
         "local_accumulation".to_string(),
         "optimized".to_string(),
+        "parallel".to_string(),
+        "simd".to_string(),
+        "blocked".to_string(),
     ];
 
-    let functions: Vec<fn(&mut Tensor2D, &Tensor2D, &Tensor2D, &mut Tensor2D)> = vec![
-        naive_linear_layer_benchmark,
-        preallocated_linear_layer_benchmark,
-        inline_linear_layer_benchmark,
-        local_accumulation_linear_layer_benchmark,
-        optimized_linear_layer_benchmark,
+    let thread_count: usize = resolve_thread_count(config.thread_count);
+    let functions: Vec<BenchmarkFunction> = vec![
+        Box::new(naive_linear_layer_benchmark),
+        Box::new(preallocated_linear_layer_benchmark),
+        Box::new(inline_linear_layer_benchmark),
+        Box::new(local_accumulation_linear_layer_benchmark),
+        Box::new(optimized_linear_layer_benchmark),
+        parallel_linear_layer_benchmark(thread_count),
+        Box::new(simd_linear_layer_benchmark),
+        blocked_linear_layer_benchmark(tuned_tile_sizes),
     ];
 
     let mut all_measurements: Vec<PerformanceMeasurements> =
@@ -154,19 +205,30 @@ fn inline_relu_benchmark(
     Tensor2D::relu_inplace_inline(input);
 }
 
+fn simd_relu_benchmark(
+    input: &mut Tensor2D,
+    _weights: &Tensor2D,
+    _bias: &Tensor2D,
+    _output: &mut Tensor2D,
+) {
+    Tensor2D::relu_inplace_simd(input);
+}
+
 fn relu_benchmark(config: &Configuration) {
     let names: Vec<String> = vec![
         "naive".to_string(),
         "preallocated".to_string(),
         "inplace".to_string(),
         "inline".to_string(),
+        "simd".to_string(),
     ];
 
-    let functions: Vec<fn(&mut Tensor2D, &Tensor2D, &Tensor2D, &mut Tensor2D)> = vec![
-        naive_relu_benchmark,
-        preallocated_relu_benchmark,
-        inplace_relu_benchmark,
-        inline_relu_benchmark,
+    let functions: Vec<BenchmarkFunction> = vec![
+        Box::new(naive_relu_benchmark),
+        Box::new(preallocated_relu_benchmark),
+        Box::new(inplace_relu_benchmark),
+        Box::new(inline_relu_benchmark),
+        Box::new(simd_relu_benchmark),
     ];
 
     let mut all_measurements: Vec<PerformanceMeasurements> =
@@ -244,19 +306,44 @@ fn inline_softmax_benchmark(
     Tensor2D::softmax_inplace_inline(input);
 }
 
+fn parallel_softmax_benchmark(thread_count: usize) -> BenchmarkFunction {
+    Box::new(
+        move |input: &mut Tensor2D,
+              _weights: &Tensor2D,
+              _bias: &Tensor2D,
+              output: &mut Tensor2D| {
+            Tensor2D::softmax_parallel(input, output, thread_count);
+        },
+    )
+}
+
+fn simd_softmax_benchmark(
+    input: &mut Tensor2D,
+    _weights: &Tensor2D,
+    _bias: &Tensor2D,
+    output: &mut Tensor2D,
+) {
+    Tensor2D::softmax_simd(input, output);
+}
+
 fn softmax_benchmark(config: &Configuration) {
     let names: Vec<String> = vec![
         "naive".to_string(),
         "preallocated".to_string(),
         "inplace".to_string(),
         "inline".to_string(),
+        "parallel".to_string(),
+        "simd".to_string(),
     ];
 
-    let functions: Vec<fn(&mut Tensor2D, &Tensor2D, &Tensor2D, &mut Tensor2D)> = vec![
-        naive_softmax_benchmark,
-        preallocated_softmax_benchmark,
-        inplace_softmax_benchmark,
-        inline_softmax_benchmark,
+    let thread_count: usize = resolve_thread_count(config.thread_count);
+    let functions: Vec<BenchmarkFunction> = vec![
+        Box::new(naive_softmax_benchmark),
+        Box::new(preallocated_softmax_benchmark),
+        Box::new(inplace_softmax_benchmark),
+        Box::new(inline_softmax_benchmark),
+        parallel_softmax_benchmark(thread_count),
+        Box::new(simd_softmax_benchmark),
     ];
 
     let mut all_measurements: Vec<PerformanceMeasurements> =
@@ -357,6 +444,20 @@ fn fused_linear_relu_benchmark(
     Tensor2D::linear_layer_optimized_relu(input, weights, bias, output);
 }
 
+fn parallel_linear_relu_softmax_benchmark(thread_count: usize) -> BenchmarkFunction {
+    Box::new(
+        move |input: &mut Tensor2D, weights: &Tensor2D, bias: &Tensor2D, output: &mut Tensor2D| {
+            Tensor2D::linear_relu_softmax_fused_parallel(
+                input,
+                weights,
+                bias,
+                output,
+                thread_count,
+            );
+        },
+    )
+}
+
 fn linear_relu_softmax_fused_benchmark(config: &Configuration) {
     let names: Vec<String> = vec![
         "naive".to_string(),
@@ -365,14 +466,17 @@ fn linear_relu_softmax_fused_benchmark(config: &Configuration) {
         "fused".to_string(),
         "linear-relu-fission".to_string(),
         "linear-relu-optimized".to_string(),
+        "parallel".to_string(),
     ];
-    let functions: Vec<fn(&mut Tensor2D, &Tensor2D, &Tensor2D, &mut Tensor2D)> = vec![
-        naive_linear_relu_softmax_benchmark,
-        local_accumulation_linear_relu_softmax_benchmark,
-        fused_fission_linear_relu_softmax_benchmark,
-        fused_linear_relu_softmax_benchmark,
-        fused_fission_linear_relu_benchmark,
-        fused_linear_relu_benchmark,
+    let thread_count: usize = resolve_thread_count(config.thread_count);
+    let functions: Vec<BenchmarkFunction> = vec![
+        Box::new(naive_linear_relu_softmax_benchmark),
+        Box::new(local_accumulation_linear_relu_softmax_benchmark),
+        Box::new(fused_fission_linear_relu_softmax_benchmark),
+        Box::new(fused_linear_relu_softmax_benchmark),
+        Box::new(fused_fission_linear_relu_benchmark),
+        Box::new(fused_linear_relu_benchmark),
+        parallel_linear_relu_softmax_benchmark(thread_count),
     ];
     let mut all_measurements: Vec<PerformanceMeasurements> =
         vec![PerformanceMeasurements::default(); functions.len()];